@@ -0,0 +1,70 @@
+// Heap allocator calls and time for many small host buffers from
+// `alloc_scratch`, which the arena serves, against the same count from
+// `alloc`/`dealloc`, which go to the heap. The scratch ones should make no
+// allocator calls at all. Run with `cargo bench`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const BUFFERS: usize = 20_000;
+
+static ALLOCATOR_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATOR_CALLS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATOR_CALLS.fetch_add(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Allocates and frees `BUFFERS` buffers of 64 bytes, a few per "call" as a
+// host would, returning the allocator calls made and the time taken
+fn churn(scratch: bool) -> (usize, Duration) {
+    const SIZE: usize = 64;
+    let request = br#"{"node": "word-count", "function": "__ping"}"#;
+    let mut out = vec![0u8; 256];
+    let mut buffers = Vec::with_capacity(4);
+    let mut calls = 0;
+    let mut elapsed = Duration::ZERO;
+    for round in 0..BUFFERS / 4 {
+        let before = ALLOCATOR_CALLS.load(Ordering::Relaxed);
+        let started = Instant::now();
+        for _ in 0..4 {
+            buffers.push(if scratch { word_counter::alloc_scratch(SIZE) } else { word_counter::alloc(SIZE) });
+        }
+        for ptr in buffers.drain(..) {
+            // SAFETY: each pointer came from `alloc` or `alloc_scratch` with this size
+            unsafe { word_counter::dealloc(ptr, SIZE) };
+        }
+        elapsed += started.elapsed();
+        calls += ALLOCATOR_CALLS.load(Ordering::Relaxed) - before;
+        // A call between rounds resets the arena; its own allocations
+        // aren't counted
+        if round % 16 == 0 {
+            // SAFETY: both buffers outlive the call and have the lengths passed
+            unsafe { word_counter::call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        }
+    }
+    (calls, elapsed)
+}
+
+fn main() {
+    let (scratch_calls, scratch_time) = churn(true);
+    let (heap_calls, heap_time) = churn(false);
+    println!("{} scratch buffers: {} allocator calls, {:?}", BUFFERS, scratch_calls, scratch_time);
+    println!("{} alloc buffers: {} allocator calls, {:?}", BUFFERS, heap_calls, heap_time);
+    if scratch_calls >= heap_calls {
+        println!("note: the arena saved no allocator calls; is another thread holding every slice?");
+    }
+}
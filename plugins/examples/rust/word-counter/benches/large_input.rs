@@ -0,0 +1,65 @@
+// Cost of a prep `call` over a large input, against what handlers used to
+// spend before doing any work: cloning the request's input `Value` to
+// deserialize it, which copied the whole text. Handlers now read the input
+// in place, so the clone's bytes and time are what each call saves. Run
+// with `cargo bench`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use word_counter::WordCounterInput;
+
+const ITERATIONS: u32 = 20;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Bytes allocated by one run of `f`, and its average time
+fn measure(mut f: impl FnMut()) -> (usize, Duration) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    let bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    (bytes, started.elapsed() / ITERATIONS)
+}
+
+fn main() {
+    // About 1MB, well within the memory permission
+    let text = "the quick brown fox jumps over the lazy dog ".repeat(24_000);
+    let request = serde_json::json!({"node": "word-count", "function": "prep", "input": {"text": text}}).to_string();
+    let mut out = vec![0u8; 4 * 1024 * 1024];
+    let (call_bytes, call_time) = measure(|| {
+        // SAFETY: both buffers outlive the call and have the lengths passed
+        let len = unsafe { word_counter::call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        assert!(len <= out.len(), "the response didn't fit");
+    });
+
+    let parsed: serde_json::Value = serde_json::from_str(&request).unwrap();
+    let (clone_bytes, clone_time) = measure(|| {
+        serde_json::from_value::<WordCounterInput>(parsed["input"].clone()).unwrap();
+    });
+
+    println!("{} byte text", text.len());
+    println!("prep call: {} bytes allocated, {:?}", call_bytes, call_time);
+    println!("the clone it no longer makes: {} bytes allocated, {:?}", clone_bytes, clone_time);
+    assert!(clone_bytes >= text.len(), "cloning the input only allocated {} bytes", clone_bytes);
+}
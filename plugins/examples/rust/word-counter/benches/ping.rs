@@ -0,0 +1,32 @@
+// Cost of a `__ping` call against a request that is parsed in full: the
+// same bytes with an unknown function, which fails right after parsing.
+// The ping should cost a small fraction of it. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20_000;
+
+fn time_call(request: &[u8]) -> Duration {
+    let mut out = vec![0u8; 4096];
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        // SAFETY: both buffers outlive the call and have the lengths passed
+        unsafe { word_counter::call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+    }
+    started.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let body = r#""node": "word-count", "config": {"stop_words": ["the", "a", "an"], "min_word_length": 2},
+        "input": {"text": "the quick brown fox jumps over the lazy dog"}"#;
+    let ping = format!(r#"{{"function": "__ping", {}}}"#, body);
+    let parsed = format!(r#"{{"function": "__pong", {}}}"#, body);
+
+    let ping_time = time_call(ping.as_bytes());
+    let parsed_time = time_call(parsed.as_bytes());
+    println!("__ping call, average of {}: {:?}", ITERATIONS, ping_time);
+    println!("fully parsed call, average of {}: {:?}", ITERATIONS, parsed_time);
+    if ping_time >= parsed_time {
+        println!("note: the ping was no faster; the machine is likely busy");
+    }
+}
@@ -0,0 +1,35 @@
+// Exec cost over degenerate input, a few words each repeated in long runs,
+// counted token by token and with `run_length`. The totals must match, and
+// the bench fails unless the run-length path is the faster. Run with
+// `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use word_counter::{Node, WordCounter, WordCounterConfig, WordCounterInput};
+
+const ITERATIONS: u32 = 5;
+
+fn average(config: &WordCounterConfig, input: &WordCounterInput) -> (Duration, usize) {
+    let node = WordCounter::default();
+    let total_words = node.exec(config, input).unwrap().total_words;
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        node.exec(config, input).unwrap();
+    }
+    (started.elapsed() / ITERATIONS, total_words)
+}
+
+fn main() {
+    let text: String = ["spam ", "Spam ", "eggs "].iter().map(|word| word.repeat(200_000)).collect();
+    let input = WordCounterInput { text, ..Default::default() };
+
+    let config = WordCounterConfig::default();
+    let (naive, naive_words) = average(&config, &input);
+    let config = WordCounterConfig { run_length: true, ..WordCounterConfig::default() };
+    let (runs, run_words) = average(&config, &input);
+    assert_eq!(naive_words, run_words);
+
+    println!("{} words, token by token: {:?}", naive_words, naive);
+    println!("{} words, run_length: {:?}", run_words, runs);
+    assert!(runs < naive, "run_length ({:?}) was no faster than token by token ({:?})", runs, naive);
+}
@@ -0,0 +1,72 @@
+// Exec cost with the default stop words: the first call builds the shared
+// stop-word set, later calls reuse it. The set's strings are allocated only
+// by that first build, so the bench fails unless every later exec makes the
+// same number of allocator calls, at least one per stop word fewer than the
+// first. Run with `cargo bench`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use word_counter::{Node, WordCounter, WordCounterConfig, WordCounterInput};
+
+const ITERATIONS: u32 = 2_000;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// One exec's allocations and time
+fn exec(node: &WordCounter, config: &WordCounterConfig, input: &WordCounterInput) -> (usize, Duration) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    node.exec(config, input).unwrap();
+    let elapsed = started.elapsed();
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, elapsed)
+}
+
+fn main() {
+    let node = WordCounter::default();
+    let config = WordCounterConfig::default();
+    let input = WordCounterInput {
+        text: "the quick brown fox jumps over the lazy dog and it is a fine day for it".repeat(4),
+        ..Default::default()
+    };
+
+    let (first_allocations, first) = exec(&node, &config, &input);
+    let mut later_allocations = Vec::with_capacity(ITERATIONS as usize);
+    let mut later = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let (allocations, elapsed) = exec(&node, &config, &input);
+        later_allocations.push(allocations);
+        later += elapsed;
+    }
+
+    println!("first exec (builds the stop-word set): {:?}, {} allocations", first, first_allocations);
+    println!("later execs, average of {}: {:?}, {} allocations", ITERATIONS, later / ITERATIONS, later_allocations[0]);
+    assert!(
+        later_allocations.iter().all(|allocations| *allocations == later_allocations[0]),
+        "later execs allocated unevenly, so the stop-word set was rebuilt: {:?}",
+        later_allocations.iter().min()..=later_allocations.iter().max()
+    );
+    assert!(
+        first_allocations >= later_allocations[0] + config.stop_words.len(),
+        "the first exec made {} allocations and later ones {}, too few for it to have built the set",
+        first_allocations,
+        later_allocations[0]
+    );
+}
@@ -0,0 +1,92 @@
+// `#[derive(PocketNode)]`: declares a node from a type implementing
+// `word_counter::Node`. The node's metadata comes from the type's
+// `#[pocket(...)]` attribute and its schemas from the `Node`'s config,
+// input and output types, or from the types `config`, `input` and `output`
+// name instead (say, a schema-only mirror of a type that can't derive
+// `JsonSchema`); `word_counter::derived` builds the definition and the
+// exec/post handlers from those, and `word_counter::derived::register`
+// adds the node to the registry.
+//
+//     #[derive(PocketNode, Default)]
+//     #[pocket(node_type = "reading-time", category = "text", description = "...")]
+//     #[pocket(input = ReadingTimeInputSchema)]
+//     pub struct ReadingTime;
+//
+// `category` defaults to "general"; `node_type` and `description` are
+// required.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr, Type};
+
+#[proc_macro_derive(PocketNode, attributes(pocket))]
+pub fn derive_pocket_node(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut node_type = None;
+    let mut category = None;
+    let mut description = None;
+    // Schema types by attribute, with the `Node` associated type each
+    // stands in for
+    let mut schemas: [(&str, &str, Option<Type>); 3] =
+        [("config", "Config", None), ("input", "Input", None), ("output", "Output", None)];
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("pocket")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some((_, _, slot)) = schemas.iter_mut().find(|(name, _, _)| meta.path.is_ident(name)) {
+                if slot.is_some() {
+                    return Err(meta.error("duplicate pocket attribute"));
+                }
+                *slot = Some(meta.value()?.parse::<Type>()?);
+                return Ok(());
+            }
+            let slot = if meta.path.is_ident("node_type") {
+                &mut node_type
+            } else if meta.path.is_ident("category") {
+                &mut category
+            } else if meta.path.is_ident("description") {
+                &mut description
+            } else {
+                return Err(meta.error(
+                    "expected `node_type`, `category`, `description`, `config`, `input` or `output`",
+                ));
+            };
+            if slot.is_some() {
+                return Err(meta.error("duplicate pocket attribute"));
+            }
+            *slot = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        })?;
+    }
+
+    let missing = |name: &str| {
+        syn::Error::new_spanned(&input.ident, format!("missing `#[pocket({} = \"...\")]`", name))
+    };
+    let node_type = node_type.ok_or_else(|| missing("node_type"))?;
+    let description = description.ok_or_else(|| missing("description"))?;
+    let category = category.unwrap_or_else(|| LitStr::new("general", input.ident.span()));
+
+    let [config, input_schema, output] = schemas.map(|(_, associated, schema)| match schema {
+        Some(schema) => quote!(#schema),
+        None => {
+            let associated = syn::Ident::new(associated, input.ident.span());
+            quote!(<Self as ::word_counter::Node>::#associated)
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::word_counter::derived::PocketNode for #ident #ty_generics #where_clause {
+            const NODE_TYPE: &'static str = #node_type;
+            const CATEGORY: &'static str = #category;
+            const DESCRIPTION: &'static str = #description;
+            type ConfigSchema = #config;
+            type InputSchema = #input_schema;
+            type OutputSchema = #output;
+        }
+    })
+}
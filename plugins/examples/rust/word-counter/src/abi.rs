@@ -0,0 +1,131 @@
+// Return-code convention for the exports that hand the host a buffer
+// (`metadata`, `run_examples`, `schema_bundle`, `call`, `call_owned`,
+// `call_bytes`, `feed_finish`, `frequencies_page`). The return value is a
+// `usize` whose top `CODE_BITS` bits hold an `AbiError` and whose other
+// bits hold a length:
+//
+// - `Ok`: a response of `length` bytes was written (it may still be a
+//   failure response)
+// - `BufferTooSmall`: nothing was written; `length` is the buffer size the
+//   response needs
+// - `SerializationFailed`: the response couldn't be encoded as requested;
+//   `length` bytes of a JSON error response were written instead
+// - `OutOfMemory`: the response was larger than the memory permission;
+//   `length` bytes of a `resource_exhausted` error response were written
+//   instead
+// - `Panicked`: the export panicked; nothing was written and `length` is 0.
+//   Builds with `panic = "abort"` (the usual wasm setting) trap instead
+//
+// `Ok` is 0, so a successful return is just the length, as it always was.
+// Lengths are capped at `MAX_LENGTH`, well above the memory permission.
+//
+// Hosts that predate the codes read any return value larger than their
+// buffer as "retry with a buffer this size", so codes are only packed once
+// a host negotiates `returnCodes` in `init_with`. Until then an export
+// returns the bare length whatever happened, as it always did.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::session;
+
+pub const CODE_BITS: u32 = 4;
+pub const MAX_LENGTH: usize = usize::MAX >> CODE_BITS;
+const CODE_SHIFT: u32 = usize::BITS - CODE_BITS;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AbiError {
+    Ok = 0,
+    BufferTooSmall = 1,
+    SerializationFailed = 2,
+    Panicked = 3,
+    OutOfMemory = 4,
+}
+
+impl AbiError {
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(AbiError::Ok),
+            1 => Some(AbiError::BufferTooSmall),
+            2 => Some(AbiError::SerializationFailed),
+            3 => Some(AbiError::Panicked),
+            4 => Some(AbiError::OutOfMemory),
+            _ => None,
+        }
+    }
+}
+
+// Packs `code` and `length` into an export's return value. `length` must
+// not exceed `MAX_LENGTH`.
+pub fn encode_return(code: AbiError, length: usize) -> usize {
+    debug_assert!(length <= MAX_LENGTH, "length {} doesn't fit the return value", length);
+    ((code as usize) << CODE_SHIFT) | (length & MAX_LENGTH)
+}
+
+// Splits an export's return value into its code and length, or `None` for
+// a code this version doesn't know
+pub fn decode_return(value: usize) -> Option<(AbiError, usize)> {
+    let code = AbiError::from_code((value >> CODE_SHIFT) as u8)?;
+    Some((code, value & MAX_LENGTH))
+}
+
+// What an export returns for `code` and `length` with what the host
+// negotiated: both packed, or just the length
+pub(crate) fn return_value(code: AbiError, length: usize) -> usize {
+    if session::return_codes() {
+        encode_return(code, length)
+    } else {
+        length
+    }
+}
+
+// Runs an export's body, turning a panic into `Panicked`
+pub(crate) fn guard(body: impl FnOnce() -> usize) -> usize {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| return_value(AbiError::Panicked, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [AbiError; 5] = [
+        AbiError::Ok,
+        AbiError::BufferTooSmall,
+        AbiError::SerializationFailed,
+        AbiError::Panicked,
+        AbiError::OutOfMemory,
+    ];
+
+    #[test]
+    fn every_code_round_trips_with_its_length() {
+        for code in CODES {
+            for length in [0, 1, 4096, MAX_LENGTH] {
+                assert_eq!(decode_return(encode_return(code, length)), Some((code, length)), "{:?}", code);
+            }
+            assert_eq!(AbiError::from_code(code as u8), Some(code));
+        }
+    }
+
+    #[test]
+    fn ok_is_the_bare_length_and_unknown_codes_are_rejected() {
+        assert_eq!(encode_return(AbiError::Ok, 1234), 1234);
+        assert!(encode_return(AbiError::BufferTooSmall, 8) > MAX_LENGTH);
+        assert_eq!(decode_return(15 << CODE_SHIFT), None);
+    }
+
+    #[test]
+    fn codes_wait_for_the_host_to_negotiate_them() {
+        assert_eq!(return_value(AbiError::BufferTooSmall, 8), 8);
+        assert_eq!(guard(|| panic!("handler bug")), 0);
+        session::apply(&serde_json::from_str(r#"{"returnCodes": true}"#).unwrap());
+        assert_eq!(return_value(AbiError::BufferTooSmall, 8), encode_return(AbiError::BufferTooSmall, 8));
+    }
+
+    #[test]
+    fn panics_become_the_panicked_code() {
+        session::apply(&serde_json::from_str(r#"{"returnCodes": true}"#).unwrap());
+        assert_eq!(guard(|| 7), 7);
+        let value = guard(|| panic!("handler bug"));
+        assert_eq!(decode_return(value), Some((AbiError::Panicked, 0)));
+    }
+}
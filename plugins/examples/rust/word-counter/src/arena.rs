@@ -0,0 +1,188 @@
+// Bump arena behind `alloc_scratch`, which hosts opt into for small
+// buffers instead of `alloc`. Hosts allocate a handful of short-lived
+// buffers per call (a request, a chunk, a dictionary), and most are small;
+// bumping a pointer through a static region is cheaper than a heap
+// round-trip for each.
+//
+// The region is split into fixed slices, one claimed by each thread that
+// allocates, so concurrent host threads never hand out the same bytes.
+// A slice is reset when a call starts on its thread (`reset`): everything
+// allocated from it before then is reclaimed, except the call's own buffers
+// (its request, binary data and output).
+// Scratch buffers aren't accounted against the memory permission, and
+// `dealloc` ignores them; the bytes come back at the next reset. A thread
+// that finds every slice claimed, or its own slice full, gets null, as do
+// requests over `SMALL_ALLOC`, and the host falls back to `alloc`.
+//
+// Arena buffers are told apart from heap ones by address, so `dealloc` may
+// free them from any thread, even after the allocating thread exits (its
+// slice is then released for another thread to claim).
+
+use std::cell::{Cell, UnsafeCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Largest request served from the arena
+pub(crate) const SMALL_ALLOC: usize = 512;
+
+// Every test thread that allocates claims one
+const SLICES: usize = if cfg!(test) { 64 } else { 8 };
+const SLICE_BYTES: usize = 16 * 1024;
+
+// Buffers start on this boundary, which hosts copying words in expect
+const ALIGN: usize = 8;
+
+#[repr(align(8))]
+struct Region(UnsafeCell<[u8; SLICES * SLICE_BYTES]>);
+
+// Each slice is only written through by the thread that claimed it, and the
+// host, through pointers that thread handed out
+unsafe impl Sync for Region {}
+
+static REGION: Region = Region(UnsafeCell::new([0; SLICES * SLICE_BYTES]));
+
+// Bit `i` is set while slice `i` is claimed
+static CLAIMED: AtomicUsize = AtomicUsize::new(0);
+
+fn region_start() -> usize {
+    REGION.0.get() as usize
+}
+
+// Whether `ptr` was handed out by the arena rather than the heap
+pub(crate) fn contains(ptr: *const u8) -> bool {
+    (region_start()..region_start() + SLICES * SLICE_BYTES).contains(&(ptr as usize))
+}
+
+// Buffers a reset keeps: a call's request, binary data and output buffer
+const KEPT: usize = 3;
+
+// This thread's slice, released when the thread exits
+struct Slice {
+    index: usize,
+    // Offset of the next free byte
+    next: Cell<usize>,
+    // Offset ranges the bump skips over, sorted; empty ones are unused
+    kept: Cell<[(usize, usize); KEPT]>,
+}
+
+impl Slice {
+    fn claim() -> Option<Slice> {
+        let claimed = CLAIMED
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |claimed| {
+                let free = (!claimed).trailing_zeros() as usize;
+                (free < SLICES).then_some(claimed | 1 << free)
+            })
+            .ok()?;
+        Some(Slice {
+            index: (!claimed).trailing_zeros() as usize,
+            next: Cell::new(0),
+            kept: Cell::new([(0, 0); KEPT]),
+        })
+    }
+
+    fn start(&self) -> usize {
+        region_start() + self.index * SLICE_BYTES
+    }
+
+    fn bump(&self, size: usize) -> Option<*mut u8> {
+        let mut offset = self.next.get().next_multiple_of(ALIGN);
+        for (start, end) in self.kept.get() {
+            if offset < end && offset + size > start {
+                offset = end.next_multiple_of(ALIGN);
+            }
+        }
+        let end = offset.checked_add(size).filter(|end| *end <= SLICE_BYTES)?;
+        self.next.set(end);
+        // In bounds of the region: offset + size is within this slice
+        Some(unsafe { (REGION.0.get() as *mut u8).add(self.index * SLICE_BYTES + offset) })
+    }
+}
+
+impl Drop for Slice {
+    fn drop(&mut self) {
+        CLAIMED.fetch_and(!(1 << self.index), Ordering::SeqCst);
+    }
+}
+
+thread_local! {
+    // Claimed when the thread first allocates; `None` if no slice was free
+    static SLICE: Option<Slice> = Slice::claim();
+}
+
+// `size` bytes from this thread's slice, or `None` if it can't serve them
+pub(crate) fn alloc(size: usize) -> Option<*mut u8> {
+    if size == 0 || size > SMALL_ALLOC {
+        return None;
+    }
+    SLICE.try_with(|slice| slice.as_ref()?.bump(size)).ok().flatten()
+}
+
+// Reclaims this thread's slice at the start of a call, keeping the call's
+// buffers (those that came from the slice) intact
+pub(crate) fn reset<const N: usize>(buffers: [(*const u8, usize); N]) {
+    const { assert!(N <= KEPT) };
+    let _ = SLICE.try_with(|slice| {
+        let Some(slice) = slice else {
+            return;
+        };
+        let mut kept = [(0, 0); KEPT];
+        for (i, (ptr, len)) in buffers.into_iter().enumerate() {
+            let offset = (ptr as usize).wrapping_sub(slice.start());
+            if offset < SLICE_BYTES {
+                kept[i] = (offset, offset + len);
+            }
+        }
+        kept.sort_unstable();
+        slice.kept.set(kept);
+        slice.next.set(0);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_requests_bump_through_the_slice_and_big_ones_are_refused() {
+        let first = alloc(10).unwrap();
+        let second = alloc(10).unwrap();
+        assert!(contains(first) && contains(second));
+        assert_eq!(second as usize - first as usize, 16);
+        assert_eq!(first as usize % ALIGN, 0);
+        assert!(alloc(SMALL_ALLOC + 1).is_none());
+        assert!(alloc(0).is_none());
+        assert!(!contains(vec![0u8; 10].as_ptr()));
+    }
+
+    #[test]
+    fn reset_reclaims_all_but_the_calls_buffers() {
+        reset([]);
+        let input = alloc(100).unwrap();
+        let spare = alloc(100).unwrap();
+        let output = alloc(100).unwrap();
+        reset([(input, 100), (output, 100)]);
+        // The spare's bytes are free again; the input and output are skipped
+        assert_eq!(alloc(100).unwrap(), spare);
+        assert_eq!(alloc(8).unwrap() as usize, (output as usize + 100).next_multiple_of(ALIGN));
+
+        reset([]);
+        assert_eq!(alloc(8).unwrap(), input);
+    }
+
+    #[test]
+    fn buffers_from_elsewhere_keep_nothing() {
+        reset([]);
+        let first = alloc(8).unwrap();
+        let heap = Box::new([0u8; 10]);
+        reset([(heap.as_ptr(), heap.len())]);
+        assert_eq!(alloc(8).unwrap(), first);
+    }
+
+    #[test]
+    fn a_full_slice_serves_nothing_until_reset() {
+        reset([]);
+        let served = std::iter::from_fn(|| alloc(SMALL_ALLOC)).count();
+        assert_eq!(served, SLICE_BYTES / SMALL_ALLOC);
+        reset([]);
+        assert!(alloc(SMALL_ALLOC).is_some());
+    }
+}
@@ -0,0 +1,117 @@
+// Bidi-aware processing (`bidi_aware`) for right-to-left scripts such as
+// Arabic and Hebrew, where the default cleaning and byte lengths go wrong:
+//
+// - Combining marks without the Alphabetic property, such as Hebrew
+//   cantillation marks, are word breaks to default cleaning, so "בְּרֵאשִׁ֖ית"
+//   comes apart. Here every mark stays with the letter before it, and one
+//   with no letter before it is dropped.
+// - Directional formatting characters (LRM, RLM, ALM and the embedding,
+//   override and isolate controls) are invisible; they're removed rather
+//   than stored in word keys or turned into word breaks.
+// - A word is split where its strong direction changes (left-to-right
+//   letters next to right-to-left ones), a boundary the bidi algorithm
+//   would lay out as separate runs. Digits and marks have no strong
+//   direction and never split a word.
+// - Lengths count grapheme clusters, so a letter with its marks is one
+//   character and an Arabic letter isn't two (its UTF-8 bytes).
+
+use unicode_bidi::{bidi_class, BidiClass};
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+fn is_format_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{061C}')
+        || matches!(
+            bidi_class(c),
+            BidiClass::LRE | BidiClass::RLE | BidiClass::LRO | BidiClass::RLO | BidiClass::PDF
+                | BidiClass::LRI | BidiClass::RLI | BidiClass::FSI | BidiClass::PDI
+        )
+}
+
+// Whether `c` is strongly right-to-left, or `None` if it has no strong direction
+fn strong_rtl(c: char) -> Option<bool> {
+    match bidi_class(c) {
+        BidiClass::L => Some(false),
+        BidiClass::R | BidiClass::AL => Some(true),
+        _ => None,
+    }
+}
+
+// Replaces everything but letters, digits, whitespace and the marks on
+// them with spaces, like default cleaning, applying the rules above.
+// `keep_underscores` keeps underscores as word characters, as default
+// cleaning does for `identifier_split: none`.
+pub(crate) fn clean(text: &str, keep_underscores: bool) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut in_word = false;
+    // Strong direction of the current word so far
+    let mut direction = None;
+    for c in text.chars() {
+        if is_format_control(c) {
+            continue;
+        }
+        let mark = is_combining_mark(c);
+        if mark && !in_word {
+            continue;
+        }
+        if c == '_' && keep_underscores {
+            cleaned.push(c);
+            continue;
+        }
+        if c.is_alphanumeric() || mark {
+            if let Some(rtl) = strong_rtl(c) {
+                if direction.is_some_and(|current| current != rtl) {
+                    cleaned.push(' ');
+                }
+                direction = Some(rtl);
+            }
+            in_word = true;
+            cleaned.push(c);
+        } else {
+            in_word = false;
+            direction = None;
+            cleaned.push(if c.is_whitespace() { c } else { ' ' });
+        }
+    }
+    cleaned
+}
+
+pub(crate) fn grapheme_len(word: &str) -> usize {
+    word.graphemes(true).count()
+}
+
+// `word` cut to `max` grapheme clusters and marked with an ellipsis, so no
+// mark is cut off its letter
+pub(crate) fn truncate(word: &str, max: Option<usize>) -> String {
+    match max.and_then(|max| word.grapheme_indices(true).nth(max)) {
+        Some((end, _)) => format!("{}…", &word[..end]),
+        None => word.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_stay_with_their_letters_and_controls_disappear() {
+        assert_eq!(clean("\u{202B}كَتَبَ، «شالوم»\u{202C}", false), "كَتَبَ   شالوم ");
+        assert_eq!(clean("a\u{200F}b \u{064E}x", false), "ab x");
+        assert_eq!(clean("בְּרֵאשִׁ֖ית", false), "בְּרֵאשִׁ֖ית");
+    }
+
+    #[test]
+    fn direction_changes_split_words() {
+        assert_eq!(clean("Unicodeيونيكود v2שלום", false), "Unicode يونيكود v2 שלום");
+        assert_eq!(clean("עברית123", false), "עברית123");
+        assert_eq!(clean("שלום_עולם a_b", true), "שלום_עולם a_b");
+    }
+
+    #[test]
+    fn lengths_and_truncation_count_graphemes() {
+        assert_eq!(grapheme_len("المَدرَسَةِ"), 7);
+        assert_eq!("المَدرَسَةِ".len(), 22);
+        assert_eq!(truncate("المَدرَسَةِ", Some(3)), "المَ…");
+        assert_eq!(truncate("שלום", Some(4)), "שלום");
+    }
+}
@@ -0,0 +1,120 @@
+// Bloom filter of words seen across documents, for novelty detection. The
+// filter lives in the host KV store so it outlasts the instance; this module
+// owns its byte layout.
+//
+// A filter reports false positives at roughly the rate it was sized for, so
+// a word may occasionally be taken as already seen. It never forgets a word.
+
+// FNV-1a, used instead of `std`'s hasher because the persisted bits must
+// stay meaningful across toolchains. The crate's one copy: the sketch, memo
+// keys and byte-digest hash with it too, the last two unseeded (the
+// standard 64-bit FNV-1a).
+pub(crate) fn fnv1a(bytes: &[u8], seed: u64) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+// Distinct words a config may size the filter for; 1.2 MiB of bits at the
+// default 1% false-positive rate
+pub(crate) const MAX_CAPACITY: usize = 1 << 20;
+
+// num_bits (u64) + num_hashes (u32), little-endian, then the bit words
+const HEADER_LEN: usize = 12;
+
+impl BloomFilter {
+    // Sized for `capacity` distinct words at `fp_rate` false positives
+    pub(crate) fn new(capacity: usize, fp_rate: f64) -> Self {
+        let n = capacity.max(1) as f64;
+        let p = fp_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-n * p.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    // Double hashing: bit i is h1 + i * h2
+    fn positions(&self, word: &str) -> impl Iterator<Item = u64> + '_ {
+        let h1 = fnv1a(word.as_bytes(), 0);
+        let h2 = fnv1a(word.as_bytes(), 0x9e37_79b9_7f4a_7c15) | 1;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    pub(crate) fn contains(&self, word: &str) -> bool {
+        self.positions(word).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    pub(crate) fn insert(&mut self, word: &str) {
+        let positions: Vec<u64> = self.positions(word).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.bits.len() * 8);
+        bytes.extend_from_slice(&self.num_bits.to_le_bytes());
+        bytes.extend_from_slice(&self.num_hashes.to_le_bytes());
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    // `None` if `bytes` isn't a filter written by `to_bytes`
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (header, body) = bytes.split_at_checked(HEADER_LEN)?;
+        let num_bits = u64::from_le_bytes(header[..8].try_into().ok()?);
+        let num_hashes = u32::from_le_bytes(header[8..].try_into().ok()?);
+        if num_bits == 0 || num_hashes == 0 || body.len() as u64 != num_bits.div_ceil(64) * 8 {
+            return None;
+        }
+        let bits = body.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect();
+        Some(BloomFilter { bits, num_bits, num_hashes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_words_are_contained() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        filter.insert("apple");
+        assert!(filter.contains("apple"));
+        assert!(!filter.contains("banana"));
+    }
+
+    #[test]
+    fn false_positive_rate_is_near_target() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&format!("seen{}", i));
+        }
+        let false_positives = (0..10_000).filter(|i| filter.contains(&format!("unseen{}", i))).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut filter = BloomFilter::new(10, 0.1);
+        filter.insert("word");
+        assert_eq!(BloomFilter::from_bytes(&filter.to_bytes()), Some(filter));
+        assert_eq!(BloomFilter::from_bytes(b"short"), None);
+    }
+}
@@ -0,0 +1,87 @@
+// Time. WASM guests have no usable clock of their own, so everything that
+// needs the time (deadlines, profiling, expiry) reads it through a `Clock`:
+// `HostClock` in production, `MockClock` in tests so time only moves when
+// the test says so.
+
+use crate::host;
+
+pub(crate) trait Clock {
+    // Nanoseconds on a monotonic scale. Only differences are meaningful.
+    fn now_ns(&self) -> u64;
+}
+
+// The host's `now_ns` import
+pub(crate) struct HostClock;
+
+impl Clock for HostClock {
+    fn now_ns(&self) -> u64 {
+        host::now_ns()
+    }
+}
+
+#[cfg(test)]
+pub(crate) struct MockClock {
+    now_ns: std::cell::Cell<u64>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub(crate) fn new(now_ns: u64) -> Self {
+        MockClock { now_ns: std::cell::Cell::new(now_ns) }
+    }
+
+    pub(crate) fn advance(&self, ns: u64) {
+        self.now_ns.set(self.now_ns.get() + ns);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now_ns(&self) -> u64 {
+        self.now_ns.get()
+    }
+}
+
+// A lifetime measured on a clock: live for `ttl_ns` after it's started
+pub(crate) struct Ttl<'a> {
+    clock: &'a dyn Clock,
+    started_ns: u64,
+    expires_ns: u64,
+}
+
+impl<'a> Ttl<'a> {
+    pub(crate) fn new(clock: &'a dyn Clock, ttl_ns: u64) -> Self {
+        let started_ns = clock.now_ns();
+        Ttl { clock, started_ns, expires_ns: started_ns.saturating_add(ttl_ns) }
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        self.clock.now_ns() >= self.expires_ns
+    }
+
+    pub(crate) fn elapsed_ns(&self) -> u64 {
+        self.clock.now_ns().saturating_sub(self.started_ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttl_expires_exactly_at_its_tick() {
+        let clock = MockClock::new(1_000);
+        let ttl = Ttl::new(&clock, 250);
+        clock.advance(249);
+        assert!(!ttl.is_expired());
+        clock.advance(1);
+        assert!(ttl.is_expired());
+    }
+
+    #[test]
+    fn unbounded_ttl_never_expires() {
+        let clock = MockClock::new(u64::MAX - 1);
+        let ttl = Ttl::new(&clock, u64::MAX);
+        assert!(!ttl.is_expired());
+    }
+}
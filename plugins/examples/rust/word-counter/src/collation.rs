@@ -0,0 +1,76 @@
+// Ordering for human-facing word lists. The default is byte order, which is
+// deterministic everywhere; a configured BCP-47 locale switches to Unicode
+// collation so accented and non-Latin words sort the way readers expect.
+
+use std::cmp::Ordering;
+
+pub(crate) struct WordOrder {
+    #[cfg(feature = "collation")]
+    collator: Option<icu_collator::Collator>,
+}
+
+impl WordOrder {
+    // Byte order for `None`, otherwise the collation for `locale`
+    #[cfg(feature = "collation")]
+    pub(crate) fn new(locale: Option<&str>) -> Result<Self, String> {
+        let collator = match locale {
+            None => None,
+            Some(tag) => {
+                let locale: icu_locid::Locale = tag
+                    .parse()
+                    .map_err(|e| format!("Invalid collation locale {:?}: {}", tag, e))?;
+                let collator = icu_collator::Collator::try_new(&(&locale).into(), Default::default())
+                    .map_err(|e| format!("No collation available for {:?}: {}", tag, e))?;
+                Some(collator)
+            }
+        };
+        Ok(WordOrder { collator })
+    }
+
+    #[cfg(not(feature = "collation"))]
+    pub(crate) fn new(locale: Option<&str>) -> Result<Self, String> {
+        match locale {
+            None => Ok(WordOrder {}),
+            Some(_) => Err("Locale-aware collation is not supported by this build".to_string()),
+        }
+    }
+
+    // Falls back to byte order between words the collation considers equal
+    // so results stay deterministic
+    pub(crate) fn compare(&self, a: &str, b: &str) -> Ordering {
+        #[cfg(feature = "collation")]
+        if let Some(collator) = &self.collator {
+            return collator.compare(a, b).then_with(|| a.cmp(b));
+        }
+        a.cmp(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(locale: Option<&str>, words: &[&str]) -> Vec<String> {
+        let order = WordOrder::new(locale).unwrap();
+        let mut words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        words.sort_by(|a, b| order.compare(a, b));
+        words
+    }
+
+    #[test]
+    fn default_is_byte_order() {
+        assert_eq!(sorted(None, &["été", "zèbre", "etre"]), ["etre", "zèbre", "été"]);
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn french_collation_orders_accented_words() {
+        assert_eq!(sorted(Some("fr"), &["été", "zèbre", "etre"]), ["été", "etre", "zèbre"]);
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn malformed_locale_is_rejected() {
+        assert!(WordOrder::new(Some("not a locale!")).is_err());
+    }
+}
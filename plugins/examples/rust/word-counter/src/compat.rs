@@ -0,0 +1,198 @@
+// Schema evolution check for hosts upgrading a plugin: is a node's new
+// schema a safe replacement for the old one, for consumers built against
+// the old? A change is breaking when the old schema promised something the
+// new one doesn't:
+//
+// - a property disappears, or stops being required
+// - a property becomes required, so values the old schema allowed fail
+// - a type or enum is narrowed (an `integer` is still a `number`)
+//
+// Added optional properties and widened types or enums are compatible.
+// Only `type`, `enum`, `properties`, `required` and `items` are compared;
+// bounds, patterns and combinators (`oneOf`, ...) are not.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "status", content = "breaking_changes", rename_all = "snake_case")]
+pub enum Compatibility {
+    Compatible,
+    // What broke, each prefixed with a JSON pointer into the old schema
+    Breaking(Vec<String>),
+}
+
+impl Compatibility {
+    pub fn is_compatible(&self) -> bool {
+        *self == Compatibility::Compatible
+    }
+}
+
+pub fn verify_schema_compat(old: &Value, new: &Value) -> Compatibility {
+    let mut breaking = Vec::new();
+    compare(old, new, "", &mut breaking);
+    if breaking.is_empty() {
+        Compatibility::Compatible
+    } else {
+        Compatibility::Breaking(breaking)
+    }
+}
+
+fn compare(old: &Value, new: &Value, path: &str, breaking: &mut Vec<String>) {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        // `true`/`false` schemas and anything malformed: only identity is safe
+        if old != new {
+            breaking.push(format!("{}: schema changed from {} to {}", pointer(path), old, new));
+        }
+        return;
+    };
+
+    match (types(old), types(new)) {
+        (Some(old_types), Some(new_types)) => {
+            let lost: Vec<&str> = old_types.iter()
+                .copied()
+                .filter(|t| !allows(&new_types, t))
+                .collect();
+            if !lost.is_empty() {
+                breaking.push(format!("{}/type: no longer allows {}", path, lost.join(", ")));
+            }
+        }
+        (None, Some(new_types)) => {
+            let allowed: Vec<&str> = new_types.into_iter().collect();
+            breaking.push(format!("{}/type: now only allows {}", path, allowed.join(", ")));
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(old_values)) = old.get("enum") {
+        let new_values = new.get("enum").and_then(Value::as_array);
+        for value in old_values {
+            if new_values.is_some_and(|values| !values.contains(value)) {
+                breaking.push(format!("{}/enum: no longer allows {}", path, value));
+            }
+        }
+    }
+
+    let old_required = required(old);
+    let new_required = required(new);
+    for name in &old_required {
+        if !new_required.contains(name) {
+            breaking.push(format!("{}/required: {:?} is no longer required", path, name));
+        }
+    }
+    for name in new_required.difference(&old_required) {
+        breaking.push(format!("{}/required: {:?} is newly required", path, name));
+    }
+
+    let empty = Map::new();
+    let new_properties = properties(new).unwrap_or(&empty);
+    for (name, old_property) in properties(old).unwrap_or(&empty) {
+        let property_path = format!("{}/properties/{}", path, name);
+        match new_properties.get(name) {
+            Some(new_property) => compare(old_property, new_property, &property_path, breaking),
+            None => breaking.push(format!("{}: removed", property_path)),
+        }
+    }
+
+    if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+        compare(old_items, new_items, &format!("{}/items", path), breaking);
+    }
+}
+
+// The root's pointer is empty, which reads badly on its own
+fn pointer(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+// `None` when the schema allows any type
+fn types(schema: &Map<String, Value>) -> Option<BTreeSet<&str>> {
+    match schema.get("type")? {
+        Value::String(t) => Some(BTreeSet::from([t.as_str()])),
+        Value::Array(types) => Some(types.iter().filter_map(Value::as_str).collect()),
+        _ => None,
+    }
+}
+
+fn allows(types: &BTreeSet<&str>, t: &str) -> bool {
+    types.contains(t) || (t == "integer" && types.contains("number"))
+}
+
+fn required(schema: &Map<String, Value>) -> BTreeSet<&str> {
+    schema.get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn properties(schema: &Map<String, Value>) -> Option<&Map<String, Value>> {
+    schema.get("properties")?.as_object()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn old_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "total_words": {"type": "integer"},
+                "format": {"type": "string", "enum": ["map", "columns"]},
+                "words": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["total_words"]
+        })
+    }
+
+    #[test]
+    fn added_optional_field_is_compatible() {
+        let mut new = old_schema();
+        new["properties"]["unique_words"] = json!({"type": "integer"});
+        assert_eq!(verify_schema_compat(&old_schema(), &new), Compatibility::Compatible);
+        assert!(verify_schema_compat(&old_schema(), &old_schema()).is_compatible());
+    }
+
+    #[test]
+    fn removed_required_field_is_breaking() {
+        let mut new = old_schema();
+        new["properties"].as_object_mut().unwrap().remove("total_words");
+        new["required"] = json!([]);
+        assert_eq!(
+            verify_schema_compat(&old_schema(), &new),
+            Compatibility::Breaking(vec![
+                "/required: \"total_words\" is no longer required".to_string(),
+                "/properties/total_words: removed".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn narrowed_types_and_enums_are_breaking_and_widened_ones_are_not() {
+        let mut widened = old_schema();
+        widened["properties"]["total_words"]["type"] = json!("number");
+        widened["properties"]["format"]["enum"] = json!(["map", "columns", "pairs"]);
+        assert!(verify_schema_compat(&old_schema(), &widened).is_compatible());
+
+        let mut narrowed = old_schema();
+        narrowed["properties"]["format"]["enum"] = json!(["map"]);
+        narrowed["properties"]["words"]["items"]["type"] = json!("integer");
+        let Compatibility::Breaking(changes) = verify_schema_compat(&old_schema(), &narrowed) else {
+            panic!("narrowing should break");
+        };
+        assert_eq!(changes, [
+            "/properties/format/enum: no longer allows \"columns\"",
+            "/properties/words/items/type: no longer allows string",
+        ]);
+        assert!(!verify_schema_compat(&widened, &old_schema()).is_compatible());
+    }
+
+    #[test]
+    fn newly_required_field_is_breaking() {
+        let mut new = old_schema();
+        new["required"] = json!(["total_words", "format"]);
+        assert!(!verify_schema_compat(&old_schema(), &new).is_compatible());
+    }
+}
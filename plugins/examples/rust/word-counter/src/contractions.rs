@@ -0,0 +1,70 @@
+// English contraction expansion (`expand_contractions`), so "don't" counts
+// as "do" and "not" instead of being cleaned into "don" and "t". Only the
+// contractions in `CONTRACTIONS` expand; a possessive like "John's" is left
+// alone.
+//
+// Some contractions are ambiguous and always take the same reading:
+// - 's is "is" ("it's" -> "it is", never "it has")
+// - 'd is "would" ("I'd" -> "I would", never "I had")
+// - "can't" is "can not", so it counts a "not" like the other n't forms
+
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+
+use crate::singleton::Singleton;
+
+// Lowercase contraction -> expansion
+const CONTRACTIONS: &[(&str, &str)] = &[
+    ("aren't", "are not"), ("can't", "can not"), ("couldn't", "could not"),
+    ("didn't", "did not"), ("doesn't", "does not"), ("don't", "do not"),
+    ("hadn't", "had not"), ("hasn't", "has not"), ("haven't", "have not"),
+    ("isn't", "is not"), ("mustn't", "must not"), ("needn't", "need not"),
+    ("shouldn't", "should not"), ("wasn't", "was not"), ("weren't", "were not"),
+    ("won't", "will not"), ("wouldn't", "would not"),
+    ("i'm", "i am"), ("you're", "you are"), ("we're", "we are"), ("they're", "they are"),
+    ("it's", "it is"), ("he's", "he is"), ("she's", "she is"), ("that's", "that is"),
+    ("there's", "there is"), ("what's", "what is"), ("where's", "where is"), ("who's", "who is"),
+    ("i've", "i have"), ("you've", "you have"), ("we've", "we have"), ("they've", "they have"),
+    ("i'll", "i will"), ("you'll", "you will"), ("he'll", "he will"), ("she'll", "she will"),
+    ("it'll", "it will"), ("we'll", "we will"), ("they'll", "they will"),
+    ("i'd", "i would"), ("you'd", "you would"), ("he'd", "he would"), ("she'd", "she would"),
+    ("we'd", "we would"), ("they'd", "they would"),
+    ("let's", "let us"),
+];
+
+// Typographic apostrophes too, as word processors write them
+static PATTERN: Singleton<Regex> = Singleton::new(|| Regex::new(r"\b[[:alpha:]]+['’][[:alpha:]]+\b").unwrap());
+
+// Expands every known contraction in `text`. A capitalized contraction
+// keeps its capital ("Don't" -> "Do not") for case-sensitive counting.
+pub(crate) fn expand(text: &str) -> Cow<'_, str> {
+    PATTERN.get().replace_all(text, |captures: &Captures| {
+        let word = &captures[0];
+        let key = word.to_lowercase().replace('’', "'");
+        match CONTRACTIONS.iter().find(|(contraction, _)| *contraction == key) {
+            Some((_, expansion)) if word.starts_with(char::is_uppercase) => {
+                let mut chars = expansion.chars();
+                chars.next().map(char::to_uppercase).into_iter().flatten().chain(chars).collect()
+            }
+            Some((_, expansion)) => expansion.to_string(),
+            None => word.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_contractions_expand() {
+        assert_eq!(expand("we don't know if it's done"), "we do not know if it is done");
+        assert_eq!(expand("Don’t panic, I'd say"), "Do not panic, I would say");
+    }
+
+    #[test]
+    fn other_apostrophes_are_left_alone() {
+        assert_eq!(expand("John's rock'n'roll 'quoted'"), "John's rock'n'roll 'quoted'");
+    }
+}
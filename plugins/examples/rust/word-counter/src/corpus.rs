@@ -0,0 +1,162 @@
+// Corpus statistics: word counts over an array of documents, with the
+// vocabulary limited by document frequency (how many documents a term
+// appears in). Terms in fewer than `min_doc_freq` documents are too rare to
+// compare documents by, and those in more than `max_doc_freq` too common;
+// both are dropped from every count before the totals are taken.
+//
+// A document that can't be counted (malformed, or failing prep) doesn't fail
+// the corpus: its entry in `documents` carries the error instead of counts,
+// and the totals and document frequencies are over the other documents.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub(crate) struct DocumentCounts {
+    pub(crate) total_words: usize,
+    pub(crate) unique_words: usize,
+    pub(crate) word_frequencies: HashMap<String, usize>,
+}
+
+// One document's outcome, in input order: its prep data or counts, or why
+// it has none
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct DocumentResult<T> {
+    pub(crate) ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) output: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl<T> DocumentResult<T> {
+    pub(crate) fn new(result: Result<T, String>) -> Self {
+        match result {
+            Ok(output) => DocumentResult { ok: true, output: Some(output), error: None },
+            Err(e) => DocumentResult { ok: false, output: None, error: Some(e) },
+        }
+    }
+}
+
+// The first error when every document failed; `None` if any succeeded or
+// there were none to fail
+pub(crate) fn all_failed<T>(documents: &[DocumentResult<T>]) -> Option<&str> {
+    if documents.iter().any(|document| document.ok) {
+        return None;
+    }
+    documents.first()?.error.as_deref()
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub(crate) struct CorpusOutput {
+    // Documents counted, leaving out those that failed
+    pub(crate) document_count: usize,
+    // Occurrences of vocabulary terms across the corpus
+    pub(crate) total_words: usize,
+    // Size of the vocabulary
+    pub(crate) unique_words: usize,
+    pub(crate) word_frequencies: HashMap<String, usize>,
+    pub(crate) document_frequencies: BTreeMap<String, usize>,
+    pub(crate) documents: Vec<DocumentResult<DocumentCounts>>,
+}
+
+// Builds the corpus statistics from each document's word frequencies, or
+// the error that document failed with
+pub(crate) fn summarize(
+    documents: Vec<Result<HashMap<String, usize>, String>>,
+    min_doc_freq: Option<usize>,
+    max_doc_freq: Option<usize>,
+) -> CorpusOutput {
+    let mut document_frequencies: BTreeMap<String, usize> = BTreeMap::new();
+    for frequencies in documents.iter().flatten() {
+        for word in frequencies.keys() {
+            *document_frequencies.entry(word.clone()).or_insert(0) += 1;
+        }
+    }
+    document_frequencies.retain(|_, df| {
+        min_doc_freq.is_none_or(|min| *df >= min) && max_doc_freq.is_none_or(|max| *df <= max)
+    });
+
+    let mut output = CorpusOutput::default();
+    for frequencies in documents {
+        let mut frequencies = match frequencies {
+            Ok(frequencies) => frequencies,
+            Err(e) => {
+                output.documents.push(DocumentResult::new(Err(e)));
+                continue;
+            }
+        };
+        frequencies.retain(|word, _| document_frequencies.contains_key(word));
+        let total_words = frequencies.values().sum();
+        for (word, count) in &frequencies {
+            *output.word_frequencies.entry(word.clone()).or_insert(0) += count;
+        }
+        output.total_words += total_words;
+        output.document_count += 1;
+        output.documents.push(DocumentResult::new(Ok(DocumentCounts {
+            total_words,
+            unique_words: frequencies.len(),
+            word_frequencies: frequencies,
+        })));
+    }
+    output.unique_words = document_frequencies.len();
+    output.document_frequencies = document_frequencies;
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frequencies(words: &[(&str, usize)]) -> HashMap<String, usize> {
+        words.iter().map(|(word, count)| (word.to_string(), *count)).collect()
+    }
+
+    fn corpus() -> Vec<Result<HashMap<String, usize>, String>> {
+        vec![
+            Ok(frequencies(&[("rust", 2), ("wasm", 1)])),
+            Ok(frequencies(&[("rust", 1), ("plugin", 1)])),
+            Ok(frequencies(&[("rust", 1), ("wasm", 2), ("host", 1)])),
+            Ok(frequencies(&[("rust", 3), ("plugin", 1)])),
+        ]
+    }
+
+    #[test]
+    fn rare_terms_are_dropped_everywhere() {
+        let output = summarize(corpus(), Some(2), None);
+        assert!(!output.document_frequencies.contains_key("host"));
+        assert_eq!(output.documents[2].output, Some(DocumentCounts {
+            total_words: 3,
+            unique_words: 2,
+            word_frequencies: frequencies(&[("rust", 1), ("wasm", 2)]),
+        }));
+        assert_eq!(output.unique_words, 3);
+        assert_eq!(output.total_words, 12);
+    }
+
+    #[test]
+    fn common_terms_are_dropped_over_max_doc_freq() {
+        let output = summarize(corpus(), None, Some(3));
+        assert_eq!(output.document_frequencies.keys().collect::<Vec<_>>(), ["host", "plugin", "wasm"]);
+        assert_eq!(output.word_frequencies, frequencies(&[("wasm", 3), ("plugin", 2), ("host", 1)]));
+        assert_eq!(output.document_count, 4);
+    }
+
+    #[test]
+    fn failed_documents_keep_their_place_but_count_for_nothing() {
+        let mut documents = corpus();
+        documents.insert(1, Err("Failed to parse input: bad".to_string()));
+        let output = summarize(documents, Some(2), None);
+        assert_eq!(output.documents[1], DocumentResult::new(Err("Failed to parse input: bad".to_string())));
+        assert!(output.documents[2].ok);
+        assert_eq!(output.document_count, 4);
+        assert_eq!(output.document_frequencies, summarize(corpus(), Some(2), None).document_frequencies);
+        assert_eq!(all_failed(&output.documents), None);
+
+        let output = summarize(vec![Err("no".to_string()), Err("nor".to_string())], None, None);
+        assert_eq!(all_failed(&output.documents), Some("no"));
+        assert_eq!(output.document_count, 0);
+        assert_eq!(all_failed(&summarize(Vec::new(), None, None).documents), None);
+    }
+}
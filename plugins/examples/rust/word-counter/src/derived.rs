@@ -0,0 +1,115 @@
+// Nodes declared with `#[derive(PocketNode)]`. The derive only names the
+// node; everything else follows from its `Node` impl: the config, input and
+// output schemas are generated by schemars from the associated types, exec
+// deserializes the config and input and runs `Node::exec`, and post routes
+// the exec result through `Node::post`. `registry::Derived::<T>::ENTRY`
+// lists such a node without any hand-written handlers, and `register` adds
+// one at run time, for a plugin built on this crate.
+//
+// A missing config is the config type's `Default`; host defaults apply as
+// for every other node (`effective_config`).
+
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{effective_config, registry, ErrorCode, Node, NodeDefinition, Request, Response};
+
+// Implemented by `#[derive(PocketNode)]`
+pub trait PocketNode: Node + Default {
+    const NODE_TYPE: &'static str;
+    const CATEGORY: &'static str;
+    const DESCRIPTION: &'static str;
+    // What the schemas describe: the `Node` types unless `#[pocket(config,
+    // input, output)]` names others
+    type ConfigSchema: JsonSchema;
+    type InputSchema: JsonSchema;
+    type OutputSchema: JsonSchema;
+}
+
+// Adds `T` to the registry for every later call, `metadata` included. An
+// error if the node type is taken.
+pub fn register<T>() -> Result<(), String>
+where
+    T: PocketNode + 'static,
+    T::Config: DeserializeOwned + Default,
+    T::Input: DeserializeOwned,
+    T::Output: Serialize + DeserializeOwned,
+{
+    registry::register(registry::Derived::<T>::ENTRY)
+}
+
+// `T`'s JSON schema, with nested types inlined rather than referenced so it
+// reads like the hand-written ones. `None` for `()`, a node without config.
+pub fn schema<T: JsonSchema>() -> Option<serde_json::Value> {
+    let settings = SchemaSettings::draft07().with(|settings| {
+        settings.inline_subschemas = true;
+        settings.meta_schema = None;
+    });
+    let mut schema = serde_json::to_value(settings.into_generator().into_root_schema_for::<T>()).unwrap();
+    let schema_object = schema.as_object_mut()?;
+    schema_object.remove("title");
+    (schema_object.get("type").and_then(serde_json::Value::as_str) != Some("null")).then_some(schema)
+}
+
+pub(crate) fn definition<T: PocketNode>() -> NodeDefinition {
+    NodeDefinition {
+        node_type: T::NODE_TYPE.to_string(),
+        category: T::CATEGORY.to_string(),
+        description: T::DESCRIPTION.to_string(),
+        config_schema: schema::<T::ConfigSchema>(),
+        input_schema: schema::<T::InputSchema>(),
+        output_schema: schema::<T::OutputSchema>(),
+        examples: Vec::new(),
+        binary_input: false,
+    }
+}
+
+pub(crate) fn handle_exec<T>(request: &Request) -> Response
+where
+    T: PocketNode,
+    T::Config: DeserializeOwned + Default,
+    T::Input: DeserializeOwned,
+    T::Output: Serialize,
+{
+    let config = match parse_config::<T::Config>(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    let input: T::Input = match request.input.as_ref() {
+        Some(input) => match T::Input::deserialize(input) {
+            Ok(input) => input,
+            Err(e) => return Response::error(format!("Failed to parse input: {}", e)),
+        },
+        None => return Response::error("No input provided"),
+    };
+    match T::default().exec(&config, &input) {
+        Ok(output) => Response::ok(serde_json::to_value(output).unwrap()),
+        Err(e) => Response::error(e),
+    }
+}
+
+pub(crate) fn handle_post<T>(request: &Request) -> Response
+where
+    T: PocketNode,
+    T::Output: DeserializeOwned,
+{
+    let Some(exec_result) = request.input.as_ref() else {
+        return Response::error("No exec result provided");
+    };
+    match T::Output::deserialize(exec_result) {
+        Ok(output) => Response {
+            next: Some(T::default().post(&output).to_string()),
+            ..Response::ok(exec_result.clone())
+        },
+        Err(e) => Response::error(format!("Failed to parse exec result: {}", e)),
+    }
+}
+
+fn parse_config<C: DeserializeOwned + Default>(request: &Request) -> Result<C, String> {
+    match effective_config(request)? {
+        Some(config) => C::deserialize(config.as_ref()).map_err(|e| format!("Invalid config: {}", e)),
+        None => Ok(C::default()),
+    }
+}
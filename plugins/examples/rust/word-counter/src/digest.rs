@@ -0,0 +1,60 @@
+// The byte-digest node: an example of a binary node. Its input is raw bytes
+// passed through `call_bytes`, never decoded as text, so it works on any
+// payload (images, archives, text in any encoding).
+
+use serde::Serialize;
+
+use crate::bloom::fnv1a;
+use crate::{NodeDefinition, Request, Response};
+
+#[derive(Serialize)]
+struct DigestOutput {
+    length: usize,
+    // 64-bit FNV-1a, as 16 lowercase hex digits
+    fnv1a64: String,
+    // Whether the bytes would also have been accepted as text
+    utf8: bool,
+}
+
+pub(crate) fn definition() -> NodeDefinition {
+    NodeDefinition {
+        node_type: "byte-digest".to_string(),
+        category: "binary".to_string(),
+        description: "Length and FNV-1a hash of raw input bytes".to_string(),
+        config_schema: None,
+        input_schema: None,
+        output_schema: Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "length": {"type": "integer"},
+                "fnv1a64": {"type": "string"},
+                "utf8": {"type": "boolean"}
+            },
+            "required": ["length", "fnv1a64", "utf8"]
+        })),
+        examples: Vec::new(),
+        binary_input: true,
+    }
+}
+
+pub(crate) fn handle_exec(_request: &Request, data: &[u8]) -> Response {
+    let output = DigestOutput {
+        length: data.len(),
+        // Unseeded, the standard FNV-1a
+        fnv1a64: format!("{:016x}", fnv1a(data, 0)),
+        utf8: std::str::from_utf8(data).is_ok(),
+    };
+    Response::ok(serde_json::to_value(output).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a64_matches_reference_vectors() {
+        assert_eq!(fnv1a(b"", 0), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a", 0), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar", 0), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,90 @@
+// Lexical diversity of a token stream. The plain type-token ratio falls as
+// texts get longer, so the length-corrected root TTR and MTLD (McCarthy &
+// Jarvis, 2010) are reported alongside it for comparing texts of different
+// sizes.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+// MTLD closes a "factor" each time the running TTR falls to this value
+const MTLD_THRESHOLD: f64 = 0.72;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct LexicalDiversity {
+    // Unique over total tokens; 0 for no tokens
+    pub ttr: f64,
+    // Unique over the square root of total tokens (Guiraud's index)
+    pub root_ttr: f64,
+    // Mean tokens per factor, averaged over forward and backward passes.
+    // `None` when the text never completes any part of a factor (too few
+    // tokens, or none repeated), where MTLD is undefined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtld: Option<f64>,
+}
+
+pub(crate) fn measure(tokens: &[&str]) -> LexicalDiversity {
+    if tokens.is_empty() {
+        return LexicalDiversity::default();
+    }
+    let unique = tokens.iter().collect::<HashSet<_>>().len() as f64;
+    let total = tokens.len() as f64;
+    let forward = mtld_factors(tokens.iter().copied());
+    let backward = mtld_factors(tokens.iter().rev().copied());
+    LexicalDiversity {
+        ttr: unique / total,
+        root_ttr: unique / total.sqrt(),
+        mtld: (forward > 0.0 && backward > 0.0).then(|| (total / forward + total / backward) / 2.0),
+    }
+}
+
+// Complete factors in one pass, plus the unfinished remainder as a fraction
+fn mtld_factors<'a>(tokens: impl Iterator<Item = &'a str>) -> f64 {
+    let mut factors = 0.0;
+    let mut types = HashSet::new();
+    let mut count = 0;
+    for token in tokens {
+        types.insert(token);
+        count += 1;
+        if types.len() as f64 / count as f64 <= MTLD_THRESHOLD {
+            factors += 1.0;
+            types.clear();
+            count = 0;
+        }
+    }
+    if count > 0 {
+        let ttr = types.len() as f64 / count as f64;
+        factors += (1.0 - ttr) / (1.0 - MTLD_THRESHOLD);
+    }
+    factors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn empty_and_single_token_texts_are_defined() {
+        assert_eq!(measure(&[]), LexicalDiversity { ttr: 0.0, root_ttr: 0.0, mtld: None });
+        assert_eq!(measure(&tokens("hello")), LexicalDiversity { ttr: 1.0, root_ttr: 1.0, mtld: None });
+    }
+
+    #[test]
+    fn varied_text_scores_higher_than_repetitive_text() {
+        let varied = measure(&tokens(
+            "a quick brown fox jumps over the lazy dog while a clever crow watches from an old oak \
+             tree and the sleepy dog barely notices the fox racing past toward distant green hills",
+        ));
+        let repetitive = measure(&tokens(
+            "the dog saw the dog and the dog saw the cat and the cat saw the dog and the dog ran \
+             and the cat ran and the dog saw the cat and the cat saw the dog and the dog sat",
+        ));
+        assert!(varied.ttr > repetitive.ttr);
+        assert!(varied.root_ttr > repetitive.root_ttr);
+        assert!(varied.mtld.unwrap() > repetitive.mtld.unwrap(), "{:?} vs {:?}", varied, repetitive);
+    }
+}
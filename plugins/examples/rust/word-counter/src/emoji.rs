@@ -0,0 +1,101 @@
+// Emoji stripping (`strip_emoji`). Most emoji are already dropped as
+// punctuation by cleaning, but not all: some are letters to Unicode
+// ("🅰", "Ⓜ", which NFKC even folds to "M"), keycaps keep their digit
+// ("1️⃣" cleans to "1"), and preserved entities keep whatever they hold.
+// Stripping runs on the raw text so all of those are gone before counting.
+//
+// Each emoji is replaced by a space, so "hi😀there" is still two words. A
+// sequence shown as one emoji counts once: a base with its modifiers and
+// variation selector, ZWJ-joined emoji ("👩‍💻"), flag pairs, tag flags
+// and keycaps. ASCII digits, `#` and `*` only count as part of a keycap.
+
+use std::borrow::Cow;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use unicode_properties::emoji::{self, UnicodeEmoji};
+
+const KEYCAP: char = '\u{20E3}';
+
+fn is_modifier(c: char) -> bool {
+    matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+// Consumes what follows an emoji's base and belongs to the same emoji
+fn skip_sequence(chars: &mut Peekable<Chars>, base: char) {
+    if emoji::is_regional_indicator(base) {
+        chars.next_if(|c| emoji::is_regional_indicator(*c));
+        return;
+    }
+    loop {
+        if chars.next_if(|c| {
+            emoji::is_emoji_presentation_selector(*c)
+                || emoji::is_text_presentation_selector(*c)
+                || is_modifier(*c)
+                || emoji::is_tag_character(*c)
+                || *c == KEYCAP
+        }).is_some() {
+            continue;
+        }
+        // A ZWJ only continues the sequence if an emoji follows it
+        let mut lookahead = chars.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some(zwj), Some(next)) if emoji::is_zwj(zwj) && next.is_emoji_char() => {
+                chars.next();
+                chars.next();
+            }
+            _ => return,
+        }
+    }
+}
+
+// Whether `c` starts a keycap: `1️⃣` is the digit, a selector, the keycap mark
+fn is_keycap(c: char, chars: &Peekable<Chars>) -> bool {
+    if !matches!(c, '0'..='9' | '#' | '*') {
+        return false;
+    }
+    let mut lookahead = chars.clone();
+    lookahead.next_if(|c| emoji::is_emoji_presentation_selector(*c));
+    lookahead.next() == Some(KEYCAP)
+}
+
+// `text` without emoji and pictographic symbols, and how many it held
+pub(crate) fn strip(text: &str) -> (Cow<'_, str>, usize) {
+    if text.is_ascii() {
+        return (Cow::Borrowed(text), 0);
+    }
+    let mut stripped = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if (!c.is_ascii() && c.is_emoji_char()) || is_keycap(c, &chars) {
+            skip_sequence(&mut chars, c);
+            stripped.push(' ');
+            count += 1;
+        } else {
+            stripped.push(c);
+        }
+    }
+    (Cow::Owned(stripped), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_count_as_one_emoji() {
+        // Skin tone, ZWJ sequence, flag, keycap, text-style heart with VS16
+        let (stripped, count) = strip("👍🏽 👩‍💻 🇳🇿 1️⃣ ❤️");
+        assert_eq!(stripped, "         ");
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn text_around_emoji_is_kept() {
+        assert_eq!(strip("hi😀there"), (Cow::Owned("hi there".to_string()), 1));
+        // Plain digits and an Indic ZWJ are text, not emoji
+        assert_eq!(strip("route 66 # क्‍ष"), (Cow::Owned("route 66 # क्‍ष".to_string()), 0));
+        assert_eq!(strip("plain"), (Cow::Borrowed("plain"), 0));
+    }
+}
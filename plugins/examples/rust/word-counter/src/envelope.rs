@@ -0,0 +1,77 @@
+// Response envelope versions. The envelope (`Response`'s own fields, not
+// the node output inside it) has grown over time, and a host built against
+// an older one may reject fields it doesn't know. A host calls `init` with
+// the newest version it understands; responses are then serialized with
+// only that version's fields. Hosts that never call `init` get the current
+// envelope.
+//
+// 1. `success`, `output`, `error`, `next`
+// 2. adds `error_code`, `meta` and `_profile`
+// 3. adds `skip_to`
+//
+// Every added field is optional, so dropping one leaves a valid older
+// envelope; what the host loses is only the information in it.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub(crate) const CURRENT: u32 = 3;
+
+// One setting per instance, as the host negotiates it once for all calls
+#[cfg(not(test))]
+static VERSION: AtomicU32 = AtomicU32::new(CURRENT);
+
+// Per test thread, as in `state`
+#[cfg(test)]
+thread_local! {
+    static VERSION: AtomicU32 = const { AtomicU32::new(CURRENT) };
+}
+
+fn with_version<R>(f: impl FnOnce(&AtomicU32) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        f(&VERSION)
+    }
+    #[cfg(test)]
+    {
+        VERSION.with(f)
+    }
+}
+
+pub(crate) fn version() -> u32 {
+    with_version(|version| version.load(Ordering::Relaxed))
+}
+
+// Settles on the newest version both sides know and returns it, or 0
+// (changing nothing) for a host claiming version 0
+pub(crate) fn negotiate(host_version: u32) -> u32 {
+    if host_version == 0 {
+        return 0;
+    }
+    let version = host_version.min(CURRENT);
+    with_version(|current| current.store(version, Ordering::Relaxed));
+    version
+}
+
+// `skip_serializing_if` for optional fields added in version 2 and 3
+pub(crate) fn skip_before_v2<T>(field: &Option<T>) -> bool {
+    field.is_none() || version() < 2
+}
+
+pub(crate) fn skip_before_v3<T>(field: &Option<T>) -> bool {
+    field.is_none() || version() < 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_settles_on_the_older_version() {
+        assert_eq!(version(), CURRENT);
+        assert_eq!(negotiate(CURRENT + 5), CURRENT);
+        assert_eq!(negotiate(1), 1);
+        assert_eq!(negotiate(0), 0);
+        assert_eq!(version(), 1);
+        negotiate(CURRENT);
+    }
+}
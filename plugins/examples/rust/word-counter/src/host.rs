@@ -0,0 +1,390 @@
+// Host imports. On wasm32 these are provided by the Pocket host under the
+// `pocket` import module; native builds (tests, embedding) get stand-ins
+// with the same signatures so callers never need to cfg-gate.
+
+// Declares the `pocket` imports and the `IMPORTS` table from one list, so
+// the two can't drift apart. An import is `required` if the plugin can't
+// work without it, or `optional` if only features with a fallback use it.
+macro_rules! host_imports {
+    ($($necessity:ident fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+        #[cfg(target_arch = "wasm32")]
+        mod imports {
+            #[link(wasm_import_module = "pocket")]
+            extern "C" {
+                $(pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+            }
+        }
+
+        pub(crate) const IMPORTS: &[Import] = &[
+            $(Import { name: stringify!($name), required: host_imports!(@required $necessity) },)*
+        ];
+    };
+    (@required required) => { true };
+    (@required optional) => { false };
+}
+
+// Wasm has no optional imports: a host must link every one of these or
+// instantiation fails. A host without the capability behind an optional
+// one links a stub answering "none" instead (`MISSING`, a negative
+// length, or doing nothing for `host_metric`), and the plugin falls back.
+host_imports! {
+    required fn now_ns() -> u64;
+    required fn random_seed() -> u64;
+    required fn kv_get(key_ptr: *const u8, key_len: usize, out_ptr: *mut u8, out_len: usize) -> u64;
+    required fn kv_set(key_ptr: *const u8, key_len: usize, value_ptr: *const u8, value_len: usize);
+    required fn host_log(level: u32, msg_ptr: *const u8, msg_len: usize);
+    required fn emit(ptr: *const u8, len: usize);
+    optional fn host_default_config(node_ptr: *const u8, node_len: usize, out_ptr: *mut u8, out_len: usize) -> u64;
+    optional fn resource_read(name_ptr: *const u8, name_len: usize, out_ptr: *mut u8, out_len: usize) -> isize;
+    optional fn host_tokenize(text_ptr: *const u8, text_len: usize, out_ptr: *mut u8, out_len: usize) -> u64;
+    optional fn host_metric(name_ptr: *const u8, name_len: usize, value: u64, trace_ptr: *const u8, trace_len: usize);
+}
+
+// One `pocket` import, as declared in metadata
+pub(crate) struct Import {
+    pub(crate) name: &'static str,
+    pub(crate) required: bool,
+}
+
+// The `required` (or optional) import names, in declaration order
+pub(crate) fn imports(required: bool) -> impl Iterator<Item = &'static str> {
+    IMPORTS.iter().filter(move |import| import.required == required).map(|import| import.name)
+}
+
+// The `required` (or optional) imports a host providing `provided` lacks,
+// in declaration order
+pub(crate) fn missing_imports(provided: &[String], required: bool) -> Vec<&'static str> {
+    imports(required).filter(|name| !provided.iter().any(|p| p == name)).collect()
+}
+
+// `kv_get` result for a missing key, `host_default_config`'s when the host
+// has no defaults, and `host_tokenize`'s when it has no tokenizer
+#[cfg(target_arch = "wasm32")]
+const MISSING: u64 = u64::MAX;
+
+// Reads a host value of unknown size through `read(out_ptr, out_len)`,
+// which returns its full length (or `MISSING`) and only writes it if it
+// fits, growing the buffer until it does
+#[cfg(target_arch = "wasm32")]
+fn read_host_value(read: impl Fn(*mut u8, usize) -> u64) -> Option<Vec<u8>> {
+    let mut value = Vec::new();
+    loop {
+        let len = read(value.as_mut_ptr(), value.capacity());
+        if len == MISSING {
+            return None;
+        }
+        let len = len as usize;
+        if len <= value.capacity() {
+            unsafe { value.set_len(len) };
+            return Some(value);
+        }
+        value.reserve_exact(len);
+    }
+}
+
+// Monotonic clock in nanoseconds. Only differences are meaningful.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ns() -> u64 {
+    unsafe { imports::now_ns() }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ns() -> u64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+// Seed for `rng::SeededRng`, chosen by the host (e.g. derived from the
+// workflow run id) so randomized output is reproducible.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn random_seed() -> u64 {
+    unsafe { imports::random_seed() }
+}
+
+// There is no host to pick a seed natively, so use a fixed one
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn random_seed() -> u64 {
+    0
+}
+
+// Host key-value store, shared by every instance of the plugin. `kv_get`
+// follows the buffer protocol of our own exports: it returns the value's
+// full length and only writes it if it fits.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn kv_get(key: &str) -> Option<Vec<u8>> {
+    read_host_value(|out_ptr, out_len| unsafe { imports::kv_get(key.as_ptr(), key.len(), out_ptr, out_len) })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn kv_set(key: &str, value: &[u8]) {
+    unsafe { imports::kv_set(key.as_ptr(), key.len(), value.as_ptr(), value.len()) }
+}
+
+// Natively the store is process-wide, like the host's, and guarded for
+// callers on several threads. Tests get one store per thread so they
+// stay isolated.
+#[cfg(not(target_arch = "wasm32"))]
+fn with_kv<R>(f: impl FnOnce(&mut std::collections::HashMap<String, Vec<u8>>) -> R) -> R {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, PoisonError};
+
+    #[cfg(not(test))]
+    {
+        static KV: std::sync::OnceLock<Mutex<HashMap<String, Vec<u8>>>> = std::sync::OnceLock::new();
+        f(&mut KV.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    #[cfg(test)]
+    {
+        thread_local! {
+            static KV: Mutex<HashMap<String, Vec<u8>>> = Default::default();
+        }
+        KV.with(|kv| f(&mut kv.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn kv_get(key: &str) -> Option<Vec<u8>> {
+    with_kv(|kv| kv.get(key).cloned())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn kv_set(key: &str, value: &[u8]) {
+    with_kv(|kv| kv.insert(key.to_string(), value.to_vec()));
+}
+
+// The operator's deployment-wide config defaults for a node type, as a
+// JSON object, if the host has any. Same buffer protocol as `kv_get`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn default_config(node: &str) -> Option<Vec<u8>> {
+    read_host_value(|out_ptr, out_len| unsafe {
+        imports::host_default_config(node.as_ptr(), node.len(), out_ptr, out_len)
+    })
+}
+
+// Natively there is no operator config
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn default_config(_node: &str) -> Option<Vec<u8>> {
+    None
+}
+
+// Tests install defaults per thread
+#[cfg(test)]
+thread_local! {
+    static DEFAULT_CONFIGS: std::cell::RefCell<std::collections::HashMap<String, Vec<u8>>> =
+        std::cell::RefCell::new(std::collections::HashMap::new());
+    static DEFAULT_CONFIG_READS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+pub(crate) fn default_config(node: &str) -> Option<Vec<u8>> {
+    DEFAULT_CONFIG_READS.with(|reads| reads.set(reads.get() + 1));
+    DEFAULT_CONFIGS.with(|configs| configs.borrow().get(node).cloned())
+}
+
+// How many times `default_config` was asked since the last take
+#[cfg(test)]
+pub(crate) fn take_default_config_reads() -> usize {
+    DEFAULT_CONFIG_READS.with(|reads| reads.take())
+}
+
+#[cfg(test)]
+pub(crate) fn set_default_config(node: &str, config: Option<&[u8]>) {
+    DEFAULT_CONFIGS.with(|configs| match config {
+        Some(config) => configs.borrow_mut().insert(node.to_string(), config.to_vec()),
+        None => configs.borrow_mut().remove(node),
+    });
+}
+
+// The contents of a named resource the host mediates access to (a file, a
+// model), or `None` if the host refused or has no such resource (a
+// negative result). Callers go through `read_resource`, which only asks
+// for declared resources.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn resource_read(name: &str) -> Option<Vec<u8>> {
+    read_host_value(|out_ptr, out_len| {
+        let len = unsafe { imports::resource_read(name.as_ptr(), name.len(), out_ptr, out_len) };
+        u64::try_from(len).unwrap_or(MISSING)
+    })
+}
+
+// Natively there are no host resources
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn resource_read(_name: &str) -> Option<Vec<u8>> {
+    None
+}
+
+// Tests provide resources per thread
+#[cfg(test)]
+thread_local! {
+    static RESOURCES: std::cell::RefCell<std::collections::HashMap<String, Vec<u8>>> =
+        std::cell::RefCell::new(std::collections::HashMap::new());
+}
+
+#[cfg(test)]
+pub(crate) fn resource_read(name: &str) -> Option<Vec<u8>> {
+    RESOURCES.with(|resources| resources.borrow().get(name).cloned())
+}
+
+#[cfg(test)]
+pub(crate) fn set_resource(name: &str, contents: &[u8]) {
+    RESOURCES.with(|resources| resources.borrow_mut().insert(name.to_string(), contents.to_vec()));
+}
+
+// The host tokenizer's tokens for `text`, as a JSON array of strings, or
+// `None` if the host has no tokenizer. Same buffer protocol as `kv_get`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn tokenize(text: &str) -> Option<Vec<u8>> {
+    read_host_value(|out_ptr, out_len| unsafe {
+        imports::host_tokenize(text.as_ptr(), text.len(), out_ptr, out_len)
+    })
+}
+
+// Natively there is no host tokenizer
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn tokenize(_text: &str) -> Option<Vec<u8>> {
+    None
+}
+
+// Tests install a tokenizer per thread: text in, the import's JSON out
+#[cfg(test)]
+pub(crate) type MockTokenizer = fn(&str) -> Vec<u8>;
+
+#[cfg(test)]
+thread_local! {
+    static TOKENIZER: std::cell::Cell<Option<MockTokenizer>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(test)]
+pub(crate) fn tokenize(text: &str) -> Option<Vec<u8>> {
+    TOKENIZER.with(|tokenizer| tokenizer.get()).map(|tokenize| tokenize(text))
+}
+
+#[cfg(test)]
+pub(crate) fn set_tokenizer(tokenizer: Option<MockTokenizer>) {
+    TOKENIZER.with(|cell| cell.set(tokenizer));
+}
+
+// One line for the host's log, at a `log::Level`
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn host_log(level: u32, message: &str) {
+    unsafe { imports::host_log(level, message.as_ptr(), message.len()) }
+}
+
+// Natively lines go to stderr only if `POCKET_LOG` names a level (`debug`,
+// `info`, `warn` or `error`), and only at or above it, so embedders aren't
+// sent lines they never asked for
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn host_log(level: u32, message: &str) {
+    use std::sync::OnceLock;
+
+    static MIN_LEVEL: OnceLock<Option<u32>> = OnceLock::new();
+    let min_level = MIN_LEVEL.get_or_init(|| {
+        let name = std::env::var("POCKET_LOG").ok()?;
+        ["debug", "info", "warn", "error"].iter().position(|level| name.eq_ignore_ascii_case(level)).map(|p| p as u32)
+    });
+    if min_level.is_some_and(|min_level| level >= min_level) {
+        eprintln!("{}", message);
+    }
+}
+
+// Tests read back what was logged on their thread
+#[cfg(test)]
+thread_local! {
+    static LOG: std::cell::RefCell<Vec<(u32, String)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(test)]
+pub(crate) fn host_log(level: u32, message: &str) {
+    LOG.with(|log| log.borrow_mut().push((level, message.to_string())));
+}
+
+#[cfg(test)]
+pub(crate) fn take_logs() -> Vec<(u32, String)> {
+    LOG.with(|log| log.take())
+}
+
+// Adds `value` to the host's counter metric `name`, labelled with the
+// call's trace id (an empty one when it has none), as its log lines are
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn metric(name: &'static str, value: u64, trace_id: Option<&str>) {
+    let trace_id = trace_id.unwrap_or("");
+    unsafe { imports::host_metric(name.as_ptr(), name.len(), value, trace_id.as_ptr(), trace_id.len()) }
+}
+
+// Natively there is no metrics sink
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn metric(_name: &'static str, _value: u64, _trace_id: Option<&str>) {}
+
+// Tests read back the latest metric counted on their thread. Only the
+// latest, so an untraced count allocates nothing for tests that track the
+// heap.
+#[cfg(test)]
+thread_local! {
+    static METRIC: std::cell::RefCell<Option<(&'static str, u64, Option<String>)>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+pub(crate) fn metric(name: &'static str, value: u64, trace_id: Option<&str>) {
+    METRIC.with(|metric| *metric.borrow_mut() = Some((name, value, trace_id.map(str::to_string))));
+}
+
+#[cfg(test)]
+pub(crate) fn take_metric() -> Option<(&'static str, u64, Option<String>)> {
+    METRIC.with(|metric| metric.take())
+}
+
+// Hands the host one result produced outside a response (a closed window),
+// encoded like a response
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn emit(payload: &[u8]) {
+    unsafe { imports::emit(payload.as_ptr(), payload.len()) }
+}
+
+// Natively nobody is listening, so emissions are dropped
+#[cfg(all(not(target_arch = "wasm32"), not(test)))]
+pub(crate) fn emit(_payload: &[u8]) {}
+
+// Tests read back what was emitted on their thread
+#[cfg(test)]
+thread_local! {
+    static EMITTED: std::cell::RefCell<Vec<Vec<u8>>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(test)]
+pub(crate) fn emit(payload: &[u8]) {
+    EMITTED.with(|emitted| emitted.borrow_mut().push(payload.to_vec()));
+}
+
+#[cfg(test)]
+pub(crate) fn take_emitted() -> Vec<Vec<u8>> {
+    EMITTED.with(|emitted| emitted.take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_split_into_required_and_optional() {
+        assert_eq!(imports(true).collect::<Vec<_>>(), ["now_ns", "random_seed", "kv_get", "kv_set", "host_log", "emit"]);
+        assert_eq!(
+            imports(false).collect::<Vec<_>>(),
+            ["host_default_config", "resource_read", "host_tokenize", "host_metric"]
+        );
+    }
+
+    #[test]
+    fn missing_imports_keep_declaration_order() {
+        let provided: Vec<String> = IMPORTS.iter()
+            .map(|import| import.name.to_string())
+            .filter(|name| !name.starts_with("kv_") && !name.starts_with("host_"))
+            .collect();
+        assert_eq!(missing_imports(&provided, true), ["kv_get", "kv_set", "host_log"]);
+        assert_eq!(missing_imports(&provided, false), ["host_default_config", "host_tokenize", "host_metric"]);
+        let everything: Vec<String> = IMPORTS.iter().map(|import| import.name.to_string()).collect();
+        assert!(missing_imports(&everything, true).is_empty() && missing_imports(&everything, false).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+// Lightweight English inflection folding, for when full stemming (`stem`)
+// changes words too much. Both rules are purely suffix-based, so what they
+// do to a word is easy to predict:
+//
+// `strip_possessive`, on the text before cleaning (which would otherwise
+// split "dog's" into "dog" and "s"): a word followed by 's or ’s loses the
+// suffix. "dogs'" needs no rule, since cleaning drops the apostrophe. This
+// runs after `expand_contractions`, so with both on "it's" is still "it
+// is"; without it, every 's counts as a possessive.
+//
+// `normalize_plurals`, on each token's key, in the first matching rule:
+// 1. fewer than 4 characters: unchanged ("gas", "bus", "its")
+// 2. ending in -ss, -us or -is: unchanged ("class", "virus", "analysis")
+// 3. -ies, with 5 or more characters: -y ("cities" → "city")
+// 4. -sses, -shes, -ches, -xes or -zzes: drop the -es ("boxes" → "box")
+// 5. any other -s: drop it ("dogs" → "dog")
+//
+// Irregular plurals ("children", "mice") and -ves ("wolves") are left
+// alone, and words merely ending in s can lose it ("news" → "new").
+
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::singleton::Singleton;
+
+static POSSESSIVE: Singleton<Regex> = Singleton::new(|| Regex::new(r"\b(\p{Alphabetic}+)['’]s\b").unwrap());
+
+pub(crate) fn strip_possessives(text: &str) -> Cow<'_, str> {
+    POSSESSIVE.get().replace_all(text, "$1")
+}
+
+// Whether `word` ends with the ASCII `suffix`, in any case
+fn ends_with(word: &str, suffix: &str) -> bool {
+    word.len() >= suffix.len()
+        && word.is_char_boundary(word.len() - suffix.len())
+        && word[word.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+}
+
+pub(crate) fn singular(word: &str) -> Cow<'_, str> {
+    let chars = word.chars().count();
+    if chars < 4 || ["ss", "us", "is"].iter().any(|suffix| ends_with(word, suffix)) {
+        return Cow::Borrowed(word);
+    }
+    let stem = |suffix_len: usize| &word[..word.len() - suffix_len];
+    if chars >= 5 && ends_with(word, "ies") {
+        // Keep the case of what it replaces
+        let y = if word.ends_with("IES") { "Y" } else { "y" };
+        return Cow::Owned(format!("{}{}", stem(3), y));
+    }
+    if ["sses", "shes", "ches", "xes", "zzes"].iter().any(|suffix| ends_with(word, suffix)) {
+        return Cow::Borrowed(stem(2));
+    }
+    if ends_with(word, "s") {
+        return Cow::Borrowed(stem(1));
+    }
+    Cow::Borrowed(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plural_rules_apply_in_order() {
+        let cases = [
+            ("dogs", "dog"), ("Dogs", "Dog"), ("gas", "gas"), ("its", "its"), ("class", "class"),
+            ("virus", "virus"), ("analysis", "analysis"), ("cities", "city"), ("CITIES", "CITY"),
+            ("ties", "tie"), ("classes", "class"), ("wishes", "wish"), ("churches", "church"),
+            ("boxes", "box"), ("buzzes", "buzz"), ("cafés", "café"), ("dog", "dog"),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(singular(word), expected, "{}", word);
+        }
+    }
+
+    #[test]
+    fn possessive_s_is_stripped() {
+        assert_eq!(strip_possessives("the dog's bone, the dogs' bones"), "the dog bone, the dogs' bones");
+        assert_eq!(strip_possessives("James’s car, rock'n'roll"), "James car, rock'n'roll");
+        // `[[:alpha:]]` would be ASCII-only and leave these alone
+        assert_eq!(strip_possessives("Zoë's café's"), "Zoë café");
+    }
+}
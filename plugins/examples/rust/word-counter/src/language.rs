@@ -0,0 +1,132 @@
+// Language detection with whatlang's trigram models: the `detect-language`
+// node, and `auto_stop_words`, which swaps word-count's stop words for the
+// detected language's preset.
+//
+// Short or ambiguous text still gets a best guess, but with a low
+// `confidence` and `reliable: false`; `auto_stop_words` only acts on a
+// reliable detection. Text with no letters gets no `lang` at all.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{InputView, NodeDefinition, Request, Response, DEFAULT_STOP_WORDS};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Detection {
+    // ISO 639-3 code ("eng", "spa", ...)
+    pub lang: Option<String>,
+    // 0 to 1
+    pub confidence: f64,
+    pub reliable: bool,
+}
+
+pub(crate) fn detect(text: &str) -> Detection {
+    match whatlang::detect(text) {
+        Some(info) => Detection {
+            lang: Some(info.lang().code().to_string()),
+            confidence: info.confidence(),
+            reliable: info.is_reliable(),
+        },
+        None => Detection { lang: None, confidence: 0.0, reliable: false },
+    }
+}
+
+const SPANISH: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "en", "es", "la", "las", "lo", "los", "más",
+    "no", "o", "para", "pero", "por", "que", "se", "su", "sus", "un", "una", "y",
+];
+const FRENCH: &[&str] = &[
+    "au", "aux", "avec", "ce", "dans", "de", "des", "du", "elle", "en", "est", "et", "il", "la",
+    "le", "les", "mais", "ne", "ou", "par", "pas", "pour", "qui", "que", "se", "sur", "un", "une",
+];
+const GERMAN: &[&str] = &[
+    "auf", "aus", "das", "dem", "den", "der", "die", "ein", "eine", "einen", "er", "es", "für",
+    "ist", "im", "in", "mit", "nicht", "sich", "sie", "und", "von", "zu",
+];
+const PORTUGUESE: &[&str] = &[
+    "a", "ao", "com", "da", "das", "de", "do", "dos", "e", "em", "é", "na", "no", "não", "o",
+    "os", "para", "por", "que", "se", "um", "uma",
+];
+const ITALIAN: &[&str] = &[
+    "a", "che", "con", "da", "del", "della", "di", "e", "è", "il", "in", "la", "le", "non",
+    "per", "si", "su", "un", "una", "lo", "gli", "i",
+];
+
+// The stop-word preset for an ISO 639-3 code, if there is one
+pub(crate) fn stop_words(lang: &str) -> Option<&'static [&'static str]> {
+    match lang {
+        "eng" => Some(DEFAULT_STOP_WORDS),
+        "spa" => Some(SPANISH),
+        "fra" => Some(FRENCH),
+        "deu" => Some(GERMAN),
+        "por" => Some(PORTUGUESE),
+        "ita" => Some(ITALIAN),
+        _ => None,
+    }
+}
+
+pub(crate) fn schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "lang": {"type": ["string", "null"]},
+            "confidence": {"type": "number"},
+            "reliable": {"type": "boolean"}
+        },
+        "required": ["lang", "confidence", "reliable"]
+    })
+}
+
+pub(crate) fn definition() -> NodeDefinition {
+    NodeDefinition {
+        node_type: "detect-language".to_string(),
+        category: "text".to_string(),
+        description: "Detect the language of a text, with a confidence".to_string(),
+        config_schema: None,
+        input_schema: crate::word_count_definition().input_schema,
+        output_schema: Some(schema()),
+        examples: Vec::new(),
+        binary_input: false,
+    }
+}
+
+pub(crate) fn handle_exec(request: &Request) -> Response {
+    let input = match request.input.as_ref() {
+        Some(input) => match InputView::deserialize(input) {
+            Ok(input) => input,
+            Err(e) => return Response::error(format!("Failed to parse input: {}", e)),
+        },
+        None => return Response::error("No input provided"),
+    };
+    Response::ok(serde_json::to_value(detect(&input.text)).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_and_spanish_are_detected_reliably() {
+        let english = detect("It was a bright cold day in April, and the clocks were striking thirteen");
+        assert_eq!(english.lang.as_deref(), Some("eng"));
+        assert!(english.reliable);
+
+        let spanish = detect("En un lugar de la Mancha, de cuyo nombre no quiero acordarme, no ha mucho tiempo que vivía un hidalgo");
+        assert_eq!(spanish.lang.as_deref(), Some("spa"));
+        assert!(spanish.reliable && spanish.confidence > 0.5);
+    }
+
+    #[test]
+    fn short_text_is_unreliable() {
+        let short = detect("ok");
+        assert!(!short.reliable);
+        assert!(short.confidence < 0.5);
+        assert_eq!(detect("12 + 34"), Detection { lang: None, confidence: 0.0, reliable: false });
+    }
+
+    #[test]
+    fn presets_exist_for_common_languages() {
+        assert_eq!(stop_words("eng"), Some(DEFAULT_STOP_WORDS));
+        assert!(stop_words("spa").unwrap().contains(&"el"));
+        assert_eq!(stop_words("xxx"), None);
+    }
+}
@@ -1,11 +1,83 @@
+// The config schema is a single `json!` literal, deeper than the default
+// macro recursion limit allows
+#![recursion_limit = "256"]
+
+// `#[derive(PocketNode)]` expands to `::word_counter` paths, which this
+// lets the crate's own derived nodes use too
+extern crate self as word_counter;
+
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::slice;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_script::{Script, UnicodeScript};
+
+use clock::{Clock, HostClock};
+use singleton::Singleton;
+use log::{Context, Level};
+
+#[macro_use]
+mod log;
+mod abi;
+mod arena;
+mod bidi;
+mod bloom;
+mod clock;
+mod collation;
+mod compat;
+mod contractions;
+mod corpus;
+pub mod derived;
+mod digest;
+mod diversity;
+mod emoji;
+mod envelope;
+mod host;
+mod inflection;
+mod language;
+mod memo;
+mod memory;
+mod ping;
+mod precision;
+mod reading_time;
+pub mod native;
+mod registry;
+mod rng;
+mod route_when;
+mod schema;
+mod schema_check;
+mod session;
+mod singleton;
+mod sketch;
+mod state;
+mod stream;
+mod timeout;
+mod transform;
+mod window;
+mod wire;
+mod zipf;
 
-// Plugin metadata types
+pub use compat::{verify_schema_compat, Compatibility};
+pub use diversity::LexicalDiversity;
+pub use language::Detection;
+pub use derived::PocketNode;
+pub use native::{Node, WordCounter};
+pub use pocket_derive::PocketNode;
+pub use reading_time::{ReadingTime, ReadingTimeConfig, ReadingTimeInput, ReadingTimeOutput};
+pub use route_when::{RouteOp, RouteRule};
+pub use schema_check::validate_metadata;
+pub use transform::OutputTransform;
+pub use sketch::SketchGuarantee;
+pub use window::{WindowConfig, WindowKind, WindowUnit};
+pub use zipf::ZipfFit;
+pub use abi::{decode_return, encode_return, AbiError};
+
+// Plugin metadata types. `Metadata` is public, its fields aren't, so a
+// plugin can check what `get_metadata` answers with `validate_metadata`.
 #[derive(Serialize, Deserialize)]
-struct Metadata {
+pub struct Metadata {
     name: String,
     version: String,
     description: String,
@@ -16,6 +88,14 @@ struct Metadata {
     nodes: Vec<NodeDefinition>,
     permissions: Permissions,
     requirements: Requirements,
+    capabilities: Capabilities,
+    // Host imports the plugin can't work without
+    #[serde(rename = "requiredImports")]
+    required_imports: Vec<String>,
+    // Host imports only features with a fallback use. The host must still
+    // link them, if only to stubs.
+    #[serde(rename = "optionalImports")]
+    optional_imports: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,12 +110,32 @@ struct NodeDefinition {
     input_schema: Option<serde_json::Value>,
     #[serde(rename = "outputSchema")]
     output_schema: Option<serde_json::Value>,
+    // Canonical payloads for docs and smoke tests; `run_examples` checks
+    // that each still reproduces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<NodeExample>,
+    // Takes its input as raw bytes through `call_bytes` instead of JSON
+    #[serde(rename = "binaryInput", default, skip_serializing_if = "std::ops::Not::not")]
+    binary_input: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct NodeExample {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    config: Option<serde_json::Value>,
+    input: serde_json::Value,
+    // Expected exec output
+    output: serde_json::Value,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Permissions {
     memory: String,
-    timeout: u64,
+    timeout: timeout::Timeout,
+    // Names the plugin may read with `resource_read`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    resources: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,374 +143,6832 @@ struct Requirements {
     pocket: String,
 }
 
-// Request/Response types
 #[derive(Serialize, Deserialize)]
+struct Capabilities {
+    // Exports may be called concurrently from several host threads
+    #[serde(rename = "threadSafe")]
+    thread_safe: bool,
+    // Newest `Response` envelope version; older ones are negotiated by `init`
+    #[serde(rename = "envelopeVersion")]
+    envelope_version: u32,
+    // Response encodings a request's `wire.encoding` may name
+    encodings: Vec<wire::Encoding>,
+}
+
+// Request/Response types
+#[derive(Serialize, Deserialize, Default)]
 struct Request {
     node: String,
     function: String,
     config: Option<serde_json::Value>,
     input: Option<serde_json::Value>,
+    #[serde(default)]
+    wire: wire::WireOpts,
+    // Per-call budget; exec enforces the smaller of this and the permission
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    // Correlates this call with the rest of its workflow run; prefixed to
+    // every log line
+    #[serde(default)]
+    trace_id: Option<String>,
+    // Least severe level this call logs; all of them when unset
+    #[serde(default)]
+    log_level: Option<Level>,
+    // Nodes whose handlers `invoke`d this call, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    call_chain: Vec<String>,
+    // Longest `call_chain` an `invoke` may create; nested calls inherit it
+    #[serde(default)]
+    max_call_depth: Option<usize>,
+    // Report the fully resolved config in `meta.applied_config`
+    #[serde(default)]
+    echo_config: bool,
+    // The config merged over the host's defaults, once `effective_config`
+    // has made it, so the handler, `echo_config` and `float_precision` share
+    // one `host_default_config` call; unset without host defaults
+    #[serde(skip)]
+    merged_config: std::cell::OnceCell<Result<Option<serde_json::Value>, String>>,
 }
 
+// Fields after the first four are left out for hosts that negotiated an
+// older envelope (see `envelope`)
 #[derive(Serialize, Deserialize)]
 struct Response {
     success: bool,
     output: Option<serde_json::Value>,
     error: Option<String>,
     next: Option<String>,
+    #[serde(default, skip_serializing_if = "envelope::skip_before_v2")]
+    error_code: Option<ErrorCode>,
+    // Annotations for the host (cost, cache hints, ...) outside the node's
+    // declared output schema
+    #[serde(default, skip_serializing_if = "envelope::skip_before_v2")]
+    meta: Option<serde_json::Value>,
+    #[serde(rename = "_profile", default, skip_serializing_if = "envelope::skip_before_v2")]
+    profile: Option<Profile>,
+    // Set by a prep that found nothing for exec to do: the host skips exec
+    // and post and takes this route
+    #[serde(default, skip_serializing_if = "envelope::skip_before_v3")]
+    skip_to: Option<String>,
 }
 
-// Word counter specific types
-#[derive(Serialize, Deserialize)]
-struct WordCounterInput {
-    text: String,
-    #[serde(default)]
-    case_sensitive: bool,
+// Machine-readable category for a failed `Response`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    // The encoded response would exceed the plugin's memory permission
+    ResourceExhausted,
+    // `Request.node` isn't a registered node type
+    UnknownNode,
+    // No registered node implements `Request.function`
+    UnknownFunction,
+    // The node exists but doesn't implement `Request.function`
+    FunctionNotSupportedByNode,
+    // `Request.config` is present but doesn't match the config schema
+    InvalidConfig,
+    // An `invoke` would re-enter a node already on the call chain
+    CycleDetected,
+    // An `invoke` would nest deeper than the call depth limit
+    DepthExceeded,
 }
 
-#[derive(Serialize, Deserialize)]
-struct WordCounterOutput {
-    total_words: usize,
-    unique_words: usize,
-    word_frequencies: HashMap<String, usize>,
-    average_word_length: f64,
-    longest_word: String,
-    shortest_word: String,
+impl Response {
+    fn ok(output: serde_json::Value) -> Self {
+        Response {
+            success: true,
+            output: Some(output),
+            error: None,
+            next: None,
+            error_code: None,
+            meta: None,
+            profile: None,
+            skip_to: None,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Response {
+            success: false,
+            output: None,
+            error: Some(message.into()),
+            next: None,
+            error_code: None,
+            meta: None,
+            profile: None,
+            skip_to: None,
+        }
+    }
+
+    fn failure(code: ErrorCode, message: impl Into<String>) -> Self {
+        Response {
+            error_code: Some(code),
+            ..Response::error(message)
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct WordCounterConfig {
-    #[serde(default = "default_min_word_length")]
-    min_word_length: usize,
-    #[serde(default = "default_stop_words")]
-    stop_words: Vec<String>,
+// Per-phase wall-clock timings in microseconds, collected when the node
+// input sets `profile: true`. The block is copied into each phase's output
+// so the next phase's input carries it forward and post sees all three.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Profile {
+    prep_us: u64,
+    exec_us: u64,
+    post_us: u64,
 }
 
-fn default_min_word_length() -> usize {
-    1
+impl Profile {
+    // The profile carried by a phase's input, if profiling was requested
+    fn from_input(input: Option<&serde_json::Value>) -> Option<Profile> {
+        let input = input?;
+        match input.get("_profile") {
+            Some(carried) => Profile::deserialize(carried).ok(),
+            None => (input.get("profile") == Some(&serde_json::Value::Bool(true))).then(Profile::default),
+        }
+    }
+
+    fn record(&mut self, function: &str, elapsed_ns: u64) {
+        let micros = elapsed_ns / 1000;
+        match function {
+            "prep" => self.prep_us = micros,
+            "exec" => self.exec_us = micros,
+            "post" => self.post_us = micros,
+            _ => {}
+        }
+    }
+
+    fn attach(self, response: &mut Response) {
+        if let Some(serde_json::Value::Object(output)) = response.output.as_mut() {
+            output.insert("_profile".to_string(), serde_json::to_value(self).unwrap());
+        }
+        response.profile = Some(self);
+    }
 }
 
-fn default_stop_words() -> Vec<String> {
-    vec![
-        "a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
-        "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
-        "to", "was", "will", "with"
-    ].into_iter().map(String::from).collect()
+// Host-reported memory pressure, from least to most severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MemoryPressure {
+    Low,
+    Moderate,
+    Critical,
 }
 
-// Memory management functions
-#[no_mangle]
-pub extern "C" fn alloc(size: usize) -> *mut u8 {
-    let mut buf = Vec::with_capacity(size);
-    let ptr = buf.as_mut_ptr();
-    std::mem::forget(buf);
-    ptr
+impl MemoryPressure {
+    fn from_level(level: u32) -> Self {
+        match level {
+            0 => MemoryPressure::Low,
+            1 => MemoryPressure::Moderate,
+            _ => MemoryPressure::Critical,
+        }
+    }
 }
 
-#[no_mangle]
-pub extern "C" fn dealloc(ptr: *mut u8, size: usize) {
-    unsafe {
-        let _ = Vec::from_raw_parts(ptr, size, size);
+// Plugin-wide lifecycle hooks invoked by the host outside of `call`
+trait Plugin {
+    // Release whatever cached state the plugin can rebuild later
+    fn on_memory_pressure(&self, _level: MemoryPressure) {}
+}
+
+struct WordCounterPlugin;
+
+impl Plugin for WordCounterPlugin {
+    fn on_memory_pressure(&self, level: MemoryPressure) {
+        match level {
+            MemoryPressure::Low => {}
+            MemoryPressure::Moderate => state::shrink(),
+            MemoryPressure::Critical => {
+                state::clear();
+                memo::clear();
+            }
+        }
     }
 }
 
-// Plugin metadata export
-#[no_mangle]
-pub extern "C" fn metadata(ptr: *mut u8, len: usize) -> usize {
-    let metadata = Metadata {
-        name: "word-counter".to_string(),
-        version: "1.0.0".to_string(),
-        description: "Word counting and analysis plugin for Pocket".to_string(),
-        author: "Pocket Team".to_string(),
-        license: "MIT".to_string(),
-        runtime: "wasm".to_string(),
-        binary: "plugin.wasm".to_string(),
-        nodes: vec![
-            NodeDefinition {
-                node_type: "word-count".to_string(),
-                category: "text".to_string(),
-                description: "Count words and analyze text statistics".to_string(),
-                config_schema: Some(serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "min_word_length": {
-                            "type": "integer",
-                            "default": 1,
-                            "minimum": 1,
-                            "description": "Minimum word length to count"
-                        },
-                        "stop_words": {
-                            "type": "array",
-                            "items": {"type": "string"},
-                            "default": ["a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
-                                       "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
-                                       "to", "was", "will", "with"],
-                            "description": "Words to exclude from counting"
-                        }
-                    }
-                })),
-                input_schema: Some(serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "text": {
-                            "type": "string",
-                            "minLength": 1,
-                            "description": "Text to analyze"
-                        },
-                        "case_sensitive": {
-                            "type": "boolean",
-                            "default": false,
-                            "description": "Whether to treat words case-sensitively"
-                        }
-                    },
-                    "required": ["text"]
-                })),
-                output_schema: Some(serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "total_words": {"type": "integer"},
-                        "unique_words": {"type": "integer"},
-                        "word_frequencies": {
-                            "type": "object",
-                            "additionalProperties": {"type": "integer"}
-                        },
-                        "average_word_length": {"type": "number"},
-                        "longest_word": {"type": "string"},
-                        "shortest_word": {"type": "string"}
-                    },
-                    "required": ["total_words", "unique_words", "word_frequencies", 
-                               "average_word_length", "longest_word", "shortest_word"]
-                })),
+static PLUGIN: WordCounterPlugin = WordCounterPlugin;
+
+// Word counter specific types. Deserializes from the object form or, for
+// hosts that only send text, a bare string: `"hi"` is `{"text": "hi"}`.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct WordCounterInput {
+    pub text: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub explain: bool,
+}
+
+// `WordCounterInput` borrowing its text from the request, which already
+// owns it; inputs can be large enough that a copy matters. The text is
+// only owned when the deserializer can't lend it (e.g. it had escapes).
+struct InputView<'a> {
+    text: Cow<'a, str>,
+    case_sensitive: bool,
+    explain: bool,
+}
+
+// The object form of an input
+#[derive(Deserialize)]
+struct InputFields<'a> {
+    #[serde(borrow)]
+    text: Cow<'a, str>,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default)]
+    explain: bool,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for InputView<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct InputVisitor<'a>(std::marker::PhantomData<&'a ()>);
+
+        impl<'de: 'a, 'a> serde::de::Visitor<'de> for InputVisitor<'a> {
+            type Value = InputView<'a>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an input object or its text as a string")
+            }
+
+            fn visit_borrowed_str<E: serde::de::Error>(self, text: &'de str) -> Result<Self::Value, E> {
+                Ok(InputView::text(Cow::Borrowed(text)))
             }
-        ],
-        permissions: Permissions {
-            memory: "5MB".to_string(),
-            timeout: 3000,
-        },
-        requirements: Requirements {
-            pocket: ">=1.0.0".to_string(),
-        },
-    };
 
-    let json = serde_json::to_string(&metadata).unwrap();
-    let bytes = json.as_bytes();
-    
-    unsafe {
-        std::ptr::copy(bytes.as_ptr(), ptr, bytes.len().min(len));
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<Self::Value, E> {
+                Ok(InputView::text(Cow::Owned(text.to_string())))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, text: String) -> Result<Self::Value, E> {
+                Ok(InputView::text(Cow::Owned(text)))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let fields = InputFields::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                Ok(InputView { text: fields.text, case_sensitive: fields.case_sensitive, explain: fields.explain })
+            }
+        }
+
+        deserializer.deserialize_any(InputVisitor(std::marker::PhantomData))
     }
-    
-    bytes.len()
 }
 
-// Main call function
-#[no_mangle]
-pub extern "C" fn call(ptr: *const u8, len: usize, out_ptr: *mut u8, out_len: usize) -> usize {
-    let input = unsafe { slice::from_raw_parts(ptr, len) };
-    let input_str = match str::from_utf8(input) {
-        Ok(s) => s,
-        Err(_) => {
-            let error_response = Response {
-                success: false,
-                output: None,
-                error: Some("Invalid UTF-8 input".to_string()),
-                next: None,
-            };
-            let output = serde_json::to_string(&error_response).unwrap();
-            let output_bytes = output.as_bytes();
-            unsafe {
-                std::ptr::copy(output_bytes.as_ptr(), out_ptr, output_bytes.len().min(out_len));
-            }
-            return output_bytes.len();
+impl<'a> InputView<'a> {
+    fn text(text: Cow<'a, str>) -> Self {
+        InputView { text, case_sensitive: false, explain: false }
+    }
+}
+
+impl<'de> Deserialize<'de> for WordCounterInput {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let view = InputView::deserialize(deserializer)?;
+        Ok(WordCounterInput {
+            text: view.text.into_owned(),
+            case_sensitive: view.case_sensitive,
+            explain: view.explain,
+        })
+    }
+}
+
+impl WordCounterInput {
+    fn view(&self) -> InputView<'_> {
+        InputView { text: Cow::Borrowed(&self.text), case_sensitive: self.case_sensitive, explain: self.explain }
+    }
+}
+
+// Deserializes the request's config in place, without cloning the `Value`
+// unless the host has defaults to merge in. No config means the defaults; a
+// malformed one is an error, never quietly replaced by them.
+fn parse_config(request: &Request) -> Result<WordCounterConfig, String> {
+    let config = read_config(request)?;
+    refuse_invalid(&config)?;
+    Ok(config)
+}
+
+// `check_config`'s problems as one error, for the entry points that run a
+// config rather than list what's wrong with it
+fn refuse_invalid(config: &WordCounterConfig) -> Result<(), String> {
+    let errors = check_config(config);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!("Invalid config: {}", errors.join("; ")))
+}
+
+// The request's config as it deserializes, before `check_config`
+fn read_config(request: &Request) -> Result<WordCounterConfig, String> {
+    match effective_config(request)? {
+        Some(c) => WordCounterConfig::deserialize(c.as_ref()).map_err(|e| format!("Invalid config: {}", e)),
+        None => Ok(WordCounterConfig::default()),
+    }
+}
+
+// Every problem with a config that deserialized, for `parse_config` to
+// refuse and validate to list
+fn check_config(config: &WordCounterConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    if config.max_word_length.is_some_and(|max| max < config.min_word_length) {
+        errors.push("max_word_length is less than min_word_length".to_string());
+    }
+    if config.collocation_window == 0 {
+        errors.push("collocation_window must be at least 1".to_string());
+    }
+    if let Err(e) = collation::WordOrder::new(config.collation.as_deref()) {
+        errors.push(e);
+    }
+    if let Some(Err(e)) = config.stem.as_deref().map(stemmer_for) {
+        errors.push(e);
+    }
+    if let Err(e) = check_pipeline(config) {
+        errors.push(e);
+    }
+    if let Err(e) = check_sketch(config) {
+        errors.push(e);
+    }
+    if let Err(e) = only_within_regex(config) {
+        errors.push(e);
+    }
+    if let Err(e) = check_tokenizer(config) {
+        errors.push(e);
+    }
+    if let Some(Err(e)) = config.output_transform.as_ref().map(OutputTransform::check) {
+        errors.push(e);
+    }
+    if let Err(e) = route_when::check(&config.route_when) {
+        errors.push(e);
+    }
+    if let Some(Err(e)) = config.window.as_ref().map(WindowConfig::check) {
+        errors.push(e);
+    }
+    if let Err(e) = check_doc_freq(config) {
+        errors.push(e);
+    }
+    if config.float_precision.is_some_and(|digits| digits > precision::MAX_DIGITS) {
+        errors.push(format!("float_precision must be at most {}", precision::MAX_DIGITS));
+    }
+    if let Some(name) = config.stop_words_resource.as_deref().filter(|name| !PERMISSION_RESOURCES.contains(name)) {
+        errors.push(format!("Resource {:?} is not declared in permissions.resources", name));
+    }
+    if let Some(version) = config.output_schema_version {
+        if version == 0 || version > WORD_COUNT_OUTPUT.current {
+            errors.push(format!(
+                "Unsupported output schema version {} (supported: 1-{})",
+                version, WORD_COUNT_OUTPUT.current
+            ));
         }
+    }
+    errors
+}
+
+// The sketch's and the novelty filter's sizes, capped so a config can't ask
+// for more counters or bits than memory holds, and the options
+// `approximate` can't serve: it keeps no token sequence
+fn check_sketch(config: &WordCounterConfig) -> Result<(), String> {
+    if config.novelty_capacity > bloom::MAX_CAPACITY {
+        return Err(format!("novelty_capacity must be at most {}", bloom::MAX_CAPACITY));
+    }
+    // Bits per word grow as the rate shrinks; near 0 they outgrow memory
+    if !(config.novelty_fp_rate > 0.0 && config.novelty_fp_rate <= 0.5) {
+        return Err(format!("novelty_fp_rate must be above 0 and at most 0.5, not {}", config.novelty_fp_rate));
+    }
+    if config.sketch_width > sketch::MAX_WIDTH {
+        return Err(format!("sketch_width must be at most {}", sketch::MAX_WIDTH));
+    }
+    if config.sketch_depth > sketch::MAX_DEPTH {
+        return Err(format!("sketch_depth must be at most {}", sketch::MAX_DEPTH));
+    }
+    if !config.approximate {
+        return Ok(());
+    }
+    let sequential = [
+        ("collocations", config.collocations),
+        ("sample_size", config.sample_size.is_some()),
+        ("include_first_seen", config.include_first_seen),
+        ("include_length_histogram", config.include_length_histogram),
+        ("lexical_diversity", config.lexical_diversity),
+    ];
+    match sequential.iter().find(|(_, set)| *set) {
+        Some((option, _)) => Err(format!("{} needs the token sequence, which approximate doesn't keep", option)),
+        None => Ok(()),
+    }
+}
+
+// The request's config over the host's defaults for the node
+// (`host_default_config`), key by key: a key the request sets replaces the
+// host's value whole, and keys neither sets take the built-in default.
+fn effective_config(request: &Request) -> Result<Option<Cow<'_, serde_json::Value>>, String> {
+    match request.merged_config.get_or_init(|| merge_host_defaults(request)) {
+        Ok(Some(merged)) => Ok(Some(Cow::Borrowed(merged))),
+        Ok(None) => Ok(request.config.as_ref().map(Cow::Borrowed)),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+// The request's config over the host's defaults, or `None` where
+// `request.config` stands as it is
+fn merge_host_defaults(request: &Request) -> Result<Option<serde_json::Value>, String> {
+    let Some(defaults) = host::default_config(&request.node) else {
+        return Ok(None);
+    };
+    let mut merged = match serde_json::from_slice(&defaults) {
+        Ok(serde_json::Value::Object(defaults)) => defaults,
+        _ => return Err(format!("Invalid host default config for {}: not a JSON object", request.node)),
     };
+    match &request.config {
+        Some(serde_json::Value::Object(config)) => merged.extend(config.clone()),
+        // Not mergeable, and rejected when deserialized
+        Some(_) => return Ok(None),
+        None => {}
+    }
+    Ok(Some(serde_json::Value::Object(merged)))
+}
+
+// What prep hands to exec
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PrepData {
+    pub original_text: String,
+    pub cleaned_text: String,
+    pub case_sensitive: bool,
+    pub explain: bool,
+    // Layout of the original text, with `count_lines`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paragraph_count: Option<usize>,
+    // With `include_char_stats`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub char_stats: Option<CharStats>,
+    // With `include_sentence_breakdown`, in text order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentences: Option<Vec<SentenceSpan>>,
+    // Characters dropped by `ignore_between`, markers included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignored_chars: Option<usize>,
+    // Emoji removed by `strip_emoji`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji_count: Option<usize>,
+    // With `auto_stop_words`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<Detection>,
+    // From the host tokenizer, which exec counts instead of splitting
+    // `cleaned_text` (then just the tokens joined by spaces)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<String>>,
+    // Problems prep worked around, such as an unclosed marker
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// A sentence of the original text and where it ends in `cleaned_text`,
+// so exec can count each one with the same tokenization as the whole
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SentenceSpan {
+    pub text: String,
+    // Byte offset in `cleaned_text` (exclusive)
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SentenceCount {
+    pub text: String,
+    pub word_count: usize,
+}
 
-    let request: Request = match serde_json::from_str(input_str) {
-        Ok(r) => r,
-        Err(e) => {
-            let error_response = Response {
-                success: false,
-                output: None,
-                error: Some(format!("Failed to parse request: {}", e)),
-                next: None,
+// How `word_frequencies` changed since the previous exec on this instance
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrequencyDelta {
+    // There was no previous exec to compare with (or its result was
+    // released), so `added` holds the whole map and the host should
+    // replace, not patch, what it has
+    pub reset: bool,
+    pub added: BTreeMap<String, usize>,
+    // New counts of words whose count changed
+    pub updated: BTreeMap<String, usize>,
+    pub removed: Vec<String>,
+}
+
+impl FrequencyDelta {
+    fn between(previous: Option<Vec<(String, usize)>>, current: &HashMap<String, usize>) -> Self {
+        let Some(previous) = previous else {
+            return FrequencyDelta {
+                reset: true,
+                added: current.iter().map(|(w, c)| (w.clone(), *c)).collect(),
+                ..FrequencyDelta::default()
             };
-            let output = serde_json::to_string(&error_response).unwrap();
-            let output_bytes = output.as_bytes();
-            unsafe {
-                std::ptr::copy(output_bytes.as_ptr(), out_ptr, output_bytes.len().min(out_len));
+        };
+        let previous: HashMap<String, usize> = previous.into_iter().collect();
+        let mut delta = FrequencyDelta::default();
+        for (word, count) in current {
+            match previous.get(word) {
+                None => {
+                    delta.added.insert(word.clone(), *count);
+                }
+                Some(old) if old != count => {
+                    delta.updated.insert(word.clone(), *count);
+                }
+                Some(_) => {}
             }
-            return output_bytes.len();
         }
-    };
+        delta.removed = previous.into_keys().filter(|word| !current.contains_key(word)).collect();
+        delta.removed.sort();
+        delta
+    }
+}
 
-    let response = match request.function.as_str() {
-        "prep" => handle_prep(&request),
-        "exec" => handle_exec(&request),
-        "post" => handle_post(&request),
-        _ => Response {
-            success: false,
-            output: None,
-            error: Some(format!("Unknown function: {}", request.function)),
-            next: None,
-        },
-    };
+// Character tallies of the original text, before cleaning drops
+// punctuation. `punctuation` is every printable character that is neither
+// alphanumeric nor whitespace, so symbols and emoji land there too.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CharStats {
+    pub letters: usize,
+    pub digits: usize,
+    pub punctuation: usize,
+    pub whitespace: usize,
+    pub total: usize,
+}
 
-    let output = serde_json::to_string(&response).unwrap();
-    let output_bytes = output.as_bytes();
-    
-    unsafe {
-        std::ptr::copy(output_bytes.as_ptr(), out_ptr, output_bytes.len().min(out_len));
-    }
-    
-    output_bytes.len()
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WordCounterOutput {
+    pub total_words: usize,
+    pub unique_words: usize,
+    pub word_frequencies: HashMap<String, usize>,
+    pub average_word_length: f64,
+    pub longest_word: String,
+    pub shortest_word: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collocations: Option<Vec<Collocation>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspicious_tokens: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanations: Option<Vec<Explanation>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weighted_score: Option<f64>,
+    // Counted key -> its most frequent original spelling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_forms: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<Vec<String>>,
+    // Counted words not seen in any earlier document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub novel_words: Option<Vec<String>>,
+    // Counted words per sentence; they sum to `total_words`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentences: Option<Vec<SentenceCount>>,
+    // Word length in characters -> counted words of that length
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_histogram: Option<BTreeMap<usize, usize>>,
+    // Counted word -> position of its first occurrence among counted tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<HashMap<String, usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lexical_diversity: Option<LexicalDiversity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zipf: Option<ZipfFit>,
+    // Each reported word's share of the reported counts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word_probabilities: Option<HashMap<String, f64>>,
+    // Prep's detection, with `auto_stop_words`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<Detection>,
+    // With `approximate`: how far `word_frequencies` may overcount
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sketch: Option<SketchGuarantee>,
+    // With `delta`, which leaves `word_frequencies` empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<FrequencyDelta>,
+    // Set when a count or total saturated instead of wrapping
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overflow: bool,
+    // Set when `max_tokens` stopped tokenizing before the end of the text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
-fn handle_prep(request: &Request) -> Response {
-    let input: WordCounterInput = match request.input.as_ref() {
-        Some(i) => match serde_json::from_value(i.clone()) {
-            Ok(inp) => inp,
-            Err(e) => return Response {
-                success: false,
-                output: None,
-                error: Some(format!("Failed to parse input: {}", e)),
-                next: None,
-            },
-        },
-        None => return Response {
-            success: false,
-            output: None,
-            error: Some("No input provided".to_string()),
-            next: None,
-        },
-    };
+// An ordered word pair that co-occurs within the collocation window
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Collocation {
+    pub pair: [String; 2],
+    pub count: usize,
+    // Pointwise mutual information, log2(p(a,b) / (p(a) p(b)))
+    pub score: f64,
+}
 
-    // Clean and prepare text
-    let cleaned_text = input.text
+// True if the token contains letters from more than one script, e.g. a
+// Latin word with a Cyrillic lookalike. Script-neutral characters (digits,
+// combining marks) don't count towards either script.
+fn is_mixed_script(token: &str) -> bool {
+    let mut scripts = token
         .chars()
-        .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { ' ' })
-        .collect::<String>();
+        .map(|c| c.script())
+        .filter(|s| !matches!(s, Script::Common | Script::Inherited | Script::Unknown));
+    match scripts.next() {
+        Some(first) => scripts.any(|s| s != first),
+        None => false,
+    }
+}
 
-    let prep_result = serde_json::json!({
-        "original_text": input.text,
-        "cleaned_text": cleaned_text,
-        "case_sensitive": input.case_sensitive,
-    });
+// Coarse part-of-speech class of a token
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenClass {
+    Noun,
+    ProperNoun,
+    Verb,
+    Adjective,
+    Adverb,
+    Other,
+    Unknown,
+}
 
-    Response {
-        success: true,
-        output: Some(prep_result),
-        error: None,
-        next: None,
+// Extension point for a part-of-speech tagger. Tokens are classified in
+// their original casing, before case folding.
+pub trait TokenClassifier {
+    fn classify(&self, token: &str) -> TokenClass;
+}
+
+// Built-in classifier: no tagger is shipped, so everything is `Unknown`
+struct UnknownClassifier;
+
+impl TokenClassifier for UnknownClassifier {
+    fn classify(&self, _token: &str) -> TokenClass {
+        TokenClass::Unknown
     }
 }
 
-fn handle_exec(request: &Request) -> Response {
-    let prep_data = match request.input.as_ref() {
-        Some(d) => d,
-        None => return Response {
-            success: false,
-            output: None,
-            error: Some("No prep data provided".to_string()),
-            next: None,
-        },
-    };
+// Why a token was or wasn't counted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Disposition {
+    Kept,
+    ExcludedClass,
+    TooShort,
+    TooLong,
+    Number,
+    NotInAllowlist,
+    StopWord,
+}
 
-    let cleaned_text = prep_data["cleaned_text"].as_str().unwrap_or("");
-    let case_sensitive = prep_data["case_sensitive"].as_bool().unwrap_or(false);
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub token: String,
+    pub kept: bool,
+    pub reason: Disposition,
+}
 
-    let config: WordCounterConfig = request.config.as_ref()
-        .and_then(|c| serde_json::from_value(c.clone()).ok())
-        .unwrap_or_else(|| WordCounterConfig {
-            min_word_length: default_min_word_length(),
-            stop_words: default_stop_words(),
-        });
+// The per-token filters exec applies, in order
+struct TokenFilter<'a> {
+    config: &'a WordCounterConfig,
+    case_sensitive: bool,
+    stop_words: &'a HashSet<String>,
+    // The stop words keyed like tokens, matched against each token's key:
+    // always with a pipeline (which may stem before the stop-word check),
+    // and with `stem_stop_words`
+    stop_word_keys: Option<HashSet<String>>,
+    // Already case-folded like the tokens it's compared against
+    allowlist: Option<HashSet<String>>,
+    classifier: &'a dyn TokenClassifier,
+    // Only when there's no pipeline; a pipeline stems at its `stem` step
+    stemmer: Option<&'a rust_stemmers::Stemmer>,
+    pipeline: Option<KeyPipeline<'a>>,
+}
 
-    // Split into words
-    let words: Vec<String> = cleaned_text
-        .split_whitespace()
-        .filter(|w| w.len() >= config.min_word_length)
-        .map(|w| if case_sensitive { w.to_string() } else { w.to_lowercase() })
-        .filter(|w| !config.stop_words.contains(w))
-        .collect();
+impl TokenFilter<'_> {
+    fn fold(&self, token: &str) -> String {
+        match &self.pipeline {
+            Some(pipeline) => pipeline.apply(token),
+            None if self.case_sensitive => token.to_string(),
+            None => token.to_lowercase(),
+        }
+    }
 
-    if words.is_empty() {
-        return Response {
-            success: true,
-            output: Some(serde_json::json!(WordCounterOutput {
-                total_words: 0,
-                unique_words: 0,
-                word_frequencies: HashMap::new(),
-                average_word_length: 0.0,
-                longest_word: String::new(),
-                shortest_word: String::new(),
-            })),
-            error: None,
-            next: None,
+    fn key(&self, folded: String) -> String {
+        // Before stemming, as the stemmer expects words
+        let folded = if self.config.normalize_plurals {
+            inflection::singular(&folded).into_owned()
+        } else {
+            folded
         };
+        match self.stemmer {
+            Some(stemmer) => stemmer.stem(&folded).into_owned(),
+            None => folded,
+        }
     }
 
-    // Count word frequencies
-    let mut word_frequencies = HashMap::new();
-    let mut total_length = 0;
-    let mut longest_word = &words[0];
-    let mut shortest_word = &words[0];
+    fn stop_word_keys(&self) -> HashSet<String> {
+        self.stop_words.iter().map(|word| self.key(self.fold(word))).collect()
+    }
+
+    // Stop words match case-insensitively unless configured otherwise,
+    // independent of whether counting itself is case-sensitive
+    fn is_stop_word(&self, stop_words: &HashSet<String>, token: &str) -> bool {
+        if self.config.stop_words_case_sensitive || !self.case_sensitive {
+            stop_words.contains(token)
+        } else {
+            stop_words.contains(&token.to_lowercase())
+        }
+    }
 
-    for word in &words {
-        *word_frequencies.entry(word.clone()).or_insert(0) += 1;
-        total_length += word.len();
-        
-        if word.len() > longest_word.len() {
-            longest_word = word;
+    // Returns the key `raw` is counted under (its stem, when stemming), or why
+    // it was dropped.
+    // `folded` is `raw` after case folding.
+    fn check(&self, raw: &str, folded: String) -> Result<String, Disposition> {
+        let config = self.config;
+        if !config.keep_classes.is_empty() && !config.keep_classes.contains(&self.classifier.classify(raw)) {
+            return Err(Disposition::ExcludedClass);
+        }
+        let length = word_length(raw, config);
+        if length < config.min_word_length {
+            return Err(Disposition::TooShort);
+        }
+        if config.max_word_length.is_some_and(|max| length > max) {
+            return Err(Disposition::TooLong);
+        }
+        let token = config.numbers.apply(folded).ok_or(Disposition::Number)?;
+        if self.allowlist.as_ref().is_some_and(|allowlist| !allowlist.contains(&token)) {
+            return Err(Disposition::NotInAllowlist);
         }
-        if word.len() < shortest_word.len() {
-            shortest_word = word;
+        if self.is_stop_word(self.stop_words, &token) {
+            return Err(Disposition::StopWord);
         }
+        let key = self.key(token);
+        if self.stop_word_keys.as_ref().is_some_and(|keys| self.is_stop_word(keys, &key)) {
+            return Err(Disposition::StopWord);
+        }
+        Ok(key)
     }
+}
 
-    let output = WordCounterOutput {
-        total_words: words.len(),
-        unique_words: word_frequencies.len(),
-        word_frequencies,
-        average_word_length: total_length as f64 / words.len() as f64,
-        longest_word: longest_word.clone(),
-        shortest_word: shortest_word.clone(),
-    };
+// Pairs seen fewer times than this are too sparse for PMI to mean much
+const MIN_COLLOCATION_COUNT: usize = 2;
 
-    Response {
-        success: true,
-        output: Some(serde_json::to_value(output).unwrap()),
-        error: None,
-        next: None,
+// Counts ordered pairs (a, b) where b follows a within `window` tokens and
+// keeps those that co-occur more often than chance (positive PMI). Windows
+// are truncated at the end of the token stream rather than wrapping.
+fn find_collocations(
+    words: &[&str],
+    frequencies: &HashMap<String, usize>,
+    window: usize,
+) -> Vec<Collocation> {
+    let mut pairs: HashMap<(&str, &str), usize> = HashMap::new();
+    for (i, a) in words.iter().enumerate() {
+        for b in words.iter().skip(i + 1).take(window) {
+            if a != b {
+                *pairs.entry((*a, *b)).or_insert(0) += 1;
+            }
+        }
     }
+
+    let total_words = words.len() as f64;
+    let total_pairs = pairs.values().sum::<usize>() as f64;
+    let mut collocations: Vec<Collocation> = pairs
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_COLLOCATION_COUNT)
+        .map(|((a, b), count)| {
+            let p_ab = count as f64 / total_pairs;
+            let p_a = frequencies[a] as f64 / total_words;
+            let p_b = frequencies[b] as f64 / total_words;
+            Collocation {
+                pair: [a.to_string(), b.to_string()],
+                count,
+                score: (p_ab / (p_a * p_b)).log2(),
+            }
+        })
+        .filter(|c| c.score > 0.0)
+        .collect();
+    collocations.sort_by(|x, y| {
+        y.score.total_cmp(&x.score)
+            .then_with(|| y.count.cmp(&x.count))
+            .then_with(|| x.pair.cmp(&y.pair))
+    });
+    collocations
 }
 
-fn handle_post(request: &Request) -> Response {
-    let exec_result = match request.input.as_ref() {
-        Some(r) => r,
-        None => return Response {
-            success: false,
-            output: None,
-            error: Some("No exec result provided".to_string()),
-            next: None,
-        },
-    };
+// Output schema history for the word-count node:
+//   v1: the original six statistics fields
+//   v2: adds `schema_version` and any optional fields enabled by config
+const WORD_COUNT_OUTPUT: schema::VersionedOutput = schema::VersionedOutput {
+    current: 2,
+    migrate: migrate_word_count_output,
+};
 
-    let total_words = exec_result["total_words"].as_u64().unwrap_or(0);
-    
-    // Route based on word count
-    let next = if total_words == 0 {
-        "empty"
-    } else if total_words < 100 {
-        "short"
-    } else if total_words < 1000 {
-        "medium"
-    } else {
-        "long"
+const WORD_COUNT_V1_FIELDS: &[&str] = &[
+    "total_words", "unique_words", "word_frequencies",
+    "average_word_length", "longest_word", "shortest_word",
+];
+
+// Exec's timeout output, which post routes by in every version
+const TIMEOUT_FIELDS: &[&str] = &["timed_out", "timeout_ms"];
+
+fn migrate_word_count_output(from: u32, output: serde_json::Value) -> serde_json::Value {
+    match from {
+        2 => schema::retain_fields(output, &[WORD_COUNT_V1_FIELDS, TIMEOUT_FIELDS].concat()),
+        _ => output,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WordCounterConfig {
+    #[serde(default = "default_min_word_length")]
+    pub min_word_length: usize,
+    #[serde(default)]
+    pub max_word_length: Option<usize>,
+    // Tokenizing stops after this many tokens, counted or not
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    // Filter and count runs of an identical token once per run rather than
+    // once per token; the output is the same either way
+    #[serde(default)]
+    pub run_length: bool,
+    // When set, only these words are counted
+    #[serde(default)]
+    pub allowlist: Option<Vec<String>>,
+    #[serde(default = "default_stop_words")]
+    pub stop_words: Vec<String>,
+    #[serde(default)]
+    pub stop_words_case_sensitive: bool,
+    // A declared resource listing more stop words, one per line
+    #[serde(default)]
+    pub stop_words_resource: Option<String>,
+    // Use the detected language's stop-word preset instead of `stop_words`
+    #[serde(default)]
+    pub auto_stop_words: bool,
+    // Also drop tokens whose stem is a stop word's stem
+    #[serde(default)]
+    pub stem_stop_words: bool,
+    #[serde(default)]
+    pub normalization: Normalization,
+    #[serde(default)]
+    pub numbers: NumberMode,
+    #[serde(default)]
+    pub preserve_entities: Vec<Entity>,
+    // Spans prep drops before counting (code blocks, front matter, ...)
+    #[serde(default)]
+    pub ignore_between: Vec<MarkerPair>,
+    // Regex whose matches (or first capture group) are the only text counted
+    #[serde(default)]
+    pub only_within: Option<String>,
+    // Report line and paragraph counts from prep
+    #[serde(default)]
+    pub count_lines: bool,
+    // Report `char_stats` from prep
+    #[serde(default)]
+    pub include_char_stats: bool,
+    #[serde(default)]
+    pub include_sentence_breakdown: bool,
+    #[serde(default)]
+    pub include_length_histogram: bool,
+    // Report where each counted word first appears
+    #[serde(default)]
+    pub include_first_seen: bool,
+    // Report changes since the previous exec instead of the full map
+    #[serde(default)]
+    pub delta: bool,
+    // Report TTR, root TTR and MTLD of the counted tokens
+    #[serde(default)]
+    pub lexical_diversity: bool,
+    // Report how well word frequencies follow Zipf's law
+    #[serde(default)]
+    pub include_zipf: bool,
+    // Report relative frequencies summing to 1 alongside the counts
+    #[serde(default)]
+    pub normalize_frequencies: bool,
+    #[serde(default)]
+    pub split_camel_case: bool,
+    // Whether identifiers split at underscores and digit boundaries
+    #[serde(default)]
+    pub identifier_split: IdentifierSplit,
+    // Rewrite English contractions ("don't") as their words before cleaning
+    #[serde(default)]
+    pub expand_contractions: bool,
+    // Drop the 's of possessives before cleaning ("dog's" -> "dog")
+    #[serde(default)]
+    pub strip_possessive: bool,
+    // Count simple English plurals as their singular ("dogs" -> "dog")
+    #[serde(default)]
+    pub normalize_plurals: bool,
+    // Remove emoji and pictographic symbols before counting
+    #[serde(default)]
+    pub strip_emoji: bool,
+    // Right-to-left script handling: marks stay in words, directional
+    // controls are dropped, words split at direction changes, and lengths
+    // count graphemes (see `bidi`)
+    #[serde(default)]
+    pub bidi_aware: bool,
+    #[serde(default)]
+    pub tokenizer: Tokenizer,
+    // Language code; counts tokens by their Snowball stem
+    #[serde(default)]
+    pub stem: Option<String>,
+    // Exact transforms, in order, that make a token's frequency-map key.
+    // When set it replaces the implicit lowercasing (`case_sensitive`) and
+    // stemming; allowlist entries go through it too.
+    #[serde(default)]
+    pub normalization_pipeline: Option<Vec<KeyTransform>>,
+    #[serde(default)]
+    pub collocations: bool,
+    #[serde(default = "default_collocation_window")]
+    pub collocation_window: usize,
+    #[serde(default)]
+    pub detect_mixed_script: bool,
+    // Empty keeps every class
+    #[serde(default)]
+    pub keep_classes: Vec<TokenClass>,
+    // Per-word multipliers for `weighted_score`, keyed by the counted form
+    #[serde(default)]
+    pub weights: Option<HashMap<String, f64>>,
+    #[serde(default = "default_weight")]
+    pub default_weight: f64,
+    #[serde(default)]
+    pub canonical_forms: bool,
+    // Track words across documents and report the new ones. The filter is
+    // sized by the settings in effect when it's first created.
+    #[serde(default)]
+    pub novelty: bool,
+    #[serde(default = "default_novelty_capacity")]
+    pub novelty_capacity: usize,
+    #[serde(default = "default_novelty_fp_rate")]
+    pub novelty_fp_rate: f64,
+    // Words counted fewer times are left out of `word_frequencies`
+    #[serde(default)]
+    pub min_frequency: Option<usize>,
+    // Count in a count-min sketch instead of exactly, reporting only the
+    // `approximate_top_k` most frequent words with estimated counts
+    #[serde(default)]
+    pub approximate: bool,
+    #[serde(default = "default_sketch_width")]
+    pub sketch_width: usize,
+    #[serde(default = "default_sketch_depth")]
+    pub sketch_depth: usize,
+    #[serde(default = "default_approximate_top_k")]
+    pub approximate_top_k: usize,
+    // Document-frequency bounds on the corpus node's vocabulary
+    #[serde(default)]
+    pub min_doc_freq: Option<usize>,
+    #[serde(default)]
+    pub max_doc_freq: Option<usize>,
+    // BCP-47 locale for ordering word lists; byte order when unset
+    #[serde(default)]
+    pub collation: Option<String>,
+    // Size of a uniform random sample of counted tokens, seeded by the host
+    #[serde(default)]
+    pub sample_size: Option<usize>,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    // Longest/shortest words past this many characters are cut short in
+    // the output; counting still sees the whole token
+    #[serde(default)]
+    pub max_word_output_len: Option<usize>,
+    // Decimal places every float in a node's output is rounded to (see
+    // `round_output`); full precision when unset
+    #[serde(default)]
+    pub float_precision: Option<u32>,
+    #[serde(default)]
+    pub output_schema_version: Option<u32>,
+    // An exec slower than this asks the host to slow down
+    #[serde(default)]
+    pub backpressure_after_ms: Option<u64>,
+    // Return the stored output when the same config and input run again
+    #[serde(default)]
+    pub memoize: bool,
+    // Memoized outputs older than this are counted again; no expiry when unset
+    #[serde(default)]
+    pub memo_ttl_ms: Option<u64>,
+    // Applied by post to the node's final output
+    #[serde(default)]
+    pub output_transform: Option<OutputTransform>,
+    // Where post routes an exec that counted no words
+    #[serde(default = "default_empty_route")]
+    pub empty_route: String,
+    // Route results whose counts are truncated or approximate to the size
+    // route with an `-approx` suffix
+    #[serde(default)]
+    pub approx_routes: bool,
+    // Routes on exec result fields, tried in order before the word-count
+    // routes (see `route_when`)
+    #[serde(default)]
+    pub route_when: Vec<RouteRule>,
+    // Have prep skip straight to `empty_route` when the text has no words
+    #[serde(default = "default_skip_empty")]
+    pub skip_empty: bool,
+    // Route named by a failed prep, exec or post, so a workflow can branch
+    // on failure; failures name no route when unset
+    #[serde(default)]
+    pub error_route: Option<String>,
+    // Windows the `record` function aggregates a stream of records into
+    #[serde(default)]
+    pub window: Option<WindowConfig>,
+}
+
+impl Default for WordCounterConfig {
+    fn default() -> Self {
+        WordCounterConfig {
+            min_word_length: default_min_word_length(),
+            max_word_length: None,
+            max_tokens: None,
+            run_length: false,
+            allowlist: None,
+            stop_words: default_stop_words(),
+            stop_words_case_sensitive: false,
+            stop_words_resource: None,
+            auto_stop_words: false,
+            stem_stop_words: false,
+            normalization: Normalization::default(),
+            numbers: NumberMode::default(),
+            preserve_entities: Vec::new(),
+            ignore_between: Vec::new(),
+            only_within: None,
+            count_lines: false,
+            include_char_stats: false,
+            include_sentence_breakdown: false,
+            include_length_histogram: false,
+            include_first_seen: false,
+            delta: false,
+            lexical_diversity: false,
+            include_zipf: false,
+            normalize_frequencies: false,
+            split_camel_case: false,
+            identifier_split: IdentifierSplit::default(),
+            expand_contractions: false,
+            strip_possessive: false,
+            normalize_plurals: false,
+            strip_emoji: false,
+            bidi_aware: false,
+            tokenizer: Tokenizer::default(),
+            stem: None,
+            normalization_pipeline: None,
+            collocations: false,
+            collocation_window: default_collocation_window(),
+            detect_mixed_script: false,
+            keep_classes: Vec::new(),
+            weights: None,
+            default_weight: default_weight(),
+            canonical_forms: false,
+            novelty: false,
+            novelty_capacity: default_novelty_capacity(),
+            novelty_fp_rate: default_novelty_fp_rate(),
+            min_frequency: None,
+            approximate: false,
+            sketch_width: default_sketch_width(),
+            sketch_depth: default_sketch_depth(),
+            approximate_top_k: default_approximate_top_k(),
+            min_doc_freq: None,
+            max_doc_freq: None,
+            collation: None,
+            sample_size: None,
+            output_format: OutputFormat::default(),
+            max_word_output_len: None,
+            float_precision: None,
+            output_schema_version: None,
+            backpressure_after_ms: None,
+            memoize: false,
+            memo_ttl_ms: None,
+            output_transform: None,
+            empty_route: default_empty_route(),
+            approx_routes: false,
+            route_when: Vec::new(),
+            skip_empty: default_skip_empty(),
+            error_route: None,
+            window: None,
+        }
+    }
+}
+
+// Unicode normalization form applied to the text before cleaning
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+    Nfkc,
+}
+
+impl Normalization {
+    fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Normalization::None => Cow::Borrowed(text),
+            Normalization::Nfc => Cow::Owned(text.nfc().collect()),
+            Normalization::Nfkc => Cow::Owned(text.nfkc().collect()),
+        }
+    }
+}
+
+// One step of `normalization_pipeline`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyTransform {
+    Lowercase,
+    // Strip combining marks: "café" → "cafe"
+    FoldDiacritics,
+    // Snowball stem in the `stem` language
+    Stem,
+}
+
+// The transforms that turn a token into its frequency-map key, in order
+#[derive(Clone, Copy)]
+struct KeyPipeline<'a> {
+    steps: &'a [KeyTransform],
+    stemmer: Option<&'a rust_stemmers::Stemmer>,
+}
+
+impl KeyPipeline<'_> {
+    fn apply(&self, token: &str) -> String {
+        self.steps.iter().fold(token.to_string(), |token, step| match step {
+            KeyTransform::Lowercase => token.to_lowercase(),
+            KeyTransform::FoldDiacritics => token.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect(),
+            KeyTransform::Stem => match self.stemmer {
+                Some(stemmer) => stemmer.stem(&token).into_owned(),
+                None => token,
+            },
+        })
+    }
+}
+
+// A `stem` step has no language unless `stem` is set
+fn check_pipeline(config: &WordCounterConfig) -> Result<(), String> {
+    let stems = config.normalization_pipeline.as_ref().is_some_and(|steps| steps.contains(&KeyTransform::Stem));
+    if stems && config.stem.is_none() {
+        return Err("normalization_pipeline has a stem step but no stem language is set".to_string());
+    }
+    Ok(())
+}
+
+// What splits the text into tokens
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    // Cleaning then whitespace splitting
+    #[default]
+    Builtin,
+    // The host's `host_tokenize` import, given the text before cleaning;
+    // its tokens are counted as they are
+    Host,
+}
+
+// Shape of `word_frequencies` in exec output
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    // {"word": count, ...}
+    #[default]
+    Map,
+    // {"words": [...], "counts": [...]}, aligned and ordered by descending
+    // count then word, for columnar stores
+    Columns,
+    // Each entry emitted to the host as its own NDJSON line, in the same
+    // order, then a summary line; the response's map is left empty
+    Ndjson,
+}
+
+// How all-digit tokens are treated when counting
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberMode {
+    #[default]
+    Keep,
+    Drop,
+    Bucket,
+}
+
+// Word boundaries inside identifiers such as "user_id_2"
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierSplit {
+    // Underscores between word characters are part of the word
+    None,
+    // Underscores are word breaks, like other punctuation
+    #[default]
+    Snake,
+    // Also split where letters meet digits ("id2" -> "id", "2")
+    All,
+}
+
+// Snowball stemmer for an ISO 639-1 language code
+fn stemmer_for(language: &str) -> Result<rust_stemmers::Stemmer, String> {
+    use rust_stemmers::Algorithm::*;
+    let algorithm = match language {
+        "ar" => Arabic,
+        "da" => Danish,
+        "nl" => Dutch,
+        "en" => English,
+        "fi" => Finnish,
+        "fr" => French,
+        "de" => German,
+        "el" => Greek,
+        "hu" => Hungarian,
+        "it" => Italian,
+        "no" => Norwegian,
+        "pt" => Portuguese,
+        "ro" => Romanian,
+        "ru" => Russian,
+        "es" => Spanish,
+        "sv" => Swedish,
+        "ta" => Tamil,
+        "tr" => Turkish,
+        _ => {
+            return Err(format!(
+                "Unsupported stemming language {:?} (supported: {})",
+                language,
+                STEM_LANGUAGES.join(", ")
+            ))
+        }
     };
+    Ok(rust_stemmers::Stemmer::create(algorithm))
+}
 
-    Response {
-        success: true,
-        output: Some(exec_result.clone()),
-        error: None,
-        next: Some(next.to_string()),
+const STEM_LANGUAGES: &[&str] = &[
+    "ar", "da", "nl", "en", "fi", "fr", "de", "el", "hu", "it", "no", "pt", "ro", "ru", "es", "sv", "ta", "tr",
+];
+
+// Sentinel that replaces all-digit tokens in `bucket` mode
+const NUMBER_TOKEN: &str = "<NUM>";
+
+impl NumberMode {
+    fn apply(self, token: String) -> Option<String> {
+        if self == NumberMode::Keep || !token.chars().all(|c| c.is_ascii_digit()) {
+            return Some(token);
+        }
+        match self {
+            NumberMode::Drop => None,
+            _ => Some(NUMBER_TOKEN.to_string()),
+        }
+    }
+}
+
+// Token kinds that survive cleaning intact instead of being split on
+// punctuation
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Url,
+    Email,
+}
+
+impl Entity {
+    fn matches(self, token: &str) -> bool {
+        match self {
+            Entity::Url => is_url(token),
+            Entity::Email => is_email(token),
+        }
+    }
+}
+
+// scheme://host[/path] for http(s), or a bare www.host
+fn is_url(token: &str) -> bool {
+    let rest = ["https://", "http://", "www."]
+        .iter()
+        .find_map(|prefix| token.strip_prefix(prefix));
+    match rest {
+        Some(rest) => {
+            let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+            is_domain(host.split(':').next().unwrap_or(""))
+        }
+        None => false,
+    }
+}
+
+fn is_email(token: &str) -> bool {
+    match token.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && local.chars().all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+                && is_domain(domain)
+        }
+        None => false,
+    }
+}
+
+// Two or more dot-separated labels, e.g. "example.com"
+fn is_domain(host: &str) -> bool {
+    let mut labels = 0;
+    for label in host.split('.') {
+        if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '-') {
+            return false;
+        }
+        labels += 1;
+    }
+    labels >= 2
+}
+
+// Sentence punctuation that may wrap an entity without being part of it
+const ENTITY_WRAPPERS: &[char] = &['.', ',', ';', ':', '!', '?', '(', ')', '[', ']', '<', '>', '"', '\''];
+
+// Replaces everything but letters, digits and whitespace with spaces,
+// except inside tokens that are one of the `preserve` entities. `bidi`
+// cleans by the `bidi_aware` rules instead.
+fn clean_text(text: &str, preserve: &[Entity], bidi: bool, keep_underscores: bool) -> String {
+    let clean = |s: &str| -> String {
+        let cleaned = if bidi {
+            bidi::clean(s, keep_underscores)
+        } else {
+            s.chars()
+                .map(|c| {
+                    let kept = c.is_alphanumeric() || c.is_whitespace() || (keep_underscores && c == '_');
+                    if kept { c } else { ' ' }
+                })
+                .collect()
+        };
+        if keep_underscores { trim_underscores(cleaned) } else { cleaned }
+    };
+    if preserve.is_empty() {
+        return clean(text);
+    }
+
+    let mut cleaned = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (token, tail) = rest.split_at(end);
+        let entity = token.trim_matches(ENTITY_WRAPPERS);
+        if !entity.is_empty() && preserve.iter().any(|kind| kind.matches(entity)) {
+            let start = token.len() - token.trim_start_matches(ENTITY_WRAPPERS).len();
+            cleaned.push_str(&clean(&token[..start]));
+            cleaned.push_str(entity);
+            cleaned.push_str(&clean(&token[start + entity.len()..]));
+        } else {
+            cleaned.push_str(&clean(token));
+        }
+        let spaces = tail.len() - tail.trim_start().len();
+        cleaned.push_str(&tail[..spaces]);
+        rest = &tail[spaces..];
+    }
+    cleaned
+}
+
+// Blanks the underscores at either end of each cleaned word, leaving only
+// those between word characters ("_id_" -> " id "). Byte offsets into the
+// text stay the same.
+fn trim_underscores(cleaned: String) -> String {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (i, c) in cleaned.char_indices().chain([(cleaned.len(), ' ')]) {
+        match (c.is_whitespace(), word_start) {
+            (true, Some(start)) => {
+                words.push(start..i);
+                word_start = None;
+            }
+            (false, None) => word_start = Some(i),
+            _ => {}
+        }
+    }
+    let mut bytes = cleaned.into_bytes();
+    for word in words {
+        let word = &mut bytes[word];
+        let leading = word.iter().take_while(|b| **b == b'_').count();
+        let trailing = word.iter().rev().take_while(|b| **b == b'_').count();
+        let len = word.len();
+        word[..leading].fill(b' ');
+        word[len - trailing..].fill(b' ');
+    }
+    // Only ASCII underscores became ASCII spaces
+    String::from_utf8(bytes).unwrap()
+}
+
+// Splits `token` for `identifier_split` and `split_camel_case`, in one
+// pass over both options' boundaries. Tokens with anything but letters and
+// digits (preserved entities, identifiers joined by underscores) stay whole.
+fn split_identifier<'a>(config: &WordCounterConfig, token: &'a str) -> Vec<&'a str> {
+    if !token.chars().all(char::is_alphanumeric) {
+        return vec![token];
+    }
+    let digits = config.identifier_split == IdentifierSplit::All;
+    split_at(token, |p, c| (config.split_camel_case && camel_case_boundary(p, c)) || (digits && digit_boundary(p, c)))
+}
+
+// Splits `token` between each pair of adjacent chars `boundary` accepts
+fn split_at(token: &str, boundary: impl Fn(char, char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    for (i, c) in token.char_indices() {
+        if prev.is_some_and(|p| boundary(p, c)) {
+            parts.push(&token[start..i]);
+            start = i;
+        }
+        prev = Some(c);
+    }
+    parts.push(&token[start..]);
+    parts
+}
+
+// Where letters meet digits, either way ("v2beta" -> "v", "2", "beta").
+// Digits are ASCII ones, as for `numbers`.
+fn digit_boundary(prev: char, c: char) -> bool {
+    prev.is_ascii_digit() != c.is_ascii_digit()
+}
+
+// Lower→upper ("getUser" → "get", "User") and letter→digit ("ID2" → "ID",
+// "2") boundaries of an identifier-like token.
+fn camel_case_boundary(prev: char, c: char) -> bool {
+    let lower_to_upper = prev.is_lowercase() && c.is_uppercase();
+    let letter_to_digit = prev.is_alphabetic() && c.is_ascii_digit();
+    lower_to_upper || letter_to_digit
+}
+
+fn default_min_word_length() -> usize {
+    1
+}
+
+fn default_empty_route() -> String {
+    "empty".to_string()
+}
+
+// Off, so a host that doesn't know `skip_to` still runs exec and post
+fn default_skip_empty() -> bool {
+    false
+}
+
+fn default_collocation_window() -> usize {
+    2
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_novelty_capacity() -> usize {
+    100_000
+}
+
+// ε = e / 2048 ≈ 0.13% of the tokens, with 98% confidence
+fn default_sketch_width() -> usize {
+    2048
+}
+
+fn default_sketch_depth() -> usize {
+    4
+}
+
+fn default_approximate_top_k() -> usize {
+    100
+}
+
+fn default_novelty_fp_rate() -> f64 {
+    0.01
+}
+
+// KV key of the cross-document word filter
+const SEEN_WORDS_KEY: &str = "word-count/seen-words";
+
+// Words of this document missing from the persisted filter, which is then
+// updated to include them
+fn novel_words(frequencies: &HashMap<String, usize>, config: &WordCounterConfig) -> Vec<String> {
+    let mut seen = host::kv_get(SEEN_WORDS_KEY)
+        .and_then(|bytes| bloom::BloomFilter::from_bytes(&bytes))
+        .unwrap_or_else(|| bloom::BloomFilter::new(config.novelty_capacity, config.novelty_fp_rate));
+    let novel: Vec<String> = frequencies.keys().filter(|word| !seen.contains(word)).cloned().collect();
+    for word in &novel {
+        seen.insert(word);
+    }
+    host::kv_set(SEEN_WORDS_KEY, &seen.to_bytes());
+    novel
+}
+
+const DEFAULT_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
+    "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
+    "to", "was", "will", "with"
+];
+
+fn default_stop_words() -> Vec<String> {
+    DEFAULT_STOP_WORDS.iter().map(|word| word.to_string()).collect()
+}
+
+// The lookup set for the default list, which nearly every config uses
+static DEFAULT_STOP_SET: Singleton<HashSet<String>> = Singleton::new(|| default_stop_words().into_iter().collect());
+
+// Advertised `Permissions.timeout`, in milliseconds
+const PERMISSION_TIMEOUT_MS: u64 = 3000;
+
+// Advertised `Permissions.memory`
+const PERMISSION_MEMORY: &str = "5MB";
+
+// Advertised `Permissions.resources`
+const PERMISSION_RESOURCES: &[&str] = &["stop-words.txt"];
+
+// Reads a host resource, refusing (without asking the host) any name not
+// declared in `Permissions.resources`
+fn read_resource(name: &str) -> Result<Vec<u8>, String> {
+    if !PERMISSION_RESOURCES.contains(&name) {
+        return Err(format!("Resource {:?} is not declared in permissions.resources", name));
     }
-}
\ No newline at end of file
+    host::resource_read(name).ok_or_else(|| format!("Resource {:?} is not available from the host", name))
+}
+
+// `stop_words` (or, with `auto_stop_words`, the preset for a reliably
+// detected `language`) plus those listed one per line in
+// `stop_words_resource`. Blank lines and lines starting with `#` are skipped.
+// The resource is read and parsed once per instance.
+fn effective_stop_words<'a>(
+    config: &'a WordCounterConfig,
+    language: Option<&Detection>,
+) -> Result<Cow<'a, [String]>, String> {
+    let preset = language
+        .filter(|detection| config.auto_stop_words && detection.reliable)
+        .and_then(|detection| language::stop_words(detection.lang.as_deref()?));
+    let base = match preset {
+        Some(preset) => Cow::Owned(preset.iter().map(|word| word.to_string()).collect()),
+        None => Cow::Borrowed(config.stop_words.as_slice()),
+    };
+    let Some(name) = config.stop_words_resource.as_deref() else {
+        return Ok(base);
+    };
+    let listed = state::resource_words(name, || {
+        let contents = read_resource(name)?;
+        let contents = str::from_utf8(&contents).map_err(|_| format!("Resource {:?} is not UTF-8 text", name))?;
+        Ok(contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect())
+    })?;
+    let mut words = base.into_owned();
+    words.extend(listed.iter().cloned());
+    Ok(Cow::Owned(words))
+}
+
+// Parses a memory size such as "512KB", "5MB" or "1GB" into bytes, using
+// the same 1024-based units as the host.
+fn parse_memory_limit(limit: &str) -> Option<usize> {
+    let split = limit.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = limit.split_at(split);
+    let value: usize = value.parse().ok()?;
+    let multiplier: usize = match unit {
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    value.checked_mul(multiplier)
+}
+
+// How many tokens exec processes between clock reads
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+// Self-enforced wall-clock budget for one exec
+struct Deadline<'a> {
+    budget_ms: u64,
+    ttl: clock::Ttl<'a>,
+    ticks: usize,
+    expired: bool,
+}
+
+impl<'a> Deadline<'a> {
+    fn with_clock(request_timeout_ms: Option<u64>, clock: &'a dyn Clock) -> Self {
+        let budget_ms = request_timeout_ms.map_or(PERMISSION_TIMEOUT_MS, |ms| ms.min(PERMISSION_TIMEOUT_MS));
+        Deadline {
+            budget_ms,
+            ttl: clock::Ttl::new(clock, budget_ms.saturating_mul(1_000_000)),
+            ticks: 0,
+            expired: false,
+        }
+    }
+
+    // For in-process callers, which aren't bound by the WASM permission
+    fn unbounded() -> Self {
+        Deadline {
+            budget_ms: u64::MAX,
+            ttl: clock::Ttl::new(&HostClock, u64::MAX),
+            ticks: 0,
+            expired: false,
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.ttl.elapsed_ns() / 1_000_000
+    }
+
+    // Counts one unit of work; returns false once the budget is spent
+    fn tick(&mut self) -> bool {
+        if !self.expired && self.ticks.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+            self.expired = self.ttl.is_expired();
+        }
+        self.ticks += 1;
+        !self.expired
+    }
+
+    // Exec output that post routes to "timeout"
+    fn timeout_response(&self) -> Response {
+        Response {
+            next: Some("timeout".to_string()),
+            ..Response::ok(serde_json::json!({
+                "timed_out": true,
+                "timeout_ms": self.budget_ms,
+            }))
+        }
+    }
+}
+
+// Node definition for the word-count node
+fn word_count_definition() -> NodeDefinition {
+    NodeDefinition {
+        node_type: "word-count".to_string(),
+        category: "text".to_string(),
+        description: "Count words and analyze text statistics".to_string(),
+        config_schema: Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "min_word_length": {
+                    "type": "integer",
+                    "default": 1,
+                    "minimum": 1,
+                    "description": "Minimum word length to count"
+                },
+                "max_word_length": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Maximum word length to count"
+                },
+                "max_tokens": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Stop after this many tokens (counted or filtered) and count only that prefix, reporting truncated"
+                },
+                "run_length": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Filter and count each run of an identical token in bulk, which speeds up highly repetitive text without changing the output"
+                },
+                "allowlist": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "When set, only these words are counted"
+                },
+                "stop_words": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "default": ["a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
+                               "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
+                               "to", "was", "will", "with"],
+                    "description": "Words to exclude from counting"
+                },
+                "stop_words_case_sensitive": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Match stop words case-sensitively (independent of case_sensitive counting)"
+                },
+                "stop_words_resource": {
+                    "type": "string",
+                    "description": "Host resource (declared in permissions.resources) listing more stop words, one per line (lines starting with # are comments)"
+                },
+                "auto_stop_words": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Detect the text's language in prep and use its stop-word preset (eng, spa, fra, deu, por, ita) instead of stop_words; unreliable detections and languages without a preset keep stop_words. Output reports it as language"
+                },
+                "stem_stop_words": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "With stem set, also exclude words whose stem is the stem of a stop word"
+                },
+                "normalization": {
+                    "type": "string",
+                    "enum": ["none", "nfc", "nfkc"],
+                    "default": "none",
+                    "description": "Unicode normalization form applied before cleaning"
+                },
+                "numbers": {
+                    "type": "string",
+                    "enum": ["keep", "drop", "bucket"],
+                    "default": "keep",
+                    "description": "Keep tokens of ASCII digits, drop them, or count them all as <NUM>"
+                },
+                "preserve_entities": {
+                    "type": "array",
+                    "items": {"type": "string", "enum": ["url", "email"]},
+                    "default": [],
+                    "description": "Keep URLs and/or email addresses as single tokens instead of splitting on punctuation"
+                },
+                "ignore_between": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "start": {"type": "string", "minLength": 1},
+                            "end": {"type": "string", "minLength": 1}
+                        },
+                        "required": ["start", "end"]
+                    },
+                    "default": [],
+                    "description": "Drop text between each start/end marker pair (markers included) before counting; an unclosed marker ignores the rest of the text"
+                },
+                "only_within": {
+                    "type": "string",
+                    "description": "Regex; count only the text it matches (its first capture group, if it has one), left-to-right non-overlapping matches joined together"
+                },
+                "count_lines": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Have prep report line_count and paragraph_count of the original text"
+                },
+                "include_char_stats": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Have prep report char_stats (letters, digits, punctuation, whitespace, total) of the original text"
+                },
+                "include_sentence_breakdown": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report each sentence with its counted words as sentences"
+                },
+                "include_length_histogram": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report length_histogram, the number of counted words of each length in characters"
+                },
+                "include_first_seen": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report first_seen, the 0-based position of each word's first occurrence among the counted tokens"
+                },
+                "delta": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Return only the word_frequencies changes since the previous exec on this instance, as delta"
+                },
+                "lexical_diversity": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report lexical_diversity (ttr, root_ttr, mtld) of the counted tokens"
+                },
+                "include_zipf": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report zipf, the log-log slope and R² of word frequency against rank (null with a warning under 2 distinct frequencies)"
+                },
+                "normalize_frequencies": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report word_probabilities, each word's count divided by the total of the reported counts (after min_frequency), so they sum to 1"
+                },
+                "split_camel_case": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Split tokens on lower-to-upper and letter-to-digit boundaries"
+                },
+                "identifier_split": {
+                    "type": "string",
+                    "enum": ["none", "snake", "all"],
+                    "default": "snake",
+                    "description": "none keeps identifiers joined by underscores whole (user_id_2), snake splits them at underscores, and all also splits where letters meet digits; composes with split_camel_case"
+                },
+                "expand_contractions": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Expand English contractions before counting (don't -> do not); 's reads as is, 'd as would"
+                },
+                "strip_possessive": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Drop the 's of English possessives before cleaning (dog's -> dog), after expand_contractions"
+                },
+                "normalize_plurals": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Count simple English plurals as their singular by suffix (dogs -> dog, cities -> city, boxes -> box), leaving words under 4 characters and -ss/-us/-is endings alone"
+                },
+                "strip_emoji": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Remove emoji and pictographic symbols before counting; prep reports how many as emoji_count"
+                },
+                "bidi_aware": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Handle right-to-left scripts: keep combining marks in words, drop directional formatting characters, split words where the text direction changes, and measure word lengths in graphemes instead of bytes"
+                },
+                "tokenizer": {
+                    "type": "string",
+                    "enum": ["builtin", "host"],
+                    "default": "builtin",
+                    "description": "Split text with the built-in cleaning and whitespace splitting, or with the host_tokenize import, whose tokens are counted as they are (not with include_sentence_breakdown)"
+                },
+                "stem": {
+                    "type": "string",
+                    "enum": ["ar", "da", "nl", "en", "fi", "fr", "de", "el", "hu", "it", "no", "pt", "ro",
+                             "ru", "es", "sv", "ta", "tr"],
+                    "description": "Count words by their Snowball stem in this language; canonical_forms then shows the most frequent spelling per stem"
+                },
+                "normalization_pipeline": {
+                    "type": "array",
+                    "items": {"type": "string", "enum": ["lowercase", "fold_diacritics", "stem"]},
+                    "description": "Transforms applied in this order to make each frequency-map key; replaces the implicit lowercasing and stemming (stem uses the stem language)"
+                },
+                "collocations": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report word pairs that co-occur more often than chance"
+                },
+                "collocation_window": {
+                    "type": "integer",
+                    "default": 2,
+                    "minimum": 1,
+                    "description": "How many following tokens count as co-occurring"
+                },
+                "detect_mixed_script": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Flag tokens mixing scripts (e.g. Latin with Cyrillic lookalikes)"
+                },
+                "keep_classes": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["noun", "proper_noun", "verb", "adjective", "adverb", "other", "unknown"]
+                    },
+                    "default": [],
+                    "description": "Only count tokens of these part-of-speech classes (empty keeps all)"
+                },
+                "weights": {
+                    "type": "object",
+                    "additionalProperties": {"type": "number"},
+                    "description": "Per-word multipliers for weighted_score, keyed by the counted (case-folded) form"
+                },
+                "default_weight": {
+                    "type": "number",
+                    "default": 1.0,
+                    "description": "Weight of words not listed in weights"
+                },
+                "canonical_forms": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report the most frequent original spelling of each counted word"
+                },
+                "novelty": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report words not seen in earlier documents (tracked in the host KV store)"
+                },
+                "novelty_capacity": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": bloom::MAX_CAPACITY,
+                    "default": 100000,
+                    "description": "Distinct words the novelty filter is sized for"
+                },
+                "novelty_fp_rate": {
+                    "type": "number",
+                    "exclusiveMinimum": 0,
+                    "maximum": 0.5,
+                    "default": 0.01,
+                    "description": "Chance that a new word is mistaken for a seen one"
+                },
+                "min_frequency": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Omit words counted fewer times than this from word_frequencies, suspicious_tokens and novel_words (total_words still counts them)"
+                },
+                "approximate": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Count in a count-min sketch with bounded memory; word_frequencies then holds the approximate_top_k most frequent words with estimated (never under-) counts, unique_words their number, and sketch the error guarantee (not with options needing the token sequence, such as collocations)"
+                },
+                "sketch_width": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": sketch::MAX_WIDTH,
+                    "default": 2048,
+                    "description": "Counters per sketch row; estimates overcount by at most e / sketch_width of the tokens"
+                },
+                "sketch_depth": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": sketch::MAX_DEPTH,
+                    "default": 4,
+                    "description": "Sketch rows; the bound holds with probability 1 - e^-sketch_depth"
+                },
+                "approximate_top_k": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "default": 100,
+                    "description": "Words with the highest estimates reported when approximate is set"
+                },
+                "min_doc_freq": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "corpus node: drop terms appearing in fewer than this many documents"
+                },
+                "max_doc_freq": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "corpus node: drop terms appearing in more than this many documents"
+                },
+                "collation": {
+                    "type": "string",
+                    "description": "BCP-47 locale used to order word lists and break ties (byte order when unset)"
+                },
+                "sample_size": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Return a uniform random sample of this many counted tokens (seeded by the host)"
+                },
+                "output_format": {
+                    "type": "string",
+                    "enum": ["map", "columns", "ndjson"],
+                    "default": "map",
+                    "description": "Emit word_frequencies as an object map, as aligned words/counts arrays, or as one NDJSON line per entry through the emit import (then a summary line), leaving the response's map empty"
+                },
+                "max_word_output_len": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Truncate longest_word/shortest_word to this many characters, marked with a trailing ellipsis"
+                },
+                "float_precision": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": precision::MAX_DIGITS,
+                    "description": "Round every float in the output (averages, scores, probabilities, diversity measures) to this many decimal places"
+                },
+                "output_schema_version": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 2,
+                    "description": "Output schema version to emit (defaults to the latest)"
+                },
+                "backpressure_after_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "When an exec takes longer than this, its response asks the host to wait meta.retry_after_ms before the next one"
+                },
+                "memoize": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Return the stored output when the same config and input run again (not with delta, novelty, sample_size, stop_words_resource or ndjson output)"
+                },
+                "memo_ttl_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Memoized results older than this are counted again; no expiry when unset"
+                },
+                "output_transform": {
+                    "type": "object",
+                    "properties": {
+                        "select": {"type": "array", "items": {"type": "string"}},
+                        "rename": {"type": "object", "additionalProperties": {"type": "string"}},
+                        "wrap": {"type": "string"}
+                    },
+                    "description": "Reshape post's output: keep the select paths, move each rename key to its value, then nest everything under wrap (paths are dotted, e.g. lexical_diversity.ttr)"
+                },
+                "empty_route": {
+                    "type": "string",
+                    "default": "empty",
+                    "description": "Route post takes when exec counted no words"
+                },
+                "approx_routes": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Route results cut short by max_tokens, counted approximately (approximate) or with saturated counts (overflow) to short-approx, medium-approx or long-approx instead of the exact size routes"
+                },
+                "route_when": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "field": {"type": "string"},
+                            "op": {"type": "string", "enum": ["eq", "ne", "gt", "gte", "lt", "lte"]},
+                            "value": {},
+                            "route": {"type": "string"}
+                        },
+                        "required": ["field", "op", "value", "route"]
+                    },
+                    "description": "Rules tried in order before the word-count routes: post takes the route of the first whose exec result field (a dotted path) compares true against value"
+                },
+                "skip_empty": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "When prep finds no words in the text, its response sets skip_to to empty_route so the host can skip exec and post"
+                },
+                "error_route": {
+                    "type": "string",
+                    "description": "Route a failed prep, exec or post names as next, kept distinct from empty_route (a malformed config can't name one)"
+                },
+                "window": {
+                    "type": "object",
+                    "properties": {
+                        "type": {"type": "string", "enum": ["tumbling", "sliding"]},
+                        "size": {"type": "integer", "minimum": 1},
+                        "slide": {"type": "integer", "minimum": 1},
+                        "by": {"type": "string", "enum": ["records", "ms"], "default": "records"}
+                    },
+                    "required": ["type", "size"],
+                    "description": "Windows the record function counts a stream in: size records (or ms of timestamp_ms) wide, starting every slide for sliding windows; each closed window's output is emitted to the host"
+                }
+            }
+        })),
+        // Object keywords don't apply to the bare-string form, nor
+        // minLength to the object
+        input_schema: Some(serde_json::json!({
+            "type": ["object", "string"],
+            "minLength": 1,
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "Text to analyze"
+                },
+                "case_sensitive": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Whether to treat words case-sensitively"
+                },
+                "profile": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Attach per-phase timings to the response under _profile"
+                },
+                "explain": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Report why each unique token was counted or filtered"
+                }
+            },
+            "required": ["text"],
+            "description": "An input object, or just the text to analyze with the defaults for everything else"
+        })),
+        output_schema: Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "schema_version": {"type": "integer"},
+                "total_words": {"type": "integer"},
+                "unique_words": {"type": "integer"},
+                "word_frequencies": {
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "additionalProperties": {"type": "integer"}
+                        },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "words": {"type": "array", "items": {"type": "string"}},
+                                "counts": {"type": "array", "items": {"type": "integer"}}
+                            },
+                            "required": ["words", "counts"]
+                        }
+                    ]
+                },
+                "average_word_length": {"type": "number"},
+                "longest_word": {"type": "string"},
+                "shortest_word": {"type": "string"},
+                "collocations": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "pair": {"type": "array", "items": {"type": "string"}, "minItems": 2, "maxItems": 2},
+                            "count": {"type": "integer"},
+                            "score": {"type": "number"}
+                        }
+                    }
+                },
+                "suspicious_tokens": {
+                    "type": "array",
+                    "items": {"type": "string"}
+                },
+                "explanations": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "token": {"type": "string"},
+                            "kept": {"type": "boolean"},
+                            "reason": {
+                                "type": "string",
+                                "enum": ["kept", "excluded_class", "too_short", "too_long",
+                                         "number", "not_in_allowlist", "stop_word"]
+                            }
+                        }
+                    }
+                },
+                "weighted_score": {"type": "number"},
+                "canonical_forms": {
+                    "type": "object",
+                    "additionalProperties": {"type": "string"}
+                },
+                "sample": {
+                    "type": "array",
+                    "items": {"type": "string"}
+                },
+                "novel_words": {
+                    "type": "array",
+                    "items": {"type": "string"}
+                },
+                "sentences": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "text": {"type": "string"},
+                            "word_count": {"type": "integer"}
+                        }
+                    }
+                },
+                "length_histogram": {
+                    "type": "object",
+                    "additionalProperties": {"type": "integer"}
+                },
+                "first_seen": {
+                    "type": "object",
+                    "additionalProperties": {"type": "integer"}
+                },
+                "lexical_diversity": lexical_diversity_schema(),
+                "sketch": {
+                    "type": "object",
+                    "properties": {
+                        "width": {"type": "integer"},
+                        "depth": {"type": "integer"},
+                        "epsilon": {"type": "number"},
+                        "confidence": {"type": "number"},
+                        "max_overestimate": {"type": "number"}
+                    },
+                    "required": ["width", "depth", "epsilon", "confidence", "max_overestimate"]
+                },
+                "zipf": {
+                    "type": "object",
+                    "properties": {
+                        "slope": {"type": ["number", "null"]},
+                        "r_squared": {"type": ["number", "null"]},
+                        "warning": {"type": "string"}
+                    },
+                    "required": ["slope", "r_squared"]
+                },
+                "word_probabilities": {
+                    "type": "object",
+                    "additionalProperties": {"type": "number"}
+                },
+                "language": language::schema(),
+                "delta": {
+                    "type": "object",
+                    "properties": {
+                        "reset": {"type": "boolean"},
+                        "added": {"type": "object", "additionalProperties": {"type": "integer"}},
+                        "updated": {"type": "object", "additionalProperties": {"type": "integer"}},
+                        "removed": {"type": "array", "items": {"type": "string"}}
+                    },
+                    "required": ["reset", "added", "updated", "removed"]
+                },
+                "overflow": {"type": "boolean"},
+                "truncated": {"type": "boolean"}
+            },
+            "required": ["schema_version", "total_words", "unique_words", "word_frequencies", 
+                       "average_word_length", "longest_word", "shortest_word"]
+        })),
+        examples: vec![NodeExample {
+            name: "Basic text analysis".to_string(),
+            config: None,
+            input: serde_json::json!({
+                "text": "The quick brown fox jumps over the lazy dog",
+                "case_sensitive": false,
+            }),
+            output: serde_json::json!({
+                "schema_version": 2,
+                "total_words": 7,
+                "unique_words": 7,
+                "word_frequencies": {
+                    "quick": 1, "brown": 1, "fox": 1, "jumps": 1, "over": 1, "lazy": 1, "dog": 1,
+                },
+                "average_word_length": 29.0 / 7.0,
+                "longest_word": "brown",
+                "shortest_word": "dog",
+            }),
+        }],
+        binary_input: false,
+    }
+}
+
+// Memory management functions
+/// Returns null once outstanding allocations would exceed the memory
+/// permission; exports given a null input report why.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub extern "C" fn alloc(size: usize) -> *mut u8 {
+    if !memory::reserve(size) {
+        return std::ptr::null_mut();
+    }
+    // The one deliberate leak: the host owns this buffer until `dealloc`.
+    // Everything else allocated during a call is an owned value, freed on
+    // every return path.
+    let mut buf = Vec::with_capacity(size);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Opt-in alternative to `alloc` for short-lived buffers of up to 512
+/// bytes, served from a per-thread arena without touching the heap. A
+/// scratch buffer is only valid until the next `call`, `call_owned` or
+/// `call_bytes` starts on the thread, other than as that call's own
+/// buffers: allocate a call's input right before making it. Scratch
+/// buffers aren't counted against the memory permission and needn't be
+/// passed to `dealloc` (which ignores them). Returns null when the arena
+/// can't serve `size`; use `alloc` then.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub extern "C" fn alloc_scratch(size: usize) -> *mut u8 {
+    arena::alloc(size).unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `ptr` must be null, a scratch buffer from `alloc_scratch`, or have been
+/// returned by `alloc` with the same `size`, or by `last_output_ptr` with
+/// `size` the length `call_owned` returned.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, size: usize) {
+    // Scratch bytes are reclaimed when the next call starts
+    if ptr.is_null() || arena::contains(ptr) {
+        return;
+    }
+    let _ = Vec::from_raw_parts(ptr, size, size);
+    memory::release(size);
+}
+
+// Plugin metadata export
+/// # Safety
+///
+/// `ptr` must be valid for writes of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn metadata(ptr: *mut u8, len: usize) -> usize {
+    abi::guard(|| metadata_with(session::codec(), ptr, len))
+}
+
+// `metadata` in `codec`'s encoding
+unsafe fn metadata_with(codec: &dyn wire::WireCodec, ptr: *mut u8, len: usize) -> usize {
+    wire::serialize_to_guest(&plugin_metadata(), ptr, len, wire::WireOpts::with_codec(codec))
+}
+
+// What `get_metadata` answers, registered nodes included
+pub fn plugin_metadata() -> Metadata {
+    Metadata {
+        name: "word-counter".to_string(),
+        version: "1.0.0".to_string(),
+        description: "Word counting and analysis plugin for Pocket".to_string(),
+        author: "Pocket Team".to_string(),
+        license: "MIT".to_string(),
+        runtime: "wasm".to_string(),
+        binary: "plugin.wasm".to_string(),
+        nodes: registry::nodes().iter().map(|node| (node.definition)()).collect(),
+        permissions: Permissions {
+            memory: PERMISSION_MEMORY.to_string(),
+            timeout: timeout::Timeout::from_millis(PERMISSION_TIMEOUT_MS).unwrap(),
+            resources: PERMISSION_RESOURCES.iter().map(|name| name.to_string()).collect(),
+        },
+        requirements: Requirements {
+            pocket: ">=1.0.0".to_string(),
+        },
+        capabilities: Capabilities {
+            thread_safe: true,
+            envelope_version: envelope::CURRENT,
+            encodings: wire::Encoding::ALL.to_vec(),
+        },
+        required_imports: host::imports(true).map(str::to_string).collect(),
+        optional_imports: host::imports(false).map(str::to_string).collect(),
+    }
+}
+
+// Shared by word-count's `lexical_diversity` field and the
+// lexical-diversity node's output
+fn lexical_diversity_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "ttr": {"type": "number"},
+            "root_ttr": {"type": "number"},
+            "mtld": {"type": "number"}
+        },
+        "required": ["ttr", "root_ttr"]
+    })
+}
+
+// Node definition for the lexical-diversity node. It tokenizes exactly like
+// word-count, so it takes the same config and input.
+fn lexical_diversity_definition() -> NodeDefinition {
+    let word_count = word_count_definition();
+    let mut output_schema = lexical_diversity_schema();
+    output_schema["properties"]["total_words"] = serde_json::json!({"type": "integer"});
+    output_schema["properties"]["unique_words"] = serde_json::json!({"type": "integer"});
+    output_schema["required"] = serde_json::json!(["total_words", "unique_words", "ttr", "root_ttr"]);
+    NodeDefinition {
+        node_type: "lexical-diversity".to_string(),
+        category: "text".to_string(),
+        description: "Measure vocabulary richness (TTR, root TTR, MTLD)".to_string(),
+        config_schema: word_count.config_schema,
+        input_schema: word_count.input_schema,
+        output_schema: Some(output_schema),
+        examples: Vec::new(),
+        binary_input: false,
+    }
+}
+
+// Node definition for the corpus node: word-count's config and tokenizing
+// over an array of documents, each in any form word-count's input takes
+fn corpus_definition() -> NodeDefinition {
+    let word_count = word_count_definition();
+    let document = word_count.input_schema.clone().unwrap_or_default();
+    let counts = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "total_words": {"type": "integer"},
+            "unique_words": {"type": "integer"},
+            "word_frequencies": {"type": "object", "additionalProperties": {"type": "integer"}}
+        },
+        "required": ["total_words", "unique_words", "word_frequencies"]
+    });
+    let mut output_schema = counts.clone();
+    output_schema["properties"]["document_count"] = serde_json::json!({"type": "integer"});
+    output_schema["properties"]["document_frequencies"] =
+        serde_json::json!({"type": "object", "additionalProperties": {"type": "integer"}});
+    output_schema["properties"]["documents"] = serde_json::json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {
+                "ok": {"type": "boolean"},
+                "output": counts,
+                "error": {"type": "string"}
+            },
+            "required": ["ok"]
+        }
+    });
+    output_schema["required"] = serde_json::json!([
+        "document_count", "total_words", "unique_words", "word_frequencies", "document_frequencies", "documents"
+    ]);
+    NodeDefinition {
+        node_type: "corpus".to_string(),
+        category: "text".to_string(),
+        description: "Count words across documents, limiting the vocabulary by document frequency".to_string(),
+        config_schema: word_count.config_schema,
+        input_schema: Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "documents": {"type": "array", "items": document}
+            },
+            "required": ["documents"]
+        })),
+        output_schema: Some(output_schema),
+        examples: Vec::new(),
+        binary_input: false,
+    }
+}
+
+// Self-test: runs every node's declared examples through prep (if the node
+// has one) and exec, and reports those whose output no longer matches
+#[derive(Serialize)]
+struct ExampleReport {
+    passed: usize,
+    failed: Vec<ExampleFailure>,
+}
+
+#[derive(Serialize)]
+struct ExampleFailure {
+    node: String,
+    example: String,
+    expected: serde_json::Value,
+    // Exec output, or the error that stopped the example
+    actual: Result<serde_json::Value, String>,
+}
+
+fn run_example(node: &registry::NodeEntry, example: &NodeExample) -> Result<serde_json::Value, String> {
+    let run = |function: &str, input: serde_json::Value| -> Result<serde_json::Value, String> {
+        let handler = node.handler(function).ok_or_else(|| format!("Unknown function: {}", function))?;
+        let response = handler(&Request {
+            node: node.node_type.to_string(),
+            function: function.to_string(),
+            config: example.config.clone(),
+            input: Some(input),
+            ..Request::default()
+        });
+        if response.success {
+            Ok(response.output.unwrap_or_default())
+        } else {
+            Err(response.error.unwrap_or_default())
+        }
+    };
+    let exec_input = if node.supports("prep") {
+        run("prep", example.input.clone())?
+    } else {
+        example.input.clone()
+    };
+    run("exec", exec_input)
+}
+
+fn run_all_examples() -> ExampleReport {
+    let mut report = ExampleReport { passed: 0, failed: Vec::new() };
+    for node in registry::nodes() {
+        for example in (node.definition)().examples {
+            let actual = run_example(node, &example);
+            if actual.as_ref() == Ok(&example.output) {
+                report.passed += 1;
+            } else {
+                report.failed.push(ExampleFailure {
+                    node: node.node_type.to_string(),
+                    example: example.name,
+                    expected: example.output,
+                    actual,
+                });
+            }
+        }
+    }
+    report
+}
+
+/// # Safety
+///
+/// `out_ptr` must be valid for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn run_examples(out_ptr: *mut u8, out_len: usize) -> usize {
+    abi::guard(|| {
+        wire::serialize_to_guest(&run_all_examples(), out_ptr, out_len, wire::WireOpts::default())
+    })
+}
+
+// Every node's schemas in one JSON Schema document, keyed by node type, for
+// documentation and client code generation
+/// # Safety
+///
+/// `out_ptr` must be valid for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn schema_bundle(out_ptr: *mut u8, out_len: usize) -> usize {
+    abi::guard(|| {
+        let nodes: serde_json::Map<String, serde_json::Value> = registry::nodes()
+            .iter()
+            .map(|node| {
+                let definition = (node.definition)();
+                let schemas = serde_json::json!({
+                    "description": definition.description,
+                    "config": definition.config_schema,
+                    "input": definition.input_schema,
+                    "output": definition.output_schema,
+                });
+                (definition.node_type, schemas)
+            })
+            .collect();
+        let bundle = serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "plugin": "word-counter",
+            "version": "1.0.0",
+            "nodes": nodes,
+        });
+
+        wire::serialize_to_guest(&bundle, out_ptr, out_len, wire::WireOpts::default())
+    })
+}
+
+// Main call function
+/// Returns an `AbiError` code and the encoded response length, packed as
+/// `abi` describes; `BufferTooSmall` means nothing was written and the host
+/// should retry with a buffer of that length.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes and `out_ptr` must be valid
+/// for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn call(ptr: *const u8, len: usize, out_ptr: *mut u8, out_len: usize) -> usize {
+    arena::reset([(ptr, len), (out_ptr, out_len)]);
+    abi::guard(|| {
+        let (response, opts) = call_response(session::codec(), ptr, len);
+        wire::serialize_to_guest(&response, out_ptr, out_len, opts)
+    })
+}
+
+/// Like `call`, but the plugin allocates the output buffer, so one call
+/// always suffices. Returns a code and the response length, as `call`
+/// does (never `BufferTooSmall`); the response itself is at
+/// `last_output_ptr()`. The buffer then belongs to the host, which must
+/// free it with `dealloc(last_output_ptr(), length)` once read.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn call_owned(ptr: *const u8, len: usize) -> usize {
+    arena::reset([(ptr, len)]);
+    abi::guard(|| {
+        let (response, opts) = call_response(session::codec(), ptr, len);
+        wire::serialize_owned(&response, opts)
+    })
+}
+
+/// The buffer holding the response of the latest `call_owned` on this
+/// thread, or null before the first.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub extern "C" fn last_output_ptr() -> *mut u8 {
+    wire::last_output()
+}
+
+// The response to a `call` request in `codec`'s encoding, and how to
+// encode the response
+unsafe fn call_response(codec: &dyn wire::WireCodec, ptr: *const u8, len: usize) -> (Response, wire::WireOpts) {
+    let fallback = wire::WireOpts::with_codec(codec);
+    // Liveness checks are answered without parsing the request
+    if codec.encoding() == wire::Encoding::Json && !ptr.is_null() && ping::is_ping(std::slice::from_raw_parts(ptr, len)) {
+        return (pong(), fallback);
+    }
+    match parse_request(codec, ptr, len) {
+        // One the scan missed, e.g. with an escaped key
+        Ok(request) if request.function == ping::FUNCTION => (pong(), fallback),
+        Ok(request) => {
+            let response = with_bytes_processed(dispatch(registry::nodes(), &request), len, request.trace_id.as_deref());
+            (response, request.wire.or_codec(codec))
+        }
+        Err(e) => (with_bytes_processed(Response::error(e), len, None), fallback),
+    }
+}
+
+// Reports the input bytes a call was handed, for the host's cost
+// accounting: added to its `bytes_processed` counter, labelled with the
+// request's trace id if it parsed, and set as `meta.bytes_processed`. Only once the host opts in with `metrics` in
+// `init_with`, so older hosts see the responses they always did. Liveness
+// checks aren't counted.
+fn with_bytes_processed(mut response: Response, bytes: usize, trace_id: Option<&str>) -> Response {
+    if !session::metrics() {
+        return response;
+    }
+    host::metric("bytes_processed", bytes as u64, trace_id);
+    let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+    meta["bytes_processed"] = bytes.into();
+    response
+}
+
+fn pong() -> Response {
+    Response::ok(serde_json::json!({"pong": true}))
+}
+
+/// Like `call`, for nodes with `binaryInput`: the request at `ptr` carries
+/// the node, function and config in the negotiated encoding, and the
+/// `data_len` bytes at `data_ptr` are the input, handed to the node as they
+/// are (they needn't be UTF-8). Same buffer-size protocol as `call`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes, `data_ptr` for reads of
+/// `data_len` bytes, and `out_ptr` for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn call_bytes(
+    ptr: *const u8,
+    len: usize,
+    data_ptr: *const u8,
+    data_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> usize {
+    arena::reset([(ptr, len), (data_ptr, data_len), (out_ptr, out_len)]);
+    abi::guard(|| {
+        let codec = session::codec();
+        let request = match parse_request(codec, ptr, len) {
+            Ok(request) => request,
+            Err(e) => {
+                let error_response = with_bytes_processed(Response::error(e), len, None);
+                return wire::serialize_to_guest(&error_response, out_ptr, out_len, wire::WireOpts::with_codec(codec));
+            }
+        };
+        let response = match memory::input(data_ptr, data_len) {
+            Ok(data) => with_bytes_processed(
+                dispatch_bytes(registry::nodes(), &request, data),
+                len + data_len,
+                request.trace_id.as_deref(),
+            ),
+            Err(e) => with_bytes_processed(Response::error(e), len, request.trace_id.as_deref()),
+        };
+        wire::serialize_to_guest(&response, out_ptr, out_len, request.wire.or_codec(codec))
+    })
+}
+
+// Decodes a `Request` in `codec`'s encoding
+unsafe fn parse_request(codec: &dyn wire::WireCodec, ptr: *const u8, len: usize) -> Result<Request, String> {
+    let input = memory::input(ptr, len)?;
+    codec.decode(input).map_err(|e| format!("Failed to parse request: {}", e))
+}
+
+// Runs the handler registered for the request's node and function
+fn dispatch(registry: &registry::NodeRegistry, request: &Request) -> Response {
+    let handler = match registry::resolve(registry, &request.node, &request.function) {
+        Ok(handler) => handler,
+        Err(e) => return with_dispatch_context(Response::failure(e.code(), e.message()), request),
+    };
+
+    let profile = Profile::from_input(request.input.as_ref());
+    let started = profile.map(|_| HostClock.now_ns());
+    let mut response = handler(request);
+
+    if let (Some(mut profile), Some(started)) = (profile, started) {
+        profile.record(&request.function, HostClock.now_ns().saturating_sub(started));
+        profile.attach(&mut response);
+    }
+    if request.echo_config {
+        echo_applied_config(&mut response, request);
+    }
+    round_output(&mut response, request);
+    with_dispatch_context(response, request)
+}
+
+// `float_precision`, applied to every node's output here rather than by
+// each handler. Read straight from the config the handler resolved, and
+// checked, if it takes one; places past `MAX_DIGITS` change nothing.
+fn round_output(response: &mut Response, request: &Request) {
+    let Some(output) = response.output.as_mut() else {
+        return;
+    };
+    let digits = match effective_config(request) {
+        Ok(Some(config)) => config.get("float_precision").and_then(serde_json::Value::as_u64),
+        _ => None,
+    };
+    if let Some(digits) = digits {
+        precision::round_floats(output, u32::try_from(digits).unwrap_or(u32::MAX));
+    }
+}
+
+// `meta.applied_config`: the config the call ran with, every option spelled
+// out, from the request's config over the host's defaults over the built-in
+// ones. Every JSON node takes word-count's config. Left out when the config
+// doesn't parse, which the response reports anyway.
+fn echo_applied_config(response: &mut Response, request: &Request) {
+    if let Ok(config) = parse_config(request) {
+        let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+        meta["applied_config"] = serde_json::to_value(config).unwrap();
+    }
+}
+
+// Names the node and function a failure came from in its `meta`. A failure
+// already naming them came from a nested `invoke`, the more precise place.
+fn with_dispatch_context(mut response: Response, request: &Request) -> Response {
+    if !response.success {
+        let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+        if let Some(meta) = meta.as_object_mut().filter(|meta| !meta.contains_key("node")) {
+            meta.insert("node".to_string(), request.node.clone().into());
+            meta.insert("function".to_string(), request.function.clone().into());
+        }
+    }
+    response
+}
+
+// `dispatch` for binary nodes' functions
+fn dispatch_bytes(registry: &registry::NodeRegistry, request: &Request, data: &[u8]) -> Response {
+    let response = match registry::resolve_bytes(registry, &request.node, &request.function) {
+        Ok(handler) => handler(request, data),
+        Err(e) => Response::failure(e.code(), e.message()),
+    };
+    with_dispatch_context(response, request)
+}
+
+// Calls nested deeper than this are refused unless the request sets
+// `max_call_depth`
+const DEFAULT_MAX_CALL_DEPTH: usize = 8;
+
+// Runs another node's function on behalf of `parent`'s handler, within the
+// parent's budget and trace. Refused with `cycle_detected` if the node is
+// already on the call chain (including `parent` itself), and with
+// `depth_exceeded` past the depth limit, so a recursive workflow fails
+// instead of exhausting the stack.
+fn invoke(
+    registry: &registry::NodeRegistry,
+    parent: &Request,
+    node: &str,
+    function: &str,
+    config: Option<serde_json::Value>,
+    input: serde_json::Value,
+) -> Response {
+    let mut call_chain = parent.call_chain.clone();
+    call_chain.push(parent.node.clone());
+    if call_chain.iter().any(|caller| caller == node) {
+        return Response::failure(
+            ErrorCode::CycleDetected,
+            format!("Invoking {} would re-enter the call chain {}", node, call_chain.join(" -> ")),
+        );
+    }
+    let max_depth = parent.max_call_depth.unwrap_or(DEFAULT_MAX_CALL_DEPTH);
+    if call_chain.len() > max_depth {
+        return Response::failure(
+            ErrorCode::DepthExceeded,
+            format!("Invoking {} would nest {} calls deep, past the limit of {}", node, call_chain.len(), max_depth),
+        );
+    }
+    dispatch(registry, &Request {
+        node: node.to_string(),
+        function: function.to_string(),
+        config,
+        input: Some(input),
+        wire: wire::WireOpts::default(),
+        timeout_ms: parent.timeout_ms,
+        trace_id: parent.trace_id.clone(),
+        log_level: parent.log_level,
+        call_chain,
+        max_call_depth: parent.max_call_depth,
+        // The parent's echo covers the call
+        echo_config: false,
+        merged_config: Default::default(),
+    })
+}
+
+// Appends a chunk of input text for a later `feed_finish`. Chunks may split
+// multibyte characters. Returns 1 if the chunk was accepted, 0 if it holds
+// invalid UTF-8 (the stream is unchanged).
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn feed(ptr: *const u8, len: usize) -> u32 {
+    memory::input(ptr, len).and_then(stream::push).is_ok() as u32
+}
+
+// Like `call`, with the text accumulated by `feed` as `input.text`. The
+// stream is reset either way.
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes and `out_ptr` must be valid
+/// for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn feed_finish(ptr: *const u8, len: usize, out_ptr: *mut u8, out_len: usize) -> usize {
+    abi::guard(|| {
+        let text = stream::take();
+        let codec = session::codec();
+        let mut request = match parse_request(codec, ptr, len) {
+            Ok(request) => request,
+            Err(e) => {
+                let error_response = with_bytes_processed(Response::error(e), len, None);
+                return wire::serialize_to_guest(&error_response, out_ptr, out_len, wire::WireOpts::with_codec(codec));
+            }
+        };
+
+        // The chunks `feed` was handed count as this call's input
+        let streamed = text.as_ref().map_or(0, String::len);
+        let response = match text {
+            Ok(text) => {
+                let input = request.input.get_or_insert_with(|| serde_json::json!({}));
+                match input.as_object_mut() {
+                    Some(input) => {
+                        input.insert("text".to_string(), serde_json::Value::String(text));
+                        dispatch(registry::nodes(), &request)
+                    }
+                    None => Response::error("Streamed requests need an object input"),
+                }
+            }
+            Err(e) => Response::error(e),
+        };
+        let response = with_bytes_processed(response, len + streamed, request.trace_id.as_deref());
+        wire::serialize_to_guest(&response, out_ptr, out_len, request.wire.or_codec(codec))
+    })
+}
+
+// Installs a zstd dictionary for responses requested with
+// `wire.dictionary`; an empty buffer removes it. Returns 1 on success, 0 if
+// the dictionary is rejected or compression isn't built in.
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn set_dictionary(ptr: *const u8, len: usize) -> u32 {
+    memory::input(ptr, len).and_then(wire::set_dictionary).is_ok() as u32
+}
+
+/// Handshake a host makes once, before its first `call`: `envelope_version`
+/// is the newest `Response` envelope it understands. Returns the version
+/// every later response uses, the older of that and the plugin's own, or 0
+/// if `envelope_version` is 0 (then nothing changes).
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub extern "C" fn init(envelope_version: u32) -> u32 {
+    envelope::negotiate(envelope_version)
+}
+
+/// Like `init`, with more to negotiate: the JSON `HostOptions` at `ptr`
+/// (see `session`). Returns the envelope version every later response
+/// uses, or 0 (changing nothing) if the options don't parse.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn init_with(ptr: *const u8, len: usize) -> u32 {
+    match memory::input(ptr, len).and_then(|input| (&wire::Json as &dyn wire::WireCodec).decode::<session::HostOptions>(input)) {
+        Ok(options) => session::apply(&options),
+        Err(_) => 0,
+    }
+}
+
+// Memory pressure callback: 0 = low, 1 = moderate, 2+ = critical
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub extern "C" fn on_memory_pressure(level: u32) {
+    PLUGIN.on_memory_pressure(MemoryPressure::from_level(level));
+}
+
+// Paged retrieval of the frequencies computed by the most recent exec
+#[derive(Serialize)]
+struct FrequencyPage {
+    offset: usize,
+    total: usize,
+    entries: Vec<FrequencyEntry>,
+}
+
+#[derive(Serialize)]
+struct FrequencyEntry {
+    word: String,
+    count: usize,
+}
+
+/// Writes a `Response` whose output is a `FrequencyPage` of at most `limit`
+/// entries starting at `offset`, ordered by descending count then word.
+/// Uses the same buffer-size protocol as `call`.
+///
+/// # Safety
+///
+/// `out_ptr` must be valid for writes of `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn frequencies_page(offset: usize, limit: usize, out_ptr: *mut u8, out_len: usize) -> usize {
+    abi::guard(|| {
+        let response = match state::frequencies_page(offset, limit) {
+            Some((entries, total)) => Response::ok(serde_json::to_value(FrequencyPage {
+                offset,
+                total,
+                entries: entries.into_iter().map(|(word, count)| FrequencyEntry { word, count }).collect(),
+            }).unwrap()),
+            None => Response::error("No exec result cached on this instance"),
+        };
+
+        wire::serialize_to_guest(&response, out_ptr, out_len, wire::WireOpts::default())
+    })
+}
+
+// `check_imports`' answer
+#[derive(Serialize)]
+struct ImportCheck {
+    compatible: bool,
+    // Required imports the host doesn't provide
+    missing: Vec<&'static str>,
+    // Optional ones it doesn't, which it must stub
+    missing_optional: Vec<&'static str>,
+    // Why the host's list couldn't be read, in which case nothing counts as
+    // provided
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Cross-checks the host's imports against `requiredImports` and
+/// `optionalImports`: the JSON array of import names at `ptr` is what the
+/// host provides, and the `{"compatible", "missing", "missing_optional"}`
+/// answer is written to `out_ptr` with the same buffer-size protocol as
+/// `call`. Only required imports count against `compatible`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes, and `out_ptr` for writes of
+/// `out_len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn check_imports(ptr: *const u8, len: usize, out_ptr: *mut u8, out_len: usize) -> usize {
+    abi::guard(|| {
+        let (provided, error) = match memory::input(ptr, len).and_then(|input| {
+            serde_json::from_slice::<Vec<String>>(input).map_err(|e| format!("Invalid import list: {}", e))
+        }) {
+            Ok(provided) => (provided, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let missing = host::missing_imports(&provided, true);
+        let missing_optional = host::missing_imports(&provided, false);
+        let check = ImportCheck { compatible: missing.is_empty(), missing, missing_optional, error };
+        wire::serialize_to_guest(&check, out_ptr, out_len, wire::WireOpts::default())
+    })
+}
+
+// Capability query: does a node implement a given function?
+#[derive(Deserialize)]
+struct FunctionQuery {
+    node: String,
+    function: String,
+}
+
+/// Returns 1 if the node named in the `{"node", "function"}` JSON query
+/// implements the function, 0 otherwise (including for malformed queries).
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+#[cfg_attr(not(feature = "native"), no_mangle)]
+pub unsafe extern "C" fn supports_function(ptr: *const u8, len: usize) -> u32 {
+    let query: FunctionQuery = match memory::input(ptr, len).map(serde_json::from_slice) {
+        Ok(Ok(q)) => q,
+        _ => return 0,
+    };
+
+    registry::find(&query.node)
+        .map(|node| node.supports(&query.function))
+        .unwrap_or(false) as u32
+}
+
+// With `error_route`, a failure names it as the next route, even when
+// `check_config` refused the rest of the config. A config too malformed to
+// read can't, so that failure names none.
+fn route_failure(request: &Request, mut response: Response) -> Response {
+    if !response.success && response.next.is_none() {
+        response.next = read_config(request).ok().and_then(|config| config.error_route);
+    }
+    response
+}
+
+fn handle_prep(request: &Request) -> Response {
+    route_failure(request, prep_response(request))
+}
+
+fn prep_response(request: &Request) -> Response {
+    let input = match request.input.as_ref() {
+        Some(i) => match InputView::deserialize(i) {
+            Ok(inp) => inp,
+            Err(e) => return Response::error(format!("Failed to parse input: {}", e)),
+        },
+        None => return Response::error("No input provided"),
+    };
+
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    match prepare(&config, input) {
+        // The prep data is still there for hosts that run exec anyway
+        Ok(prep) if config.skip_empty && prep.cleaned_text.split_whitespace().next().is_none() => Response {
+            skip_to: Some(config.empty_route.clone()),
+            ..Response::ok(serde_json::to_value(prep).unwrap())
+        },
+        Ok(prep) => Response::ok(serde_json::to_value(prep).unwrap()),
+        Err(e) => Response::error(e),
+    }
+}
+
+// Byte order mark some Windows tools put at the start of UTF-8 text
+const BOM: char = '\u{FEFF}';
+
+// Strip a leading BOM, normalize, then clean and prepare text
+fn prepare(config: &WordCounterConfig, input: InputView) -> Result<PrepData, String> {
+    let only_within = only_within_regex(config)?;
+    check_tokenizer(config)?;
+    let text = input.text.strip_prefix(BOM).unwrap_or(&input.text);
+    // Before normalization, which folds some emoji into letters
+    let (counted_source, emoji_count) = if config.strip_emoji {
+        let (stripped, count) = emoji::strip(text);
+        (stripped, Some(count))
+    } else {
+        (Cow::Borrowed(text), None)
+    };
+    let normalized_text = config.normalization.apply(&counted_source);
+    let (line_count, paragraph_count) = if config.count_lines {
+        let (lines, paragraphs) = count_lines(&normalized_text);
+        (Some(lines), Some(paragraphs))
+    } else {
+        (None, None)
+    };
+    let mut warnings = Vec::new();
+    let (counted_text, ignored_chars) = if config.ignore_between.is_empty() {
+        (Cow::Borrowed(normalized_text.as_ref()), None)
+    } else {
+        let (kept, ignored) = strip_between(&normalized_text, &config.ignore_between, &mut warnings);
+        (Cow::Owned(kept), Some(ignored))
+    };
+    let counted_text = match &only_within {
+        Some(pattern) => Cow::Owned(keep_within(&counted_text, pattern, &mut warnings)),
+        None => counted_text,
+    };
+    let counted_text = if config.expand_contractions {
+        Cow::Owned(contractions::expand(&counted_text).into_owned())
+    } else {
+        counted_text
+    };
+    let counted_text = if config.strip_possessive {
+        Cow::Owned(inflection::strip_possessives(&counted_text).into_owned())
+    } else {
+        counted_text
+    };
+    let language = config.auto_stop_words.then(|| language::detect(&counted_text));
+    if config.tokenizer == Tokenizer::Host {
+        let tokens = host_tokens(&counted_text)?;
+        return Ok(PrepData {
+            original_text: text.to_string(),
+            cleaned_text: tokens.join(" "),
+            case_sensitive: input.case_sensitive,
+            explain: input.explain,
+            line_count,
+            paragraph_count,
+            char_stats: config.include_char_stats.then(|| char_stats(text)),
+            ignored_chars,
+            emoji_count,
+            language,
+            tokens: Some(tokens),
+            warnings,
+            ..PrepData::default()
+        });
+    }
+    let keep_underscores = config.identifier_split == IdentifierSplit::None;
+    let (cleaned_text, sentences) = if config.include_sentence_breakdown {
+        // Cleaned piece by piece so each sentence's end is known
+        let mut cleaned = String::with_capacity(counted_text.len());
+        let sentences = split_sentences(&counted_text)
+            .into_iter()
+            .map(|sentence| {
+                cleaned.push_str(&clean_text(sentence, &config.preserve_entities, config.bidi_aware, keep_underscores));
+                SentenceSpan { text: sentence.trim().to_string(), end: cleaned.len() }
+            })
+            .collect();
+        (cleaned, Some(sentences))
+    } else {
+        (clean_text(&counted_text, &config.preserve_entities, config.bidi_aware, keep_underscores), None)
+    };
+    Ok(PrepData {
+        original_text: text.to_string(),
+        cleaned_text,
+        case_sensitive: input.case_sensitive,
+        explain: input.explain,
+        line_count,
+        paragraph_count,
+        char_stats: config.include_char_stats.then(|| char_stats(text)),
+        sentences,
+        ignored_chars,
+        emoji_count,
+        language,
+        tokens: None,
+        warnings,
+    })
+}
+
+// Tokens from the host's tokenizer, without empty ones
+fn host_tokens(text: &str) -> Result<Vec<String>, String> {
+    let tokens = host::tokenize(text).ok_or("The host provides no tokenizer (host_tokenize)")?;
+    let tokens: Vec<String> = serde_json::from_slice(&tokens)
+        .map_err(|e| format!("The host tokenizer didn't return a JSON array of strings: {}", e))?;
+    Ok(tokens.into_iter().filter(|token| !token.is_empty()).collect())
+}
+
+fn check_tokenizer(config: &WordCounterConfig) -> Result<(), String> {
+    if config.tokenizer == Tokenizer::Host && config.include_sentence_breakdown {
+        return Err("include_sentence_breakdown needs the builtin tokenizer".to_string());
+    }
+    Ok(())
+}
+
+fn only_within_regex(config: &WordCounterConfig) -> Result<Option<regex::Regex>, String> {
+    config.only_within.as_deref()
+        .map(|pattern| regex::Regex::new(pattern).map_err(|e| format!("Invalid only_within pattern: {}", e)))
+        .transpose()
+}
+
+// Keeps only the text `pattern` matches, or its first capture group if it
+// has one, with a space between spans. Matches are found left to right and
+// never overlap: one starting inside an earlier match isn't considered.
+// Empty spans add nothing, and if none is left there's nothing to count,
+// with a warning.
+fn keep_within(text: &str, pattern: &regex::Regex, warnings: &mut Vec<String>) -> String {
+    let group = usize::from(pattern.captures_len() > 1);
+    let mut kept = String::new();
+    for captures in pattern.captures_iter(text) {
+        let Some(span) = captures.get(group).filter(|span| !span.is_empty()) else {
+            continue;
+        };
+        if !kept.is_empty() {
+            kept.push(' ');
+        }
+        kept.push_str(span.as_str());
+    }
+    if kept.is_empty() {
+        warnings.push(format!("only_within pattern {:?} matched no text; nothing was counted", pattern.as_str()));
+    }
+    kept
+}
+
+// A start/end marker pair for `ignore_between`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MarkerPair {
+    pub start: String,
+    pub end: String,
+}
+
+// Removes every span from a start marker through its end marker, leaving a
+// space so the words on either side stay apart. Returns the kept text and
+// how many characters were dropped. An unclosed span runs to the end of the
+// text, with a warning.
+fn strip_between(text: &str, markers: &[MarkerPair], warnings: &mut Vec<String>) -> (String, usize) {
+    let markers: Vec<&MarkerPair> = markers.iter().filter(|m| !m.start.is_empty()).collect();
+    let mut kept = String::with_capacity(text.len());
+    let mut ignored = 0;
+    let mut offset = 0;
+    loop {
+        let rest = &text[offset..];
+        // The earliest start marker; the longest one if several start there
+        let next = markers.iter()
+            .filter_map(|m| rest.find(&m.start).map(|i| (i, m)))
+            .min_by(|(i, a), (j, b)| i.cmp(j).then_with(|| b.start.len().cmp(&a.start.len())));
+        let Some((start, marker)) = next else {
+            kept.push_str(rest);
+            return (kept, ignored);
+        };
+        kept.push_str(&rest[..start]);
+        let body = start + marker.start.len();
+        match rest[body..].find(&marker.end) {
+            Some(end) => {
+                let span_end = body + end + marker.end.len();
+                ignored += rest[start..span_end].chars().count();
+                kept.push(' ');
+                offset += span_end;
+            }
+            None => {
+                warnings.push(format!(
+                    "Unclosed {:?} marker at character {}; ignored the rest of the text",
+                    marker.start,
+                    text[..offset + start].chars().count()
+                ));
+                ignored += rest[start..].chars().count();
+                return (kept, ignored);
+            }
+        }
+    }
+}
+
+const SENTENCE_TERMINATORS: &[char] = &['.', '!', '?', '…'];
+// Closing quotes and brackets after a terminator stay with its sentence
+const SENTENCE_CLOSERS: &[char] = &['"', '\'', ')', ']', '”', '’', '»'];
+
+// Splits after a run of terminators (and closers) followed by whitespace.
+// The pieces keep their trailing whitespace, so they concatenate back to
+// `text`.
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !SENTENCE_TERMINATORS.contains(&c) {
+            continue;
+        }
+        while chars.next_if(|(_, c)| SENTENCE_TERMINATORS.contains(c) || SENTENCE_CLOSERS.contains(c)).is_some() {}
+        if chars.peek().is_some_and(|(_, c)| !c.is_whitespace()) {
+            continue;
+        }
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let end = chars.peek().map_or(text.len(), |(i, _)| *i);
+        sentences.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+fn char_stats(text: &str) -> CharStats {
+    let mut stats = CharStats::default();
+    for c in text.chars() {
+        stats.total += 1;
+        if c.is_alphabetic() {
+            stats.letters += 1;
+        } else if c.is_numeric() {
+            stats.digits += 1;
+        } else if c.is_whitespace() {
+            stats.whitespace += 1;
+        } else if !c.is_control() {
+            stats.punctuation += 1;
+        }
+    }
+    stats
+}
+
+// Lines and blank-line-separated paragraphs. `\r\n` counts as one line
+// break and a trailing newline doesn't start another line.
+fn count_lines(text: &str) -> (usize, usize) {
+    let mut lines = 0;
+    let mut paragraphs = 0;
+    let mut in_paragraph = false;
+    // `str::lines` strips a trailing `\r` and ignores a final newline
+    for line in text.lines() {
+        lines += 1;
+        let blank = line.trim().is_empty();
+        if !blank && !in_paragraph {
+            paragraphs += 1;
+        }
+        in_paragraph = !blank;
+    }
+    (lines, paragraphs)
+}
+
+// Optional phase: checks config and input without counting anything.
+// Prep and exec refuse the same configs (`check_config`), but this lists
+// every problem, the input's included.
+fn handle_validate(request: &Request) -> Response {
+    let mut errors = Vec::new();
+
+    let config = effective_config(request).unwrap_or_else(|e| {
+        errors.push(e);
+        None
+    });
+    match config.as_deref().map(WordCounterConfig::deserialize) {
+        Some(Err(e)) => errors.push(format!("Invalid config: {}", e)),
+        Some(Ok(config)) => errors.extend(check_config(&config)),
+        None => {}
+    }
+
+    match request.input.as_ref().map(InputView::deserialize) {
+        Some(Err(e)) => errors.push(format!("Invalid input: {}", e)),
+        Some(Ok(_)) => {}
+        None => errors.push("No input provided".to_string()),
+    }
+
+    Response::ok(serde_json::json!({
+        "valid": errors.is_empty(),
+        "errors": errors,
+    }))
+}
+
+// The parts of prep's output that exec reads. The original text isn't
+// among them, so it isn't copied.
+fn read_prep(prep_data: &serde_json::Value) -> PrepData {
+    PrepData {
+        cleaned_text: prep_data["cleaned_text"].as_str().unwrap_or("").to_string(),
+        case_sensitive: prep_data["case_sensitive"].as_bool().unwrap_or(false),
+        explain: prep_data["explain"].as_bool().unwrap_or(false),
+        sentences: Option::deserialize(&prep_data["sentences"]).unwrap_or_default(),
+        tokens: Option::deserialize(&prep_data["tokens"]).unwrap_or_default(),
+        language: Option::deserialize(&prep_data["language"]).unwrap_or_default(),
+        warnings: Vec::deserialize(&prep_data["warnings"]).unwrap_or_default(),
+        ..PrepData::default()
+    }
+}
+
+fn handle_exec(request: &Request) -> Response {
+    route_failure(request, exec_with(request, &UnknownClassifier, &HostClock))
+}
+
+fn exec_with(request: &Request, classifier: &dyn TokenClassifier, clock: &dyn Clock) -> Response {
+    let prep = match request.input.as_ref() {
+        Some(d) => read_prep(d),
+        None => return Response::error("No prep data provided"),
+    };
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+
+    let context = Context::for_request(request);
+    let memo_key = memo_key(request, &config);
+    let mut deadline = Deadline::with_clock(request.timeout_ms, clock);
+    // A memoized output is the counted one, before `exec_response`, so a hit
+    // goes through the rest just like a count
+    let memoized = memo_key.as_ref()
+        .and_then(|key| memo::get(key, config.memo_ttl_ms, clock))
+        .and_then(|output| WordCounterOutput::deserialize(output).ok());
+    let hit = memoized.is_some();
+    let counted = match memoized {
+        Some(output) => {
+            pocket_log!(context, Level::Debug, "returning memoized exec output");
+            Ok(output)
+        }
+        None => count_words(&config, &prep, classifier, &mut deadline),
+    };
+    let mut response = match counted {
+        Ok(mut output) => {
+            if !hit {
+                pocket_log!(context, Level::Debug, "counted {} words ({} unique)", output.total_words, output.unique_words);
+                if let Some(key) = memo_key.clone() {
+                    memo::insert(key, clock, serde_json::to_value(&output).unwrap());
+                }
+            }
+            // Not for nodes counting through an `invoke`d exec
+            if request.call_chain.is_empty() {
+                cache_frequencies(&mut output, &config);
+            }
+            let mut response = exec_response(output, &config);
+            if memo_key.is_some() && response.success {
+                annotate_memo(&mut response, hit);
+            }
+            response
+        }
+        Err(ExecError::TimedOut) => {
+            pocket_log!(context, Level::Warn, "exec stopped at its deadline");
+            timeout_exec_response(&deadline, &config)
+        }
+        Err(ExecError::Failed(e)) => {
+            pocket_log!(context, Level::Error, "exec failed: {}", e);
+            return Response::error(e);
+        }
+    };
+    if let Some(retry_after_ms) = backpressure_delay(&config, &deadline) {
+        pocket_log!(context, Level::Warn, "exec ran {}ms over backpressure_after_ms", retry_after_ms);
+        let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+        meta["backpressure"] = serde_json::Value::Bool(true);
+        meta["retry_after_ms"] = retry_after_ms.into();
+    }
+    // Passed on so a host sees why, say, nothing was counted
+    if response.success && !prep.warnings.is_empty() {
+        let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+        meta["warnings"] = prep.warnings.into();
+    }
+    response
+}
+
+// Makes `output`'s frequencies the instance's latest, and with `delta`
+// swaps them for the changes since the previous ones. Only a host's own
+// word-count exec does this; corpus, windows and lexical-diversity count
+// without touching the cache.
+fn cache_frequencies(output: &mut WordCounterOutput, config: &WordCounterConfig) {
+    let previous = state::cache_frequencies(&output.word_frequencies);
+    if config.delta {
+        output.delta = Some(FrequencyDelta::between(previous, &output.word_frequencies));
+        output.word_frequencies = HashMap::new();
+    }
+}
+
+// The memo key for exec, if `memoize` applies. Outputs that depend on more
+// than the config and input (instance or host state, randomness, emitted
+// lines) are always counted.
+fn memo_key(request: &Request, config: &WordCounterConfig) -> Option<memo::Key> {
+    let pure = !config.delta
+        && !config.novelty
+        && config.sample_size.is_none()
+        && config.stop_words_resource.is_none()
+        && config.output_format != OutputFormat::Ndjson;
+    if !config.memoize || !pure {
+        return None;
+    }
+    memo::fingerprint(&request.node, config, request.input.as_ref())
+}
+
+// `meta.memo`: whether this exec was a memo hit, and the instance's running
+// hit and miss counts
+fn annotate_memo(response: &mut Response, hit: bool) {
+    let stats = memo::stats();
+    let meta = response.meta.get_or_insert_with(|| serde_json::json!({}));
+    meta["memo"] = serde_json::json!({"hit": hit, "hits": stats.hits, "misses": stats.misses});
+}
+
+// How long the host should hold off the next exec: by as much as this one
+// overran `backpressure_after_ms`, so a host that honours it keeps the
+// node within the limit on average. `None` when it didn't.
+fn backpressure_delay(config: &WordCounterConfig, deadline: &Deadline) -> Option<u64> {
+    let limit = config.backpressure_after_ms?;
+    let elapsed = deadline.elapsed_ms();
+    (elapsed > limit).then(|| elapsed - limit)
+}
+
+// Why exec produced no output
+enum ExecError {
+    TimedOut,
+    Failed(String),
+}
+
+fn count_words(
+    config: &WordCounterConfig,
+    prep: &PrepData,
+    classifier: &dyn TokenClassifier,
+    deadline: &mut Deadline,
+) -> Result<WordCounterOutput, ExecError> {
+    let cleaned_text = prep.cleaned_text.as_str();
+    let order = collation::WordOrder::new(config.collation.as_deref()).map_err(ExecError::Failed)?;
+    let stemmer = config.stem.as_deref().map(stemmer_for).transpose().map_err(ExecError::Failed)?;
+    check_pipeline(config).map_err(ExecError::Failed)?;
+    let pipeline = config.normalization_pipeline.as_deref()
+        .map(|steps| KeyPipeline { steps, stemmer: stemmer.as_ref() });
+    // A pipeline does its own case folding
+    let case_sensitive = prep.case_sensitive || pipeline.is_some();
+
+    let mut explanations: Option<BTreeMap<String, Disposition>> = prep.explain.then(BTreeMap::new);
+    // Counted key -> original spelling -> occurrences
+    // Stems aren't words, so stemming always reports a representative spelling
+    let mut surface_forms: Option<HashMap<String, HashMap<String, usize>>> =
+        (config.canonical_forms || stemmer.is_some()).then(HashMap::new);
+
+    // Split into words
+    let allowlist = config.allowlist.as_ref().map(|words| {
+        words.iter()
+            .map(|w| match &pipeline {
+                Some(pipeline) => pipeline.apply(w),
+                None if case_sensitive => w.clone(),
+                None => w.to_lowercase(),
+            })
+            .collect()
+    });
+    // Tokenized one sentence at a time when there's a breakdown to report
+    let sentences = prep.sentences.as_ref().filter(|_| config.include_sentence_breakdown);
+    // Host tokens carry no offsets to split them into sentences by
+    if sentences.is_some() && prep.tokens.is_some() {
+        return Err(ExecError::Failed("Prep data can't have both host tokens and a sentence breakdown".to_string()));
+    }
+    let segments = match sentences {
+        Some(sentences) => {
+            let mut start = 0;
+            let mut segments = Vec::with_capacity(sentences.len());
+            for sentence in sentences {
+                let segment = cleaned_text.get(start..sentence.end).ok_or_else(|| {
+                    ExecError::Failed(format!("Sentence end {} is outside the cleaned text", sentence.end))
+                })?;
+                segments.push(segment);
+                start = sentence.end;
+            }
+            segments
+        }
+        None => vec![cleaned_text],
+    };
+    let mut segment_counts = Vec::with_capacity(segments.len());
+    let mut processed = 0;
+    let mut truncated = false;
+
+    // Tokens are counted as they're kept. Only exact counting keeps them
+    // all, for the options that need the sequence; `approximate` keeps
+    // nothing per token but the sketch's top words.
+    let mut word_frequencies = HashMap::new();
+    let mut heavy_hitters = config.approximate
+        .then(|| sketch::HeavyHitters::new(config.sketch_width, config.sketch_depth, config.approximate_top_k))
+        .transpose()
+        .map_err(ExecError::Failed)?;
+    let mut lengths = LengthStats::default();
+    let mut total_words = 0;
+    let mut overflow = false;
+    let stop_words = effective_stop_words(config, prep.language.as_ref()).map_err(ExecError::Failed)?;
+    let words: Vec<(String, usize)> = state::with_stop_words(&stop_words, config.stop_words_case_sensitive, |stop_words| {
+        let mut filter = TokenFilter {
+            config,
+            case_sensitive,
+            stop_words,
+            stop_word_keys: None,
+            allowlist,
+            classifier,
+            stemmer: stemmer.as_ref().filter(|_| pipeline.is_none()),
+            pipeline,
+        };
+        if filter.pipeline.is_some() || (config.stem_stop_words && filter.stemmer.is_some()) {
+            filter.stop_word_keys = Some(filter.stop_word_keys());
+        }
+        // Runs of the same kept token, in order
+        let mut words: Vec<(String, usize)> = Vec::new();
+        for segment in &segments {
+            let counted_before = total_words;
+            let raw_tokens: Box<dyn Iterator<Item = &str>> = match &prep.tokens {
+                Some(tokens) => Box::new(tokens.iter().map(String::as_str)),
+                None => Box::new(segment.split_whitespace()),
+            };
+            let mut tokens = raw_tokens
+                .take_while(|_| deadline.tick())
+                // Tokens still holding punctuation (preserved entities, and
+                // underscores under identifier_split none) stay whole
+                .flat_map(|w| split_identifier(config, w))
+                .peekable();
+            while let Some(raw) = tokens.next() {
+                // With `run_length`, a run of the same token is filtered,
+                // explained and counted once
+                let mut run = 1;
+                if config.run_length {
+                    while tokens.next_if_eq(&raw).is_some() {
+                        run += 1;
+                    }
+                }
+                let room = config.max_tokens.map_or(usize::MAX, |max| max - processed);
+                if run > room {
+                    truncated = true;
+                    run = room;
+                }
+                if run == 0 {
+                    break;
+                }
+                processed += run;
+                let folded = filter.fold(raw);
+                let key = explanations.is_some().then(|| folded.clone());
+                let result = filter.check(raw, folded);
+                if let (Some(explanations), Some(key)) = (explanations.as_mut(), key) {
+                    explanations.entry(key).or_insert(match &result {
+                        Ok(_) => Disposition::Kept,
+                        Err(reason) => *reason,
+                    });
+                }
+                if let Ok(token) = result {
+                    if let Some(surface_forms) = surface_forms.as_mut() {
+                        *surface_forms.entry(token.clone()).or_default().entry(raw.to_string()).or_insert(0) += run;
+                    }
+                    total_words += run;
+                    overflow |= lengths.add(&token, word_length(&token, config), run, &order);
+                    match heavy_hitters.as_mut() {
+                        Some(heavy_hitters) => heavy_hitters.add(&token, run as u64),
+                        None => {
+                            overflow |= count_into(&mut word_frequencies, &token, run);
+                            match words.last_mut() {
+                                Some((last, count)) if *last == token => *count += run,
+                                _ => words.push((token, run)),
+                            }
+                        }
+                    }
+                }
+                if truncated {
+                    break;
+                }
+            }
+            segment_counts.push(total_words - counted_before);
+        }
+        words
+    });
+
+    if deadline.expired {
+        return Err(ExecError::TimedOut);
+    }
+
+    let sketch = heavy_hitters.map(|heavy_hitters| {
+        let (frequencies, guarantee) = heavy_hitters.finish();
+        word_frequencies = frequencies;
+        guarantee
+    });
+
+    let average_word_length = if total_words == 0 {
+        0.0
+    } else {
+        lengths.total as f64 / total_words as f64
+    };
+    // Each counted token in order, for the options that need the sequence
+    let sequence: Vec<&str> = if config.collocations || config.sample_size.is_some() || config.lexical_diversity {
+        words.iter().flat_map(|(word, run)| std::iter::repeat_n(word.as_str(), *run)).collect()
+    } else {
+        Vec::new()
+    };
+    let collocations = config.collocations
+        .then(|| find_collocations(&sequence, &word_frequencies, config.collocation_window));
+    let weighted_score = config.weights.as_ref().map(|weights| {
+        word_frequencies.iter()
+            .map(|(word, count)| *count as f64 * weights.get(word).copied().unwrap_or(config.default_weight))
+            .sum()
+    });
+    // Ties go to the lexicographically smallest spelling, as for longest_word
+    let mut canonical_forms: Option<HashMap<String, String>> = surface_forms.map(|surface_forms| {
+        surface_forms.into_iter()
+            .filter_map(|(key, spellings)| {
+                let canonical = spellings.into_iter()
+                    .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))?
+                    .0;
+                Some((key, canonical))
+            })
+            .collect()
+    });
+    let sample = config.sample_size.map(|size| {
+        rng::reservoir_sample(&sequence, size, &mut rng::SeededRng::new(host::random_seed()))
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    });
+    // Over the whole vocabulary, including words `min_frequency` drops
+    let zipf = config.include_zipf.then(|| zipf::fit(word_frequencies.values().copied()));
+    if let Some(min) = config.min_frequency {
+        word_frequencies.retain(|_, count| *count >= min);
+        if let Some(canonical_forms) = canonical_forms.as_mut() {
+            canonical_forms.retain(|word, _| word_frequencies.contains_key(word));
+        }
+    }
+    // From what `min_frequency` leaves, so they only name reported words;
+    // novelty doesn't remember the dropped ones either
+    let suspicious_tokens = config.detect_mixed_script.then(|| {
+        let mut tokens: Vec<String> = word_frequencies.keys()
+            .filter(|w| is_mixed_script(w))
+            .cloned()
+            .collect();
+        tokens.sort_by(|a, b| order.compare(a, b));
+        tokens
+    });
+    let novel_words = config.novelty.then(|| {
+        let mut words = novel_words(&word_frequencies, config);
+        words.sort_by(|a, b| order.compare(a, b));
+        words
+    });
+    // Keyed like the frequency map, so words `min_frequency` dropped are
+    // left out, though their positions still count
+    let first_seen = config.include_first_seen.then(|| {
+        let mut first_seen = HashMap::new();
+        let mut position = 0;
+        for (word, run) in &words {
+            if word_frequencies.contains_key(word) && !first_seen.contains_key(word) {
+                first_seen.insert(word.clone(), position);
+            }
+            position += run;
+        }
+        first_seen
+    });
+    let word_probabilities = config.normalize_frequencies.then(|| probabilities(&word_frequencies));
+    let unique_words = word_frequencies.len();
+
+    let lexical_diversity = config.lexical_diversity.then(|| diversity::measure(&sequence));
+    let length_histogram = config.include_length_histogram.then(|| {
+        let mut histogram = BTreeMap::new();
+        for (word, run) in &words {
+            *histogram.entry(word_length(word, config)).or_insert(0) += run;
+        }
+        histogram
+    });
+
+    let output = WordCounterOutput {
+        total_words,
+        unique_words,
+        word_frequencies,
+        average_word_length,
+        longest_word: lengths.longest.map(|(w, _)| output_word(&w, config)).unwrap_or_default(),
+        shortest_word: lengths.shortest.map(|(w, _)| output_word(&w, config)).unwrap_or_default(),
+        collocations,
+        suspicious_tokens,
+        explanations: explanations.map(|explanations| {
+            let mut explanations: Vec<Explanation> = explanations.into_iter()
+                .map(|(token, reason)| Explanation { token, kept: reason == Disposition::Kept, reason })
+                .collect();
+            explanations.sort_by(|a, b| order.compare(&a.token, &b.token));
+            explanations
+        }),
+        weighted_score,
+        canonical_forms,
+        sample,
+        novel_words,
+        sentences: sentences.map(|sentences| {
+            sentences.iter()
+                .zip(segment_counts)
+                .map(|(sentence, word_count)| SentenceCount { text: sentence.text.clone(), word_count })
+                .collect()
+        }),
+        length_histogram,
+        first_seen,
+        lexical_diversity,
+        zipf,
+        word_probabilities,
+        language: prep.language.clone(),
+        sketch,
+        delta: None,
+        overflow,
+        truncated,
+    };
+    Ok(output)
+}
+
+// Word lengths over the counted tokens, taken as they're counted
+#[derive(Default)]
+struct LengthStats {
+    total: usize,
+    // With their lengths
+    longest: Option<(String, usize)>,
+    shortest: Option<(String, usize)>,
+}
+
+impl LengthStats {
+    // Counts `count` occurrences of `word`. Returns whether the total
+    // saturated.
+    fn add(&mut self, word: &str, length: usize, count: usize, order: &collation::WordOrder) -> bool {
+        // Ties go to the word that sorts first so the pick doesn't depend
+        // on input order
+        if self.longest.as_ref().is_none_or(|(longest, longest_length)| {
+            longest_length.cmp(&length).then_with(|| order.compare(word, longest)).is_lt()
+        }) {
+            self.longest = Some((word.to_string(), length));
+        }
+        if self.shortest.as_ref().is_none_or(|(shortest, shortest_length)| {
+            length.cmp(shortest_length).then_with(|| order.compare(word, shortest)).is_lt()
+        }) {
+            self.shortest = Some((word.to_string(), length));
+        }
+        let lengths = length.checked_mul(count);
+        saturating_accumulate(&mut self.total, lengths.unwrap_or(usize::MAX)) || lengths.is_none()
+    }
+}
+
+// Adds `count` occurrences of `word`, cloning it only when it's new.
+// Returns whether its count saturated.
+fn count_into(frequencies: &mut HashMap<String, usize>, word: &str, count: usize) -> bool {
+    match frequencies.get_mut(word) {
+        Some(existing) => saturating_accumulate(existing, count),
+        None => {
+            frequencies.insert(word.to_string(), count);
+            false
+        }
+    }
+}
+
+// Each count as a share of their total; empty when nothing was counted
+fn probabilities(frequencies: &HashMap<String, usize>) -> HashMap<String, f64> {
+    let total: f64 = frequencies.values().map(|count| *count as f64).sum();
+    if total == 0.0 {
+        return HashMap::new();
+    }
+    frequencies.iter().map(|(word, count)| (word.clone(), *count as f64 / total)).collect()
+}
+
+// Word length for the length filters, statistics and histogram: chars, or
+// graphemes with `bidi_aware`
+fn word_length(word: &str, config: &WordCounterConfig) -> usize {
+    if config.bidi_aware { bidi::grapheme_len(word) } else { word.chars().count() }
+}
+
+fn output_word(word: &str, config: &WordCounterConfig) -> String {
+    if config.bidi_aware {
+        bidi::truncate(word, config.max_word_output_len)
+    } else {
+        truncate_for_output(word, config.max_word_output_len)
+    }
+}
+
+// Keeps the first `max` characters of `word`, plus "…" if anything was cut
+fn truncate_for_output(word: &str, max: Option<usize>) -> String {
+    match max.and_then(|max| word.char_indices().nth(max)) {
+        Some((end, _)) => format!("{}…", &word[..end]),
+        None => word.to_string(),
+    }
+}
+
+// Adds `amount` to `acc`, pinning it at `usize::MAX` instead of wrapping.
+// Returns whether it saturated.
+fn saturating_accumulate(acc: &mut usize, amount: usize) -> bool {
+    match acc.checked_add(amount) {
+        Some(sum) => {
+            *acc = sum;
+            false
+        }
+        None => {
+            *acc = usize::MAX;
+            true
+        }
+    }
+}
+
+// `OutputFormat::Columns` form of a frequency map
+fn frequency_columns(frequencies: &HashMap<String, usize>) -> serde_json::Value {
+    let (words, counts): (Vec<&String>, Vec<&usize>) = sorted_entries(frequencies).into_iter().unzip();
+    serde_json::json!({"words": words, "counts": counts})
+}
+
+// By descending count, then word
+fn sorted_entries(frequencies: &HashMap<String, usize>) -> Vec<(&String, &usize)> {
+    let mut entries: Vec<(&String, &usize)> = frequencies.iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    entries
+}
+
+// One NDJSON line per frequency entry: `{"word": ..., "count": ...}`
+fn ndjson_lines(frequencies: &HashMap<String, usize>) -> Vec<Vec<u8>> {
+    sorted_entries(frequencies)
+        .into_iter()
+        .map(|(word, count)| ndjson_line(&serde_json::json!({"word": word, "count": count})))
+        .collect()
+}
+
+fn ndjson_line(value: &serde_json::Value) -> Vec<u8> {
+    let mut line = serde_json::to_vec(value).unwrap();
+    line.push(b'\n');
+    line
+}
+
+// Stamps an exec output with its schema version, migrating it down if the
+// consumer asked for an older shape, and annotates its cost
+fn annotated_response(output: serde_json::Value, cost: usize, config: &WordCounterConfig) -> Response {
+    match WORD_COUNT_OUTPUT.stamp(output, config.output_schema_version) {
+        Ok(output) => Response {
+            meta: Some(serde_json::json!({"cost": cost})),
+            ..Response::ok(output)
+        },
+        Err(e) => Response::error(e),
+    }
+}
+
+// Exec's "timeout" response, costing the tokens read before the deadline
+fn timeout_exec_response(deadline: &Deadline, config: &WordCounterConfig) -> Response {
+    let timeout = deadline.timeout_response();
+    Response {
+        next: timeout.next,
+        ..annotated_response(timeout.output.unwrap(), deadline.ticks, config)
+    }
+}
+
+// Exec's response for a counted output. Its cost annotation is the number
+// of counted words.
+fn exec_response(output: WordCounterOutput, config: &WordCounterConfig) -> Response {
+    let cost = output.total_words;
+    let columns = (config.output_format == OutputFormat::Columns)
+        .then(|| frequency_columns(&output.word_frequencies));
+    let lines = (config.output_format == OutputFormat::Ndjson).then(|| ndjson_lines(&output.word_frequencies));
+    let mut output = serde_json::to_value(output).unwrap();
+    if let Some(columns) = columns {
+        output["word_frequencies"] = columns;
+    }
+    if lines.is_some() {
+        output["word_frequencies"] = serde_json::json!({});
+    }
+    let mut response = annotated_response(output, cost, config);
+    // Only emitted once nothing can fail
+    if let (Some(lines), Some(output), Some(meta)) = (lines, response.output.as_ref(), response.meta.as_mut()) {
+        let entries = lines.len();
+        for line in lines {
+            host::emit(&line);
+        }
+        // Emitted lines miss the rounding `dispatch` gives the response
+        let mut summary = serde_json::json!({"summary": output, "entries": entries});
+        if let Some(digits) = config.float_precision {
+            precision::round_floats(&mut summary, digits);
+        }
+        host::emit(&ndjson_line(&summary));
+        meta["ndjson_entries"] = entries.into();
+    }
+    response
+}
+
+// lexical-diversity exec: invokes word-count's exec with
+// `lexical_diversity` on and keeps only the diversity measures
+fn handle_diversity_exec(request: &Request) -> Response {
+    let input = match request.input.as_ref() {
+        Some(d) => d.clone(),
+        None => return Response::error("No prep data provided"),
+    };
+    let mut config = match effective_config(request) {
+        Ok(config) => config.map(Cow::into_owned).unwrap_or_else(|| serde_json::json!({})),
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    if let Some(config) = config.as_object_mut() {
+        config.insert("lexical_diversity".to_string(), serde_json::Value::Bool(true));
+        // Older output versions drop the field
+        config.remove("output_schema_version");
+        // Meant for this node's output, which its post reshapes
+        config.remove("output_transform");
+    }
+    let response = invoke(registry::nodes(), request, "word-count", "exec", Some(config), input);
+    let mut output = match response.output {
+        Some(output) if response.success && output.get("timed_out").is_none() => output,
+        _ => return response,
+    };
+    let mut diversity = match output["lexical_diversity"].take() {
+        serde_json::Value::Object(diversity) => diversity,
+        _ => serde_json::Map::new(),
+    };
+    diversity.insert("total_words".to_string(), output["total_words"].take());
+    diversity.insert("unique_words".to_string(), output["unique_words"].take());
+    Response {
+        output: Some(serde_json::Value::Object(diversity)),
+        ..response
+    }
+}
+
+// lexical-diversity post: nothing to route on but a timeout, or no words at
+// all (`empty_route`)
+fn handle_diversity_post(request: &Request) -> Response {
+    route_failure(request, diversity_post_response(request))
+}
+
+fn diversity_post_response(request: &Request) -> Response {
+    let exec_result = match request.input.as_ref() {
+        Some(r) => r,
+        None => return Response::error("No exec result provided"),
+    };
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    let timed_out = exec_result["timed_out"].as_bool().unwrap_or(false);
+    // The route prep's `skip_to` takes on the same input
+    let empty = exec_result["total_words"].as_u64() == Some(0);
+    let next = match () {
+        _ if timed_out => Some("timeout".to_string()),
+        _ if empty => Some(config.empty_route.clone()),
+        _ => None,
+    };
+    match final_output(&config, exec_result, timed_out) {
+        Ok(output) => Response { next, ..Response::ok(output) },
+        Err(e) => Response::error(e),
+    }
+}
+
+fn check_doc_freq(config: &WordCounterConfig) -> Result<(), String> {
+    match (config.min_doc_freq, config.max_doc_freq) {
+        (Some(min), Some(max)) if max < min => Err("max_doc_freq is less than min_doc_freq".to_string()),
+        _ => Ok(()),
+    }
+}
+
+// corpus prep: word-count's prep of each document
+fn handle_corpus_prep(request: &Request) -> Response {
+    route_failure(request, corpus_prep_response(request))
+}
+
+fn corpus_prep_response(request: &Request) -> Response {
+    let documents = match request.input.as_ref().map(|i| &i["documents"]) {
+        Some(serde_json::Value::Array(documents)) => documents,
+        Some(_) => return Response::error("Failed to parse input: documents must be an array"),
+        None => return Response::error("No input provided"),
+    };
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    let documents: Vec<corpus::DocumentResult<PrepData>> = documents
+        .iter()
+        .map(|document| {
+            let prep = InputView::deserialize(document)
+                .map_err(|e| format!("Failed to parse input: {}", e))
+                .and_then(|input| prepare(&config, input));
+            corpus::DocumentResult::new(prep)
+        })
+        .collect();
+    corpus_response(serde_json::json!({"documents": documents}), corpus::all_failed(&documents))
+}
+
+// Succeeds if any document did; when none did, the per-document errors still
+// go out with the failure
+fn corpus_response(output: serde_json::Value, all_failed: Option<&str>) -> Response {
+    match all_failed {
+        Some(e) => Response {
+            output: Some(output),
+            ..Response::error(format!("No document could be counted: {}", e))
+        },
+        None => Response::ok(output),
+    }
+}
+
+// corpus exec: counts each document like word-count's exec, then keeps the
+// terms within the document-frequency bounds
+fn handle_corpus_exec(request: &Request) -> Response {
+    route_failure(request, corpus_exec_response(request))
+}
+
+fn corpus_exec_response(request: &Request) -> Response {
+    let documents = match request.input.as_ref().and_then(|d| d["documents"].as_array()) {
+        Some(documents) => documents,
+        None => return Response::error("No prep data provided"),
+    };
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+
+    // One budget for the whole corpus
+    let mut deadline = Deadline::with_clock(request.timeout_ms, &HostClock);
+    let mut frequencies = Vec::with_capacity(documents.len());
+    for document in documents {
+        if deadline.expired {
+            frequencies.push(Err(format!("Timed out after {}ms", deadline.budget_ms)));
+            continue;
+        }
+        let prep = match (document["ok"].as_bool(), document.get("output")) {
+            (Some(true), Some(prep)) => prep,
+            (Some(true), None) => {
+                frequencies.push(Err("Document has no output".to_string()));
+                continue;
+            }
+            // Documents that failed prep pass their error through
+            (Some(false), _) => {
+                frequencies.push(Err(document["error"].as_str().unwrap_or("Prep failed").to_string()));
+                continue;
+            }
+            (None, _) => {
+                frequencies.push(Err("Document has no boolean ok".to_string()));
+                continue;
+            }
+        };
+        match count_words(&config, &read_prep(prep), &UnknownClassifier, &mut deadline) {
+            Ok(output) => frequencies.push(Ok(output.word_frequencies)),
+            // This document and the rest are marked timed out
+            Err(ExecError::TimedOut) => frequencies.push(Err(format!("Timed out after {}ms", deadline.budget_ms))),
+            Err(ExecError::Failed(e)) => frequencies.push(Err(e)),
+        }
+    }
+    let output = corpus::summarize(frequencies, config.min_doc_freq, config.max_doc_freq);
+    let meta = Some(serde_json::json!({"cost": output.total_words}));
+    if deadline.expired {
+        // The documents counted in time, routed to "timeout" like exec's
+        let mut output = serde_json::to_value(output).unwrap();
+        output["timed_out"] = true.into();
+        output["timeout_ms"] = deadline.budget_ms.into();
+        return Response { next: Some("timeout".to_string()), meta, ..Response::ok(output) };
+    }
+    let all_failed = corpus::all_failed(&output.documents).map(str::to_string);
+    Response {
+        meta,
+        ..corpus_response(serde_json::to_value(output).unwrap(), all_failed.as_deref())
+    }
+}
+
+// Post's output: exec's, reshaped by `output_transform`. Timeout results
+// aren't in the declared shape, so they pass through untouched.
+fn final_output(
+    config: &WordCounterConfig,
+    exec_result: &serde_json::Value,
+    timed_out: bool,
+) -> Result<serde_json::Value, String> {
+    match &config.output_transform {
+        Some(transform) if !timed_out => {
+            transform.check()?;
+            Ok(transform.apply(exec_result.clone()))
+        }
+        _ => Ok(exec_result.clone()),
+    }
+}
+
+// Route based on word count
+fn route(total_words: u64) -> &'static str {
+    if total_words == 0 {
+        "empty"
+    } else if total_words < 100 {
+        "short"
+    } else if total_words < 1000 {
+        "medium"
+    } else {
+        "long"
+    }
+}
+
+fn handle_post(request: &Request) -> Response {
+    route_failure(request, post_response(request))
+}
+
+fn post_response(request: &Request) -> Response {
+    let exec_result = match request.input.as_ref() {
+        Some(r) => r,
+        None => return Response::error("No exec result provided"),
+    };
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+
+    if let Err(e) = route_when::check(&config.route_when) {
+        return Response::failure(ErrorCode::InvalidConfig, e);
+    }
+
+    let total_words = exec_result["total_words"].as_u64().unwrap_or(0);
+    let timed_out = exec_result["timed_out"].as_bool().unwrap_or(false);
+    let rule_route = route_when::first_match(&config.route_when, exec_result);
+    let next = match (total_words, rule_route) {
+        _ if timed_out => "timeout".to_string(),
+        (_, Some(route)) => route.to_string(),
+        (0, None) => config.empty_route.clone(),
+        _ if config.approx_routes && is_approximate(exec_result) => format!("{}-approx", route(total_words)),
+        _ => route(total_words).to_string(),
+    };
+    pocket_log!(Context::for_request(request), Level::Info, "routing to {}", next);
+
+    match final_output(&config, exec_result, timed_out) {
+        Ok(output) => Response {
+            next: Some(next),
+            ..Response::ok(output)
+        },
+        Err(e) => Response::error(e),
+    }
+}
+
+// Whether exec's counts stand for less than the whole text (`max_tokens`
+// cut it short), are estimates (`approximate`), or saturated (`overflow`).
+// A `sample_size` sample doesn't count: the counts beside it are exact.
+fn is_approximate(exec_result: &serde_json::Value) -> bool {
+    exec_result["truncated"].as_bool().unwrap_or(false)
+        || exec_result["overflow"].as_bool().unwrap_or(false)
+        || exec_result.get("sketch").is_some()
+}
+
+// The input of a `record` call: one record of a windowed stream
+#[derive(Deserialize)]
+struct RecordInput {
+    text: String,
+    // Needed by time windows
+    #[serde(default)]
+    timestamp_ms: Option<u64>,
+}
+
+// Streaming function: adds a record to the instance's stream (see
+// `window`) and emits the result of each window it closes
+fn handle_record(request: &Request) -> Response {
+    route_failure(request, record_response(request))
+}
+
+fn record_response(request: &Request) -> Response {
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    let Some(window) = config.window else {
+        return Response::failure(ErrorCode::InvalidConfig, "record needs a window config");
+    };
+    if let Err(e) = window.check() {
+        return Response::failure(ErrorCode::InvalidConfig, e);
+    }
+    let record = match request.input.as_ref().map(RecordInput::deserialize) {
+        Some(Ok(record)) => record,
+        Some(Err(e)) => return Response::error(format!("Failed to parse input: {}", e)),
+        None => return Response::error("No input provided"),
+    };
+    match window::push(window, record.text, record.timestamp_ms) {
+        Ok(closed) => emit_windows(request, &config, closed),
+        Err(e) => Response::error(e),
+    }
+}
+
+// Streaming function: ends the stream, emitting its partial final windows
+fn handle_flush(request: &Request) -> Response {
+    route_failure(request, flush_response(request))
+}
+
+fn flush_response(request: &Request) -> Response {
+    let config = match parse_config(request) {
+        Ok(config) => config,
+        Err(e) => return Response::failure(ErrorCode::InvalidConfig, e),
+    };
+    emit_windows(request, &config, window::finish())
+}
+
+// Counts each window's records as one text and emits `{window, output}`,
+// in the request's wire encoding. The response says how many were emitted.
+fn emit_windows(request: &Request, config: &WordCounterConfig, windows: Vec<window::ClosedWindow>) -> Response {
+    let total = windows.len();
+    // One budget for every window this call closes
+    let mut deadline = Deadline::with_clock(request.timeout_ms, &HostClock);
+    for (emitted, closed) in windows.into_iter().enumerate() {
+        let input = WordCounterInput { text: closed.texts.join("\n"), ..WordCounterInput::default() };
+        let prep = match prepare(config, input.view()) {
+            Ok(prep) => prep,
+            Err(e) => return Response::error(e),
+        };
+        let output = match count_words(config, &prep, &UnknownClassifier, &mut deadline) {
+            Ok(output) => output,
+            Err(ExecError::TimedOut) => {
+                // The windows not yet emitted are dropped along with it
+                let mut response = deadline.timeout_response();
+                if let Some(output) = response.output.as_mut() {
+                    output["emitted"] = emitted.into();
+                    output["dropped"] = (total - emitted).into();
+                }
+                return response;
+            }
+            Err(ExecError::Failed(e)) => return Response::error(e),
+        };
+        let output = match exec_response(output, config) {
+            Response { success: true, output: Some(output), .. } => output,
+            response => return response,
+        };
+        let emission = serde_json::json!({
+            "window": {
+                "start": closed.start,
+                "end": closed.end,
+                "partial": closed.partial,
+                "records": closed.texts.len(),
+            },
+            "output": output,
+        });
+        match wire::encode(&emission, request.wire) {
+            Ok(bytes) => host::emit(&bytes),
+            Err(e) => return Response::error(e),
+        }
+    }
+    Response::ok(serde_json::json!({"emitted": total}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(function: &str, config: serde_json::Value, input: serde_json::Value) -> Request {
+        Request {
+            node: "word-count".to_string(),
+            function: function.to_string(),
+            config: Some(config),
+            input: Some(input),
+            ..Request::default()
+        }
+    }
+
+    // Runs prep followed by exec and returns the exec output
+    fn count(config: serde_json::Value, input: serde_json::Value) -> serde_json::Value {
+        let prep = handle_prep(&request("prep", config.clone(), input));
+        assert!(prep.success, "prep failed: {:?}", prep.error);
+        let exec = handle_exec(&request("exec", config, prep.output.unwrap()));
+        assert!(exec.success, "exec failed: {:?}", exec.error);
+        exec.output.unwrap()
+    }
+
+    #[test]
+    fn nfc_collapses_precomposed_and_decomposed_forms() {
+        let output = count(
+            json!({"normalization": "nfc"}),
+            json!({"text": "caf\u{e9} cafe\u{301}"}),
+        );
+        assert_eq!(output["word_frequencies"], json!({"caf\u{e9}": 2}));
+        assert_eq!(output["unique_words"], 1);
+    }
+
+    #[test]
+    fn without_normalization_forms_stay_distinct() {
+        let output = count(json!({}), json!({"text": "caf\u{e9} cafe\u{301}"}));
+        assert_eq!(output["unique_words"], 2);
+    }
+
+    #[test]
+    fn nfkc_folds_compatibility_characters() {
+        let output = count(json!({"normalization": "nfkc"}), json!({"text": "\u{fb01}ne fine"}));
+        assert_eq!(output["word_frequencies"], json!({"fine": 2}));
+    }
+
+    fn supports(node: &str, function: &str) -> bool {
+        let query = json!({"node": node, "function": function}).to_string();
+        unsafe { supports_function(query.as_ptr(), query.len()) == 1 }
+    }
+
+    #[test]
+    fn word_count_supports_lifecycle_functions() {
+        assert!(supports("word-count", "prep"));
+        assert!(supports("word-count", "exec"));
+        assert!(supports("word-count", "post"));
+        assert!(supports("word-count", "validate"));
+        assert!(!supports("word-count", "frobnicate"));
+        assert!(!supports("no-such-node", "exec"));
+    }
+
+    #[test]
+    fn supports_function_rejects_malformed_query() {
+        let query = b"not json";
+        assert_eq!(unsafe { supports_function(query.as_ptr(), query.len()) }, 0);
+    }
+
+    #[test]
+    fn numbers_are_kept_by_default() {
+        let output = count(json!({}), json!({"text": "route 66 and 101 miles"}));
+        assert_eq!(output["word_frequencies"], json!({"route": 1, "66": 1, "101": 1, "miles": 1}));
+    }
+
+    #[test]
+    fn numbers_can_be_dropped() {
+        let output = count(json!({"numbers": "drop"}), json!({"text": "route 66 and 101 miles"}));
+        assert_eq!(output["word_frequencies"], json!({"route": 1, "miles": 1}));
+        assert_eq!(output["total_words"], 2);
+    }
+
+    #[test]
+    fn numbers_can_be_bucketed() {
+        let output = count(json!({"numbers": "bucket"}), json!({"text": "route 66 and 101 miles, not 7th"}));
+        assert_eq!(
+            output["word_frequencies"],
+            json!({"route": 1, "<NUM>": 2, "miles": 1, "not": 1, "7th": 1})
+        );
+        // Only ASCII digits make a number; numeric letters and other
+        // scripts' digits are words
+        let output = count(json!({"numbers": "drop"}), json!({"text": "42 \u{bd} \u{662}\u{663} \u{2166}"}));
+        assert_eq!(output["word_frequencies"], json!({"\u{bd}": 1, "\u{662}\u{663}": 1, "\u{2176}": 1}));
+    }
+
+    #[test]
+    fn memory_pressure_releases_cached_state() {
+        count(json!({}), json!({"text": "the cache is warm"}));
+        assert!(!state::is_empty());
+
+        on_memory_pressure(0);
+        assert!(!state::is_empty());
+        on_memory_pressure(1);
+        assert!(!state::is_empty());
+        on_memory_pressure(2);
+        assert!(state::is_empty());
+
+        // Counting still works after the cache is dropped
+        let output = count(json!({}), json!({"text": "the cache is cold"}));
+        assert_eq!(output["word_frequencies"], json!({"cache": 1, "cold": 1}));
+    }
+
+    #[test]
+    fn exec_output_carries_current_schema_version() {
+        let output = count(json!({}), json!({"text": "hello world"}));
+        assert_eq!(output["schema_version"], 2);
+    }
+
+    #[test]
+    fn v2_output_migrates_down_to_v1_shape() {
+        let v2 = json!({
+            "total_words": 2, "unique_words": 2, "word_frequencies": {"hello": 1, "world": 1},
+            "average_word_length": 5.0, "longest_word": "hello", "shortest_word": "hello",
+            "added_in_v2": true,
+        });
+        let v1 = WORD_COUNT_OUTPUT.stamp(v2, Some(1)).unwrap();
+        let mut keys: Vec<&String> = v1.as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, [
+            "average_word_length", "longest_word", "schema_version", "shortest_word",
+            "total_words", "unique_words", "word_frequencies",
+        ]);
+        assert_eq!(v1["schema_version"], 1);
+    }
+
+    #[test]
+    fn unsupported_schema_version_is_an_error() {
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": "hello"})));
+        let exec = handle_exec(&request("exec", json!({"output_schema_version": 9}), prep.output.unwrap()));
+        assert!(!exec.success);
+        assert!(exec.error.unwrap().contains("Unsupported output schema version 9"));
+    }
+
+    // Sends a request through the `call` export and decodes the response
+    fn call_json(request: serde_json::Value) -> serde_json::Value {
+        let input = request.to_string();
+        let mut out = vec![0u8; 64 * 1024];
+        let len = unsafe { call(input.as_ptr(), input.len(), out.as_mut_ptr(), out.len()) };
+        serde_json::from_slice(&out[..len]).unwrap()
+    }
+
+    #[test]
+    fn profile_block_is_absent_unless_requested() {
+        let response = call_json(json!({
+            "node": "word-count", "function": "prep", "input": {"text": "hello world"}
+        }));
+        assert_eq!(response["success"], true);
+        assert!(response.get("_profile").is_none());
+        assert!(response["output"].get("_profile").is_none());
+    }
+
+    #[test]
+    fn profile_block_is_carried_through_all_phases() {
+        let prep = call_json(json!({
+            "node": "word-count", "function": "prep", "input": {"text": "hello world", "profile": true}
+        }));
+        let exec = call_json(json!({"node": "word-count", "function": "exec", "input": prep["output"]}));
+        let post = call_json(json!({"node": "word-count", "function": "post", "input": exec["output"]}));
+
+        let profile = post["_profile"].as_object().unwrap();
+        assert_eq!(profile.len(), 3);
+        for field in ["prep_us", "exec_us", "post_us"] {
+            assert!(profile[field].is_u64(), "{} is not numeric", field);
+        }
+        assert_eq!(exec["output"]["total_words"], 2);
+    }
+
+    #[test]
+    fn case_sensitive_counting_still_filters_capitalized_stop_words() {
+        let output = count(json!({}), json!({"text": "The cat and The Hat", "case_sensitive": true}));
+        assert_eq!(output["word_frequencies"], json!({"cat": 1, "Hat": 1}));
+    }
+
+    #[test]
+    fn stop_words_can_match_case_sensitively() {
+        let output = count(
+            json!({"stop_words_case_sensitive": true}),
+            json!({"text": "The cat and the Hat", "case_sensitive": true}),
+        );
+        assert_eq!(output["word_frequencies"], json!({"The": 1, "cat": 1, "Hat": 1}));
+    }
+
+    fn page(offset: usize, limit: usize) -> serde_json::Value {
+        let mut out = vec![0u8; 64 * 1024];
+        let len = unsafe { frequencies_page(offset, limit, out.as_mut_ptr(), out.len()) };
+        serde_json::from_slice(&out[..len]).unwrap()
+    }
+
+    #[test]
+    fn frequencies_can_be_paged_after_exec() {
+        let text: Vec<String> = (0..1000).map(|i| format!("w{:04}", i)).collect();
+        let output = count(json!({}), json!({"text": text.join(" ")}));
+        assert_eq!(output["unique_words"], 1000);
+
+        let mut seen = Vec::new();
+        for offset in (0..1000).step_by(100) {
+            let response = page(offset, 100);
+            assert_eq!(response["success"], true);
+            assert_eq!(response["output"]["total"], 1000);
+            let entries = response["output"]["entries"].as_array().unwrap();
+            assert_eq!(entries.len(), 100);
+            seen.extend(entries.iter().map(|e| e["word"].as_str().unwrap().to_string()));
+        }
+        assert_eq!(seen, text);
+        assert_eq!(page(1000, 100)["output"]["entries"], json!([]));
+    }
+
+    #[test]
+    fn frequencies_page_errors_without_cached_exec() {
+        state::clear();
+        let response = page(0, 10);
+        assert_eq!(response["success"], false);
+    }
+
+    #[test]
+    fn camel_case_splits_on_case_and_digit_boundaries() {
+        assert_eq!(split_at("getUserID2", camel_case_boundary), ["get", "User", "ID", "2"]);
+        assert_eq!(split_at("plain", camel_case_boundary), ["plain"]);
+        assert_eq!(split_at("7th", camel_case_boundary), ["7th"]);
+    }
+
+    #[test]
+    fn camel_case_split_composes_with_whitespace_split() {
+        let output = count(json!({"split_camel_case": true}), json!({"text": "getUserID2 user setUser"}));
+        assert_eq!(output["word_frequencies"], json!({"get": 1, "user": 3, "id": 1, "2": 1, "set": 1}));
+
+        let output = count(json!({}), json!({"text": "getUserID2"}));
+        assert_eq!(output["word_frequencies"], json!({"getuserid2": 1}));
+    }
+
+    #[test]
+    fn call_encodes_response_as_requested() {
+        let request = json!({
+            "node": "word-count", "function": "prep", "input": {"text": "hi"},
+            "wire": {"encoding": "msgpack"}
+        }).to_string();
+        let mut out = vec![0u8; 1024];
+        let len = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        let response: serde_json::Value = rmp_serde::from_slice(&out[..len]).unwrap();
+        assert_eq!(response["success"], true);
+        assert_eq!(response["output"]["cleaned_text"], "hi");
+    }
+
+    #[test]
+    fn call_reports_required_size_when_buffer_is_too_small() {
+        let request = json!({"node": "word-count", "function": "prep", "input": {"text": "hello"}}).to_string();
+        let mut out = vec![0u8; 8];
+        let len = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        assert!(len > out.len());
+        assert!(call_json(serde_json::from_str(&request).unwrap())["success"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn collocations_find_frequent_pair() {
+        let output = count(
+            json!({"collocations": true}),
+            json!({"text": "I love New York. New York is big, and New York never sleeps while people love food"}),
+        );
+        let top = &output["collocations"][0];
+        assert_eq!(top["pair"], json!(["new", "york"]));
+        assert_eq!(top["count"], 3);
+        assert!(top["score"].as_f64().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn collocations_are_omitted_unless_enabled() {
+        let output = count(json!({}), json!({"text": "new york new york"}));
+        assert!(output.get("collocations").is_none());
+    }
+
+    #[test]
+    fn collocation_window_is_truncated_at_text_boundaries() {
+        let words = ["alpha", "beta"];
+        let frequencies = HashMap::from([("alpha".to_string(), 1), ("beta".to_string(), 1)]);
+        assert!(find_collocations(&words, &frequencies, 10).is_empty());
+        assert!(find_collocations(&[], &HashMap::new(), 2).is_empty());
+
+        let output = count(json!({"collocations": true}), json!({"text": "the"}));
+        assert_eq!(output["collocations"], json!([]));
+        assert_eq!(output["longest_word"], "");
+    }
+
+    #[test]
+    fn mixed_script_tokens_are_flagged() {
+        // "p\u{430}ypal" uses a Cyrillic "а" in place of the Latin "a"
+        let output = count(
+            json!({"detect_mixed_script": true}),
+            json!({"text": "login to p\u{430}ypal or paypal today, \u{43c}\u{438}\u{440} 2024"}),
+        );
+        assert_eq!(output["suspicious_tokens"], json!(["p\u{430}ypal"]));
+    }
+
+    #[test]
+    fn single_script_tokens_are_not_flagged() {
+        assert!(!is_mixed_script("paypal"));
+        assert!(!is_mixed_script("\u{43c}\u{438}\u{440}"));
+        assert!(!is_mixed_script("route66"));
+        assert!(!is_mixed_script("cafe\u{301}"));
+        assert!(is_mixed_script("p\u{430}ypal"));
+    }
+
+    #[test]
+    fn exec_routes_to_timeout_when_budget_is_exceeded() {
+        let text = "lorem ipsum dolor sit amet ".repeat(20_000);
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": text})));
+        let exec = handle_exec(&Request {
+            timeout_ms: Some(0),
+            ..request("exec", json!({}), prep.output.clone().unwrap())
+        });
+        assert!(exec.success);
+        assert_eq!(exec.next.as_deref(), Some("timeout"));
+        assert_eq!(exec.output.as_ref().unwrap()["timed_out"], true);
+        assert_eq!(exec.output.as_ref().unwrap()["schema_version"], 2);
+        assert!(exec.meta.as_ref().unwrap()["cost"].is_u64());
+
+        let post = handle_post(&request("post", json!({}), exec.output.unwrap()));
+        assert_eq!(post.next.as_deref(), Some("timeout"));
+
+        // An older output shape still carries what post routes by
+        let v1 = handle_exec(&Request {
+            timeout_ms: Some(0),
+            ..request("exec", json!({"output_schema_version": 1}), prep.output.unwrap())
+        });
+        assert_eq!(v1.output.unwrap(), json!({"timed_out": true, "timeout_ms": 0, "schema_version": 1}));
+    }
+
+    #[test]
+    fn request_timeout_is_capped_by_permission() {
+        assert_eq!(Deadline::with_clock(Some(50), &HostClock).budget_ms, 50);
+        assert_eq!(Deadline::with_clock(Some(60_000), &HostClock).budget_ms, PERMISSION_TIMEOUT_MS);
+        assert_eq!(Deadline::with_clock(None, &HostClock).budget_ms, PERMISSION_TIMEOUT_MS);
+    }
+
+    #[test]
+    fn deadline_expires_at_the_first_check_past_its_budget() {
+        let clock = clock::MockClock::new(0);
+        let mut deadline = Deadline::with_clock(Some(5), &clock);
+        assert!(deadline.tick());
+        clock.advance(5_000_000);
+        // The clock is only read every DEADLINE_CHECK_INTERVAL ticks
+        for _ in 1..DEADLINE_CHECK_INTERVAL {
+            assert!(deadline.tick());
+        }
+        assert!(!deadline.tick());
+    }
+
+    // Marks capitalized tokens as proper nouns
+    struct CapitalizedClassifier;
+
+    impl TokenClassifier for CapitalizedClassifier {
+        fn classify(&self, token: &str) -> TokenClass {
+            match token.chars().next() {
+                Some(c) if c.is_uppercase() => TokenClass::ProperNoun,
+                _ => TokenClass::Other,
+            }
+        }
+    }
+
+    #[test]
+    fn keep_classes_filters_by_classifier() {
+        let config = json!({"keep_classes": ["proper_noun"]});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "Alice met Bob in Paris"})));
+        let exec = exec_with(&request("exec", config, prep.output.unwrap()), &CapitalizedClassifier, &HostClock);
+        assert_eq!(exec.output.unwrap()["word_frequencies"], json!({"alice": 1, "bob": 1, "paris": 1}));
+    }
+
+    #[test]
+    fn default_classifier_marks_everything_unknown() {
+        let output = count(json!({"keep_classes": ["unknown"]}), json!({"text": "Alice met Bob"}));
+        assert_eq!(output["total_words"], 3);
+        let output = count(json!({"keep_classes": ["noun"]}), json!({"text": "Alice met Bob"}));
+        assert_eq!(output["total_words"], 0);
+    }
+
+    #[test]
+    fn longest_and_shortest_ties_break_lexicographically() {
+        for text in ["pear plum fig kiwi", "kiwi fig plum pear", "plum kiwi pear fig"] {
+            let output = count(json!({}), json!({"text": text}));
+            assert_eq!(output["longest_word"], "kiwi", "for {:?}", text);
+            assert_eq!(output["shortest_word"], "fig", "for {:?}", text);
+        }
+        let output = count(json!({}), json!({"text": "bb aa"}));
+        assert_eq!(output["longest_word"], "aa");
+        assert_eq!(output["shortest_word"], "aa");
+    }
+
+    #[test]
+    fn explain_reports_disposition_of_each_unique_token() {
+        let output = count(
+            json!({"min_word_length": 3, "max_word_length": 8, "allowlist": ["quick", "fox", "the", "extraordinary"]}),
+            json!({"text": "The quick fox is extraordinary, ok? Quick!", "explain": true}),
+        );
+        assert_eq!(output["word_frequencies"], json!({"quick": 2, "fox": 1}));
+        assert_eq!(output["explanations"], json!([
+            {"token": "extraordinary", "kept": false, "reason": "too_long"},
+            {"token": "fox", "kept": true, "reason": "kept"},
+            {"token": "is", "kept": false, "reason": "too_short"},
+            {"token": "ok", "kept": false, "reason": "too_short"},
+            {"token": "quick", "kept": true, "reason": "kept"},
+            {"token": "the", "kept": false, "reason": "stop_word"},
+        ]));
+    }
+
+    #[test]
+    fn explain_reports_allowlist_and_number_filtering() {
+        let output = count(
+            json!({"allowlist": ["apple"], "numbers": "drop"}),
+            json!({"text": "apple pear 42", "explain": true}),
+        );
+        let reasons: Vec<&str> = output["explanations"].as_array().unwrap().iter()
+            .map(|e| e["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, ["number", "kept", "not_in_allowlist"]);
+    }
+
+    #[test]
+    fn explanations_are_omitted_unless_requested() {
+        let output = count(json!({}), json!({"text": "the quick fox"}));
+        assert!(output.get("explanations").is_none());
+    }
+
+    #[test]
+    fn weighted_score_sums_count_times_weight() {
+        let output = count(
+            json!({"weights": {"rust": 5.0, "wasm": 2.5}}),
+            json!({"text": "Rust and WASM, rust again, plain words"}),
+        );
+        // rust×2 at 5.0, wasm×1 at 2.5, again/plain/words×1 at 1.0
+        assert_eq!(output["weighted_score"], 15.5);
+    }
+
+    #[test]
+    fn default_weight_applies_to_unlisted_words() {
+        let output = count(
+            json!({"weights": {"rust": 3.0}, "default_weight": 0.0}),
+            json!({"text": "rust rust other words"}),
+        );
+        assert_eq!(output["weighted_score"], 6.0);
+
+        let output = count(json!({}), json!({"text": "rust"}));
+        assert!(output.get("weighted_score").is_none());
+    }
+
+    #[test]
+    fn memory_limits_parse_like_the_host() {
+        assert_eq!(parse_memory_limit("512KB"), Some(512 * 1024));
+        assert_eq!(parse_memory_limit(PERMISSION_MEMORY), Some(5 * 1024 * 1024));
+        assert_eq!(parse_memory_limit("1GB"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_memory_limit("5"), None);
+        assert_eq!(parse_memory_limit("MB"), None);
+        assert_eq!(parse_memory_limit("5TB"), None);
+    }
+
+    #[test]
+    fn canonical_form_is_most_frequent_spelling() {
+        let output = count(
+            json!({"canonical_forms": true}),
+            json!({"text": "Apple apple Apple Apple"}),
+        );
+        assert_eq!(output["word_frequencies"]["apple"], 4);
+        assert_eq!(output["canonical_forms"], json!({"apple": "Apple"}));
+    }
+
+    #[test]
+    fn canonical_form_ties_pick_smallest_spelling() {
+        let output = count(json!({"canonical_forms": true}), json!({"text": "rust Rust"}));
+        assert_eq!(output["canonical_forms"]["rust"], "Rust");
+        let output = count(json!({}), json!({"text": "rust Rust"}));
+        assert!(output.get("canonical_forms").is_none());
+    }
+
+    #[test]
+    fn sample_draws_from_counted_tokens() {
+        let config = json!({"sample_size": 3});
+        let input = json!({"text": "alpha beta gamma delta epsilon zeta eta theta"});
+        let output = count(config.clone(), input.clone());
+        let sample = output["sample"].as_array().unwrap();
+        assert_eq!(sample.len(), 3);
+        assert!(sample.iter().all(|w| output["word_frequencies"].get(w.as_str().unwrap()).is_some()));
+        assert_eq!(count(config, input)["sample"], output["sample"]);
+    }
+
+    #[test]
+    fn validate_accepts_well_formed_request() {
+        let response = handle_validate(&request("validate", json!({"min_word_length": 3}), json!({"text": "hi"})));
+        assert_eq!(response.output.unwrap(), json!({"valid": true, "errors": []}));
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let config = json!({"min_word_length": 5, "max_word_length": 2, "output_schema_version": 9});
+        let response = call_json(json!({
+            "node": "word-count",
+            "function": "validate",
+            "config": config,
+            "input": {"case_sensitive": true},
+        }));
+        assert_eq!(response["success"], true);
+        assert_eq!(response["output"]["valid"], false);
+        assert_eq!(response["output"]["errors"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn exec_refuses_what_validate_reports() {
+        let prep = json!({"cleaned_text": "red fish blue fish"});
+        for config in [json!({"collocations": true, "collocation_window": 0}), json!({"min_word_length": 5, "max_word_length": 2})] {
+            let validation = handle_validate(&request("validate", config.clone(), json!({"text": "hi"}))).output.unwrap();
+            let exec = handle_exec(&request("exec", config.clone(), prep.clone()));
+            assert_eq!(exec.error_code, Some(ErrorCode::InvalidConfig), "{}", config);
+            assert_eq!(exec.error.unwrap(), format!("Invalid config: {}", validation["errors"][0].as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn accumulators_saturate_at_the_boundary() {
+        let mut acc = usize::MAX - 1;
+        assert!(!saturating_accumulate(&mut acc, 1));
+        assert_eq!(acc, usize::MAX);
+        assert!(saturating_accumulate(&mut acc, 1));
+        assert_eq!(acc, usize::MAX);
+
+        let mut acc = usize::MAX - 2;
+        assert!(saturating_accumulate(&mut acc, 5));
+        assert_eq!(acc, usize::MAX);
+    }
+
+    #[test]
+    fn overflow_flag_is_omitted_when_counts_fit() {
+        let output = count(json!({}), json!({"text": "plenty of room here"}));
+        assert!(output.get("overflow").is_none());
+    }
+
+    #[test]
+    fn min_frequency_drops_hapax_legomena() {
+        let output = count(
+            json!({"min_frequency": 2}),
+            json!({"text": "rust wasm rust plugin wasm host"}),
+        );
+        assert_eq!(output["word_frequencies"], json!({"rust": 2, "wasm": 2}));
+        assert_eq!(output["unique_words"], 2);
+        assert_eq!(output["total_words"], 6);
+    }
+
+    #[test]
+    fn min_frequency_applies_to_suspicious_and_novel_words() {
+        let config = json!({"min_frequency": 2, "detect_mixed_script": true, "novelty": true, "novelty_fp_rate": 0.001});
+        let output = count(config, json!({"text": "p\u{430}ypal g\u{43e}ogle g\u{43e}ogle kiwi kiwi lime"}));
+        assert_eq!(output["suspicious_tokens"], json!(["g\u{43e}ogle"]));
+        assert_eq!(output["novel_words"], json!(["g\u{43e}ogle", "kiwi"]));
+    }
+
+    #[test]
+    fn schema_bundle_has_every_schema_for_word_count() {
+        let mut out = vec![0u8; 64 * 1024];
+        let len = unsafe { schema_bundle(out.as_mut_ptr(), out.len()) };
+        let bundle: serde_json::Value = serde_json::from_slice(&out[..len]).unwrap();
+        let node = &bundle["nodes"]["word-count"];
+        for schema in ["config", "output"] {
+            assert_eq!(node[schema]["type"], "object", "{} schema missing", schema);
+        }
+        assert_eq!(node["input"]["type"], json!(["object", "string"]), "input schema missing");
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn collation_orders_explanations_and_ties() {
+        let input = json!({"text": "zèbre etre été", "explain": true});
+        let tokens = |output: &serde_json::Value| -> Vec<String> {
+            output["explanations"].as_array().unwrap().iter()
+                .map(|e| e["token"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let output = count(json!({}), input.clone());
+        assert_eq!(tokens(&output), ["etre", "zèbre", "été"]);
+
+        let output = count(json!({"collation": "fr"}), input);
+        assert_eq!(tokens(&output), ["été", "etre", "zèbre"]);
+
+        // Both are five chars long
+        let input = json!({"text": "ezzzz étaie"});
+        assert_eq!(count(json!({}), input.clone())["shortest_word"], "ezzzz");
+        assert_eq!(count(json!({"collation": "fr"}), input)["shortest_word"], "étaie");
+    }
+
+    #[test]
+    fn unknown_collation_is_an_error() {
+        let response = handle_validate(&request("validate", json!({"collation": "???"}), json!({"text": "hi"})));
+        assert_eq!(response.output.unwrap()["valid"], false);
+    }
+
+    #[test]
+    fn fed_chunks_split_mid_character_count_as_one_word() {
+        let text = "café crème café".as_bytes();
+        // Split inside the two-byte "é"
+        let split = "caf".len() + 1;
+        for chunk in [&text[..split], &text[split..]] {
+            assert_eq!(unsafe { feed(chunk.as_ptr(), chunk.len()) }, 1);
+        }
+        let finish = json!({"node": "word-count", "function": "prep"}).to_string();
+        let mut out = vec![0u8; 64 * 1024];
+        let len = unsafe { feed_finish(finish.as_ptr(), finish.len(), out.as_mut_ptr(), out.len()) };
+        let prep: serde_json::Value = serde_json::from_slice(&out[..len]).unwrap();
+        assert_eq!(prep["output"]["original_text"], "café crème café");
+
+        let output = count(json!({}), json!({"text": prep["output"]["original_text"]}));
+        assert_eq!(output["word_frequencies"]["café"], 2);
+    }
+
+    #[test]
+    fn meta_is_serialized_only_when_present() {
+        let plain = serde_json::to_value(Response::ok(json!({}))).unwrap();
+        assert!(plain.get("meta").is_none());
+
+        let annotated = Response { meta: Some(json!({"cache_ttl": 60})), ..Response::ok(json!({})) };
+        let annotated = serde_json::to_value(annotated).unwrap();
+        assert_eq!(annotated["meta"], json!({"cache_ttl": 60}));
+        assert!(annotated["output"].get("cache_ttl").is_none());
+    }
+
+    #[test]
+    fn exec_annotates_cost() {
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": "one two three"})));
+        let response = handle_exec(&request("exec", json!({}), prep.output.unwrap()));
+        assert_eq!(response.meta, Some(json!({"cost": 3})));
+    }
+
+    #[test]
+    fn preserved_entities_are_counted_whole() {
+        let output = count(
+            json!({"preserve_entities": ["url", "email"]}),
+            json!({"text": "Mail user@example.com or see (https://example.com/docs?page=2), thanks."}),
+        );
+        let frequencies = output["word_frequencies"].as_object().unwrap();
+        assert_eq!(frequencies["user@example.com"], 1);
+        assert_eq!(frequencies["https://example.com/docs?page=2"], 1);
+        assert_eq!(frequencies["thanks"], 1);
+        assert!(!frequencies.contains_key("example"));
+    }
+
+    #[test]
+    fn entities_are_split_unless_preserved() {
+        let output = count(json!({}), json!({"text": "user@example.com"}));
+        assert_eq!(output["word_frequencies"], json!({"user": 1, "example": 1, "com": 1}));
+        let output = count(json!({"preserve_entities": ["url"]}), json!({"text": "user@example.com"}));
+        assert_eq!(output["total_words"], 3);
+    }
+
+    #[test]
+    fn entity_detection() {
+        assert!(is_url("http://example.com"));
+        assert!(is_url("www.example.org/path"));
+        assert!(!is_url("https://localhost"));
+        assert!(!is_url("example.com"));
+        assert!(is_email("first.last+tag@mail.example.co"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("user@host"));
+    }
+
+    fn prep_output(config: serde_json::Value, text: &str) -> serde_json::Value {
+        handle_prep(&request("prep", config, json!({"text": text}))).output.unwrap()
+    }
+
+    #[test]
+    fn prep_counts_paragraphs_separated_by_blank_lines() {
+        let output = prep_output(json!({"count_lines": true}), "One.\nStill one.\n\n  \nTwo.\n");
+        assert_eq!(output["line_count"], 5);
+        assert_eq!(output["paragraph_count"], 2);
+    }
+
+    #[test]
+    fn prep_treats_crlf_as_one_line_break() {
+        let output = prep_output(json!({"count_lines": true}), "a\r\nb\r\n\r\nc");
+        assert_eq!(output["line_count"], 4);
+        assert_eq!(output["paragraph_count"], 2);
+        let output = prep_output(json!({}), "a\r\nb");
+        assert!(output.get("line_count").is_none());
+    }
+
+    #[test]
+    fn prep_reports_char_stats_of_the_original_text() {
+        let output = prep_output(json!({"include_char_stats": true}), "Hi, Zoë! 42 ÷ 7?\t\u{7}");
+        assert_eq!(
+            output["char_stats"],
+            json!({"letters": 5, "digits": 3, "punctuation": 4, "whitespace": 5, "total": 18})
+        );
+        assert!(prep_output(json!({}), "Hi!").get("char_stats").is_none());
+    }
+
+    #[test]
+    fn novel_words_exclude_earlier_documents() {
+        let config = json!({"novelty": true, "novelty_fp_rate": 0.001});
+        let first = count(config.clone(), json!({"text": "apple banana cherry"}));
+        assert_eq!(first["novel_words"], json!(["apple", "banana", "cherry"]));
+        let second = count(config, json!({"text": "banana cherry date"}));
+        assert_eq!(second["novel_words"], json!(["date"]));
+        assert!(count(json!({}), json!({"text": "fig"})).get("novel_words").is_none());
+    }
+
+    #[test]
+    fn columns_format_round_trips_to_the_same_frequencies() {
+        let input = json!({"text": "rust wasm rust host rust wasm"});
+        let map = count(json!({}), input.clone());
+        let columns = count(json!({"output_format": "columns"}), input);
+
+        let words = columns["word_frequencies"]["words"].as_array().unwrap();
+        let counts = columns["word_frequencies"]["counts"].as_array().unwrap();
+        assert_eq!(words, &[json!("rust"), json!("wasm"), json!("host")]);
+        assert_eq!(counts, &[json!(3), json!(2), json!(1)]);
+        let rebuilt: serde_json::Map<String, serde_json::Value> = words.iter()
+            .map(|w| w.as_str().unwrap().to_string())
+            .zip(counts.iter().cloned())
+            .collect();
+        assert_eq!(serde_json::Value::Object(rebuilt), map["word_frequencies"]);
+    }
+
+    #[test]
+    fn english_stemming_collapses_inflections() {
+        let output = count(json!({"stem": "en"}), json!({"text": "run running runs running"}));
+        assert_eq!(output["word_frequencies"], json!({"run": 4}));
+        assert_eq!(output["canonical_forms"], json!({"run": "running"}));
+    }
+
+    #[test]
+    fn unknown_stemming_language_is_an_error() {
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": "run"})));
+        let response = handle_exec(&request("exec", json!({"stem": "xx"}), prep.output.unwrap()));
+        assert!(!response.success);
+        assert!(response.error.unwrap().contains("Unsupported stemming language \"xx\""));
+    }
+
+    #[test]
+    fn alloc_past_the_memory_permission_returns_null_and_call_errors() {
+        let limit = parse_memory_limit(PERMISSION_MEMORY).unwrap();
+        assert!(alloc(limit + 1).is_null());
+
+        let mut out = vec![0u8; 4096];
+        let len = unsafe { call(std::ptr::null(), 64, out.as_mut_ptr(), out.len()) };
+        let response: serde_json::Value = serde_json::from_slice(&out[..len]).unwrap();
+        assert_eq!(response["success"], false);
+        let error = response["error"].as_str().unwrap();
+        assert!(error.starts_with("No input buffer"), "{}", error);
+
+        let before = memory::outstanding();
+        let ptr = alloc(1024);
+        assert!(!ptr.is_null());
+        unsafe { dealloc(ptr, 1024) };
+        assert!(memory::outstanding() <= before);
+    }
+
+    #[test]
+    fn declared_examples_reproduce_exactly() {
+        let report = run_all_examples();
+        assert!(report.failed.is_empty(), "{}", serde_json::to_string_pretty(&report.failed).unwrap());
+        assert_eq!(report.passed, 1);
+    }
+
+    #[test]
+    fn changed_example_output_is_reported() {
+        let node = registry::find("word-count").unwrap();
+        let mut example = (node.definition)().examples.remove(0);
+        example.output["total_words"] = json!(6);
+        assert_ne!(run_example(node, &example).as_ref(), Ok(&example.output));
+    }
+
+    #[test]
+    fn long_words_are_capped_in_output_only() {
+        let blob = "x".repeat(10_000);
+        let input = json!({"text": format!("{} tiny", blob)});
+        let output = count(json!({"max_word_output_len": 20}), input.clone());
+        assert_eq!(output["longest_word"], format!("{}…", "x".repeat(20)));
+        assert_eq!(output["shortest_word"], "tiny");
+        // The full token is still what gets counted
+        assert_eq!(output["word_frequencies"][&blob], 1);
+        assert_eq!(output["average_word_length"], (10_000.0 + 4.0) / 2.0);
+
+        assert_eq!(count(json!({}), input)["longest_word"], blob);
+    }
+
+    #[test]
+    fn output_cap_counts_characters_not_bytes() {
+        assert_eq!(truncate_for_output("ééééé", Some(3)), "ééé…");
+        assert_eq!(truncate_for_output("ééé", Some(3)), "ééé");
+        assert_eq!(truncate_for_output("ééé", None), "ééé");
+    }
+
+    #[test]
+    fn exec_logs_carry_the_request_trace_id() {
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": "one two three"})));
+        let exec = Request {
+            trace_id: Some("run-42/node-7".to_string()),
+            ..request("exec", json!({}), prep.output.unwrap())
+        };
+        host::take_logs();
+        assert!(handle_exec(&exec).success);
+        let logs = host::take_logs();
+        assert!(!logs.is_empty());
+        for (_, line) in &logs {
+            assert!(line.starts_with("[trace_id=run-42/node-7] "), "{}", line);
+        }
+    }
+
+    #[test]
+    fn leading_bom_is_not_part_of_the_first_word() {
+        let output = count(json!({}), json!({"text": "\u{FEFF}hello wonderful world"}));
+        assert_eq!(output["word_frequencies"]["hello"], 1);
+        assert_eq!(output["shortest_word"], "hello");
+
+        let prep = prep_output(json!({"preserve_entities": ["url"]}), "\u{FEFF}https://example.com/a more");
+        assert_eq!(prep["original_text"], "https://example.com/a more");
+        assert!(prep["cleaned_text"].as_str().unwrap().starts_with("https://example.com/a "));
+    }
+
+    #[test]
+    fn sentence_breakdown_sums_to_the_total() {
+        let output = count(
+            json!({"include_sentence_breakdown": true}),
+            json!({"text": "The cat sat on the warm mat. Then it slept until noon!"}),
+        );
+        assert_eq!(
+            output["sentences"],
+            json!([
+                {"text": "The cat sat on the warm mat.", "word_count": 4},
+                {"text": "Then it slept until noon!", "word_count": 4},
+            ])
+        );
+        assert_eq!(output["total_words"], 8);
+        assert_eq!(count(json!({}), json!({"text": "The cat sat on the warm mat. Then it slept until noon!"}))["total_words"], 8);
+    }
+
+    #[test]
+    fn sentences_split_only_before_whitespace() {
+        assert_eq!(
+            split_sentences("He said \"Stop!\" Then left... Version 1.2 is out?! ok"),
+            vec!["He said \"Stop!\" ", "Then left... ", "Version 1.2 is out?! ", "ok"]
+        );
+        assert_eq!(split_sentences("No terminator"), vec!["No terminator"]);
+        assert!(split_sentences("").is_empty());
+    }
+
+    #[test]
+    fn dispatch_reports_routing_failures_by_code() {
+        let cases = [
+            ("no-such-node", "exec", "unknown_node"),
+            ("word-count", "summon", "unknown_function"),
+        ];
+        for (node, function, code) in cases {
+            let response = dispatch(registry::NODES, &Request { node: node.to_string(), ..request(function, json!({}), json!({})) });
+            assert!(!response.success);
+            assert_eq!(serde_json::to_value(response.error_code).unwrap(), code, "for {}/{}", node, function);
+        }
+    }
+
+    fn pipeline_keys(steps: serde_json::Value, text: &str) -> serde_json::Value {
+        let config = json!({"stem": "en", "normalization_pipeline": steps, "stop_words": []});
+        count(config, json!({"text": text}))["word_frequencies"].clone()
+    }
+
+    #[test]
+    fn pipeline_order_decides_the_keys() {
+        let text = "Running RUNNING";
+        assert_eq!(pipeline_keys(json!(["lowercase", "stem"]), text), json!({"run": 2}));
+        // Snowball leaves the all-caps spelling alone before it's lowercased
+        assert_eq!(pipeline_keys(json!(["stem", "lowercase"]), text), json!({"run": 1, "running": 1}));
+        assert_eq!(pipeline_keys(json!([]), text), json!({"Running": 1, "RUNNING": 1}));
+    }
+
+    #[test]
+    fn pipeline_keys_are_stable_and_apply_to_the_allowlist() {
+        let steps = json!(["fold_diacritics", "lowercase"]);
+        let text = "Café CAFÉ cafe naïve";
+        assert_eq!(pipeline_keys(steps.clone(), text), json!({"cafe": 3, "naive": 1}));
+        assert_eq!(pipeline_keys(steps.clone(), text), pipeline_keys(steps.clone(), text));
+
+        let config = json!({"normalization_pipeline": steps, "allowlist": ["CAFÉ"]});
+        assert_eq!(count(config, json!({"text": text}))["word_frequencies"], json!({"cafe": 3}));
+    }
+
+    #[test]
+    fn pipeline_stem_step_needs_a_language() {
+        let exec = handle_exec(&request(
+            "exec",
+            json!({"normalization_pipeline": ["stem"]}),
+            json!({"original_text": "runs", "cleaned_text": "runs", "case_sensitive": false}),
+        ));
+        assert!(!exec.success);
+        assert!(exec.error.unwrap().contains("no stem language"));
+    }
+
+    #[test]
+    fn erroring_calls_free_everything_they_allocate() {
+        let requests: Vec<Vec<u8>> = vec![
+            b"\xff\xfe".to_vec(),
+            b"{not json".to_vec(),
+            br#"{"node": "no-such-node", "function": "exec"}"#.to_vec(),
+            br#"{"node": "word-count", "function": "summon"}"#.to_vec(),
+            br#"{"node": "word-count", "function": "prep"}"#.to_vec(),
+            br#"{"node": "word-count", "function": "prep", "input": {"text": 5}}"#.to_vec(),
+            br#"{"node": "word-count", "function": "exec"}"#.to_vec(),
+            br#"{"node": "word-count", "function": "exec", "config": {"stem": "xx"}, "input": {"cleaned_text": "a b"}}"#.to_vec(),
+            br#"{"node": "word-count", "function": "post"}"#.to_vec(),
+            br#"{"node": "word-count", "function": "exec", "input": {}, "wire": {"compress": true, "dictionary": true}}"#.to_vec(),
+        ];
+        let mut out = vec![0u8; 4096];
+        let mut run = || {
+            for request in &requests {
+                let returned = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+                // The last one can't be encoded as asked (SerializationFailed)
+                let (_, len) = decode_return(returned).unwrap();
+                let response: serde_json::Value = serde_json::from_slice(&out[..len]).unwrap();
+                assert_eq!(response["success"], false, "{}", String::from_utf8_lossy(request));
+            }
+            // Captured log lines aren't the calls' to free
+            host::take_logs();
+        };
+        // Lazily initialized caches and statics settle on the first round
+        run();
+        let before = memory::heap::net_bytes();
+        for _ in 0..100 {
+            run();
+        }
+        assert_eq!(memory::heap::net_bytes(), before);
+    }
+
+    #[test]
+    fn length_histogram_buckets_words_by_character_count() {
+        let output = count(
+            json!({"include_length_histogram": true, "stop_words": []}),
+            json!({"text": "cat dog bird naïve über fish elephant cat"}),
+        );
+        assert_eq!(output["length_histogram"], json!({"3": 3, "4": 3, "5": 1, "8": 1}));
+        assert!(count(json!({}), json!({"text": "cat"})).get("length_histogram").is_none());
+
+        // The length filters and statistics use the same length
+        let config = json!({"include_length_histogram": true, "min_word_length": 5, "stop_words": []});
+        let output = count(config, json!({"text": "café naïve"}));
+        assert_eq!(output["length_histogram"], json!({"5": 1}));
+        assert_eq!(output["average_word_length"], 5.0);
+    }
+
+    #[test]
+    fn delta_mode_returns_only_changed_frequencies() {
+        let config = json!({"delta": true, "stop_words": []});
+        let first = count(config.clone(), json!({"text": "apple banana cherry"}));
+        assert_eq!(first["delta"]["reset"], true);
+        assert_eq!(first["delta"]["added"], json!({"apple": 1, "banana": 1, "cherry": 1}));
+
+        let second = count(config.clone(), json!({"text": "apple banana cherry banana date"}));
+        assert_eq!(
+            second["delta"],
+            json!({"reset": false, "added": {"date": 1}, "updated": {"banana": 2}, "removed": []})
+        );
+        assert_eq!(second["word_frequencies"], json!({}));
+        assert_eq!(second["total_words"], 5);
+        assert_eq!(second["unique_words"], 4);
+
+        let third = count(config, json!({"text": "banana date"}));
+        assert_eq!(
+            third["delta"],
+            json!({"reset": false, "added": {}, "updated": {"banana": 1}, "removed": ["apple", "cherry"]})
+        );
+    }
+
+    #[test]
+    fn only_word_count_exec_updates_the_frequency_cache() {
+        let config = json!({"delta": true, "stop_words": []});
+        count(config.clone(), json!({"text": "apple banana"}));
+
+        let prep = handle_corpus_prep(&request("prep", json!({}), json!({"documents": ["cherry date"]})));
+        assert!(handle_corpus_exec(&request("exec", json!({}), prep.output.unwrap())).success);
+        let diversity = call_json(json!({
+            "node": "lexical-diversity", "function": "exec", "input": {"cleaned_text": "elder fig", "case_sensitive": false}
+        }));
+        assert_eq!(diversity["success"], true, "{}", diversity);
+
+        let output = count(config, json!({"text": "apple banana"}));
+        assert_eq!(output["delta"], json!({"reset": false, "added": {}, "updated": {}, "removed": []}));
+    }
+
+    #[test]
+    fn delta_after_released_state_is_a_reset() {
+        count(json!({}), json!({"text": "apple banana"}));
+        state::clear();
+        let output = count(json!({"delta": true}), json!({"text": "apple banana"}));
+        assert_eq!(output["delta"]["reset"], true);
+        assert_eq!(output["delta"]["added"], json!({"apple": 1, "banana": 1}));
+    }
+
+    fn allocated_by(f: impl FnOnce()) -> usize {
+        let before = memory::heap::allocated_bytes();
+        f();
+        memory::heap::allocated_bytes() - before
+    }
+
+    #[test]
+    fn large_inputs_are_read_in_place() {
+        let text = "word ".repeat(200_000);
+        let input = json!({"text": text});
+        let config = json!({"min_word_length": 2, "stop_words": ["the"]});
+
+        // What cloning the `Value` first used to cost
+        let cloned = allocated_by(|| {
+            serde_json::from_value::<WordCounterInput>(input.clone()).unwrap();
+        });
+        let borrowed = allocated_by(|| {
+            InputView::deserialize(&input).unwrap();
+        });
+        assert!(cloned >= text.len(), "{} bytes", cloned);
+        assert_eq!(borrowed, 0);
+
+        let prep = request("prep", config, input);
+        let before = allocated_by(|| {
+            let input: WordCounterInput = serde_json::from_value(prep.input.clone().unwrap()).unwrap();
+            let config: WordCounterConfig = serde_json::from_value(prep.config.clone().unwrap()).unwrap();
+            Response::ok(serde_json::to_value(prepare(&config, input.view())).unwrap());
+        });
+        let after = allocated_by(|| {
+            handle_prep(&prep);
+        });
+        assert!(before - after >= text.len(), "{} vs {} bytes", before, after);
+    }
+
+    fn ignoring(text: &str) -> serde_json::Value {
+        let config = json!({"ignore_between": [{"start": "```", "end": "```"}, {"start": "---", "end": "---"}]});
+        prep_output(config, text)
+    }
+
+    #[test]
+    fn fenced_code_and_front_matter_are_not_counted() {
+        let text = "---\ntitle: Notes\n---\nRead this.\n```\nlet skipped = 1;\n```\nThen more.";
+        let prep = ignoring(text);
+        assert_eq!(prep["ignored_chars"], 20 + 24);
+        assert!(prep.get("warnings").is_none());
+        assert_eq!(prep["original_text"], text);
+        let config = json!({"ignore_between": [{"start": "```", "end": "```"}, {"start": "---", "end": "---"}]});
+        let output = count(config, json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"read": 1, "this": 1, "then": 1, "more": 1}));
+    }
+
+    #[test]
+    fn unclosed_marker_ignores_to_the_end_with_a_warning() {
+        let prep = ignoring("kept words ```never closed");
+        assert_eq!(prep["cleaned_text"], "kept words ");
+        assert_eq!(prep["ignored_chars"], 15);
+        assert_eq!(prep["warnings"], json!(["Unclosed \"```\" marker at character 11; ignored the rest of the text"]));
+    }
+
+    fn diversity(text: &str) -> serde_json::Value {
+        let node = |function: &str, input: serde_json::Value| {
+            let response = dispatch(
+                registry::NODES,
+                &Request { node: "lexical-diversity".to_string(), ..request(function, json!({}), input) },
+            );
+            assert!(response.success, "{} failed: {:?}", function, response.error);
+            response
+        };
+        let prep = node("prep", json!({"text": text})).output.unwrap();
+        let exec = node("exec", prep).output.unwrap();
+        let post = node("post", exec);
+        assert_eq!(post.next, None);
+        post.output.unwrap()
+    }
+
+    #[test]
+    fn lexical_diversity_node_ranks_varied_text_higher() {
+        let varied = diversity(
+            "Quick brown foxes leap over sleepy dogs while clever crows watch from ancient oaks, \
+             barely noticing travellers heading toward distant green hills beyond quiet rivers",
+        );
+        let repetitive = diversity(
+            "Dogs chase cats and cats chase dogs, dogs chase cats again, cats chase dogs again, \
+             dogs chase cats and cats chase dogs and dogs chase cats",
+        );
+        assert_eq!(varied["total_words"], varied["unique_words"]);
+        for measure in ["ttr", "root_ttr"] {
+            assert!(varied[measure].as_f64() > repetitive[measure].as_f64(), "{}", measure);
+        }
+        // Never repeating a word, the varied text completes no MTLD factor
+        assert!(varied.get("mtld").is_none());
+        assert!(repetitive["mtld"].as_f64().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn lexical_diversity_is_optional_on_word_count() {
+        assert!(count(json!({}), json!({"text": "hello"})).get("lexical_diversity").is_none());
+        let output = count(json!({"lexical_diversity": true}), json!({"text": "hello hello"}));
+        assert_eq!(output["lexical_diversity"], json!({"ttr": 0.5, "root_ttr": 1.0 / 2f64.sqrt(), "mtld": 2.0}));
+    }
+
+    // Sends a request through `call_owned`, decodes the response and frees
+    // the buffer it was handed
+    fn call_owned_json(request: &str) -> serde_json::Value {
+        let len = unsafe { call_owned(request.as_ptr(), request.len()) };
+        let ptr = last_output_ptr();
+        assert!(!ptr.is_null());
+        let response = serde_json::from_slice(unsafe { std::slice::from_raw_parts(ptr, len) }).unwrap();
+        unsafe { dealloc(ptr, len) };
+        response
+    }
+
+    #[test]
+    fn call_owned_hands_over_the_same_response_as_call() {
+        let requests = [
+            json!({"node": "word-count", "function": "prep", "input": {"text": "hello world"}}).to_string(),
+            // Larger than the buffer `call` would likely be given first
+            json!({"node": "word-count", "function": "prep", "input": {"text": "word ".repeat(20_000)}}).to_string(),
+            "{not json".to_string(),
+        ];
+        for request in &requests {
+            let owned = call_owned_json(request);
+            let mut out = vec![0u8; 1024 * 1024];
+            let len = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+            assert_eq!(owned, serde_json::from_slice::<serde_json::Value>(&out[..len]).unwrap());
+        }
+    }
+
+    #[test]
+    fn call_owned_buffers_are_freed_by_dealloc() {
+        let request = json!({"node": "word-count", "function": "prep", "input": {"text": "hello world"}}).to_string();
+        call_owned_json(&request);
+        let before = memory::heap::net_bytes();
+        for _ in 0..10 {
+            assert_eq!(call_owned_json(&request)["success"], true);
+        }
+        assert_eq!(memory::heap::net_bytes(), before);
+    }
+
+    #[test]
+    fn stem_stop_words_filters_every_inflection_of_a_stop_word() {
+        let text = json!({"text": "Running runs run ran quickly"});
+        let surface = count(json!({"stem": "en", "stop_words": ["run"]}), text.clone());
+        assert_eq!(surface["word_frequencies"], json!({"run": 2, "ran": 1, "quick": 1}));
+
+        let stemmed = count(json!({"stem": "en", "stop_words": ["run"], "stem_stop_words": true}), text.clone());
+        assert_eq!(stemmed["word_frequencies"], json!({"ran": 1, "quick": 1}));
+
+        // The stop word's own inflections are stemmed before matching
+        let inflected = count(json!({"stem": "en", "stop_words": ["running"], "stem_stop_words": true}), text);
+        assert_eq!(inflected["word_frequencies"], stemmed["word_frequencies"]);
+    }
+
+    #[test]
+    fn pipeline_keys_stop_words_like_tokens() {
+        let config = json!({
+            "stem": "en",
+            "normalization_pipeline": ["lowercase", "stem"],
+            "stop_words": ["running"],
+        });
+        let output = count(config, json!({"text": "Running runs ran", "explain": true}));
+        assert_eq!(output["word_frequencies"], json!({"ran": 1}));
+        assert_eq!(output["explanations"][1], json!({"token": "run", "kept": false, "reason": "stop_word"}));
+    }
+
+    // Nodes that invoke each other: "self" itself, "ping" and "pong" each
+    // other, and "outer" -> "middle" -> word-count
+    static INVOKING: &registry::NodeRegistry = &[
+        registry::NodeEntry { node_type: "self", definition: word_count_definition, functions: &[("exec", invoke_self)], bytes_functions: &[] },
+        registry::NodeEntry { node_type: "ping", definition: word_count_definition, functions: &[("exec", invoke_pong)], bytes_functions: &[] },
+        registry::NodeEntry { node_type: "pong", definition: word_count_definition, functions: &[("exec", invoke_ping)], bytes_functions: &[] },
+        registry::NodeEntry { node_type: "outer", definition: word_count_definition, functions: &[("exec", invoke_middle)], bytes_functions: &[] },
+        registry::NodeEntry { node_type: "middle", definition: word_count_definition, functions: &[("exec", invoke_word_count)], bytes_functions: &[] },
+        registry::NodeEntry { node_type: "word-count", definition: word_count_definition, functions: &[("prep", handle_prep)], bytes_functions: &[] },
+    ];
+
+    fn invoke_self(request: &Request) -> Response {
+        invoke(INVOKING, request, "self", "exec", None, json!({}))
+    }
+
+    fn invoke_ping(request: &Request) -> Response {
+        invoke(INVOKING, request, "ping", "exec", None, json!({}))
+    }
+
+    fn invoke_pong(request: &Request) -> Response {
+        invoke(INVOKING, request, "pong", "exec", None, json!({}))
+    }
+
+    fn invoke_middle(request: &Request) -> Response {
+        invoke(INVOKING, request, "middle", "exec", None, json!({}))
+    }
+
+    fn invoke_word_count(request: &Request) -> Response {
+        invoke(INVOKING, request, "word-count", "prep", None, json!({"text": "hello"}))
+    }
+
+    fn run_invoking(node: &str, max_call_depth: Option<usize>) -> Response {
+        dispatch(INVOKING, &Request { node: node.to_string(), max_call_depth, ..request("exec", json!({}), json!({})) })
+    }
+
+    #[test]
+    fn self_invoking_node_is_a_cycle() {
+        let response = run_invoking("self", None);
+        assert_eq!(response.error_code, Some(ErrorCode::CycleDetected));
+        assert_eq!(response.error.unwrap(), "Invoking self would re-enter the call chain self");
+    }
+
+    #[test]
+    fn two_node_cycle_is_detected() {
+        let response = run_invoking("ping", None);
+        assert_eq!(response.error_code, Some(ErrorCode::CycleDetected));
+        assert_eq!(response.error.unwrap(), "Invoking ping would re-enter the call chain ping -> pong");
+    }
+
+    #[test]
+    fn nesting_past_max_call_depth_is_refused() {
+        assert!(run_invoking("outer", None).success);
+        assert!(run_invoking("outer", Some(2)).success);
+        let response = run_invoking("outer", Some(1));
+        assert_eq!(response.error_code, Some(ErrorCode::DepthExceeded));
+        assert_eq!(response.error.unwrap(), "Invoking word-count would nest 2 calls deep, past the limit of 1");
+    }
+
+    #[test]
+    fn only_within_counts_just_the_quoted_text() {
+        let config = json!({"only_within": "\"([^\"]*)\"", "stop_words": []});
+        let text = r#"She said "hello there" and then "goodbye" before leaving"#;
+        let output = count(config.clone(), json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"hello": 1, "there": 1, "goodbye": 1}));
+
+        // Matches don't overlap: `"a "b" c"` is read as `"a "`, b, `" c"`.
+        // `""` adds nothing.
+        let output = count(config, json!({"text": r#""a "b" c" "" "d""#}));
+        assert_eq!(output["word_frequencies"], json!({"a": 1, "c": 1, "d": 1}));
+    }
+
+    #[test]
+    fn only_within_without_a_match_counts_nothing_and_warns() {
+        let config = json!({"only_within": "\"([^\"]*)\""});
+        let prep = prep_output(config.clone(), "no quotes here");
+        assert_eq!(prep["cleaned_text"], "");
+        assert_eq!(prep["warnings"], json!(["only_within pattern \"\\\"([^\\\"]*)\\\"\" matched no text; nothing was counted"]));
+        let exec = handle_exec(&request("exec", config, prep.clone()));
+        assert_eq!(exec.output.unwrap()["total_words"], 0);
+        assert_eq!(exec.meta.unwrap()["warnings"], prep["warnings"]);
+    }
+
+    #[test]
+    fn invalid_only_within_pattern_is_an_error() {
+        let config = json!({"only_within": "(unclosed"});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "hello"})));
+        assert!(!prep.success);
+        assert!(prep.error.unwrap().starts_with("Invalid config: Invalid only_within pattern"));
+        let validation = handle_validate(&request("validate", config, json!({"text": "hello"}))).output.unwrap();
+        assert_eq!(validation["valid"], false);
+    }
+
+    #[test]
+    fn input_may_be_a_bare_string() {
+        let object: WordCounterInput = serde_json::from_str(r#"{"text": "hello world"}"#).unwrap();
+        let bare: WordCounterInput = serde_json::from_str(r#""hello world""#).unwrap();
+        assert_eq!(serde_json::to_value(&bare).unwrap(), serde_json::to_value(&object).unwrap());
+        assert!(!bare.case_sensitive);
+
+        let from_object = prep_output(json!({}), "Hello world");
+        let from_bare = handle_prep(&request("prep", json!({}), json!("Hello world"))).output.unwrap();
+        assert_eq!(from_bare, from_object);
+
+        let error = serde_json::from_value::<WordCounterInput>(json!(5)).unwrap_err().to_string();
+        assert_eq!(error, "invalid type: integer `5`, expected an input object or its text as a string");
+    }
+
+    // Takes 10ms of mock time per token it classifies
+    struct SlowClassifier<'a>(&'a clock::MockClock);
+
+    impl TokenClassifier for SlowClassifier<'_> {
+        fn classify(&self, _token: &str) -> TokenClass {
+            self.0.advance(10_000_000);
+            TokenClass::Other
+        }
+    }
+
+    #[test]
+    fn slow_exec_signals_backpressure() {
+        let config = json!({"keep_classes": ["other"], "backpressure_after_ms": 25});
+        let exec = |text: &str| {
+            let prep = handle_prep(&request("prep", config.clone(), json!({"text": text})));
+            let clock = clock::MockClock::new(0);
+            exec_with(&request("exec", config.clone(), prep.output.unwrap()), &SlowClassifier(&clock), &clock)
+        };
+
+        let fast = exec("one two");
+        assert!(fast.success);
+        assert_eq!(fast.meta.unwrap(), json!({"cost": 2}));
+
+        let slow = exec("one two three four five");
+        assert!(slow.success);
+        assert_eq!(slow.output.unwrap()["total_words"], 5);
+        assert_eq!(slow.meta.unwrap(), json!({"cost": 5, "backpressure": true, "retry_after_ms": 25}));
+    }
+
+    #[test]
+    fn output_transform_reshapes_post_output_after_routing() {
+        let config = json!({"output_transform": {"select": ["total_words", "unique_words"], "rename": {"total_words": "count"}}});
+        let exec = count(config.clone(), json!({"text": "one two two"}));
+        let post = handle_post(&request("post", config, exec));
+        assert_eq!(post.next.as_deref(), Some("short"));
+        assert_eq!(post.output.unwrap(), json!({"count": 3, "unique_words": 2}));
+
+        let invalid = json!({"output_transform": {"select": [""]}});
+        let validation = handle_validate(&request("validate", invalid, json!({"text": "hi"}))).output.unwrap();
+        assert_eq!(validation["errors"], json!(["Invalid output_transform path \"\""]));
+    }
+
+    #[test]
+    fn absent_config_uses_defaults_but_malformed_config_is_an_error() {
+        let none = Request { config: None, ..request("prep", json!({}), json!({"text": "the cat"})) };
+        let prep = handle_prep(&none);
+        assert!(prep.success);
+        let exec = handle_exec(&Request { config: None, ..request("exec", json!({}), prep.output.unwrap()) });
+        assert_eq!(exec.output.unwrap()["word_frequencies"], json!({"cat": 1}));
+
+        let malformed = json!({"min_word_length": "three"});
+        for (function, input) in [("prep", json!({"text": "the cat"})), ("exec", json!({})), ("post", json!({}))] {
+            let response = dispatch(registry::NODES, &request(function, malformed.clone(), input));
+            assert!(!response.success, "{}", function);
+            assert_eq!(response.error_code, Some(ErrorCode::InvalidConfig), "{}", function);
+            assert!(response.error.unwrap().starts_with("Invalid config: invalid type: string \"three\""));
+        }
+    }
+
+    #[test]
+    fn error_and_empty_routes_are_distinct_and_configurable() {
+        let config = json!({"empty_route": "no-content", "error_route": "failed", "stem": "xx"});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "the"})));
+        assert!(!prep.success);
+        assert_eq!(prep.next.as_deref(), Some("failed"));
+        let exec = handle_exec(&request("exec", config.clone(), json!({"cleaned_text": "the"})));
+        assert!(!exec.success);
+        assert_eq!(exec.next.as_deref(), Some("failed"));
+
+        let config = json!({"empty_route": "no-content", "error_route": "failed"});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "the"})));
+        let exec = handle_exec(&request("exec", config.clone(), prep.output.unwrap()));
+        let post = handle_post(&request("post", config.clone(), exec.output.unwrap()));
+        assert_eq!(post.next.as_deref(), Some("no-content"));
+        assert_eq!(handle_prep(&request("prep", config, json!({}))).next.as_deref(), Some("failed"));
+
+        // Unset, they keep their old behavior
+        let failed = handle_exec(&request("exec", json!({"stem": "xx"}), json!({})));
+        assert_eq!(failed.next, None);
+        let empty = handle_post(&request("post", json!({}), json!({"total_words": 0})));
+        assert_eq!(empty.next.as_deref(), Some("empty"));
+    }
+
+    fn call_bytes_json(request: serde_json::Value, data: &[u8]) -> serde_json::Value {
+        let request = request.to_string();
+        let mut out = vec![0u8; 4096];
+        let len = unsafe {
+            call_bytes(request.as_ptr(), request.len(), data.as_ptr(), data.len(), out.as_mut_ptr(), out.len())
+        };
+        serde_json::from_slice(&out[..len]).unwrap()
+    }
+
+    #[test]
+    fn binary_node_takes_non_utf8_bytes() {
+        let data = [0xff, 0xfe, 0x00, 0x80, b'a'];
+        let response = call_bytes_json(json!({"node": "byte-digest", "function": "exec"}), &data);
+        assert_eq!(response["success"], true);
+        assert_eq!(response["output"]["length"], 5);
+        assert_eq!(response["output"]["utf8"], false);
+        assert_eq!(response["output"]["fnv1a64"].as_str().unwrap().len(), 16);
+
+        let text = call_bytes_json(json!({"node": "byte-digest", "function": "exec"}), b"a");
+        assert_eq!(text["output"], json!({"length": 1, "fnv1a64": "af63dc4c8601ec8c", "utf8": true}));
+    }
+
+    #[test]
+    fn binary_and_json_functions_are_called_through_their_own_export() {
+        let response = call_bytes_json(json!({"node": "word-count", "function": "exec"}), b"hello");
+        assert_eq!(response["error_code"], "function_not_supported_by_node");
+        let response = call_json(json!({"node": "byte-digest", "function": "exec", "input": {}}));
+        assert_eq!(response["error_code"], "function_not_supported_by_node");
+
+        let metadata = plugin_metadata();
+        let digest = metadata.nodes.iter().find(|node| node.node_type == "byte-digest").unwrap();
+        assert!(digest.binary_input);
+        assert!(serde_json::to_value(&metadata.nodes[0]).unwrap().get("binaryInput").is_none());
+    }
+
+    fn emitted() -> Vec<serde_json::Value> {
+        host::take_emitted().iter().map(|payload| serde_json::from_slice(payload).unwrap()).collect()
+    }
+
+    #[test]
+    fn record_emits_once_per_completed_window() {
+        host::take_emitted();
+        let config = json!({"window": {"type": "tumbling", "size": 2}});
+        let records = ["red fish", "blue fish", "red car", "green car", "red"];
+        let counts: Vec<serde_json::Value> = records.iter()
+            .map(|text| {
+                let response = handle_record(&request("record", config.clone(), json!({"text": text})));
+                assert!(response.success, "record failed: {:?}", response.error);
+                response.output.unwrap()["emitted"].clone()
+            })
+            .collect();
+        assert_eq!(counts, [json!(0), json!(1), json!(0), json!(1), json!(0)]);
+
+        let windows = emitted();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0]["window"], json!({"start": 0, "end": 2, "partial": false, "records": 2}));
+        assert_eq!(windows[0]["output"]["word_frequencies"], json!({"red": 1, "blue": 1, "fish": 2}));
+        assert_eq!(windows[1]["output"]["word_frequencies"], json!({"red": 1, "green": 1, "car": 2}));
+
+        // The last window only got one of its two records
+        assert_eq!(handle_flush(&request("flush", config.clone(), json!({}))).output.unwrap()["emitted"], 1);
+        let rest = emitted();
+        assert_eq!(rest[0]["window"], json!({"start": 4, "end": 6, "partial": true, "records": 1}));
+        assert_eq!(rest[0]["output"]["total_words"], 1);
+        assert_eq!(handle_flush(&request("flush", config, json!({}))).output.unwrap()["emitted"], 0);
+    }
+
+    #[test]
+    fn closed_windows_are_counted_within_the_request_timeout() {
+        host::take_emitted();
+        let config = json!({"window": {"type": "tumbling", "size": 1}});
+        let response = handle_record(&Request {
+            timeout_ms: Some(0),
+            ..request("record", config.clone(), json!({"text": "red fish"}))
+        });
+        assert_eq!(response.next.as_deref(), Some("timeout"));
+        assert_eq!(response.output.unwrap(), json!({"timed_out": true, "timeout_ms": 0, "emitted": 0, "dropped": 1}));
+        assert!(emitted().is_empty());
+        handle_flush(&request("flush", config, json!({})));
+    }
+
+    #[test]
+    fn sliding_time_windows_emit_as_records_pass_their_end() {
+        host::take_emitted();
+        let config = json!({"window": {"type": "sliding", "size": 1000, "slide": 500, "by": "ms"}});
+        let record = |text: &str, timestamp_ms: u64| {
+            let response = handle_record(&request("record", config.clone(), json!({"text": text, "timestamp_ms": timestamp_ms})));
+            response.output.unwrap()["emitted"].as_u64().unwrap()
+        };
+        assert_eq!(record("alpha", 0), 0);
+        assert_eq!(record("beta", 600), 0);
+        assert_eq!(record("gamma", 1000), 1);
+        assert_eq!(record("delta", 1600), 1);
+        let windows: Vec<serde_json::Value> = emitted().into_iter().map(|w| w["window"].clone()).collect();
+        assert_eq!(windows, [
+            json!({"start": 0, "end": 1000, "partial": false, "records": 2}),
+            json!({"start": 500, "end": 1500, "partial": false, "records": 2}),
+        ]);
+        handle_flush(&request("flush", config, json!({})));
+        let partial: Vec<u64> = emitted().iter().map(|w| w["window"]["records"].as_u64().unwrap()).collect();
+        assert_eq!(partial, [2, 1]);
+    }
+
+    #[test]
+    fn record_needs_a_valid_window_config() {
+        let response = handle_record(&request("record", json!({}), json!({"text": "hi"})));
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidConfig));
+        let config = json!({"window": {"type": "sliding", "size": 2}});
+        let response = handle_record(&request("record", config.clone(), json!({"text": "hi"})));
+        assert_eq!(response.error.as_deref(), Some("Invalid config: sliding windows need a slide"));
+        let validation = handle_validate(&request("validate", config, json!({"text": "hi"}))).output.unwrap();
+        assert_eq!(validation["errors"], json!(["sliding windows need a slide"]));
+    }
+
+    #[test]
+    fn corpus_drops_terms_below_min_doc_freq() {
+        let config = json!({"min_doc_freq": 2});
+        let input = json!({"documents": [
+            "rust compiles to wasm",
+            {"text": "Rust plugins"},
+            "wasm plugins for hosts",
+            "rust rust plugins",
+        ]});
+        let prep = handle_corpus_prep(&request("prep", config.clone(), input));
+        assert!(prep.success, "prep failed: {:?}", prep.error);
+        let exec = handle_corpus_exec(&request("exec", config.clone(), prep.output.unwrap()));
+        assert!(exec.success, "exec failed: {:?}", exec.error);
+        let output = exec.output.unwrap();
+
+        // "compiles" and "hosts" each appear in one document
+        assert_eq!(output["document_frequencies"], json!({"plugins": 3, "rust": 3, "wasm": 2}));
+        assert_eq!(output["word_frequencies"], json!({"rust": 4, "plugins": 3, "wasm": 2}));
+        assert_eq!(output["documents"][0]["output"], json!({"total_words": 2, "unique_words": 2, "word_frequencies": {"rust": 1, "wasm": 1}}));
+        assert_eq!(output["document_count"], 4);
+        assert_eq!(output["total_words"], 9);
+
+        let post = handle_post(&request("post", config, output));
+        assert_eq!(post.next.as_deref(), Some("short"));
+    }
+
+    #[test]
+    fn corpus_documents_need_an_ok_flag_and_output() {
+        let exec = handle_corpus_exec(&request("exec", json!({}), json!({"documents": [
+            {"ok": true, "output": {"cleaned_text": "red fish"}},
+            {"ok": "yes", "output": {"cleaned_text": "blue fish"}},
+            {"ok": true},
+            {"output": {"cleaned_text": "one fish"}},
+        ]})));
+        assert!(exec.success);
+        let documents = exec.output.unwrap()["documents"].clone();
+        assert_eq!(documents[0]["ok"], true);
+        let errors: Vec<&serde_json::Value> = documents.as_array().unwrap()[1..].iter().map(|d| &d["error"]).collect();
+        assert_eq!(errors, ["Document has no boolean ok", "Document has no output", "Document has no boolean ok"]);
+
+        let exec = handle_corpus_exec(&request("exec", json!({}), json!({"document": []})));
+        assert_eq!(exec.error.as_deref(), Some("No prep data provided"));
+    }
+
+    #[test]
+    fn corpus_timeout_keeps_what_was_counted() {
+        let input = json!({"documents": [
+            {"ok": true, "output": {"cleaned_text": "red fish"}},
+            {"ok": true, "output": {"cleaned_text": "blue fish"}},
+        ]});
+        let exec = handle_corpus_exec(&Request { timeout_ms: Some(0), ..request("exec", json!({}), input) });
+        assert_eq!(exec.next.as_deref(), Some("timeout"));
+        let output = exec.output.unwrap();
+        assert_eq!(output["timed_out"], true);
+        assert_eq!(output["document_count"], 0);
+        assert_eq!(output["documents"][1], json!({"ok": false, "error": "Timed out after 0ms"}));
+        let post = handle_post(&request("post", json!({}), output));
+        assert_eq!(post.next.as_deref(), Some("timeout"));
+    }
+
+    #[test]
+    fn corpus_doc_freq_bounds_must_be_ordered() {
+        let config = json!({"min_doc_freq": 3, "max_doc_freq": 2});
+        let exec = handle_corpus_exec(&request("exec", config.clone(), json!({"documents": []})));
+        assert_eq!(exec.error_code, Some(ErrorCode::InvalidConfig));
+        let validation = handle_validate(&request("validate", config, json!({"text": "hi"}))).output.unwrap();
+        assert_eq!(validation["errors"], json!(["max_doc_freq is less than min_doc_freq"]));
+    }
+
+    #[test]
+    fn call_failures_name_the_node_and_function() {
+        let response = call_json(json!({"node": "word-count", "function": "foo", "input": {}}));
+        assert_eq!(response["error"], "Unknown function: foo");
+        assert_eq!(response["meta"], json!({"node": "word-count", "function": "foo"}));
+
+        let response = call_json(json!({"node": "corpus", "function": "exec"}));
+        assert_eq!(response["error"], "No prep data provided");
+        assert_eq!(response["meta"], json!({"node": "corpus", "function": "exec"}));
+
+        let response = call_bytes_json(json!({"node": "nope", "function": "exec"}), b"");
+        assert_eq!(response["meta"], json!({"node": "nope", "function": "exec"}));
+
+        let ok = call_json(json!({"node": "word-count", "function": "prep", "input": {"text": "hi"}}));
+        assert!(ok.get("meta").is_none());
+    }
+
+    #[test]
+    fn nested_failures_name_the_invoked_node() {
+        let response = call_json(json!({
+            "node": "lexical-diversity", "function": "exec", "config": {"min_word_length": "long"}, "input": {}
+        }));
+        assert_eq!(response["error_code"], "invalid_config");
+        assert_eq!(response["meta"], json!({"node": "word-count", "function": "exec"}));
+    }
+
+    // A host's run of the lifecycle: the functions it called and the route
+    // it took
+    fn run_lifecycle(config: serde_json::Value, input: serde_json::Value) -> (Vec<&'static str>, Option<String>) {
+        let prep = handle_prep(&request("prep", config.clone(), input));
+        assert!(prep.success, "prep failed: {:?}", prep.error);
+        if let Some(route) = prep.skip_to {
+            return (vec!["prep"], Some(route));
+        }
+        let exec = handle_exec(&request("exec", config.clone(), prep.output.unwrap()));
+        let post = handle_post(&request("post", config, exec.output.unwrap()));
+        (vec!["prep", "exec", "post"], post.next)
+    }
+
+    #[test]
+    fn prep_on_empty_input_skips_exec() {
+        let skip = json!({"skip_empty": true});
+        assert_eq!(run_lifecycle(skip.clone(), json!({"text": "  \n "})), (vec!["prep"], Some("empty".to_string())));
+        // Nothing left after cleaning is just as empty
+        let config = json!({"skip_empty": true, "empty_route": "blank"});
+        assert_eq!(run_lifecycle(config, json!("?! --")), (vec!["prep"], Some("blank".to_string())));
+        assert_eq!(run_lifecycle(skip.clone(), json!("hello")), (vec!["prep", "exec", "post"], Some("short".to_string())));
+
+        let prep = call_json(json!({"node": "word-count", "function": "prep", "config": skip, "input": {"text": ""}}));
+        assert_eq!(prep["skip_to"], "empty");
+        assert_eq!(prep["output"]["cleaned_text"], "");
+    }
+
+    #[test]
+    fn skip_empty_is_opt_in() {
+        assert_eq!(run_lifecycle(json!({}), json!({"text": ""})), (vec!["prep", "exec", "post"], Some("empty".to_string())));
+        // Words that are all stop words only turn out empty in exec
+        let skip = json!({"skip_empty": true});
+        assert_eq!(run_lifecycle(skip, json!("the the")), (vec!["prep", "exec", "post"], Some("empty".to_string())));
+    }
+
+    #[test]
+    fn lexical_diversity_routes_empty_text_where_prep_would_skip_to() {
+        let config = json!({"skip_empty": true, "empty_route": "blank"});
+        let prep = call_json(json!({"node": "lexical-diversity", "function": "prep", "config": config, "input": ""}));
+        assert_eq!(prep["skip_to"], "blank");
+        let exec = call_json(json!({"node": "lexical-diversity", "function": "exec", "config": config, "input": prep["output"]}));
+        let post = call_json(json!({"node": "lexical-diversity", "function": "post", "config": config, "input": exec["output"]}));
+        assert_eq!(post["next"], "blank");
+
+        let prep = call_json(json!({"node": "lexical-diversity", "function": "prep", "input": "some words"}));
+        let exec = call_json(json!({"node": "lexical-diversity", "function": "exec", "input": prep["output"]}));
+        let post = call_json(json!({"node": "lexical-diversity", "function": "post", "input": exec["output"]}));
+        assert_eq!(post["next"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn max_tokens_counts_only_the_prefix() {
+        let text = "one two three four five six seven eight nine ten eleven twelve thirteen";
+        let output = count(json!({"max_tokens": 10}), json!({"text": text}));
+        assert_eq!(output["total_words"], 10);
+        assert_eq!(output["truncated"], true);
+        assert!(output["word_frequencies"].get("ten").is_some());
+        assert!(output["word_frequencies"].get("eleven").is_none());
+
+        // Filtered tokens use up the budget too
+        let output = count(json!({"max_tokens": 3}), json!({"text": "the cat the dog"}));
+        assert_eq!(output["word_frequencies"], json!({"cat": 1}));
+
+        let output = count(json!({"max_tokens": 13}), json!({"text": text}));
+        assert_eq!(output["total_words"], 13);
+        assert!(output.get("truncated").is_none());
+    }
+
+    #[test]
+    fn request_config_overrides_host_defaults_over_built_in_ones() {
+        host::set_default_config("word-count", Some(br#"{"stop_words": ["foo"], "min_word_length": 3}"#));
+        let config = parse_config(&request("exec", json!({"min_word_length": 1}), json!({}))).unwrap();
+        let output = count(json!({"min_word_length": 1}), json!({"text": "foo the a bar"}));
+        // Defaults are per node type
+        let other = parse_config(&Request { node: "corpus".to_string(), ..request("exec", json!({}), json!({})) });
+        host::set_default_config("word-count", None);
+
+        // stop_words from the host, min_word_length from the request, and
+        // the rest built in
+        assert_eq!(config.stop_words, ["foo"]);
+        assert_eq!(config.min_word_length, 1);
+        assert_eq!(config.empty_route, "empty");
+        assert_eq!(output["word_frequencies"], json!({"the": 1, "a": 1, "bar": 1}));
+        assert_eq!(other.unwrap().stop_words, default_stop_words());
+    }
+
+    #[test]
+    fn a_call_reads_host_defaults_once() {
+        host::set_default_config("word-count", Some(br#"{"float_precision": 1}"#));
+        host::take_default_config_reads();
+        let response = call_json(json!({
+            "node": "word-count", "function": "exec", "echo_config": true, "input": {"cleaned_text": "ab abc abc"},
+        }));
+        host::set_default_config("word-count", None);
+        assert_eq!(host::take_default_config_reads(), 1);
+        assert_eq!(response["output"]["average_word_length"], 2.7);
+        assert_eq!(response["meta"]["applied_config"]["float_precision"], 1);
+    }
+
+    #[test]
+    fn host_defaults_apply_without_a_request_config_and_must_be_an_object() {
+        host::set_default_config("word-count", Some(br#"{"min_word_length": 4}"#));
+        let config = parse_config(&Request { config: None, ..request("exec", json!({}), json!({})) });
+        assert_eq!(config.unwrap().min_word_length, 4);
+
+        host::set_default_config("word-count", Some(b"[1]"));
+        let response = handle_prep(&request("prep", json!({}), json!({"text": "hi"})));
+        host::set_default_config("word-count", None);
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidConfig));
+        assert_eq!(response.error.as_deref(), Some("Invalid host default config for word-count: not a JSON object"));
+    }
+
+    #[test]
+    fn first_seen_gives_each_words_first_position() {
+        let output = count(
+            json!({"include_first_seen": true}),
+            json!({"text": "Rust is fast, rust is safe; the fast compiler checks rust"}),
+        );
+        // "is" and "the" are stop words and take no position
+        assert_eq!(output["first_seen"], json!({"rust": 0, "fast": 1, "safe": 3, "compiler": 5, "checks": 6}));
+
+        let output = count(json!({"include_first_seen": true, "min_frequency": 2}), json!({"text": "b d b c d"}));
+        assert_eq!(output["first_seen"], json!({"b": 0, "d": 1}));
+        assert!(count(json!({}), json!({"text": "cat"})).get("first_seen").is_none());
+    }
+
+    #[test]
+    fn ndjson_emits_a_line_per_entry_then_a_summary() {
+        host::take_emitted();
+        let output = count(json!({"output_format": "ndjson"}), json!({"text": "red fish blue fish red fish"}));
+        assert_eq!(output["word_frequencies"], json!({}));
+        assert_eq!(output["total_words"], 6);
+
+        let lines: Vec<String> = host::take_emitted().into_iter().map(|line| String::from_utf8(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| line.ends_with('\n') && line.matches('\n').count() == 1));
+        let lines: Vec<serde_json::Value> = lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[..3], [
+            json!({"word": "fish", "count": 3}),
+            json!({"word": "red", "count": 2}),
+            json!({"word": "blue", "count": 1}),
+        ]);
+        assert_eq!(lines[3]["entries"], 3);
+        assert_eq!(lines[3]["summary"]["unique_words"], 3);
+        assert_eq!(lines[3]["summary"]["schema_version"], 2);
+    }
+
+    #[test]
+    fn expanded_contractions_count_as_their_words() {
+        let config = json!({"expand_contractions": true});
+        let output = count(config.clone(), json!({"text": "Don't stop, I don't"}));
+        assert_eq!(output["word_frequencies"], json!({"do": 2, "not": 2, "stop": 1, "i": 1}));
+        assert_eq!(output["total_words"], 6);
+
+        // "it" and "is" are stop words
+        assert_eq!(count(config, json!({"text": "it's here"}))["word_frequencies"], json!({"here": 1}));
+        let output = count(json!({}), json!({"text": "don't"}));
+        assert_eq!(output["word_frequencies"], json!({"don": 1, "t": 1}));
+    }
+
+    #[test]
+    fn declared_resource_adds_stop_words() {
+        host::set_resource("stop-words.txt", b"# extra stop words\nfish\n\n  red  \n");
+        let config = json!({"stop_words_resource": "stop-words.txt"});
+        let output = count(config.clone(), json!({"text": "one fish two fish red fish"}));
+        assert_eq!(output["word_frequencies"], json!({"one": 1, "two": 1}));
+        assert_eq!(plugin_metadata().permissions.resources, ["stop-words.txt"]);
+
+        // Read once per instance: a changed resource isn't seen until the
+        // state is released
+        host::set_resource("stop-words.txt", b"one");
+        let output = count(config.clone(), json!({"text": "one fish two fish red fish"}));
+        assert_eq!(output["word_frequencies"], json!({"one": 1, "two": 1}));
+        state::clear();
+        let output = count(config, json!({"text": "one fish two fish red fish"}));
+        assert_eq!(output["word_frequencies"], json!({"two": 1, "fish": 3, "red": 1}));
+    }
+
+    #[test]
+    fn undeclared_resource_is_refused_without_asking_the_host() {
+        host::set_resource("secrets.txt", b"fish");
+        let config = json!({"stop_words_resource": "secrets.txt"});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "fish"})));
+        assert_eq!(prep.error.as_deref(), Some("Invalid config: Resource \"secrets.txt\" is not declared in permissions.resources"));
+        let exec = handle_exec(&request("exec", config.clone(), json!({"cleaned_text": "fish"})));
+        assert_eq!(exec.error_code, Some(ErrorCode::InvalidConfig));
+        assert_eq!(read_resource("secrets.txt"), Err("Resource \"secrets.txt\" is not declared in permissions.resources".to_string()));
+
+        let validation = handle_validate(&request("validate", config, json!({"text": "fish"})));
+        assert_eq!(validation.output.unwrap()["valid"], false);
+
+        // Declared, but the host has none
+        assert_eq!(read_resource("stop-words.txt"), Err("Resource \"stop-words.txt\" is not available from the host".to_string()));
+    }
+
+    #[test]
+    fn repeated_exec_is_served_from_the_memo() {
+        let config = json!({"memoize": true});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "memo memo pad"}))).output.unwrap();
+        let first = handle_exec(&request("exec", config.clone(), prep.clone()));
+        assert_eq!(first.meta.as_ref().unwrap()["memo"], json!({"hit": false, "hits": 0, "misses": 1}));
+        let second = handle_exec(&request("exec", config.clone(), prep.clone()));
+        assert_eq!(second.meta.as_ref().unwrap()["memo"], json!({"hit": true, "hits": 1, "misses": 1}));
+        assert_eq!(second.output, first.output);
+        assert_eq!(second.meta.unwrap()["cost"], 3);
+
+        // A different config is a different key
+        let other = handle_exec(&request("exec", json!({"memoize": true, "min_word_length": 4}), prep.clone()));
+        assert_eq!(other.meta.unwrap()["memo"]["hit"], false);
+        assert_eq!(memo::stats(), memo::MemoStats { hits: 1, misses: 2 });
+
+        // Without memoize, or with output that isn't a pure function of
+        // the request, exec always counts
+        for config in [json!({}), json!({"memoize": true, "delta": true})] {
+            let response = handle_exec(&request("exec", config, prep.clone()));
+            assert!(response.meta.unwrap().get("memo").is_none());
+        }
+        assert_eq!(memo::stats(), memo::MemoStats { hits: 1, misses: 2 });
+    }
+
+    // A millisecond passes at every reading
+    struct TickingClock(clock::MockClock);
+
+    impl Clock for TickingClock {
+        fn now_ns(&self) -> u64 {
+            self.0.advance(1_000_000);
+            self.0.now_ns()
+        }
+    }
+
+    #[test]
+    fn memo_hits_update_the_frequency_cache_and_report_backpressure() {
+        let clock = TickingClock(clock::MockClock::new(0));
+        let config = json!({"memoize": true, "backpressure_after_ms": 0});
+        let exec = |text: &str| {
+            let prep = handle_prep(&request("prep", config.clone(), json!({"text": text}))).output.unwrap();
+            exec_with(&request("exec", config.clone(), prep), &UnknownClassifier, &clock)
+        };
+        exec("apple banana");
+        exec("cherry");
+        let hit = exec("apple banana");
+        let meta = hit.meta.unwrap();
+        assert_eq!(meta["memo"]["hit"], true);
+        assert_eq!(meta["backpressure"], true);
+        assert_eq!(meta["cost"], 2);
+        assert_eq!(state::frequencies_page(0, 10).unwrap().1, 2);
+    }
+
+    #[test]
+    fn memo_capacity_is_negotiated_per_instance() {
+        let config = json!({"memoize": true});
+        let prep = |text: &str| handle_prep(&request("prep", config.clone(), json!({"text": text}))).output.unwrap();
+        let hit = |text: &str| handle_exec(&request("exec", config.clone(), prep(text))).meta.unwrap()["memo"]["hit"] == true;
+        let options = br#"{"memoCapacity": 1}"#;
+        unsafe { init_with(options.as_ptr(), options.len()) };
+        hit("one");
+        hit("two");
+        assert!(!hit("one"));
+        assert!(hit("one"));
+        memo::set_capacity(memo::DEFAULT_CAPACITY);
+    }
+
+    #[test]
+    fn memoized_outputs_expire_after_memo_ttl_ms() {
+        let clock = clock::MockClock::new(0);
+        let config = json!({"memoize": true, "memo_ttl_ms": 100});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "brief"}))).output.unwrap();
+        let exec = || exec_with(&request("exec", config.clone(), prep.clone()), &UnknownClassifier, &clock);
+        exec();
+        clock.advance(99_000_000);
+        assert_eq!(exec().meta.unwrap()["memo"]["hit"], true);
+        clock.advance(1_000_000);
+        assert_eq!(exec().meta.unwrap()["memo"]["hit"], false);
+    }
+
+    #[test]
+    fn permissions_timeout_accepts_manifest_strings_and_serializes_as_ms() {
+        let metadata = serde_json::to_value(plugin_metadata()).unwrap();
+        assert_eq!(metadata["permissions"]["timeout"], PERMISSION_TIMEOUT_MS);
+
+        let permissions: Permissions = serde_json::from_value(json!({"memory": "5MB", "timeout": "3s"})).unwrap();
+        assert_eq!(permissions.timeout, timeout::Timeout::from_millis(PERMISSION_TIMEOUT_MS).unwrap());
+        assert!(serde_json::from_value::<Permissions>(json!({"memory": "5MB", "timeout": 0})).is_err());
+    }
+
+    #[test]
+    fn stripped_emoji_are_excluded_and_counted() {
+        let text = "rust 🦀 ftw 🅰 1️⃣ 🇯🇵 👩‍🔬";
+        let output = count(json!({}), json!({"text": text}));
+        assert_eq!(output["word_frequencies"]["🅰"], 1);
+        assert_eq!(output["word_frequencies"]["1"], 1);
+
+        let config = json!({"strip_emoji": true, "normalization": "nfkc"});
+        let prep = prep_output(config.clone(), "Ⓜ rust 🦀 ftw 🅰 1️⃣ 🇯🇵 👩‍🔬");
+        assert_eq!(prep["emoji_count"], 6);
+        let output = count(config, json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"rust": 1, "ftw": 1}));
+        assert!(prep_output(json!({}), text).get("emoji_count").is_none());
+    }
+
+    #[test]
+    fn default_stop_word_set_is_built_once() {
+        for text in ["the first call", "and the second", "a third for good measure"] {
+            let output = count(json!({}), json!({"text": text}));
+            assert!(output["word_frequencies"].get("the").is_none());
+        }
+        assert_eq!(DEFAULT_STOP_SET.builds(), 1);
+        // Not the default list, so kept per instance in `state`
+        count(json!({"stop_words": ["call"]}), json!({"text": "the call"}));
+        assert!(!state::is_empty());
+        assert_eq!(DEFAULT_STOP_SET.builds(), 1);
+    }
+
+    #[test]
+    fn zipf_fit_is_reported_on_request() {
+        // Word n appears 60 / n times
+        let text: Vec<String> = (1..=6)
+            .flat_map(|rank| std::iter::repeat_n(format!("w{}", rank), 60 / rank))
+            .collect();
+        let output = count(json!({"include_zipf": true}), json!({"text": text.join(" ")}));
+        let slope = output["zipf"]["slope"].as_f64().unwrap();
+        assert!((slope + 1.0).abs() < 0.05, "slope {}", slope);
+        assert!(output["zipf"]["r_squared"].as_f64().unwrap() > 0.99);
+        assert!(output["zipf"].get("warning").is_none());
+        assert!(count(json!({}), json!({"text": "w1"})).get("zipf").is_none());
+
+        let output = count(json!({"include_zipf": true}), json!({"text": "one two three"}));
+        assert_eq!(output["zipf"]["slope"], serde_json::Value::Null);
+        assert_eq!(output["zipf"]["r_squared"], serde_json::Value::Null);
+        assert_eq!(output["zipf"]["warning"], "Zipf fit needs at least 2 distinct word frequencies");
+    }
+
+    // Splits on "|" only, so tokens keep their spaces and punctuation
+    fn pipe_tokenizer(text: &str) -> Vec<u8> {
+        serde_json::to_vec(&text.split('|').map(str::trim).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn host_tokenizer_tokens_are_counted_as_given() {
+        host::set_tokenizer(Some(pipe_tokenizer));
+        let config = json!({"tokenizer": "host"});
+        let text = "New York | state-of-the-art | new york | the | ";
+        let prep = prep_output(config.clone(), text);
+        assert_eq!(prep["tokens"], json!(["New York", "state-of-the-art", "new york", "the"]));
+        let output = count(config, json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"new york": 2, "state-of-the-art": 1}));
+        assert_eq!(output["total_words"], 3);
+
+        // The builtin tokenizer never asks the host
+        let output = count(json!({}), json!({"text": "New York|state-of-the-art"}));
+        assert_eq!(output["word_frequencies"]["york"], 1);
+        host::set_tokenizer(None);
+    }
+
+    #[test]
+    fn host_tokenizer_failures_are_reported() {
+        let config = json!({"tokenizer": "host"});
+        let prep = handle_prep(&request("prep", config.clone(), json!({"text": "a b"})));
+        assert_eq!(prep.error.as_deref(), Some("The host provides no tokenizer (host_tokenize)"));
+
+        host::set_tokenizer(Some(|_| b"{\"tokens\": []}".to_vec()));
+        let prep = handle_prep(&request("prep", config, json!({"text": "a b"})));
+        assert!(prep.error.unwrap().starts_with("The host tokenizer didn't return a JSON array of strings"));
+        host::set_tokenizer(None);
+
+        let config = json!({"tokenizer": "host", "include_sentence_breakdown": true});
+        let validation = handle_validate(&request("validate", config, json!({"text": "a b"})));
+        assert_eq!(validation.output.unwrap()["errors"], json!(["include_sentence_breakdown needs the builtin tokenizer"]));
+
+        // Nor can exec count both, or it would count the tokens once a sentence
+        let prep = json!({
+            "cleaned_text": "a. b.",
+            "tokens": ["a", "b"],
+            "sentences": [{"text": "a.", "end": 3}, {"text": "b.", "end": 5}],
+        });
+        let exec = handle_exec(&request("exec", json!({"include_sentence_breakdown": true}), prep));
+        assert_eq!(exec.error.as_deref(), Some("Prep data can't have both host tokens and a sentence breakdown"));
+    }
+
+    #[test]
+    fn applied_config_shows_request_over_host_defaults_over_built_ins() {
+        host::set_default_config("word-count", Some(br#"{"min_word_length": 3, "stop_words": ["foo"]}"#));
+        let response = call_json(json!({
+            "node": "word-count", "function": "prep", "echo_config": true,
+            "config": {"stop_words": ["bar"], "count_lines": true},
+            "input": {"text": "hello world"}
+        }));
+        host::set_default_config("word-count", None);
+        let applied = &response["meta"]["applied_config"];
+        assert_eq!(applied["stop_words"], json!(["bar"]));
+        assert_eq!(applied["count_lines"], true);
+        assert_eq!(applied["min_word_length"], 3);
+        assert_eq!(applied["empty_route"], "empty");
+        assert_eq!(applied["output_format"], "map");
+        assert_eq!(
+            serde_json::from_value::<WordCounterConfig>(applied.clone()).unwrap().collocation_window,
+            WordCounterConfig::default().collocation_window
+        );
+
+        let response = call_json(json!({"node": "word-count", "function": "prep", "input": {"text": "hello"}}));
+        assert!(response.get("meta").is_none());
+        // An invalid config isn't echoed
+        let response = call_json(json!({
+            "node": "word-count", "function": "prep", "echo_config": true,
+            "config": {"min_word_length": "three"}, "input": {"text": "hello"}
+        }));
+        assert!(response["meta"].get("applied_config").is_none());
+    }
+
+    #[test]
+    fn approximate_counts_stay_within_the_reported_bound() {
+        // Word n appears 120 / n times, in round-robin order
+        let mut tokens = Vec::new();
+        for round in 0..120 {
+            for rank in 1..=40 {
+                if round < 120 / rank {
+                    tokens.push(format!("w{}", rank));
+                }
+            }
+        }
+        let text = tokens.join(" ");
+        let exact = count(json!({}), json!({"text": text}));
+        let config = json!({"approximate": true, "sketch_width": 64, "sketch_depth": 3, "approximate_top_k": 5});
+        let approximate = count(config, json!({"text": text}));
+
+        assert_eq!(approximate["total_words"], exact["total_words"]);
+        let sketch = &approximate["sketch"];
+        assert_eq!((sketch["width"].as_u64(), sketch["depth"].as_u64()), (Some(64), Some(3)));
+        let bound = sketch["max_overestimate"].as_f64().unwrap();
+        assert!((bound - std::f64::consts::E / 64.0 * tokens.len() as f64).abs() < 1e-9);
+
+        let frequencies = approximate["word_frequencies"].as_object().unwrap();
+        let mut kept: Vec<&String> = frequencies.keys().collect();
+        kept.sort();
+        assert_eq!(kept, ["w1", "w2", "w3", "w4", "w5"]);
+        for (word, estimate) in frequencies {
+            let estimate = estimate.as_u64().unwrap();
+            let actual = exact["word_frequencies"][word].as_u64().unwrap();
+            assert!(estimate >= actual && (estimate - actual) as f64 <= bound, "{}: {} vs {}", word, estimate, actual);
+        }
+        assert_eq!(approximate["unique_words"], 5);
+        assert!(exact.get("sketch").is_none());
+    }
+
+    #[test]
+    fn oversized_sketches_and_filters_and_sequence_options_are_refused() {
+        let input = json!({"text": "hi"});
+        for (config, error) in [
+            (json!({"approximate": true, "sketch_width": sketch::MAX_WIDTH + 1}), "sketch_width must be at most 1048576"),
+            (json!({"sketch_depth": usize::MAX}), "sketch_depth must be at most 8"),
+            (json!({"novelty": true, "novelty_capacity": usize::MAX}), "novelty_capacity must be at most 1048576"),
+            (json!({"novelty": true, "novelty_fp_rate": 0}), "novelty_fp_rate must be above 0 and at most 0.5"),
+            (json!({"novelty_fp_rate": 0.75}), "novelty_fp_rate must be above 0 and at most 0.5"),
+            (json!({"approximate": true, "collocations": true}), "collocations needs the token sequence"),
+        ] {
+            let response = handle_exec(&request("exec", config.clone(), input.clone()));
+            assert_eq!(response.error_code, Some(ErrorCode::InvalidConfig), "{}", config);
+            assert!(response.error.unwrap().contains(error), "{}", config);
+        }
+        assert!(handle_exec(&request("exec", json!({"collocations": true}), input)).success);
+    }
+
+    #[test]
+    fn possessives_and_plurals_collapse_only_when_enabled() {
+        let text = "dog's dogs dog";
+        let output = count(json!({}), json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"dog": 2, "s": 1, "dogs": 1}));
+
+        let config = json!({"strip_possessive": true, "normalize_plurals": true});
+        let output = count(config, json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"dog": 3}));
+        assert_eq!(output["total_words"], 3);
+
+        let output = count(json!({"strip_possessive": true}), json!({"text": text}));
+        assert_eq!(output["word_frequencies"], json!({"dog": 2, "dogs": 1}));
+        let output = count(json!({"normalize_plurals": true}), json!({"text": "cities city boxes"}));
+        assert_eq!(output["word_frequencies"], json!({"city": 2, "box": 1}));
+    }
+
+    #[test]
+    fn old_envelope_versions_get_only_their_fields() {
+        let response = || Response {
+            meta: Some(json!({"node": "word-count"})),
+            skip_to: Some("empty".to_string()),
+            ..Response::failure(ErrorCode::InvalidConfig, "bad")
+        };
+        let current = serde_json::to_value(response()).unwrap();
+        assert_eq!(current["error_code"], "invalid_config");
+        assert_eq!(current["meta"], json!({"node": "word-count"}));
+        assert_eq!(current["skip_to"], "empty");
+
+        assert_eq!(init(2), 2);
+        let v2 = serde_json::to_value(response()).unwrap();
+        assert!(v2.get("meta").is_some() && v2.get("skip_to").is_none());
+
+        assert_eq!(init(1), 1);
+        let v1 = serde_json::to_value(response()).unwrap();
+        assert_eq!(v1, json!({"success": false, "output": null, "error": "bad", "next": null}));
+        // Through `call` too
+        let called = call_json(json!({"node": "nope", "function": "prep", "input": {}}));
+        assert_eq!(called.as_object().unwrap().keys().collect::<Vec<_>>(), ["error", "next", "output", "success"]);
+
+        assert_eq!(init(envelope::CURRENT), envelope::CURRENT);
+        let metadata = serde_json::to_value(plugin_metadata()).unwrap();
+        assert_eq!(metadata["capabilities"]["envelopeVersion"], envelope::CURRENT);
+    }
+
+    #[test]
+    fn bidi_aware_counts_arabic_words_by_grapheme() {
+        let input = json!({"text": "\u{202B}ذَهَبَ الوَلَدُ إلى المَدرَسَةِ.\u{202C}"});
+        // A char per letter and mark by default
+        assert_eq!(count(json!({}), input.clone())["average_word_length"], 7.0);
+
+        let output = count(json!({"bidi_aware": true, "include_length_histogram": true}), input.clone());
+        assert_eq!(output["total_words"], 4);
+        assert_eq!(output["word_frequencies"], json!({"ذَهَبَ": 1, "الوَلَدُ": 1, "إلى": 1, "المَدرَسَةِ": 1}));
+        assert_eq!(output["longest_word"], "المَدرَسَةِ");
+        assert_eq!(output["shortest_word"], "إلى");
+        assert_eq!(output["average_word_length"], 4.5);
+        assert_eq!(output["length_histogram"], json!({"3": 2, "5": 1, "7": 1}));
+
+        let output = count(json!({"bidi_aware": true, "min_word_length": 4, "max_word_output_len": 3}), input);
+        assert_eq!(output["total_words"], 2);
+        assert_eq!(output["longest_word"], "المَ…");
+    }
+
+    #[test]
+    fn ping_is_answered_without_parsing_the_request() {
+        // `node` has the wrong type, so a full parse would fail
+        let raw_call = |request: &[u8]| -> serde_json::Value {
+            let mut out = vec![0u8; 256];
+            let len = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+            serde_json::from_slice(&out[..len]).unwrap()
+        };
+        let pong = json!({"success": true, "output": {"pong": true}, "error": null, "next": null});
+        assert_eq!(raw_call(br#"{"function": "__ping", "node": 42"#), pong);
+        // The scan doesn't decode escapes, so this one is parsed in full
+        assert_eq!(raw_call(br#"{"node": "word-count", "\u0066unction": "__ping"}"#), pong);
+        assert_eq!(call_json(json!({"node": "word-count", "function": "__pong", "input": {}}))["success"], false);
+    }
+
+    #[test]
+    fn normalized_frequencies_are_proportions_summing_to_one() {
+        let config = json!({"normalize_frequencies": true});
+        let output = count(config.clone(), json!({"text": "red fish blue fish red fish one"}));
+        let probabilities = output["word_probabilities"].as_object().unwrap();
+        let total: f64 = probabilities.values().map(|p| p.as_f64().unwrap()).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        for (word, count) in output["word_frequencies"].as_object().unwrap() {
+            let expected = count.as_f64().unwrap() / 7.0;
+            assert!((probabilities[word].as_f64().unwrap() - expected).abs() < 1e-12, "for {}", word);
+        }
+
+        // Over what min_frequency leaves: red 2, fish 3
+        let output = count(json!({"normalize_frequencies": true, "min_frequency": 2}), json!({"text": "red fish blue fish red fish one"}));
+        assert_eq!(output["word_probabilities"], json!({"red": 0.4, "fish": 0.6}));
+        assert_eq!(count(config, json!({"text": "the and of"}))["word_probabilities"], json!({}));
+        assert!(count(json!({}), json!({"text": "red"})).get("word_probabilities").is_none());
+    }
+
+    #[test]
+    fn exports_report_short_buffers_with_a_return_code() {
+        let request = json!({"node": "word-count", "function": "prep", "input": {"text": "hello"}}).to_string();
+        let mut out = vec![0u8; 8];
+        // Older hosts get the bare length they retry with
+        let needed = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        assert!(needed > out.len() && needed < abi::MAX_LENGTH);
+        let options = br#"{"returnCodes": true}"#;
+        unsafe { init_with(options.as_ptr(), options.len()) };
+
+        let returned = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        assert_eq!(decode_return(returned), Some((AbiError::BufferTooSmall, needed)));
+        let mut out = vec![0u8; needed];
+        let returned = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        assert_eq!(decode_return(returned), Some((AbiError::Ok, needed)));
+
+        let returned = unsafe { metadata(std::ptr::null_mut(), 0) };
+        let (code, needed) = decode_return(returned).unwrap();
+        assert_eq!(code, AbiError::BufferTooSmall);
+        let mut out = vec![0u8; needed];
+        assert_eq!(unsafe { metadata(out.as_mut_ptr(), out.len()) }, encode_return(AbiError::Ok, needed));
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&out).unwrap()["name"], "word-counter");
+    }
+
+    #[test]
+    fn request_log_level_drops_less_severe_lines() {
+        let prep = handle_prep(&request("prep", json!({}), json!({"text": "one two three"})));
+        let exec = |log_level| {
+            host::take_logs();
+            let request = Request { log_level, ..request("exec", json!({}), prep.output.clone().unwrap()) };
+            assert!(handle_exec(&request).success);
+            host::take_logs()
+        };
+        let counted = [(Level::Debug as u32, "counted 3 words (3 unique)".to_string())];
+        assert_eq!(exec(Some(Level::Debug)), counted);
+        assert_eq!(exec(None), counted);
+        assert!(exec(Some(Level::Info)).is_empty());
+
+        let request: Request = serde_json::from_value(json!({"node": "word-count", "function": "exec", "log_level": "warn"})).unwrap();
+        assert_eq!(request.log_level, Some(Level::Warn));
+    }
+
+    #[test]
+    fn detect_language_node_reports_lang_and_confidence() {
+        let detect = |text: &str| {
+            let response = call_json(json!({"node": "detect-language", "function": "exec", "input": {"text": text}}));
+            assert_eq!(response["success"], true);
+            response["output"].clone()
+        };
+        let english = detect("It was a bright cold day in April, and the clocks were striking thirteen");
+        assert_eq!((&english["lang"], &english["reliable"]), (&json!("eng"), &json!(true)));
+        let spanish = detect("En un lugar de la Mancha, de cuyo nombre no quiero acordarme, no ha mucho tiempo que vivía un hidalgo");
+        assert_eq!((&spanish["lang"], &spanish["reliable"]), (&json!("spa"), &json!(true)));
+        let short = detect("ok");
+        assert_eq!(short["reliable"], false);
+        assert!(short["confidence"].as_f64().unwrap() < 0.5);
+    }
+
+    #[test]
+    fn auto_stop_words_uses_the_detected_languages_preset() {
+        let config = json!({"auto_stop_words": true});
+        let spanish = "En un lugar de la Mancha, de cuyo nombre no quiero acordarme, no ha mucho tiempo que vivía un hidalgo";
+        let output = count(config.clone(), json!({"text": spanish}));
+        assert_eq!(output["language"]["lang"], "spa");
+        let frequencies = output["word_frequencies"].as_object().unwrap();
+        for stop_word in ["en", "un", "de", "la", "no", "que"] {
+            assert!(!frequencies.contains_key(stop_word), "{}", stop_word);
+        }
+        assert_eq!(frequencies["mancha"], 1);
+        // Without it, the English defaults miss every Spanish stop word
+        assert_eq!(count(json!({}), json!({"text": spanish}))["word_frequencies"]["de"], 2);
+
+        // Too short to trust: the configured stop words stay
+        let output = count(json!({"auto_stop_words": true, "stop_words": ["ok"]}), json!({"text": "ok la"}));
+        assert_eq!(output["language"]["reliable"], false);
+        assert_eq!(output["word_frequencies"], json!({"la": 1}));
+    }
+
+    #[test]
+    fn approx_routes_sends_truncated_and_approximate_results_apart() {
+        let post = |config: serde_json::Value, text: &str| {
+            let exec = count(config.clone(), json!({"text": text}));
+            handle_post(&request("post", config, exec)).next.unwrap()
+        };
+        let text = "alpha beta gamma delta";
+        assert_eq!(post(json!({"max_tokens": 2}), text), "short");
+        assert_eq!(post(json!({"approx_routes": true, "max_tokens": 2}), text), "short-approx");
+        assert_eq!(post(json!({"approx_routes": true, "approximate": true}), text), "short-approx");
+        assert_eq!(post(json!({"approx_routes": true, "sample_size": 2}), text), "short");
+        assert_eq!(post(json!({"approx_routes": true}), &"word ".repeat(150)), "medium");
+        assert_eq!(post(json!({"approx_routes": true, "approximate": true}), &"word ".repeat(1500)), "long-approx");
+        // Saturated counts aren't exact either
+        let saturated = json!({"total_words": 3, "overflow": true});
+        assert_eq!(handle_post(&request("post", json!({"approx_routes": true}), saturated)).next.unwrap(), "short-approx");
+    }
+
+    #[test]
+    fn derived_nodes_are_listed_with_generated_schemas() {
+        let definition = serde_json::to_value((registry::find("reading-time").unwrap().definition)()).unwrap();
+        assert_eq!(definition["category"], "text");
+        assert_eq!(definition["configSchema"]["properties"]["words_per_minute"]["default"], 238);
+        assert_eq!(definition["inputSchema"]["required"], json!(["text"]));
+        assert_eq!(definition["outputSchema"]["properties"]["minutes"]["type"], "integer");
+        assert!(registry::find("reading-time").unwrap().supports("post"));
+    }
+
+    #[test]
+    fn derived_nodes_run_their_node_impl_through_call() {
+        let text = "word ".repeat(300);
+        let exec = call_json(json!({
+            "node": "reading-time", "function": "exec",
+            "config": {"words_per_minute": 100}, "input": {"text": text},
+        }));
+        assert_eq!(exec["output"], json!({"words": 300, "minutes": 3}));
+
+        let post = call_json(json!({"node": "reading-time", "function": "post", "input": exec["output"]}));
+        assert_eq!(post["next"], "default");
+        assert_eq!(post["output"], exec["output"]);
+
+        let invalid = call_json(json!({
+            "node": "reading-time", "function": "exec",
+            "config": {"words_per_minute": "fast"}, "input": {"text": text},
+        }));
+        assert_eq!(invalid["error_code"], "invalid_config");
+        let missing = call_json(json!({"node": "reading-time", "function": "exec"}));
+        assert_eq!(missing["error"], "No input provided");
+    }
+
+    // Counts characters; its config schema is a mirror type's
+    #[derive(PocketNode, Default)]
+    #[pocket(node_type = "char-count", description = "Count characters", config = CharCountConfigSchema)]
+    struct CharCount;
+
+    #[derive(Deserialize, Default)]
+    struct CharCountConfig {
+        #[serde(default)]
+        skip_spaces: bool,
+    }
+
+    #[derive(schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct CharCountConfigSchema {
+        /// Leave spaces uncounted
+        skip_spaces: bool,
+    }
+
+    impl Node for CharCount {
+        type Config = CharCountConfig;
+        type Input = String;
+        type Output = usize;
+
+        fn exec(&self, config: &CharCountConfig, input: &String) -> Result<usize, String> {
+            Ok(input.chars().filter(|c| !config.skip_spaces || *c != ' ').count())
+        }
+
+        fn post(&self, _: &usize) -> &'static str {
+            "default"
+        }
+    }
+
+    #[test]
+    fn registered_derived_nodes_are_listed_and_dispatched_through_call() {
+        derived::register::<CharCount>().unwrap();
+        assert_eq!(derived::register::<CharCount>().unwrap_err(), "Node type char-count is already registered");
+        assert!(derived::register::<ReadingTimeTwin>().is_err());
+
+        let definition = serde_json::to_value((registry::find("char-count").unwrap().definition)()).unwrap();
+        assert_eq!(definition["configSchema"]["properties"]["skip_spaces"]["description"], "Leave spaces uncounted");
+        assert!(plugin_metadata().nodes.iter().any(|node| node.node_type == "char-count"));
+
+        let exec = call_json(json!({
+            "node": "char-count", "function": "exec", "config": {"skip_spaces": true}, "input": "a b c"
+        }));
+        assert_eq!(exec["output"], 3);
+        let post = call_json(json!({"node": "char-count", "function": "post", "input": 3}));
+        assert_eq!(post["next"], "default");
+    }
+
+    // Taken already, by the crate's own reading-time node
+    #[derive(PocketNode, Default)]
+    #[pocket(node_type = "reading-time", description = "Twin")]
+    struct ReadingTimeTwin;
+
+    impl Node for ReadingTimeTwin {
+        type Config = ();
+        type Input = ();
+        type Output = ();
+
+        fn exec(&self, _: &(), _: &()) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn post(&self, _: &()) -> &'static str {
+            "default"
+        }
+    }
+
+    #[test]
+    fn route_when_routes_on_a_readability_threshold_before_the_size_routes() {
+        let config = json!({"route_when": [
+            {"field": "average_word_length", "op": "gt", "value": 7, "route": "too-complex"},
+            {"field": "total_words", "op": "gte", "value": 3, "route": "ok"},
+        ]});
+        let post = |text: &str| {
+            let exec = count(config.clone(), json!({"text": text}));
+            handle_post(&request("post", config.clone(), exec)).next.unwrap()
+        };
+        assert_eq!(post("notwithstanding institutional considerations"), "too-complex");
+        assert_eq!(post("the cat sat on the mat"), "ok");
+        // No rule matches: the word-count routes as before
+        assert_eq!(post("hello world"), "short");
+    }
+
+    #[test]
+    fn malformed_route_when_rules_fail_post_and_validate() {
+        let config = json!({"route_when": [{"field": "total_words", "op": "gt", "value": "many", "route": "wordy"}]});
+        let exec = count(json!({}), json!({"text": "hello world"}));
+        let response = handle_post(&request("post", config.clone(), exec));
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidConfig));
+
+        let validation = handle_validate(&request("validate", config, json!({"text": "hello"})));
+        assert_eq!(validation.output.unwrap()["errors"], json!(["route_when[0]: \"gt\" needs a numeric value"]));
+    }
+
+    #[test]
+    fn scratch_buffers_are_reused_across_calls_from_the_arena() {
+        let request = json!({"node": "reading-time", "function": "exec", "input": {"text": "hello arena"}}).to_string();
+        let mut out = vec![0u8; 64 * 1024];
+        let mut run = || {
+            let ptr = alloc_scratch(request.len());
+            unsafe { std::ptr::copy_nonoverlapping(request.as_ptr(), ptr, request.len()) };
+            let len = unsafe { call(ptr, request.len(), out.as_mut_ptr(), out.len()) };
+            let response: serde_json::Value = serde_json::from_slice(&out[..len]).unwrap();
+            assert_eq!(response["output"]["words"], 2);
+            ptr
+        };
+        let first = run();
+        assert!(arena::contains(first));
+        // The second call's input skips the first, which that call kept;
+        // the third reuses the first's bytes
+        let second = run();
+        assert_ne!(second, first);
+        assert_eq!(run(), first);
+        assert!(alloc_scratch(arena::SMALL_ALLOC + 1).is_null());
+    }
+
+    #[test]
+    fn small_alloc_buffers_stay_live_across_calls() {
+        let request = json!({"node": "reading-time", "function": "exec", "input": {"text": "hi"}}).to_string();
+        let a = alloc(request.len());
+        let b = alloc(16);
+        assert!(!arena::contains(a) && !arena::contains(b));
+        unsafe {
+            std::ptr::copy_nonoverlapping(request.as_ptr(), a, request.len());
+            std::ptr::write_bytes(b, 7, 16);
+            let mut out = vec![0u8; 1024];
+            call(a, request.len(), out.as_mut_ptr(), out.len());
+            let c = alloc(16);
+            std::ptr::write_bytes(c, 9, 16);
+            assert_eq!(std::slice::from_raw_parts(b, 16), [7; 16]);
+            dealloc(a, request.len());
+            dealloc(b, 16);
+            dealloc(c, 16);
+        }
+    }
+
+    #[test]
+    fn every_call_reports_its_input_length_as_bytes_processed() {
+        let request = json!({"node": "word-count", "function": "prep", "input": {"text": "hello world"}});
+        // Not until the host asks
+        assert!(call_json(request.clone()).get("meta").is_none());
+        assert_eq!(host::take_metric(), None);
+        let options = br#"{"metrics": true}"#;
+        unsafe { init_with(options.as_ptr(), options.len()) };
+
+        let response = call_json(request.clone());
+        let len = request.to_string().len() as u64;
+        assert_eq!(response["meta"]["bytes_processed"], len);
+        assert_eq!(host::take_metric(), Some(("bytes_processed", len, None)));
+        // Labelled like the call's log lines
+        let traced = json!({"node": "word-count", "function": "prep", "input": {"text": "hi"}, "trace_id": "run-7"});
+        call_json(traced.clone());
+        assert_eq!(host::take_metric(), Some(("bytes_processed", traced.to_string().len() as u64, Some("run-7".to_string()))));
+
+        // Failures count too, and binary input adds its data
+        let response = call_json(json!({"node": "word-count", "function": "foo"}));
+        assert_eq!(Some(response["meta"]["bytes_processed"].as_u64().unwrap()), host::take_metric().map(|(_, n, _)| n));
+        let request = json!({"node": "byte-digest", "function": "exec"});
+        let response = call_bytes_json(request.clone(), b"\xff\xfe\x00");
+        let len = request.to_string().len() as u64 + 3;
+        assert_eq!(response["meta"]["bytes_processed"], len);
+        assert_eq!(host::take_metric(), Some(("bytes_processed", len, None)));
+
+        // Pings aren't processing
+        call_json(json!({"function": "__ping"}));
+        assert_eq!(host::take_metric(), None);
+    }
+
+    fn check_imports_json(provided: &[u8]) -> serde_json::Value {
+        let mut out = vec![0u8; 4096];
+        let len = unsafe { check_imports(provided.as_ptr(), provided.len(), out.as_mut_ptr(), out.len()) };
+        serde_json::from_slice(&out[..len]).unwrap()
+    }
+
+    #[test]
+    fn metadata_declares_the_required_host_imports() {
+        let metadata = serde_json::to_value(plugin_metadata()).unwrap();
+        let required = metadata["requiredImports"].as_array().unwrap();
+        let optional = metadata["optionalImports"].as_array().unwrap();
+        assert!(required.contains(&json!("kv_get")) && optional.contains(&json!("host_tokenize")));
+        assert_eq!(required.len() + optional.len(), host::IMPORTS.len());
+    }
+
+    #[test]
+    fn check_imports_flags_what_the_host_lacks() {
+        let names: Vec<&str> = host::IMPORTS.iter().map(|import| import.name).collect();
+        let everything = serde_json::to_vec(&names).unwrap();
+        assert_eq!(check_imports_json(&everything), json!({"compatible": true, "missing": [], "missing_optional": []}));
+
+        let without_kv: Vec<&str> = names.iter().copied().filter(|name| *name != "kv_get").collect();
+        let check = check_imports_json(&serde_json::to_vec(&without_kv).unwrap());
+        assert_eq!(check, json!({"compatible": false, "missing": ["kv_get"], "missing_optional": []}));
+
+        // Lacking an optional import leaves the plugin compatible
+        let without_tokenizer: Vec<&str> = names.iter().copied().filter(|name| *name != "host_tokenize").collect();
+        let check = check_imports_json(&serde_json::to_vec(&without_tokenizer).unwrap());
+        assert_eq!(check, json!({"compatible": true, "missing": [], "missing_optional": ["host_tokenize"]}));
+
+        let malformed = check_imports_json(b"{\"kv_get\": true}");
+        assert_eq!(malformed["compatible"], false);
+        assert_eq!(malformed["missing"].as_array().unwrap().len(), host::imports(true).count());
+        assert!(malformed["error"].as_str().unwrap().starts_with("Invalid import list"));
+    }
+
+    #[test]
+    fn identifier_split_modes_for_snake_case_identifiers() {
+        let frequencies = |mode: &str, text: &str| {
+            count(json!({"identifier_split": mode, "stop_words": []}), json!({"text": text}))["word_frequencies"].clone()
+        };
+        assert_eq!(frequencies("none", "user_id_2"), json!({"user_id_2": 1}));
+        assert_eq!(frequencies("snake", "user_id_2"), json!({"user": 1, "id": 1, "2": 1}));
+        assert_eq!(frequencies("all", "user_id_2"), json!({"user": 1, "id": 1, "2": 1}));
+        // Only all splits where letters meet digits
+        assert_eq!(frequencies("snake", "user_id2"), json!({"user": 1, "id2": 1}));
+        assert_eq!(frequencies("all", "user_id2 v2beta"), json!({"user": 1, "id": 1, "2": 2, "v": 1, "beta": 1}));
+        // Other numeric chars aren't digits, so they don't split
+        assert_eq!(frequencies("all", "x\u{b2} \u{216b}b"), json!({"x\u{b2}": 1, "\u{217b}b": 1}));
+        // Underscores at the ends of a word, or alone, are still punctuation
+        assert_eq!(frequencies("none", "_private __init__ _ a_ (b_c)"), json!({"private": 1, "init": 1, "a": 1, "b_c": 1}));
+    }
+
+    #[test]
+    fn identifier_split_composes_with_camel_case() {
+        let config = json!({"identifier_split": "all", "split_camel_case": true, "stop_words": []});
+        let output = count(config, json!({"text": "getUser_id2"}));
+        assert_eq!(output["word_frequencies"], json!({"get": 1, "user": 1, "id": 1, "2": 1}));
+        // Kept whole, a joined identifier isn't split on case either
+        let config = json!({"identifier_split": "none", "split_camel_case": true, "stop_words": []});
+        let output = count(config, json!({"text": "getUser_id getUser"}));
+        assert_eq!(output["word_frequencies"], json!({"getuser_id": 1, "get": 1, "user": 1}));
+    }
+
+    #[test]
+    fn corpus_reports_each_document_and_survives_a_malformed_one() {
+        let config = json!({"stop_words": []});
+        let input = json!({"documents": ["rust wasm", 42, {"text": "rust plugins"}]});
+        let prep = handle_corpus_prep(&request("prep", config.clone(), input));
+        assert!(prep.success, "prep failed: {:?}", prep.error);
+        let prep_output = prep.output.unwrap();
+        assert_eq!(prep_output["documents"][1]["ok"], false);
+        assert!(prep_output["documents"][1]["error"].as_str().unwrap().starts_with("Failed to parse input:"));
+
+        let exec = handle_corpus_exec(&request("exec", config, prep_output.clone()));
+        assert!(exec.success, "exec failed: {:?}", exec.error);
+        let output = exec.output.unwrap();
+        let documents = output["documents"].as_array().unwrap();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0], json!({
+            "ok": true,
+            "output": {"total_words": 2, "unique_words": 2, "word_frequencies": {"rust": 1, "wasm": 1}}
+        }));
+        assert_eq!(documents[1]["error"], prep_output["documents"][1]["error"]);
+        assert_eq!(documents[1]["ok"], false);
+        assert!(documents[1].get("output").is_none());
+        assert_eq!(documents[2]["ok"], true);
+        assert_eq!(output["document_count"], 2);
+        assert_eq!(output["word_frequencies"], json!({"rust": 2, "wasm": 1, "plugins": 1}));
+    }
+
+    #[test]
+    fn corpus_fails_only_when_every_document_does() {
+        let prep = handle_corpus_prep(&request("prep", json!({}), json!({"documents": [1, null]})));
+        assert!(!prep.success);
+        assert!(prep.error.unwrap().starts_with("No document could be counted: Failed to parse input:"));
+        let documents = &prep.output.unwrap()["documents"];
+        assert_eq!(documents.as_array().unwrap().iter().filter(|document| document["ok"] == false).count(), 2);
+
+        let prep = handle_corpus_prep(&request("prep", json!({}), json!({"documents": "rust"})));
+        assert_eq!(prep.error.as_deref(), Some("Failed to parse input: documents must be an array"));
+        let empty = handle_corpus_prep(&request("prep", json!({}), json!({"documents": []})));
+        assert!(empty.success);
+    }
+
+    // Every float in `value` with its path, for checking them all at once
+    fn floats(value: &serde_json::Value, path: String, found: &mut Vec<(String, f64)>) {
+        match value {
+            serde_json::Value::Number(number) if number.is_f64() => found.push((path, number.as_f64().unwrap())),
+            serde_json::Value::Array(values) => {
+                values.iter().enumerate().for_each(|(i, value)| floats(value, format!("{}[{}]", path, i), found))
+            }
+            serde_json::Value::Object(fields) => {
+                fields.iter().for_each(|(key, value)| floats(value, format!("{}.{}", path, key), found))
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn float_precision_rounds_every_float_field() {
+        let config = json!({
+            "float_precision": 3,
+            "stop_words": [],
+            "normalize_frequencies": true,
+            "collocations": true,
+            "lexical_diversity": true,
+            "include_zipf": true,
+            "weights": {"rust": 1.23456},
+        });
+        let text = "rust rust rust and wasm plugins compile rust to wasm for hosts, and hosts run wasm plugins";
+        // Rounded by `dispatch`, as for a host's call
+        let count = |config: serde_json::Value, input| {
+            let prep = handle_prep(&request("prep", config.clone(), input)).output.unwrap();
+            dispatch(registry::nodes(), &request("exec", config, prep)).output.unwrap()
+        };
+        let output = count(config.clone(), json!({"text": text}));
+        let mut found = Vec::new();
+        floats(&output, String::new(), &mut found);
+        for field in [".average_word_length", ".weighted_score", ".word_probabilities.rust", ".lexical_diversity.ttr", ".zipf.slope"] {
+            assert!(found.iter().any(|(path, _)| path == field), "no {} in {:?}", field, found);
+        }
+        assert!(found.iter().any(|(path, _)| path.starts_with(".collocations[")));
+        for (path, x) in &found {
+            assert_eq!(*x, (x * 1000.0).round() / 1000.0, "{} = {}", path, x);
+        }
+
+        // Unrounded, some of the same fields need more places
+        let mut config = config;
+        config.as_object_mut().unwrap().remove("float_precision");
+        let full = count(config, json!({"text": text}));
+        let mut unrounded = Vec::new();
+        floats(&full, String::new(), &mut unrounded);
+        assert!(unrounded.iter().any(|(_, x)| *x != (x * 1000.0).round() / 1000.0));
+        assert_eq!(output["total_words"], full["total_words"]);
+    }
+
+    #[test]
+    fn float_precision_past_max_digits_is_refused() {
+        let exec = handle_exec(&request("exec", json!({"float_precision": 16}), json!({"cleaned_text": "hi"})));
+        assert_eq!(exec.error_code, Some(ErrorCode::InvalidConfig));
+        assert_eq!(exec.error.as_deref(), Some("Invalid config: float_precision must be at most 15"));
+    }
+
+    #[test]
+    fn float_precision_covers_every_node() {
+        let text = "It was a bright cold day in April, and the clocks were striking thirteen";
+        let detect = |config: serde_json::Value| {
+            call_json(json!({"node": "detect-language", "function": "exec", "config": config, "input": {"text": text}}))
+        };
+        let full = detect(json!({}))["output"]["confidence"].as_f64().unwrap();
+        let rounded = detect(json!({"float_precision": 1}))["output"]["confidence"].as_f64().unwrap();
+        assert_eq!(rounded, (full * 10.0).round() / 10.0);
+    }
+
+    #[test]
+    fn requests_and_responses_round_trip_through_every_codec() {
+        let request: Request = serde_json::from_value(json!({
+            "node": "word-count", "function": "prep", "input": {"text": "Hello, codecs"}
+        }))
+        .unwrap();
+        let mut outputs = Vec::new();
+        for encoding in wire::Encoding::ALL {
+            let codec = wire::codec(encoding);
+            let bytes = codec.encode(&request).unwrap();
+            let (response, _) = unsafe { call_response(codec, bytes.as_ptr(), bytes.len()) };
+            assert!(response.success, "{:?}: {:?}", encoding, response.error);
+
+            let decoded: Response = codec.decode(&codec.encode(&response).unwrap()).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&response).unwrap());
+            outputs.push(decoded.output);
+        }
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+
+        let (response, _) = unsafe { call_response(wire::codec(wire::Encoding::Cbor), b"{}".as_ptr(), 2) };
+        assert!(response.error.unwrap().starts_with("Failed to parse request:"));
+        let metadata: serde_json::Value = serde_json::to_value(plugin_metadata()).unwrap();
+        assert_eq!(metadata["capabilities"]["encodings"], json!(["json", "msgpack", "cbor"]));
+    }
+
+    #[test]
+    fn init_with_switches_call_and_metadata_to_the_negotiated_encoding() {
+        let options = br#"{"encoding": "cbor"}"#;
+        assert_eq!(unsafe { init_with(options.as_ptr(), options.len()) }, envelope::CURRENT);
+        assert_eq!(unsafe { init_with(b"{".as_ptr(), 1) }, 0);
+
+        let cbor = wire::codec(wire::Encoding::Cbor);
+        let request = cbor
+            .encode(&json!({"node": "word-count", "function": "prep", "input": {"text": "Hello, codecs"}}))
+            .unwrap();
+        let mut out = vec![0u8; 64 * 1024];
+        let len = unsafe { call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+        let response: serde_json::Value = cbor.decode(&out[..len]).unwrap();
+        assert_eq!(response["success"], true, "{}", response);
+
+        // JSON is no longer what requests are read as
+        let json_request = br#"{"node": "word-count", "function": "prep", "input": {"text": "hi"}}"#;
+        let len = unsafe { call(json_request.as_ptr(), json_request.len(), out.as_mut_ptr(), out.len()) };
+        let response: serde_json::Value = cbor.decode(&out[..len]).unwrap();
+        assert!(response["error"].as_str().unwrap().starts_with("Failed to parse request:"));
+
+        let len = unsafe { metadata(out.as_mut_ptr(), out.len()) };
+        let metadata: serde_json::Value = cbor.decode(&out[..len]).unwrap();
+        assert_eq!(metadata["name"], plugin_metadata().name);
+
+        let options = br#"{"encoding": "json"}"#;
+        unsafe { init_with(options.as_ptr(), options.len()) };
+    }
+
+    #[test]
+    fn run_length_counting_matches_token_by_token() {
+        let text = format!(
+            "{} The the THE cat. {} dogs dog's Dog {}",
+            "spam ".repeat(500),
+            "Eggs eggs ".repeat(50),
+            "a a a 42 42 x".repeat(20)
+        );
+        let configs = [
+            json!({}),
+            json!({"stop_words": [], "min_word_length": 2, "canonical_forms": true}),
+            json!({"include_sentence_breakdown": true, "include_first_seen": true, "collocations": true}),
+            json!({"stem": "en", "normalize_plurals": true, "include_length_histogram": true}),
+            json!({"approximate": true, "approximate_top_k": 3}),
+            json!({"max_tokens": 520, "numbers": "drop"}),
+            // Cut inside the run of "spam"
+            json!({"max_tokens": 250, "lexical_diversity": true, "sample_size": 5}),
+        ];
+        for config in configs {
+            let naive = count(config.clone(), json!({"text": text, "explain": true}));
+            let mut runs = config.clone();
+            runs["run_length"] = json!(true);
+            let runs = count(runs, json!({"text": text, "explain": true}));
+            assert_eq!(runs, naive, "with {}", config);
+        }
+    }
+}
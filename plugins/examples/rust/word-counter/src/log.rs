@@ -0,0 +1,84 @@
+// Logging through the host. Every line is tagged with the trace id of the
+// request being handled, so lines from one workflow run can be correlated
+// across nodes. Use `pocket_log!` rather than calling `emit` directly.
+//
+// A request's `log_level` sets the least severe level it logs; lines below
+// it are dropped before they're formatted or cross into the host. Without
+// one, every level is logged.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{host, Request};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+#[repr(u32)]
+pub(crate) enum Level {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+// Per-call details that follow a request through its handler
+pub(crate) struct Context<'a> {
+    pub(crate) trace_id: Option<&'a str>,
+    pub(crate) min_level: Level,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn for_request(request: &'a Request) -> Self {
+        Context {
+            trace_id: request.trace_id.as_deref(),
+            min_level: request.log_level.unwrap_or(Level::Debug),
+        }
+    }
+}
+
+pub(crate) fn emit(context: &Context, level: Level, message: std::fmt::Arguments) {
+    if level < context.min_level {
+        return;
+    }
+    let line = match context.trace_id {
+        Some(trace_id) => format!("[trace_id={}] {}", trace_id, message),
+        None => message.to_string(),
+    };
+    host::host_log(level as u32, &line);
+}
+
+// `pocket_log!(context, Level::Info, "counted {} words", n)`
+macro_rules! pocket_log {
+    ($context:expr, $level:expr, $($arg:tt)+) => {
+        $crate::log::emit(&$context, $level, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_prefixed_with_the_trace_id() {
+        pocket_log!(Context { trace_id: Some("abc123"), min_level: Level::Debug }, Level::Warn, "{} left", 3);
+        pocket_log!(Context { trace_id: None, min_level: Level::Debug }, Level::Debug, "untraced");
+        assert_eq!(
+            host::take_logs(),
+            vec![
+                (Level::Warn as u32, "[trace_id=abc123] 3 left".to_string()),
+                (Level::Debug as u32, "untraced".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_below_the_minimum_level_are_dropped() {
+        let context = Context { trace_id: None, min_level: Level::Info };
+        pocket_log!(context, Level::Debug, "dropped");
+        pocket_log!(context, Level::Info, "kept");
+        pocket_log!(context, Level::Error, "also kept");
+        assert_eq!(
+            host::take_logs(),
+            vec![(Level::Info as u32, "kept".to_string()), (Level::Error as u32, "also kept".to_string())]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+// Memoized exec results (`memoize`). Counting is a pure function of the
+// config and the prep data, so a repeat of the same pair can return the
+// earlier output instead of counting again. Results are keyed by the
+// node, the effective config and the input in canonical form, found by its
+// hash and then compared in full, so a collision is a miss rather than
+// another request's output; a request that can't be keyed is simply
+// counted. The memo holds `capacity` results per instance, as negotiated
+// in `init_with` (see `session`).
+//
+// Like `state`, this is only a cache: dropping it never changes results,
+// so it's cleared under critical memory pressure.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::clock::Clock;
+use crate::bloom::fnv1a;
+
+pub(crate) const DEFAULT_CAPACITY: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Key {
+    hash: u64,
+    canonical: String,
+}
+
+struct Entry {
+    key: Key,
+    stored_ns: u64,
+    output: Value,
+}
+
+// How often lookups found a live entry, over the instance's lifetime
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MemoStats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+}
+
+struct Memo {
+    // Least recently used first
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    stats: MemoStats,
+}
+
+impl Memo {
+    const fn new() -> Self {
+        Memo { entries: VecDeque::new(), capacity: DEFAULT_CAPACITY, stats: MemoStats { hits: 0, misses: 0 } }
+    }
+
+    fn get(&mut self, key: &Key, ttl_ms: Option<u64>, now_ns: u64) -> Option<Value> {
+        let expired = |entry: &Entry| {
+            ttl_ms.is_some_and(|ttl| now_ns.saturating_sub(entry.stored_ns) >= ttl.saturating_mul(1_000_000))
+        };
+        // The hash first, as the cheap comparison
+        let found = self.entries.iter()
+            .position(|entry| entry.key.hash == key.hash && entry.key.canonical == key.canonical);
+        let entry = found.and_then(|index| self.entries.remove(index)).filter(|entry| !expired(entry));
+        let Some(entry) = entry else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        let output = entry.output.clone();
+        self.entries.push_back(entry);
+        Some(output)
+    }
+
+    fn insert(&mut self, key: Key, now_ns: u64, output: Value) {
+        self.entries.retain(|entry| entry.key != key);
+        self.entries.push_back(Entry { key, stored_ns: now_ns, output });
+        self.evict();
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+fn with_memo<R>(f: impl FnOnce(&mut Memo) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        static MEMO: Mutex<Memo> = Mutex::new(Memo::new());
+        f(&mut MEMO.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    // Per test thread, as in `state`
+    #[cfg(test)]
+    {
+        thread_local! {
+            static MEMO: Mutex<Memo> = const { Mutex::new(Memo::new()) };
+        }
+        MEMO.with(|memo| f(&mut memo.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+// The memo key for running `node` with `config` on `input`. `None` when
+// there's no input to key on.
+pub(crate) fn fingerprint(node: &str, config: &impl Serialize, input: Option<&Value>) -> Option<Key> {
+    // Object keys serialize sorted, so equal values give equal bytes
+    let config = serde_json::to_string(&serde_json::to_value(config).ok()?).ok()?;
+    let input = serde_json::to_string(input?).ok()?;
+    let canonical = format!("{}\0{}\0{}", node, config, input);
+    Some(Key { hash: fnv1a(canonical.as_bytes(), 0), canonical })
+}
+
+// The output stored under `key`, unless older than `ttl_ms`
+pub(crate) fn get(key: &Key, ttl_ms: Option<u64>, clock: &dyn Clock) -> Option<Value> {
+    let now_ns = clock.now_ns();
+    with_memo(|memo| memo.get(key, ttl_ms, now_ns))
+}
+
+// Stores a result, evicting the least recently used past the capacity
+pub(crate) fn insert(key: Key, clock: &dyn Clock, output: Value) {
+    let now_ns = clock.now_ns();
+    with_memo(|memo| memo.insert(key, now_ns, output));
+}
+
+// Results kept from now on; the least recently used past it go at once
+pub(crate) fn set_capacity(capacity: usize) {
+    with_memo(|memo| memo.set_capacity(capacity));
+}
+
+pub(crate) fn stats() -> MemoStats {
+    with_memo(|memo| memo.stats)
+}
+
+// Drops every entry; the counters keep counting
+pub(crate) fn clear() {
+    with_memo(|memo| memo.entries.clear());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(n: u64) -> Key {
+        fingerprint("word-count", &json!({}), Some(&json!(n))).unwrap()
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut memo = Memo::new();
+        memo.set_capacity(2);
+        for n in 0..3 {
+            memo.insert(key(n), 0, json!(n));
+        }
+        assert!(memo.get(&key(0), None, 0).is_none());
+        assert_eq!(memo.get(&key(1), None, 0), Some(json!(1)));
+        memo.insert(key(3), 0, json!(3));
+        assert!(memo.get(&key(2), None, 0).is_none());
+        assert_eq!(memo.stats, MemoStats { hits: 1, misses: 2 });
+
+        memo.set_capacity(1);
+        assert!(memo.get(&key(1), None, 0).is_none());
+        assert!(memo.get(&key(3), None, 0).is_some());
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let mut memo = Memo::new();
+        memo.insert(key(7), 1_000_000, json!("out"));
+        assert!(memo.get(&key(7), Some(5), 5_999_999).is_some());
+        assert!(memo.get(&key(7), Some(5), 6_000_000).is_none());
+        // Expired entries are dropped, not kept for a longer ttl
+        assert!(memo.get(&key(7), None, 6_000_000).is_none());
+    }
+
+    #[test]
+    fn a_colliding_hash_is_a_miss() {
+        let mut memo = Memo::new();
+        memo.insert(key(1), 0, json!("one"));
+        let collision = Key { hash: key(1).hash, canonical: key(2).canonical };
+        assert!(memo.get(&collision, None, 0).is_none());
+        assert_eq!(memo.get(&key(1), None, 0), Some(json!("one")));
+    }
+
+    #[test]
+    fn fingerprint_ignores_key_order_but_not_values() {
+        let input = json!({"cleaned_text": "a b", "case_sensitive": false});
+        let reordered = json!({"case_sensitive": false, "cleaned_text": "a b"});
+        let key = fingerprint("word-count", &json!({"x": 1}), Some(&input));
+        assert!(key.is_some());
+        assert_eq!(key, fingerprint("word-count", &json!({"x": 1}), Some(&reordered)));
+        assert_ne!(key, fingerprint("word-count", &json!({"x": 2}), Some(&input)));
+        assert_ne!(key, fingerprint("corpus", &json!({"x": 1}), Some(&input)));
+        assert_eq!(fingerprint("word-count", &json!({}), None), None);
+    }
+}
@@ -0,0 +1,192 @@
+// Accounting for guest memory handed out through `alloc`. Outstanding
+// bytes (allocated but not yet `dealloc`ed) are capped at the plugin's
+// memory permission so a runaway host loop or handler fails fast with a
+// null pointer instead of exhausting the instance. Hosts may call `alloc`
+// and `dealloc` from several threads at once, so the count is atomic.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{parse_memory_limit, PERMISSION_MEMORY};
+
+// Bytes allocated and not yet released
+pub(crate) struct AllocAccount {
+    outstanding: AtomicUsize,
+}
+
+impl AllocAccount {
+    pub(crate) const fn new() -> Self {
+        AllocAccount { outstanding: AtomicUsize::new(0) }
+    }
+
+    // Accounts for `size` more bytes unless that would cross `ceiling`
+    pub(crate) fn reserve_within(&self, size: usize, ceiling: usize) -> Result<(), String> {
+        self.outstanding
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |outstanding| {
+                outstanding.checked_add(size).filter(|total| *total <= ceiling)
+            })
+            .map(|_| ())
+            .map_err(|outstanding| {
+                format!(
+                    "alloc of {} bytes refused: {} bytes already outstanding of the {} byte memory permission",
+                    size, outstanding, ceiling
+                )
+            })
+    }
+
+    pub(crate) fn release(&self, size: usize) {
+        let _ = self.outstanding.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |outstanding| {
+            Some(outstanding.saturating_sub(size))
+        });
+    }
+
+    #[cfg(test)]
+    fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::SeqCst)
+    }
+}
+
+static ALLOCATIONS: AllocAccount = AllocAccount::new();
+
+// Why the most recent allocation was refused, for the error reported by
+// the export that then receives the null pointer
+static LAST_REFUSAL: Mutex<Option<String>> = Mutex::new(None);
+
+fn ceiling() -> usize {
+    parse_memory_limit(PERMISSION_MEMORY).unwrap_or(usize::MAX)
+}
+
+// Accounts for `size` more bytes, or refuses if that would cross the ceiling
+pub(crate) fn reserve(size: usize) -> bool {
+    match ALLOCATIONS.reserve_within(size, ceiling()) {
+        Ok(()) => true,
+        Err(reason) => {
+            *LAST_REFUSAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+            false
+        }
+    }
+}
+
+pub(crate) fn release(size: usize) {
+    ALLOCATIONS.release(size);
+}
+
+// Accounts for a buffer the plugin allocated itself and handed to the host,
+// which frees it with `dealloc`. Never refuses: the size was already capped
+// at the permission, so a host that holds on to outputs sees its own later
+// `alloc`s refused instead.
+pub(crate) fn charge(size: usize) {
+    let _ = ALLOCATIONS.reserve_within(size, usize::MAX);
+}
+
+// Borrows a host-provided input buffer. A null `ptr` is what the host ends
+// up passing after a refused `alloc`, so it's reported with that reason.
+///
+/// # Safety
+///
+/// A non-null `ptr` must be valid for reads of `len` bytes.
+pub(crate) unsafe fn input<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], String> {
+    if !ptr.is_null() {
+        return Ok(std::slice::from_raw_parts(ptr, len));
+    }
+    match LAST_REFUSAL.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        Some(reason) => Err(format!("No input buffer ({})", reason)),
+        None => Err("No input buffer".to_string()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn outstanding() -> usize {
+    ALLOCATIONS.outstanding()
+}
+
+// Net heap bytes allocated on the current thread, so tests can check that
+// a code path frees everything it allocates
+#[cfg(test)]
+pub(crate) mod heap {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static NET: Cell<isize> = const { Cell::new(0) };
+        // Every byte ever allocated, freed or not
+        static TOTAL: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn track(delta: isize) {
+        // Unavailable while the thread is being torn down
+        let _ = NET.try_with(|net| net.set(net.get() + delta));
+        if delta > 0 {
+            let _ = TOTAL.try_with(|total| total.set(total.get() + delta as usize));
+        }
+    }
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                track(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            track(-(layout.size() as isize));
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    pub(crate) fn net_bytes() -> isize {
+        NET.with(Cell::get)
+    }
+
+    pub(crate) fn allocated_bytes() -> usize {
+        TOTAL.with(Cell::get)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn reservations_past_the_ceiling_are_refused() {
+        let account = AllocAccount::new();
+        assert_eq!(account.reserve_within(60, 100), Ok(()));
+        let refusal = account.reserve_within(60, 100).unwrap_err();
+        assert!(refusal.starts_with("alloc of 60 bytes refused"), "{}", refusal);
+        account.release(60);
+        assert_eq!(account.outstanding(), 0);
+    }
+
+    #[test]
+    fn concurrent_reservations_never_cross_the_ceiling() {
+        const CHUNK: usize = 64;
+        const CEILING: usize = 4 * CHUNK;
+        let account = Arc::new(AllocAccount::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let account = Arc::clone(&account);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        if account.reserve_within(CHUNK, CEILING).is_ok() {
+                            assert!(account.outstanding() <= CEILING);
+                            account.release(CHUNK);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(account.outstanding(), 0);
+    }
+}
@@ -0,0 +1,122 @@
+// Typed in-process API. A native Rust host can run the nodes directly,
+// without the WASM ABI or JSON round-trips; build with the `native` feature
+// so the `#[no_mangle]` exports (`alloc`, `call`, ...) aren't emitted into
+// the host binary.
+
+use crate::{
+    count_words, prepare, refuse_invalid, route, Deadline, ExecError, TokenClassifier, UnknownClassifier,
+    WordCounterConfig, WordCounterInput, WordCounterOutput,
+};
+
+// A node as ordinary Rust: exec runs the node's prep and exec phases, post
+// picks the route for an exec result.
+pub trait Node {
+    type Config;
+    type Input;
+    type Output;
+
+    fn exec(&self, config: &Self::Config, input: &Self::Input) -> Result<Self::Output, String>;
+
+    fn post(&self, output: &Self::Output) -> &'static str;
+}
+
+// The word-count node
+pub struct WordCounter {
+    classifier: Box<dyn TokenClassifier>,
+}
+
+impl WordCounter {
+    // Uses `classifier` for `keep_classes` filtering instead of the built-in
+    // one, which classifies everything as `unknown`
+    pub fn with_classifier(classifier: impl TokenClassifier + 'static) -> Self {
+        WordCounter { classifier: Box::new(classifier) }
+    }
+}
+
+impl Default for WordCounter {
+    fn default() -> Self {
+        WordCounter::with_classifier(UnknownClassifier)
+    }
+}
+
+impl Node for WordCounter {
+    type Config = WordCounterConfig;
+    type Input = WordCounterInput;
+    type Output = WordCounterOutput;
+
+    // Not subject to the plugin's timeout permission, so this never times
+    // out; nor does it touch the instance's frequency cache (no `delta`).
+    // The config is checked as a request's is, size caps included.
+    fn exec(&self, config: &WordCounterConfig, input: &WordCounterInput) -> Result<WordCounterOutput, String> {
+        refuse_invalid(config)?;
+        let prep = prepare(config, input.view())?;
+        count_words(config, &prep, self.classifier.as_ref(), &mut Deadline::unbounded()).map_err(|e| match e {
+            ExecError::TimedOut => "Timed out".to_string(),
+            ExecError::Failed(e) => e,
+        })
+    }
+
+    fn post(&self, output: &WordCounterOutput) -> &'static str {
+        route(output.total_words as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenClass;
+
+    fn input(text: &str) -> WordCounterInput {
+        WordCounterInput { text: text.to_string(), ..WordCounterInput::default() }
+    }
+
+    #[test]
+    fn exec_counts_without_the_abi() {
+        let output = WordCounter::default()
+            .exec(&WordCounterConfig::default(), &input("The cat and the hat"))
+            .unwrap();
+        assert_eq!(output.total_words, 2);
+        assert_eq!(output.word_frequencies["cat"], 1);
+        assert_eq!(WordCounter::default().post(&output), "short");
+    }
+
+    #[test]
+    fn typed_config_applies() {
+        let config = WordCounterConfig { min_word_length: 4, ..WordCounterConfig::default() };
+        let output = WordCounter::default().exec(&config, &input("tiny words stay out")).unwrap();
+        assert_eq!(output.total_words, 3);
+        assert_eq!(output.longest_word, "words");
+    }
+
+    #[test]
+    fn custom_classifier_is_used() {
+        struct Nouns;
+        impl TokenClassifier for Nouns {
+            fn classify(&self, token: &str) -> TokenClass {
+                if token == "rust" { TokenClass::Noun } else { TokenClass::Verb }
+            }
+        }
+        let config = WordCounterConfig { keep_classes: vec![TokenClass::Noun], ..WordCounterConfig::default() };
+        let output = WordCounter::with_classifier(Nouns).exec(&config, &input("rust runs fast")).unwrap();
+        assert_eq!(output.total_words, 1);
+        assert_eq!(WordCounter::default().post(&output), "short");
+    }
+
+    #[test]
+    fn invalid_config_is_an_error() {
+        let config = WordCounterConfig { collation: Some("???".to_string()), ..WordCounterConfig::default() };
+        assert!(WordCounter::default().exec(&config, &input("hi")).is_err());
+    }
+
+    #[test]
+    fn oversized_config_is_an_error_not_an_allocation() {
+        let configs = [
+            WordCounterConfig { novelty: true, novelty_capacity: usize::MAX, ..WordCounterConfig::default() },
+            WordCounterConfig { approximate: true, sketch_width: usize::MAX, ..WordCounterConfig::default() },
+        ];
+        for config in configs {
+            let error = WordCounter::default().exec(&config, &input("hi")).unwrap_err();
+            assert!(error.starts_with("Invalid config: "), "{}", error);
+        }
+    }
+}
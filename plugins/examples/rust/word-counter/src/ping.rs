@@ -0,0 +1,83 @@
+// `call` fast path for liveness checks. A host probing the plugin sends a
+// request whose `function` is `__ping` (no node needed), often enough that
+// parsing it in full would dominate; `is_ping` spots one with a single
+// pass over the bytes that allocates nothing, tracking only string and
+// nesting boundaries until it meets the top-level `function` key. The rest
+// of the request isn't read, so a ping with a malformed body still gets
+// its pong.
+
+pub(crate) const FUNCTION: &str = "__ping";
+
+pub(crate) fn is_ping(request: &[u8]) -> bool {
+    let mut depth = 0usize;
+    // Whether the next string at the top level is an object key
+    let mut expect_key = false;
+    let mut i = 0;
+    while i < request.len() {
+        match request[i] {
+            b'{' => {
+                depth += 1;
+                expect_key = depth == 1;
+            }
+            b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b',' => expect_key = depth == 1,
+            b'"' => {
+                let Some(end) = string_end(request, i) else {
+                    return false;
+                };
+                if depth == 1 && expect_key && &request[i..end] == b"\"function\"" {
+                    return value_after_key(&request[end..]).starts_with(b"\"__ping\"");
+                }
+                expect_key = false;
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+// Index just past the closing quote of the string opening at `start`
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// What follows a key's `:`, or nothing if the colon is missing
+fn value_after_key(rest: &[u8]) -> &[u8] {
+    match rest.trim_ascii_start().strip_prefix(b":") {
+        Some(value) => value.trim_ascii_start(),
+        None => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_level_ping_function_is_spotted() {
+        assert!(is_ping(br#"{"function":"__ping"}"#));
+        assert!(is_ping(b"{ \"node\": \"word-count\",\n  \"function\" : \"__ping\", \"input\": {"));
+        assert!(!is_ping(br#"{"function": "exec", "node": "word-count"}"#));
+        assert!(!is_ping(br#"{"function": "__pinged"}"#));
+    }
+
+    #[test]
+    fn ping_lookalikes_elsewhere_are_not_pings() {
+        assert!(!is_ping(br#"{"input": {"function": "__ping"}, "function": "exec"}"#));
+        assert!(!is_ping(br#"{"node": "function", "config": ["function", "__ping"]}"#));
+        assert!(!is_ping(br#"{"input": {"text": "\"function\": \"__ping\""}}"#));
+        assert!(!is_ping(br#"{"function": "#));
+    }
+}
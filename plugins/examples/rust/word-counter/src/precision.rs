@@ -0,0 +1,62 @@
+// Rounding of an output's floats to `float_precision` decimal places, so
+// probabilities and scores serialize compactly and snapshots compare equal
+// across platforms whose last bits differ. It walks the serialized output,
+// so every float field is covered, however deeply nested; integers are left
+// alone, and a rounded float stays a float (`1.0`, not `1`). Small negatives
+// round to `0.0`, not `-0.0`.
+
+use serde_json::Value;
+
+// Past this many places rounding changes nothing an f64 can show
+pub(crate) const MAX_DIGITS: u32 = 15;
+
+pub(crate) fn round_floats(value: &mut Value, digits: u32) {
+    match value {
+        Value::Number(number) if number.is_f64() && digits < MAX_DIGITS => {
+            let scale = 10f64.powi(digits as i32);
+            let x = number.as_f64().unwrap();
+            // Adding 0.0 turns -0.0 into 0.0 and leaves everything else be
+            if let Some(rounded) = serde_json::Number::from_f64((x * scale).round() / scale + 0.0) {
+                *number = rounded;
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| round_floats(value, digits)),
+        Value::Object(fields) => fields.values_mut().for_each(|value| round_floats(value, digits)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn nested_floats_round_and_integers_stay() {
+        let mut output = json!({
+            "total_words": 3,
+            "average_word_length": 4.666666666666667,
+            "word_probabilities": {"a": 0.3333333333333333, "b": 0.6666666666666666},
+            "collocations": [{"score": 1.0049999}],
+        });
+        round_floats(&mut output, 2);
+        assert_eq!(output, json!({
+            "total_words": 3,
+            "average_word_length": 4.67,
+            "word_probabilities": {"a": 0.33, "b": 0.67},
+            "collocations": [{"score": 1.0}],
+        }));
+        assert!(output["collocations"][0]["score"].is_f64());
+    }
+
+    #[test]
+    fn zero_places_and_the_maximum() {
+        let mut output = json!([2.5, -0.4, 0.1 + 0.2]);
+        round_floats(&mut output, 0);
+        // `-0.0 == 0.0`, so compare them as written
+        assert_eq!(output.to_string(), "[3.0,0.0,0.0]");
+        let mut output = json!([0.1 + 0.2]);
+        round_floats(&mut output, MAX_DIGITS);
+        assert_eq!(output, json!([0.30000000000000004]));
+    }
+}
@@ -0,0 +1,83 @@
+// The reading-time node, declared with `#[derive(PocketNode)]`: its
+// definition, schemas and exec/post handlers all come from the `Node` impl
+// below (see `derived`). Field doc comments become schema descriptions.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{Node, PocketNode};
+
+#[derive(PocketNode, Default)]
+#[pocket(node_type = "reading-time", category = "text", description = "Estimate how long a text takes to read")]
+pub struct ReadingTime;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ReadingTimeConfig {
+    /// Reading speed, in words per minute
+    #[schemars(range(min = 1))]
+    pub words_per_minute: u32,
+}
+
+impl Default for ReadingTimeConfig {
+    // An adult's average silent reading speed for non-fiction
+    fn default() -> Self {
+        ReadingTimeConfig { words_per_minute: 238 }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct ReadingTimeInput {
+    /// The text to estimate
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ReadingTimeOutput {
+    /// Whitespace-separated words in the text
+    pub words: usize,
+    /// Whole minutes, rounded up
+    pub minutes: u64,
+}
+
+impl Node for ReadingTime {
+    type Config = ReadingTimeConfig;
+    type Input = ReadingTimeInput;
+    type Output = ReadingTimeOutput;
+
+    fn exec(&self, config: &ReadingTimeConfig, input: &ReadingTimeInput) -> Result<ReadingTimeOutput, String> {
+        if config.words_per_minute == 0 {
+            return Err("words_per_minute must be at least 1".to_string());
+        }
+        let words = input.text.split_whitespace().count();
+        Ok(ReadingTimeOutput { words, minutes: (words as u64).div_ceil(config.words_per_minute.into()) })
+    }
+
+    // A minute or less is a quick read
+    fn post(&self, output: &ReadingTimeOutput) -> &'static str {
+        if output.minutes <= 1 { "quick" } else { "default" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(text: &str, words_per_minute: u32) -> Result<ReadingTimeOutput, String> {
+        let input = ReadingTimeInput { text: text.to_string() };
+        ReadingTime.exec(&ReadingTimeConfig { words_per_minute }, &input)
+    }
+
+    #[test]
+    fn minutes_round_up_and_short_texts_route_quick() {
+        let output = estimate("one two three", 2).unwrap();
+        assert_eq!(output, ReadingTimeOutput { words: 3, minutes: 2 });
+        assert_eq!(ReadingTime.post(&output), "default");
+        assert_eq!(ReadingTime.post(&estimate("", 238).unwrap()), "quick");
+    }
+
+    #[test]
+    fn zero_reading_speed_is_rejected() {
+        assert!(estimate("one", 0).is_err());
+    }
+}
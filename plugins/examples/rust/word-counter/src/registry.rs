@@ -0,0 +1,290 @@
+// Node registry: maps each node type to its definition and the lifecycle
+// functions it implements. `call`, `metadata` and `supports_function` are
+// all driven from this table, so a node only needs to be listed once.
+// Besides the prep/exec/post lifecycle, a node may register any other named
+// function (e.g. `validate`); `call` dispatches on `Request.function` alone.
+//
+// `nodes` is the table in use: `NODES`, plus any derived nodes a plugin
+// built on this crate `register`s at run time (see `derived::register`).
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::derived::{self, PocketNode};
+use crate::reading_time::ReadingTime;
+use crate::{
+    corpus_definition, digest, language, handle_corpus_exec, handle_corpus_prep, handle_diversity_exec,
+    handle_diversity_post, handle_exec, handle_flush, handle_post, handle_prep, handle_record,
+    handle_validate, lexical_diversity_definition, word_count_definition, ErrorCode, NodeDefinition,
+    Request, Response,
+};
+
+pub(crate) type Handler = fn(&Request) -> Response;
+
+// A function of a binary node, given the raw input passed to `call_bytes`
+pub(crate) type BytesHandler = fn(&Request, &[u8]) -> Response;
+
+pub(crate) type NodeRegistry = [NodeEntry];
+
+// Functions every node type is expected to have, registered or not
+const LIFECYCLE: &[&str] = &["prep", "exec", "post"];
+
+#[derive(Clone, Copy)]
+pub(crate) struct NodeEntry {
+    pub(crate) node_type: &'static str,
+    pub(crate) definition: fn() -> NodeDefinition,
+    pub(crate) functions: &'static [(&'static str, Handler)],
+    // Functions called through `call_bytes` rather than `call`
+    pub(crate) bytes_functions: &'static [(&'static str, BytesHandler)],
+}
+
+impl NodeEntry {
+    pub(crate) fn handler(&self, function: &str) -> Option<Handler> {
+        self.functions
+            .iter()
+            .find(|(name, _)| *name == function)
+            .map(|(_, handler)| *handler)
+    }
+
+    pub(crate) fn bytes_handler(&self, function: &str) -> Option<BytesHandler> {
+        self.bytes_functions
+            .iter()
+            .find(|(name, _)| *name == function)
+            .map(|(_, handler)| *handler)
+    }
+
+    pub(crate) fn supports(&self, function: &str) -> bool {
+        self.handler(function).is_some() || self.bytes_handler(function).is_some()
+    }
+}
+
+pub(crate) static NODES: &[NodeEntry] = &[
+    NodeEntry {
+        node_type: "word-count",
+        definition: word_count_definition,
+        functions: &[
+            ("prep", handle_prep),
+            ("exec", handle_exec),
+            ("post", handle_post),
+            ("validate", handle_validate),
+            ("record", handle_record),
+            ("flush", handle_flush),
+        ],
+        bytes_functions: &[],
+    },
+    NodeEntry {
+        node_type: "lexical-diversity",
+        definition: lexical_diversity_definition,
+        functions: &[
+            ("prep", handle_prep),
+            ("exec", handle_diversity_exec),
+            ("post", handle_diversity_post),
+            ("validate", handle_validate),
+        ],
+        bytes_functions: &[],
+    },
+    NodeEntry {
+        node_type: "corpus",
+        definition: corpus_definition,
+        functions: &[
+            ("prep", handle_corpus_prep),
+            ("exec", handle_corpus_exec),
+            ("post", handle_post),
+        ],
+        bytes_functions: &[],
+    },
+    NodeEntry {
+        node_type: "detect-language",
+        definition: language::definition,
+        functions: &[("exec", language::handle_exec)],
+        bytes_functions: &[],
+    },
+    NodeEntry {
+        node_type: "byte-digest",
+        definition: digest::definition,
+        functions: &[],
+        bytes_functions: &[("exec", digest::handle_exec)],
+    },
+    Derived::<ReadingTime>::ENTRY,
+];
+
+// The entry for a `#[derive(PocketNode)]` node, with exec and post
+// generated from its `Node` impl (see `derived`)
+pub(crate) struct Derived<T>(PhantomData<T>);
+
+impl<T> Derived<T>
+where
+    T: PocketNode,
+    T::Config: DeserializeOwned + Default,
+    T::Input: DeserializeOwned,
+    T::Output: Serialize + DeserializeOwned,
+{
+    pub(crate) const ENTRY: NodeEntry = NodeEntry {
+        node_type: T::NODE_TYPE,
+        definition: derived::definition::<T>,
+        functions: &[("exec", derived::handle_exec::<T>), ("post", derived::handle_post::<T>)],
+        bytes_functions: &[],
+    };
+}
+
+// `NODES` and the registered nodes after them, once any are. Each
+// registration leaks a new table, as handlers may still be reading the
+// old one; a plugin registers its few nodes once.
+#[cfg(not(test))]
+static REGISTERED: AtomicPtr<Vec<NodeEntry>> = AtomicPtr::new(std::ptr::null_mut());
+
+// Per test thread, as in `envelope`
+#[cfg(test)]
+thread_local! {
+    static REGISTERED: AtomicPtr<Vec<NodeEntry>> = const { AtomicPtr::new(std::ptr::null_mut()) };
+}
+
+fn with_registered<R>(f: impl FnOnce(&AtomicPtr<Vec<NodeEntry>>) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        f(&REGISTERED)
+    }
+    #[cfg(test)]
+    {
+        REGISTERED.with(f)
+    }
+}
+
+// Serializes registrations, so none is lost to a racing one
+static REGISTERING: Mutex<()> = Mutex::new(());
+
+pub(crate) fn nodes() -> &'static NodeRegistry {
+    let registered = with_registered(|registered| registered.load(Ordering::Acquire));
+    // SAFETY: a stored table is leaked, so it lives for the program
+    match unsafe { registered.as_ref() } {
+        Some(table) => table,
+        None => NODES,
+    }
+}
+
+pub(crate) fn register(entry: NodeEntry) -> Result<(), String> {
+    let _guard = REGISTERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let current = nodes();
+    if current.iter().any(|node| node.node_type == entry.node_type) {
+        return Err(format!("Node type {} is already registered", entry.node_type));
+    }
+    let table: Vec<NodeEntry> = current.iter().copied().chain([entry]).collect();
+    with_registered(|registered| registered.store(Box::into_raw(Box::new(table)), Ordering::Release));
+    Ok(())
+}
+
+pub(crate) fn find(node_type: &str) -> Option<&'static NodeEntry> {
+    nodes().iter().find(|node| node.node_type == node_type)
+}
+
+// Why a request couldn't be routed to a handler
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RouteError {
+    UnknownNode(String),
+    // No node in the registry implements the function
+    UnknownFunction(String),
+    // A real function, but not one this node implements
+    FunctionNotSupportedByNode { node: String, function: String },
+}
+
+impl RouteError {
+    pub(crate) fn code(&self) -> ErrorCode {
+        match self {
+            RouteError::UnknownNode(_) => ErrorCode::UnknownNode,
+            RouteError::UnknownFunction(_) => ErrorCode::UnknownFunction,
+            RouteError::FunctionNotSupportedByNode { .. } => ErrorCode::FunctionNotSupportedByNode,
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            RouteError::UnknownNode(node) => format!("Unknown node: {}", node),
+            RouteError::UnknownFunction(function) => format!("Unknown function: {}", function),
+            RouteError::FunctionNotSupportedByNode { node, function } => {
+                format!("Node {} does not support function {}", node, function)
+            }
+        }
+    }
+}
+
+pub(crate) fn resolve(registry: &NodeRegistry, node_type: &str, function: &str) -> Result<Handler, RouteError> {
+    resolve_with(registry, node_type, function, NodeEntry::handler)
+}
+
+pub(crate) fn resolve_bytes(
+    registry: &NodeRegistry,
+    node_type: &str,
+    function: &str,
+) -> Result<BytesHandler, RouteError> {
+    resolve_with(registry, node_type, function, NodeEntry::bytes_handler)
+}
+
+fn resolve_with<H>(
+    registry: &NodeRegistry,
+    node_type: &str,
+    function: &str,
+    lookup: fn(&NodeEntry, &str) -> Option<H>,
+) -> Result<H, RouteError> {
+    let node = registry
+        .iter()
+        .find(|node| node.node_type == node_type)
+        .ok_or_else(|| RouteError::UnknownNode(node_type.to_string()))?;
+    if let Some(handler) = lookup(node, function) {
+        return Ok(handler);
+    }
+    if LIFECYCLE.contains(&function) || registry.iter().any(|node| node.supports(function)) {
+        Err(RouteError::FunctionNotSupportedByNode { node: node_type.to_string(), function: function.to_string() })
+    } else {
+        Err(RouteError::UnknownFunction(function.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_types_are_unique() {
+        for (i, node) in NODES.iter().enumerate() {
+            assert!(
+                NODES[i + 1..].iter().all(|other| other.node_type != node.node_type),
+                "duplicate node type {}",
+                node.node_type
+            );
+        }
+    }
+
+    #[test]
+    fn definitions_match_registered_type() {
+        for node in NODES {
+            assert_eq!((node.definition)().node_type, node.node_type);
+        }
+    }
+
+    #[test]
+    fn unknown_node_is_not_found() {
+        assert!(find("no-such-node").is_none());
+    }
+
+    static PARTIAL: &NodeRegistry = &[
+        NodeEntry { node_type: "full", definition: word_count_definition, functions: &[("exec", handle_exec), ("validate", handle_validate)], bytes_functions: &[] },
+        NodeEntry { node_type: "bare", definition: word_count_definition, functions: &[("exec", handle_exec)], bytes_functions: &[] },
+    ];
+
+    #[test]
+    fn routing_errors_distinguish_node_and_function() {
+        assert!(resolve(PARTIAL, "bare", "exec").is_ok());
+        assert_eq!(resolve(PARTIAL, "missing", "exec").unwrap_err(), RouteError::UnknownNode("missing".to_string()));
+        assert_eq!(resolve(PARTIAL, "bare", "summon").unwrap_err(), RouteError::UnknownFunction("summon".to_string()));
+        for function in ["validate", "post"] {
+            assert_eq!(
+                resolve(PARTIAL, "bare", function).unwrap_err(),
+                RouteError::FunctionNotSupportedByNode { node: "bare".to_string(), function: function.to_string() }
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Deterministic randomness. WASM guests have no entropy source of their own,
+// so every randomized feature draws from a `SeededRng` whose seed comes from
+// the host (`host::random_seed`); the same seed always yields the same
+// results.
+
+// SplitMix64: tiny, fast, and good enough for sampling
+pub(crate) struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in `0..bound`. `bound` must be non-zero.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        // Rejection sampling avoids modulo bias
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+// Picks up to `size` items uniformly at random in one pass (Algorithm R).
+pub(crate) fn reservoir_sample<T: Clone>(items: &[T], size: usize, rng: &mut SeededRng) -> Vec<T> {
+    let mut reservoir: Vec<T> = items.iter().take(size).cloned().collect();
+    for (i, item) in items.iter().enumerate().skip(size) {
+        let j = rng.below(i as u64 + 1) as usize;
+        if j < size {
+            reservoir[j] = item.clone();
+        }
+    }
+    reservoir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seed: u64) -> Vec<u32> {
+        let items: Vec<u32> = (0..1000).collect();
+        reservoir_sample(&items, 10, &mut SeededRng::new(seed))
+    }
+
+    #[test]
+    fn same_seed_gives_same_sample() {
+        assert_eq!(sample(42), sample(42));
+    }
+
+    #[test]
+    fn different_seeds_give_different_samples() {
+        assert_ne!(sample(1), sample(2));
+    }
+
+    #[test]
+    fn small_inputs_are_kept_whole() {
+        let items = vec!["a", "b"];
+        assert_eq!(reservoir_sample(&items, 5, &mut SeededRng::new(0)), items);
+    }
+}
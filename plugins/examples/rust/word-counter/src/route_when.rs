@@ -0,0 +1,126 @@
+// Routing on output fields (`route_when`): post takes the route of the
+// first rule whose comparison holds for the exec result, and falls back to
+// the word-count routes when none does. Fields are dotted paths into nested
+// objects, as in `output_transform` (`lexical_diversity.ttr`), and name the
+// exec result's fields, before any transform.
+//
+// `eq` and `ne` compare any JSON values (numbers by value, so 5 equals
+// 5.0); the ordering ops compare numbers only. A rule whose field is absent
+// or not a number never matches an ordering op.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RouteRule {
+    pub field: String,
+    pub op: RouteOp,
+    pub value: Value,
+    pub route: String,
+}
+
+impl RouteRule {
+    fn matches(&self, output: &Value) -> bool {
+        let field = lookup(output, &self.field);
+        match self.op {
+            RouteOp::Eq => field.is_some_and(|field| same(field, &self.value)),
+            RouteOp::Ne => !field.is_some_and(|field| same(field, &self.value)),
+            op => {
+                let (Some(field), Some(value)) = (field.and_then(Value::as_f64), self.value.as_f64()) else {
+                    return false;
+                };
+                match op {
+                    RouteOp::Gt => field > value,
+                    RouteOp::Gte => field >= value,
+                    RouteOp::Lt => field < value,
+                    _ => field <= value,
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn check(rules: &[RouteRule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.field.split('.').any(str::is_empty) {
+            return Err(format!("route_when[{}]: invalid field path {:?}", i, rule.field));
+        }
+        if rule.route.is_empty() {
+            return Err(format!("route_when[{}]: route is empty", i));
+        }
+        if !matches!(rule.op, RouteOp::Eq | RouteOp::Ne) && !rule.value.is_number() {
+            return Err(format!("route_when[{}]: {} needs a numeric value", i, serde_json::to_value(rule.op).unwrap()));
+        }
+    }
+    Ok(())
+}
+
+// The route of the first rule `output` matches
+pub(crate) fn first_match<'a>(rules: &'a [RouteRule], output: &Value) -> Option<&'a str> {
+    rules.iter().find(|rule| rule.matches(output)).map(|rule| rule.route.as_str())
+}
+
+fn lookup<'a>(output: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(output, |value, key| value.get(key))
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(field: &str, op: RouteOp, value: Value, route: &str) -> RouteRule {
+        RouteRule { field: field.to_string(), op, value, route: route.to_string() }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            rule("total_words", RouteOp::Lt, json!(3), "tiny"),
+            rule("lexical_diversity.ttr", RouteOp::Gte, json!(0.5), "varied"),
+            rule("total_words", RouteOp::Gt, json!(1), "wordy"),
+        ];
+        let output = json!({"total_words": 4, "lexical_diversity": {"ttr": 0.5}});
+        assert_eq!(first_match(&rules, &output), Some("varied"));
+        assert_eq!(first_match(&rules, &json!({"total_words": 4})), Some("wordy"));
+        assert_eq!(first_match(&rules, &json!({"total_words": 1})), Some("tiny"));
+        assert_eq!(first_match(&rules, &json!({})), None);
+    }
+
+    #[test]
+    fn equality_compares_numbers_by_value_and_other_json_exactly() {
+        assert!(rule("n", RouteOp::Eq, json!(5), "x").matches(&json!({"n": 5.0})));
+        assert!(rule("lang", RouteOp::Eq, json!("eng"), "x").matches(&json!({"lang": "eng"})));
+        assert!(rule("lang", RouteOp::Ne, json!("eng"), "x").matches(&json!({})));
+        assert!(!rule("lang", RouteOp::Gt, json!(1), "x").matches(&json!({"lang": "eng"})));
+    }
+
+    #[test]
+    fn malformed_rules_are_rejected() {
+        assert!(check(&[rule("total_words", RouteOp::Gt, json!(1), "wordy")]).is_ok());
+        assert!(check(&[rule("a..b", RouteOp::Eq, json!(1), "x")]).is_err());
+        assert!(check(&[rule("a", RouteOp::Eq, json!(1), "")]).is_err());
+        assert_eq!(
+            check(&[rule("a", RouteOp::Eq, json!(1), "x"), rule("a", RouteOp::Lte, json!("1"), "y")]),
+            Err("route_when[1]: \"lte\" needs a numeric value".to_string())
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// Output schema versioning. Nodes with a declared output schema stamp every
+// exec output with a `schema_version`; consumers built against an older
+// shape can ask for it and the node's migration walks the output down one
+// version at a time.
+
+use serde_json::Value;
+
+// Converts an output of version `from` into the shape of version `from - 1`
+pub(crate) type Migration = fn(from: u32, output: Value) -> Value;
+
+pub(crate) struct VersionedOutput {
+    pub(crate) current: u32,
+    pub(crate) migrate: Migration,
+}
+
+impl VersionedOutput {
+    // Migrates `output` (in the current shape) to `requested`, defaulting to
+    // the current version, and records the version it ended up in.
+    pub(crate) fn stamp(&self, output: Value, requested: Option<u32>) -> Result<Value, String> {
+        let target = requested.unwrap_or(self.current);
+        if target == 0 || target > self.current {
+            return Err(format!(
+                "Unsupported output schema version {} (supported: 1-{})",
+                target, self.current
+            ));
+        }
+
+        let mut output = output;
+        for from in ((target + 1)..=self.current).rev() {
+            output = (self.migrate)(from, output);
+        }
+        if let Value::Object(map) = &mut output {
+            map.insert("schema_version".to_string(), Value::from(target));
+        }
+        Ok(output)
+    }
+}
+
+// Keeps only the listed top-level fields of an object
+pub(crate) fn retain_fields(output: Value, fields: &[&str]) -> Value {
+    match output {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, _)| fields.contains(&key.as_str()))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn migrate(from: u32, output: Value) -> Value {
+        match from {
+            3 => retain_fields(output, &["a", "b"]),
+            2 => retain_fields(output, &["a"]),
+            _ => output,
+        }
+    }
+
+    const OUTPUT: VersionedOutput = VersionedOutput { current: 3, migrate };
+
+    #[test]
+    fn current_version_is_stamped_by_default() {
+        let output = OUTPUT.stamp(json!({"a": 1, "b": 2, "c": 3}), None).unwrap();
+        assert_eq!(output, json!({"a": 1, "b": 2, "c": 3, "schema_version": 3}));
+    }
+
+    #[test]
+    fn migrations_chain_down_to_requested_version() {
+        let output = OUTPUT.stamp(json!({"a": 1, "b": 2, "c": 3}), Some(1)).unwrap();
+        assert_eq!(output, json!({"a": 1, "schema_version": 1}));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        assert!(OUTPUT.stamp(json!({}), Some(0)).is_err());
+        assert!(OUTPUT.stamp(json!({}), Some(4)).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+// Self-check for the hand-written schemas in `Metadata`. Typos in keywords
+// or `type` values aren't caught by serde and would otherwise only surface
+// when a host validator rejects the plugin, so the tests run every node's
+// schemas through this, and a plugin built on this crate can run its own
+// (`plugin_metadata()`, after `register`) through it too.
+
+use serde_json::{Map, Value};
+
+use crate::Metadata;
+
+const TYPES: &[&str] = &["null", "boolean", "object", "array", "number", "integer", "string"];
+
+const KEYWORDS: &[&str] = &[
+    "$schema", "$id", "$ref", "$defs", "title", "description", "type", "enum", "const", "default",
+    "examples", "deprecated", "readOnly", "writeOnly", "properties", "patternProperties",
+    "additionalProperties", "required", "minProperties", "maxProperties", "items", "prefixItems",
+    "minItems", "maxItems", "uniqueItems", "minimum", "maximum", "exclusiveMinimum",
+    "exclusiveMaximum", "multipleOf", "minLength", "maxLength", "pattern", "format", "oneOf",
+    "anyOf", "allOf", "not",
+];
+
+// Checks every schema embedded in `metadata`. Errors are prefixed with the
+// node type and a JSON pointer to the offending keyword.
+pub fn validate_metadata(metadata: &Metadata) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    for node in &metadata.nodes {
+        let schemas = [
+            ("configSchema", &node.config_schema),
+            ("inputSchema", &node.input_schema),
+            ("outputSchema", &node.output_schema),
+        ];
+        for (name, schema) in schemas {
+            if let Some(schema) = schema {
+                check_schema(schema, &format!("{}/{}", node.node_type, name), &mut errors);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_schema(schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(_) => return,
+        Value::Object(schema) => schema,
+        _ => return errors.push(format!("{}: schema must be an object or boolean", path)),
+    };
+
+    for keyword in schema.keys() {
+        if !KEYWORDS.contains(&keyword.as_str()) {
+            errors.push(format!("{}: unknown keyword {:?}", path, keyword));
+        }
+    }
+
+    let mut declared_type = None;
+    match schema.get("type") {
+        None => {}
+        Some(Value::String(t)) if TYPES.contains(&t.as_str()) => declared_type = Some(t.as_str()),
+        Some(Value::Array(types))
+            if !types.is_empty() && types.iter().all(|t| t.as_str().is_some_and(|t| TYPES.contains(&t))) => {}
+        Some(other) => errors.push(format!("{}/type: invalid type {}", path, other)),
+    }
+
+    if let (Some(declared), Some(default)) = (declared_type, schema.get("default")) {
+        if !matches_type(default, declared) {
+            errors.push(format!("{}/default: {} is not of type {:?}", path, default, declared));
+        }
+    }
+
+    if let Some(properties) = schema.get("properties") {
+        match properties {
+            Value::Object(properties) => {
+                for (name, property) in properties {
+                    check_schema(property, &format!("{}/properties/{}", path, name), errors);
+                }
+            }
+            _ => errors.push(format!("{}/properties: must be an object", path)),
+        }
+    }
+    check_required(schema, path, errors);
+
+    for keyword in ["items", "additionalProperties", "not"] {
+        if let Some(sub) = schema.get(keyword) {
+            check_schema(sub, &format!("{}/{}", path, keyword), errors);
+        }
+    }
+    for keyword in ["oneOf", "anyOf", "allOf"] {
+        match schema.get(keyword) {
+            None => {}
+            Some(Value::Array(subs)) if !subs.is_empty() => {
+                for (i, sub) in subs.iter().enumerate() {
+                    check_schema(sub, &format!("{}/{}/{}", path, keyword, i), errors);
+                }
+            }
+            Some(_) => errors.push(format!("{}/{}: must be a non-empty array", path, keyword)),
+        }
+    }
+
+    if schema.get("enum").is_some_and(|e| e.as_array().is_none_or(|e| e.is_empty())) {
+        errors.push(format!("{}/enum: must be a non-empty array", path));
+    }
+    for keyword in ["minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum", "multipleOf"] {
+        if schema.get(keyword).is_some_and(|v| !v.is_number()) {
+            errors.push(format!("{}/{}: must be a number", path, keyword));
+        }
+    }
+    for keyword in ["minItems", "maxItems", "minLength", "maxLength", "minProperties", "maxProperties"] {
+        if schema.get(keyword).is_some_and(|v| v.as_u64().is_none()) {
+            errors.push(format!("{}/{}: must be a non-negative integer", path, keyword));
+        }
+    }
+}
+
+// `required` must list declared properties, when there are any
+fn check_required(schema: &Map<String, Value>, path: &str, errors: &mut Vec<String>) {
+    let required = match schema.get("required") {
+        None => return,
+        Some(Value::Array(required)) => required,
+        Some(_) => return errors.push(format!("{}/required: must be an array", path)),
+    };
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for name in required {
+        match (name.as_str(), properties) {
+            (None, _) => errors.push(format!("{}/required: {} is not a string", path, name)),
+            (Some(name), Some(properties)) if !properties.contains_key(name) => {
+                errors.push(format!("{}/required: {:?} is not a declared property", path, name))
+            }
+            _ => {}
+        }
+    }
+}
+
+fn matches_type(value: &Value, declared: &str) -> bool {
+    match declared {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => value.is_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_metadata;
+    use serde_json::json;
+
+    #[test]
+    fn plugin_metadata_is_valid() {
+        assert_eq!(validate_metadata(&plugin_metadata()), Ok(()));
+    }
+
+    #[test]
+    fn malformed_schema_is_flagged() {
+        let mut metadata = plugin_metadata();
+        metadata.nodes[0].config_schema = Some(json!({
+            "type": "object",
+            "properties": {
+                "limit": {"type": "int", "default": 3},
+                "mode": {"type": "string", "enumm": ["a", "b"]},
+                "strict": {"type": "boolean", "default": "yes"}
+            },
+            "required": ["limit", "missing"]
+        }));
+        let errors = validate_metadata(&metadata).unwrap_err();
+        assert_eq!(errors.len(), 4, "{:#?}", errors);
+        assert!(errors.iter().any(|e| e.contains("word-count/configSchema/properties/limit/type")));
+        assert!(errors.iter().any(|e| e.contains("\"enumm\"")));
+        assert!(errors.iter().any(|e| e.contains("strict/default")));
+        assert!(errors.iter().any(|e| e.contains("\"missing\"")));
+    }
+}
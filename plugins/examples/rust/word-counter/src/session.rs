@@ -0,0 +1,157 @@
+// Settings a host negotiates once per instance with `init_with`, for every
+// later call. The handshake itself is JSON, as nothing is negotiated yet:
+//
+// - `envelopeVersion`: as for `init`
+// - `encoding`: the wire encoding of requests, and of responses whose
+//   request names none in `wire.encoding`; JSON until negotiated
+// - `memoCapacity`: exec results `memoize` keeps, 64 until set
+// - `metrics`: whether calls report `bytes_processed`, off until set
+// - `returnCodes`: whether exports pack an `abi::AbiError` into their
+//   return value, off until set
+//
+// Fields left out keep their current setting.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use serde::Deserialize;
+
+use crate::{envelope, memo};
+use crate::wire::{self, Encoding, WireCodec};
+
+#[derive(Deserialize, Default)]
+pub(crate) struct HostOptions {
+    #[serde(default, rename = "envelopeVersion")]
+    envelope_version: Option<u32>,
+    #[serde(default)]
+    encoding: Option<Encoding>,
+    #[serde(default, rename = "memoCapacity")]
+    memo_capacity: Option<usize>,
+    #[serde(default)]
+    metrics: Option<bool>,
+    #[serde(default, rename = "returnCodes")]
+    return_codes: Option<bool>,
+}
+
+// Index into `Encoding::ALL`
+#[cfg(not(test))]
+static ENCODING: AtomicU8 = AtomicU8::new(0);
+
+// Per test thread, as in `envelope`
+#[cfg(test)]
+thread_local! {
+    static ENCODING: AtomicU8 = const { AtomicU8::new(0) };
+}
+
+fn with_encoding<R>(f: impl FnOnce(&AtomicU8) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        f(&ENCODING)
+    }
+    #[cfg(test)]
+    {
+        ENCODING.with(f)
+    }
+}
+
+#[cfg(not(test))]
+static METRICS: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    static METRICS: AtomicBool = const { AtomicBool::new(false) };
+}
+
+fn with_metrics<R>(f: impl FnOnce(&AtomicBool) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        f(&METRICS)
+    }
+    #[cfg(test)]
+    {
+        METRICS.with(f)
+    }
+}
+
+// Whether the host asked for per-call metrics
+pub(crate) fn metrics() -> bool {
+    with_metrics(|metrics| metrics.load(Ordering::Relaxed))
+}
+
+#[cfg(not(test))]
+static RETURN_CODES: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    static RETURN_CODES: AtomicBool = const { AtomicBool::new(false) };
+}
+
+fn with_return_codes<R>(f: impl FnOnce(&AtomicBool) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        f(&RETURN_CODES)
+    }
+    #[cfg(test)]
+    {
+        RETURN_CODES.with(f)
+    }
+}
+
+// Whether the host reads `abi` return codes
+pub(crate) fn return_codes() -> bool {
+    with_return_codes(|return_codes| return_codes.load(Ordering::Relaxed))
+}
+
+pub(crate) fn encoding() -> Encoding {
+    Encoding::ALL[with_encoding(|encoding| encoding.load(Ordering::Relaxed)) as usize]
+}
+
+// The codec requests are decoded with
+pub(crate) fn codec() -> &'static dyn WireCodec {
+    wire::codec(encoding())
+}
+
+// Applies `options` and returns the envelope version now in use
+pub(crate) fn apply(options: &HostOptions) -> u32 {
+    if let Some(encoding) = options.encoding {
+        let index = Encoding::ALL.iter().position(|e| *e == encoding).unwrap() as u8;
+        with_encoding(|current| current.store(index, Ordering::Relaxed));
+    }
+    if let Some(capacity) = options.memo_capacity {
+        memo::set_capacity(capacity);
+    }
+    if let Some(metrics) = options.metrics {
+        with_metrics(|current| current.store(metrics, Ordering::Relaxed));
+    }
+    if let Some(return_codes) = options.return_codes {
+        with_return_codes(|current| current.store(return_codes, Ordering::Relaxed));
+    }
+    match options.envelope_version {
+        Some(version) => envelope::negotiate(version),
+        None => envelope::version(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn left_out_options_keep_their_setting() {
+        assert_eq!(encoding(), Encoding::Json);
+        let options: HostOptions = serde_json::from_str(r#"{"encoding": "cbor"}"#).unwrap();
+        assert_eq!(apply(&options), envelope::CURRENT);
+        assert_eq!(encoding(), Encoding::Cbor);
+        assert_eq!(apply(&HostOptions { envelope_version: Some(1), ..HostOptions::default() }), 1);
+        assert_eq!(encoding(), Encoding::Cbor);
+        apply(&HostOptions { metrics: Some(true), ..HostOptions::default() });
+        assert!(metrics());
+        apply(&HostOptions {
+            envelope_version: Some(envelope::CURRENT),
+            encoding: Some(Encoding::Json),
+            memo_capacity: None,
+            metrics: None,
+            return_codes: None,
+        });
+        assert!(metrics() && encoding() == Encoding::Json);
+    }
+}
@@ -0,0 +1,62 @@
+// Node-level globals: resources a node builds once per instance and then
+// shares across every call and host thread, such as a compiled regex or an
+// embedded dictionary. Declare one as a static next to the code using it
+// and read it with `get`:
+//
+//     static PATTERN: Singleton<Regex> = Singleton::new(|| Regex::new("...").unwrap());
+//     PATTERN.get().is_match(text)
+//
+// The first `get` runs the builder; calls racing it from other threads wait
+// for that one build rather than starting their own. Unlike the caches in
+// `state`, a singleton is never dropped, so keep it to data derived from
+// the code itself, not from a request.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+pub(crate) struct Singleton<T> {
+    cell: OnceLock<T>,
+    build: fn() -> T,
+    // Times `build` has run; only ever 0 or 1
+    builds: AtomicUsize,
+}
+
+impl<T> Singleton<T> {
+    pub(crate) const fn new(build: fn() -> T) -> Self {
+        Singleton { cell: OnceLock::new(), build, builds: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn get(&self) -> &T {
+        self.cell.get_or_init(|| {
+            self.builds.fetch_add(1, Ordering::Relaxed);
+            (self.build)()
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn builds(&self) -> usize {
+        self.builds.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn concurrent_first_calls_build_once() {
+        static SLOW: Singleton<Vec<u32>> = Singleton::new(|| {
+            // Long enough for every thread to arrive mid-build
+            std::thread::sleep(Duration::from_millis(50));
+            (0..100).collect()
+        });
+        let addresses: Vec<usize> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8).map(|_| scope.spawn(|| SLOW.get() as *const _ as usize)).collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        });
+        assert_eq!(SLOW.builds(), 1);
+        assert!(addresses.windows(2).all(|pair| pair[0] == pair[1]));
+        assert_eq!(SLOW.get().len(), 100);
+    }
+}
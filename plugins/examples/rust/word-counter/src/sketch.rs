@@ -0,0 +1,235 @@
+// Approximate frequencies in bounded memory (`approximate`): a count-min
+// sketch of `depth` rows of `width` counters. Each token adds one to a
+// counter per row and its estimate is the smallest of those counters, so
+// an estimate never undercounts, and with N tokens it overcounts by more
+// than (e / width) · N only with probability e^-depth.
+//
+// A sketch can't list the words it has seen, so alongside it the `top_k`
+// words with the highest estimates so far are kept as the reported
+// frequency table. Memory is the counters plus those words, however long
+// the text. The config caps the counters at `MAX_WIDTH` · `MAX_DEPTH`.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bloom::fnv1a;
+
+// ε = e / 2^20, past anything a text needs; 64 MiB of counters at full depth
+pub(crate) const MAX_WIDTH: usize = 1 << 20;
+// Confidence 1 - e^-8 > 99.9%
+pub(crate) const MAX_DEPTH: usize = 8;
+
+// The sketch's error guarantee for one exec
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SketchGuarantee {
+    pub width: usize,
+    pub depth: usize,
+    // Estimates exceed the true count by at most `epsilon` · total_words...
+    pub epsilon: f64,
+    // ...with this probability
+    pub confidence: f64,
+    // `epsilon` · total_words
+    pub max_overestimate: f64,
+}
+
+pub(crate) struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    // An error if the counters wouldn't fit in memory at all
+    pub(crate) fn new(width: usize, depth: usize) -> Result<Self, String> {
+        let (width, depth) = (width.max(1), depth.max(1));
+        let cells = width.checked_mul(depth)
+            .ok_or_else(|| format!("A {} by {} sketch has too many counters", depth, width))?;
+        Ok(CountMinSketch { width, depth, counters: vec![0; cells], total: 0 })
+    }
+
+    // Counter index in each row, by double hashing as in `bloom`
+    fn cells(&self, word: &str) -> impl Iterator<Item = usize> + '_ {
+        let h1 = fnv1a(word.as_bytes(), 0);
+        let h2 = fnv1a(word.as_bytes(), 0x9e37_79b9_7f4a_7c15) | 1;
+        (0..self.depth).map(move |row| {
+            let column = h1.wrapping_add((row as u64).wrapping_mul(h2)) % self.width as u64;
+            row * self.width + column as usize
+        })
+    }
+
+    // Counts `count` occurrences of `word`, returning its new estimate
+    pub(crate) fn add(&mut self, word: &str, count: u64) -> u64 {
+        let cells: Vec<usize> = self.cells(word).collect();
+        self.total = self.total.saturating_add(count);
+        cells.into_iter()
+            .map(|cell| {
+                self.counters[cell] = self.counters[cell].saturating_add(count);
+                self.counters[cell]
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Counting goes through `add`, which already returns the estimate
+    #[cfg(test)]
+    fn estimate(&self, word: &str) -> u64 {
+        self.cells(word).map(|cell| self.counters[cell]).min().unwrap_or(0)
+    }
+
+    pub(crate) fn guarantee(&self) -> SketchGuarantee {
+        let epsilon = std::f64::consts::E / self.width as f64;
+        SketchGuarantee {
+            width: self.width,
+            depth: self.depth,
+            epsilon,
+            confidence: 1.0 - (-(self.depth as f64)).exp(),
+            max_overestimate: epsilon * self.total as f64,
+        }
+    }
+}
+
+// A sketch plus the `top_k` words with the highest estimates: by word, and
+// ranked lowest estimate first (the greater word first on ties, so which
+// one goes doesn't depend on arrival order)
+pub(crate) struct HeavyHitters {
+    sketch: CountMinSketch,
+    top_k: usize,
+    top: HashMap<String, u64>,
+    ranked: BTreeSet<(u64, Reverse<String>)>,
+}
+
+impl HeavyHitters {
+    pub(crate) fn new(width: usize, depth: usize, top_k: usize) -> Result<Self, String> {
+        Ok(HeavyHitters {
+            sketch: CountMinSketch::new(width, depth)?,
+            top_k: top_k.max(1),
+            top: HashMap::new(),
+            ranked: BTreeSet::new(),
+        })
+    }
+
+    // Counts `count` occurrences of `word`, as many single ones would
+    pub(crate) fn add(&mut self, word: &str, count: u64) {
+        let estimate = self.sketch.add(word, count);
+        if let Some(count) = self.top.get_mut(word) {
+            let mut entry = (*count, Reverse(word.to_string()));
+            self.ranked.remove(&entry);
+            *count = estimate;
+            entry.0 = estimate;
+            self.ranked.insert(entry);
+            return;
+        }
+        if self.top.len() == self.top_k {
+            // Displaces the lowest estimate if this one beats it
+            match self.ranked.first() {
+                Some((lowest, _)) if estimate > *lowest => {
+                    let (_, Reverse(lowest)) = self.ranked.pop_first().unwrap();
+                    self.top.remove(&lowest);
+                }
+                _ => return,
+            }
+        }
+        self.top.insert(word.to_string(), estimate);
+        self.ranked.insert((estimate, Reverse(word.to_string())));
+    }
+
+    // The kept words with their estimates, and the sketch's guarantee
+    pub(crate) fn finish(self) -> (HashMap<String, usize>, SketchGuarantee) {
+        let guarantee = self.sketch.guarantee();
+        let frequencies = self.top.into_iter()
+            .map(|(word, count)| (word, usize::try_from(count).unwrap_or(usize::MAX)))
+            .collect();
+        (frequencies, guarantee)
+    }
+
+    #[cfg(test)]
+    fn estimate(&self, word: &str) -> u64 {
+        self.sketch.estimate(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Word n appears 2000 / n times
+    fn zipfian() -> Vec<(String, u64)> {
+        (1..=500).map(|rank| (format!("w{}", rank), 2000 / rank)).collect()
+    }
+
+    #[test]
+    fn estimates_stay_within_the_error_bound() {
+        let mut sketch = CountMinSketch::new(272, 5).unwrap();
+        for (word, count) in zipfian() {
+            for _ in 0..count {
+                sketch.add(&word, 1);
+            }
+        }
+        let guarantee = sketch.guarantee();
+        assert!((guarantee.epsilon - 0.01).abs() < 0.0001);
+        assert!(guarantee.confidence > 0.99);
+        let mut over_bound = 0;
+        for (word, count) in zipfian() {
+            let estimate = sketch.estimate(&word);
+            assert!(estimate >= count, "{} undercounted", word);
+            if (estimate - count) as f64 > guarantee.max_overestimate {
+                over_bound += 1;
+            }
+        }
+        // Allowed for 1 - confidence of words; a few at most
+        assert!(over_bound <= 5, "{} words over the bound", over_bound);
+    }
+
+    #[test]
+    fn heavy_hitters_keep_the_most_frequent_words() {
+        let mut hitters = HeavyHitters::new(272, 5, 10).unwrap();
+        // Interleaved, so the top words must push out early arrivals
+        let stream = zipfian();
+        let longest = stream.iter().map(|(_, count)| *count).max().unwrap();
+        for round in 0..longest {
+            for (word, count) in stream.iter().rev() {
+                if round < *count {
+                    hitters.add(word, 1);
+                }
+            }
+        }
+        assert!(hitters.estimate("w1") >= 2000);
+        let (frequencies, _) = hitters.finish();
+        let mut kept: Vec<&str> = frequencies.keys().map(String::as_str).collect();
+        kept.sort_by_key(|word| word[1..].parse::<usize>().unwrap());
+        assert_eq!(kept, ["w1", "w2", "w3", "w4", "w5", "w6", "w7", "w8", "w9", "w10"]);
+    }
+
+    #[test]
+    fn ties_displace_the_greater_word_whatever_the_order() {
+        for words in [["b", "a", "c"], ["a", "b", "c"]] {
+            let mut hitters = HeavyHitters::new(64, 4, 2).unwrap();
+            for word in words {
+                hitters.add(word, 1);
+            }
+            hitters.add("c", 1);
+            let (frequencies, _) = hitters.finish();
+            assert_eq!(frequencies, HashMap::from([("a".to_string(), 1), ("c".to_string(), 2)]));
+        }
+    }
+
+    #[test]
+    fn a_run_counts_like_its_single_occurrences() {
+        let mut runs = HeavyHitters::new(64, 4, 2).unwrap();
+        let mut singles = HeavyHitters::new(64, 4, 2).unwrap();
+        for (word, count) in [("a", 3), ("b", 1), ("c", 5), ("a", 2)] {
+            runs.add(word, count);
+            (0..count).for_each(|_| singles.add(word, 1));
+        }
+        assert_eq!(runs.finish().0, singles.finish().0);
+    }
+
+    #[test]
+    fn oversized_sketches_are_refused() {
+        assert!(CountMinSketch::new(usize::MAX, 2).is_err());
+        assert_eq!(CountMinSketch::new(0, 0).unwrap().guarantee().width, 1);
+    }
+}
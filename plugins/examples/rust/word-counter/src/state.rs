@@ -0,0 +1,270 @@
+// Guest-resident state that survives between calls on the same instance.
+// Everything here is a cache: dropping it must never change results, only
+// cost, so the host can ask us to release it under memory pressure. (`delta`
+// output compares against the cached frequencies, and reports a reset when
+// they're gone.)
+//
+// Hosts may run the module with shared memory and call in from several
+// threads at once, so the state sits behind a mutex rather than in a
+// thread-local.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{DEFAULT_STOP_SET, DEFAULT_STOP_WORDS};
+
+#[derive(Default)]
+pub(crate) struct InstanceState {
+    // Last configured stop-word list, whether it matches case-sensitively,
+    // and the lookup set built from it (shared, so a call can use it
+    // without holding the state)
+    stop_words: Option<(Vec<String>, bool, Arc<HashSet<String>>)>,
+    // Frequencies from the most recent exec, for paged retrieval and as the
+    // baseline of `delta` output
+    frequencies: Option<CachedFrequencies>,
+    // Stop words read from each host resource, by resource name
+    resource_words: Option<HashMap<String, Arc<[String]>>>,
+}
+
+struct CachedFrequencies {
+    entries: Vec<(String, usize)>,
+    // Entries are sorted lazily on the first page request
+    sorted: bool,
+}
+
+impl InstanceState {
+    const fn new() -> Self {
+        InstanceState { stop_words: None, frequencies: None, resource_words: None }
+    }
+
+    fn stop_words(&mut self, words: &[String], case_sensitive: bool) -> Arc<HashSet<String>> {
+        let stale = !matches!(
+            &self.stop_words,
+            Some((cached, cached_case, _)) if cached.as_slice() == words && *cached_case == case_sensitive
+        );
+        if stale {
+            let set = words
+                .iter()
+                .map(|w| if case_sensitive { w.clone() } else { w.to_lowercase() })
+                .collect();
+            self.stop_words = Some((words.to_vec(), case_sensitive, Arc::new(set)));
+        }
+        let (_, _, set) = self.stop_words.as_ref().unwrap();
+        Arc::clone(set)
+    }
+
+    fn cache_frequencies(&mut self, frequencies: &HashMap<String, usize>) -> Option<Vec<(String, usize)>> {
+        let entries = frequencies.iter().map(|(w, c)| (w.clone(), *c)).collect();
+        let previous = self.frequencies.replace(CachedFrequencies { entries, sorted: false });
+        previous.map(|cached| cached.entries)
+    }
+
+    fn frequencies_page(&mut self, offset: usize, limit: usize) -> Option<(Vec<(String, usize)>, usize)> {
+        let cached = self.frequencies.as_mut()?;
+        if !cached.sorted {
+            cached.entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            cached.sorted = true;
+        }
+        let total = cached.entries.len();
+        let page = cached.entries.iter().skip(offset).take(limit).cloned().collect();
+        Some((page, total))
+    }
+
+    fn shrink(&mut self) {
+        if let Some((words, _, set)) = self.stop_words.as_mut() {
+            words.shrink_to_fit();
+            // Not while a call is still using it
+            if let Some(set) = Arc::get_mut(set) {
+                set.shrink_to_fit();
+            }
+        }
+        if let Some(cached) = self.frequencies.as_mut() {
+            cached.entries.shrink_to_fit();
+        }
+        if let Some(resource_words) = self.resource_words.as_mut() {
+            resource_words.shrink_to_fit();
+        }
+    }
+}
+
+fn with_state<R>(f: impl FnOnce(&mut InstanceState) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        static STATE: Mutex<InstanceState> = Mutex::new(InstanceState::new());
+        f(&mut STATE.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    // Each test thread acts as its own instance so tests running in
+    // parallel can't see each other's caches
+    #[cfg(test)]
+    {
+        thread_local! {
+            static STATE: Mutex<InstanceState> = const { Mutex::new(InstanceState::new()) };
+        }
+        STATE.with(|state| f(&mut state.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+// Runs `f` with a lookup set for `words`, rebuilding it only when the
+// configured list changes between calls. Case-insensitive sets hold the
+// lowercased words. The state is locked only to look the set up, not while
+// `f` runs, so other calls (and `f` itself) can use it meanwhile.
+pub(crate) fn with_stop_words<R>(
+    words: &[String],
+    case_sensitive: bool,
+    f: impl FnOnce(&HashSet<String>) -> R,
+) -> R {
+    // The default list (already lowercase) is shared by every instance
+    // without locking the state
+    let default = words.len() == DEFAULT_STOP_WORDS.len() && words.iter().zip(DEFAULT_STOP_WORDS).all(|(a, b)| a == b);
+    if default {
+        return f(DEFAULT_STOP_SET.get());
+    }
+    let set = with_state(|state| state.stop_words(words, case_sensitive));
+    f(&set)
+}
+
+// The stop words read from resource `name`, calling `read` for them only
+// the first time. A failed read isn't cached, so a later call asks again.
+// The state isn't locked while `read` runs.
+pub(crate) fn resource_words(
+    name: &str,
+    read: impl FnOnce() -> Result<Vec<String>, String>,
+) -> Result<Arc<[String]>, String> {
+    let cached = with_state(|state| state.resource_words.as_ref().and_then(|words| words.get(name).cloned()));
+    if let Some(words) = cached {
+        return Ok(words);
+    }
+    let words: Arc<[String]> = read()?.into();
+    with_state(|state| {
+        state.resource_words.get_or_insert_with(HashMap::new).insert(name.to_string(), Arc::clone(&words))
+    });
+    Ok(words)
+}
+
+// Replaces the cached frequencies with those of the latest exec, returning
+// the ones it replaced.
+pub(crate) fn cache_frequencies(frequencies: &HashMap<String, usize>) -> Option<Vec<(String, usize)>> {
+    with_state(|state| state.cache_frequencies(frequencies))
+}
+
+// Returns up to `limit` cached entries starting at `offset`, ordered by
+// descending count then word, plus the total entry count. `None` if no exec
+// has run on this instance (or its result was released).
+pub(crate) fn frequencies_page(offset: usize, limit: usize) -> Option<(Vec<(String, usize)>, usize)> {
+    with_state(|state| state.frequencies_page(offset, limit))
+}
+
+// Returns spare capacity held by cached collections to the allocator.
+pub(crate) fn shrink() {
+    with_state(InstanceState::shrink);
+}
+
+// Drops every cache.
+pub(crate) fn clear() {
+    with_state(|state| *state = InstanceState::default());
+}
+
+#[cfg(test)]
+pub(crate) fn is_empty() -> bool {
+    with_state(|state| state.stop_words.is_none() && state.frequencies.is_none() && state.resource_words.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn stop_word_set_is_rebuilt_when_list_changes() {
+        let first = vec!["the".to_string()];
+        let second = vec!["a".to_string()];
+        assert!(with_stop_words(&first, false, |set| set.contains("the")));
+        assert!(!with_stop_words(&second, false, |set| set.contains("the")));
+        assert!(with_stop_words(&second, false, |set| set.contains("a")));
+    }
+
+    #[test]
+    fn stop_word_set_is_rebuilt_when_case_mode_changes() {
+        let words = vec!["The".to_string()];
+        assert!(with_stop_words(&words, false, |set| set.contains("the")));
+        assert!(with_stop_words(&words, true, |set| set.contains("The") && !set.contains("the")));
+    }
+
+    #[test]
+    fn frequencies_page_is_sorted_by_count_then_word() {
+        let frequencies = HashMap::from([("b".to_string(), 1), ("a".to_string(), 1), ("c".to_string(), 3)]);
+        cache_frequencies(&frequencies);
+        let (page, total) = frequencies_page(0, 10).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(page, [("c".to_string(), 3), ("a".to_string(), 1), ("b".to_string(), 1)]);
+        assert_eq!(frequencies_page(3, 10).unwrap().0, []);
+    }
+
+    #[test]
+    fn the_state_is_free_while_the_stop_words_are_used() {
+        let words = vec!["the".to_string()];
+        let frequencies = HashMap::from([("cat".to_string(), 1)]);
+        // Would deadlock if the lookup held the state
+        let cached = with_stop_words(&words, false, |set| {
+            cache_frequencies(&frequencies);
+            clear();
+            set.contains("the")
+        });
+        assert!(cached);
+        assert!(is_empty());
+    }
+
+    #[test]
+    fn resource_words_are_read_once_until_cleared() {
+        let read = |words: &[&str]| {
+            let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+            move || Ok(words)
+        };
+        assert_eq!(resource_words("stop-words.txt", read(&["fish"])).unwrap()[..], ["fish"]);
+        assert_eq!(resource_words("stop-words.txt", read(&["red"])).unwrap()[..], ["fish"]);
+        assert_eq!(resource_words("other.txt", read(&["red"])).unwrap()[..], ["red"]);
+        assert!(resource_words("missing.txt", || Err("gone".to_string())).is_err());
+        assert_eq!(resource_words("missing.txt", read(&["blue"])).unwrap()[..], ["blue"]);
+        clear();
+        assert_eq!(resource_words("stop-words.txt", read(&["red"])).unwrap()[..], ["red"]);
+    }
+
+    #[test]
+    fn clear_drops_cached_set() {
+        with_stop_words(&["the".to_string()], false, |_| ());
+        assert!(!is_empty());
+        clear();
+        assert!(is_empty());
+    }
+
+    #[test]
+    fn shared_state_stays_consistent_across_threads() {
+        let state = Arc::new(Mutex::new(InstanceState::new()));
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let state = Arc::clone(&state);
+                std::thread::spawn(move || {
+                    // Each thread's exec has `t + 1` distinct words
+                    let frequencies: HashMap<String, usize> =
+                        (0..=t).map(|i| (format!("w{}", i), i + 1)).collect();
+                    let words = vec![format!("stop{}", t)];
+                    for _ in 0..200 {
+                        let mut guard = state.lock().unwrap();
+                        assert!(guard.stop_words(&words, false).contains(&words[0]));
+                        guard.cache_frequencies(&frequencies);
+                        drop(guard);
+
+                        let (page, total) = state.lock().unwrap().frequencies_page(0, 100).unwrap();
+                        // Whichever thread cached last, its entries are whole and sorted
+                        assert_eq!(page.len(), total);
+                        assert!(page.windows(2).all(|w| w[0].1 >= w[1].1));
+                        assert!(page.iter().all(|(word, count)| *word == format!("w{}", count - 1)));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
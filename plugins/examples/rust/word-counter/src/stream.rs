@@ -0,0 +1,110 @@
+// Chunked input. Hosts with text too large for one `call` buffer `feed` it
+// in pieces and then `feed_finish`. Chunk boundaries are arbitrary bytes, so
+// a multibyte character may arrive split; its leading bytes are held back
+// until the rest shows up.
+//
+// Unlike `state`, this is not a cache: it holds input the host has already
+// handed over, so memory pressure must not drop it.
+
+use std::sync::{Mutex, PoisonError};
+
+#[derive(Default)]
+pub(crate) struct Utf8Stream {
+    text: String,
+    // Trailing bytes of an incomplete character (at most 3)
+    pending: Vec<u8>,
+}
+
+impl Utf8Stream {
+    const fn new() -> Self {
+        Utf8Stream { text: String::new(), pending: Vec::new() }
+    }
+
+    // Appends `chunk`, decoding every complete character. Fails on bytes
+    // that can never become valid UTF-8; the stream is left as it was.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<(), String> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+        match std::str::from_utf8(&bytes) {
+            Ok(text) => self.text.push_str(text),
+            Err(e) if e.error_len().is_none() => {
+                // Only the tail is incomplete; keep it for the next chunk
+                let (complete, incomplete) = bytes.split_at(e.valid_up_to());
+                self.text.push_str(std::str::from_utf8(complete).unwrap());
+                self.pending = incomplete.to_vec();
+            }
+            Err(e) => {
+                bytes.truncate(bytes.len() - chunk.len());
+                self.pending = bytes;
+                return Err(format!("Invalid UTF-8 in chunk: {}", e));
+            }
+        }
+        Ok(())
+    }
+
+    // The decoded text, or an error if input ended mid-character
+    pub(crate) fn finish(self) -> Result<String, String> {
+        if self.pending.is_empty() {
+            Ok(self.text)
+        } else {
+            Err(format!("Input ended with {} bytes of an incomplete character", self.pending.len()))
+        }
+    }
+}
+
+// One stream per instance, shared by every thread the host calls from
+fn with_stream<R>(f: impl FnOnce(&mut Utf8Stream) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        static STREAM: Mutex<Utf8Stream> = Mutex::new(Utf8Stream::new());
+        f(&mut STREAM.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    // Per test thread, as in `state`
+    #[cfg(test)]
+    {
+        thread_local! {
+            static STREAM: Mutex<Utf8Stream> = const { Mutex::new(Utf8Stream::new()) };
+        }
+        STREAM.with(|stream| f(&mut stream.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+pub(crate) fn push(chunk: &[u8]) -> Result<(), String> {
+    with_stream(|stream| stream.push(chunk))
+}
+
+// Takes the accumulated text, leaving an empty stream for the next input
+pub(crate) fn take() -> Result<String, String> {
+    with_stream(std::mem::take).finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_split_across_chunks_decodes_once() {
+        let bytes = "🦀".as_bytes();
+        let mut stream = Utf8Stream::default();
+        stream.push(&bytes[..2]).unwrap();
+        stream.push(&bytes[2..]).unwrap();
+        let text = stream.finish().unwrap();
+        assert_eq!(text, "🦀");
+        assert_eq!(text.chars().count(), 1);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let mut stream = Utf8Stream::default();
+        stream.push(&"é".as_bytes()[..1]).unwrap();
+        assert!(stream.finish().is_err());
+    }
+
+    #[test]
+    fn invalid_bytes_are_rejected_without_losing_earlier_text() {
+        let mut stream = Utf8Stream::default();
+        stream.push(b"ok ").unwrap();
+        assert!(stream.push(&[0xff, b'x']).is_err());
+        assert_eq!(stream.finish().unwrap(), "ok ");
+    }
+}
@@ -0,0 +1,109 @@
+// `Permissions.timeout`: how long the host lets one call run. On the wire
+// it's either a number of milliseconds (`3000`) or a string with a unit
+// (`"3s"`, `"500ms"`, `"2m"`), as manifests write it; it always serializes
+// as milliseconds. Values under `MIN` can't fit any real work and values
+// over `MAX` are almost certainly a unit mistake, so both are rejected.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Timeout(Duration);
+
+impl Timeout {
+    pub(crate) const MIN: Duration = Duration::from_millis(10);
+    pub(crate) const MAX: Duration = Duration::from_secs(60 * 60);
+
+    pub(crate) fn new(duration: Duration) -> Result<Self, String> {
+        if duration < Self::MIN || duration > Self::MAX {
+            return Err(format!(
+                "timeout must be between {}ms and {}ms, got {}ms",
+                Self::MIN.as_millis(), Self::MAX.as_millis(), duration.as_millis()
+            ));
+        }
+        Ok(Timeout(duration))
+    }
+
+    pub(crate) fn from_millis(ms: u64) -> Result<Self, String> {
+        Self::new(Duration::from_millis(ms))
+    }
+
+    // "3s", "500ms", "2m" or a bare number of milliseconds
+    pub(crate) fn parse(timeout: &str) -> Result<Self, String> {
+        let split = timeout.find(|c: char| !c.is_ascii_digit()).unwrap_or(timeout.len());
+        let (value, unit) = timeout.split_at(split);
+        let value: u64 = value.parse().map_err(|_| format!("Invalid timeout {:?}", timeout))?;
+        let duration = match unit {
+            "" | "ms" => Duration::from_millis(value),
+            "s" => Duration::from_secs(value),
+            "m" => Duration::from_secs(value.saturating_mul(60)),
+            _ => return Err(format!("Invalid timeout {:?}: the unit must be ms, s or m", timeout)),
+        };
+        Self::new(duration)
+    }
+}
+
+impl Serialize for Timeout {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Within `MAX`, so this can't truncate
+        serializer.serialize_u64(self.0.as_millis() as u64)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timeout {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TimeoutVisitor)
+    }
+}
+
+struct TimeoutVisitor;
+
+impl Visitor<'_> for TimeoutVisitor {
+    type Value = Timeout;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("milliseconds or a duration string such as \"3s\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, ms: u64) -> Result<Timeout, E> {
+        Timeout::from_millis(ms).map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, ms: i64) -> Result<Timeout, E> {
+        let ms = u64::try_from(ms).map_err(|_| E::custom(format!("timeout must not be negative, got {}", ms)))?;
+        self.visit_u64(ms)
+    }
+
+    fn visit_str<E: de::Error>(self, timeout: &str) -> Result<Timeout, E> {
+        Timeout::parse(timeout).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn milliseconds_and_duration_strings_parse() {
+        let three_seconds = Timeout(Duration::from_secs(3));
+        assert_eq!(serde_json::from_str::<Timeout>("3000").unwrap(), three_seconds);
+        assert_eq!(serde_json::from_str::<Timeout>("\"3s\"").unwrap(), three_seconds);
+        assert_eq!(Timeout::parse("3000ms"), Ok(three_seconds));
+        assert_eq!(Timeout::parse("2m"), Ok(Timeout(Duration::from_secs(120))));
+        assert_eq!(serde_json::to_string(&three_seconds).unwrap(), "3000");
+    }
+
+    #[test]
+    fn zero_and_out_of_range_timeouts_are_rejected() {
+        let error = serde_json::from_str::<Timeout>("0").unwrap_err().to_string();
+        assert!(error.starts_with("timeout must be between 10ms and 3600000ms, got 0ms"), "{}", error);
+        assert!(serde_json::from_str::<Timeout>("\"0s\"").is_err());
+        assert!(serde_json::from_str::<Timeout>("-5").is_err());
+        assert!(Timeout::parse("2h").is_err());
+        assert!(Timeout::parse("61m").is_err());
+        assert!(Timeout::parse("s").is_err());
+    }
+}
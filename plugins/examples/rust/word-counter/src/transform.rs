@@ -0,0 +1,125 @@
+// Declarative reshaping of a node's final output (`output_transform`), so
+// consumers can pick, rename and nest fields without a dedicated option for
+// each. Fields are named by dotted paths into nested objects
+// (`lexical_diversity.ttr`); there's no indexing into arrays. The steps run
+// in a fixed order: select, then rename, then wrap.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutputTransform {
+    // Keep only these fields; listed fields that are absent are skipped
+    #[serde(default)]
+    pub select: Option<Vec<String>>,
+    // Move each field (if present) to a new path, replacing what's there
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+    // Nest the whole output under this path
+    #[serde(default)]
+    pub wrap: Option<String>,
+}
+
+impl OutputTransform {
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let paths = self.select.iter().flatten()
+            .chain(self.rename.iter().flat_map(|(from, to)| [from, to]))
+            .chain(&self.wrap);
+        for path in paths {
+            if path.split('.').any(str::is_empty) {
+                return Err(format!("Invalid output_transform path {:?}", path));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, output: Value) -> Value {
+        let mut output = match &self.select {
+            Some(paths) => {
+                let mut source = output;
+                let mut selected = Value::Object(Map::new());
+                for path in paths {
+                    if let Some(value) = take(&mut source, path) {
+                        insert(&mut selected, path, value);
+                    }
+                }
+                selected
+            }
+            None => output,
+        };
+        // Taken first so a rename's target can't clobber another's source
+        let moved: Vec<(&String, Value)> = self.rename.iter()
+            .filter_map(|(from, to)| take(&mut output, from).map(|value| (to, value)))
+            .collect();
+        for (to, value) in moved {
+            insert(&mut output, to, value);
+        }
+        match &self.wrap {
+            Some(path) => {
+                let mut wrapped = Value::Object(Map::new());
+                insert(&mut wrapped, path, output);
+                wrapped
+            }
+            None => output,
+        }
+    }
+}
+
+// Removes and returns the value at `path`
+fn take(value: &mut Value, path: &str) -> Option<Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (parent.split('.').try_fold(value, |v, k| v.get_mut(k))?, key),
+        None => (value, path),
+    };
+    parent.as_object_mut()?.remove(key)
+}
+
+// Sets the value at `path`, creating (or replacing non-object) parents
+fn insert(value: &mut Value, path: &str, new: Value) {
+    let mut target = value;
+    for key in path.split('.') {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        target = target.as_object_mut().unwrap().entry(key).or_insert(Value::Null);
+    }
+    *target = new;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transform(spec: Value, output: Value) -> Value {
+        serde_json::from_value::<OutputTransform>(spec).unwrap().apply(output)
+    }
+
+    #[test]
+    fn select_keeps_listed_paths() {
+        let output = json!({"a": 1, "b": 2, "n": {"x": 3, "y": 4}});
+        assert_eq!(transform(json!({"select": ["a", "n.y", "missing"]}), output), json!({"a": 1, "n": {"y": 4}}));
+    }
+
+    #[test]
+    fn rename_moves_fields_between_paths() {
+        let output = json!({"a": 1, "b": 2, "n": {"x": 3}});
+        let spec = json!({"rename": {"a": "b", "b": "a", "n.x": "x", "missing": "c"}});
+        assert_eq!(transform(spec, output), json!({"a": 2, "b": 1, "n": {}, "x": 3}));
+    }
+
+    #[test]
+    fn wrap_nests_the_result() {
+        let spec = json!({"select": ["a"], "rename": {"a": "count"}, "wrap": "result.stats"});
+        assert_eq!(transform(spec, json!({"a": 1, "b": 2})), json!({"result": {"stats": {"count": 1}}}));
+    }
+
+    #[test]
+    fn empty_path_segments_are_rejected() {
+        let spec: OutputTransform = serde_json::from_value(json!({"rename": {"a": "b."}})).unwrap();
+        assert_eq!(spec.check(), Err("Invalid output_transform path \"b.\"".to_string()));
+        assert_eq!(OutputTransform::default().check(), Ok(()));
+    }
+}
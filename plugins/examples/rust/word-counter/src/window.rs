@@ -0,0 +1,290 @@
+// Windowed counting over a stream of records. Each `record` call adds one
+// record to the instance's window state; when a record shows a window can
+// get no more records, that window is counted and its result emitted to the
+// host. `flush` ends the stream, emitting the partial windows still open.
+//
+// Windows are `size` wide and start every `slide` (every `size` for
+// tumbling windows), measured in records or in milliseconds of the
+// records' `timestamp_ms`. A time window closes when a record at or past
+// its end arrives, so timestamps must not go backwards, and a timestamp
+// too late for its windows' ends to fit in a u64 is refused. Windows
+// holding no records are skipped rather than emitted empty.
+//
+// Like `stream`, this holds input the host has handed over, so memory
+// pressure must not drop it.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowKind {
+    Tumbling,
+    Sliding,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowUnit {
+    #[default]
+    Records,
+    Ms,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowConfig {
+    #[serde(rename = "type")]
+    pub kind: WindowKind,
+    pub size: u64,
+    // Distance between sliding window starts
+    #[serde(default)]
+    pub slide: Option<u64>,
+    #[serde(default)]
+    pub by: WindowUnit,
+}
+
+impl WindowConfig {
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.size == 0 {
+            return Err("window size must be at least 1".to_string());
+        }
+        match (self.kind, self.slide) {
+            (WindowKind::Tumbling, Some(slide)) if slide != self.size => {
+                Err("window slide only applies to sliding windows".to_string())
+            }
+            (WindowKind::Sliding, None) => Err("sliding windows need a slide".to_string()),
+            (WindowKind::Sliding, Some(slide)) if slide == 0 || slide > self.size => {
+                Err("window slide must be between 1 and the window size".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn slide(&self) -> u64 {
+        self.slide.unwrap_or(self.size)
+    }
+
+    // The first window start (a multiple of slide) whose window holds
+    // `position`. No more than `position`, so it can't overflow.
+    fn first_start_covering(&self, position: u64) -> u64 {
+        let earliest = position.saturating_add(1).saturating_sub(self.size);
+        earliest.div_ceil(self.slide()) * self.slide()
+    }
+
+    // Whether every window holding `position` ends within a u64; the last
+    // of them starts at or before it
+    fn fits(&self, position: u64) -> bool {
+        (position / self.slide() * self.slide()).checked_add(self.size).is_some()
+    }
+}
+
+// A window ready to be counted
+#[derive(Debug, PartialEq)]
+pub(crate) struct ClosedWindow {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) partial: bool,
+    pub(crate) texts: Vec<String>,
+}
+
+pub(crate) struct Windows {
+    config: WindowConfig,
+    // Position (index or timestamp) and text of each record some open
+    // window may still need, oldest first
+    records: VecDeque<(u64, String)>,
+    next_start: u64,
+    next_index: u64,
+}
+
+impl Windows {
+    pub(crate) fn new(config: WindowConfig) -> Self {
+        Windows { config, records: VecDeque::new(), next_start: 0, next_index: 0 }
+    }
+
+    // Adds a record, returning the windows it closed in order
+    pub(crate) fn push(&mut self, text: String, timestamp_ms: Option<u64>) -> Result<Vec<ClosedWindow>, String> {
+        match self.config.by {
+            WindowUnit::Records => {
+                let index = self.next_index;
+                self.next_index = self.next_index.saturating_add(1);
+                self.records.push_back((index, text));
+                Ok(self.close_before(index.saturating_add(1)))
+            }
+            WindowUnit::Ms => {
+                let timestamp = timestamp_ms.ok_or("Time windows need a timestamp_ms on every record")?;
+                if !self.config.fits(timestamp) {
+                    return Err(format!("Record at {}ms is too late for a {}ms window to end", timestamp, self.config.size));
+                }
+                if let Some((last, _)) = self.records.back().filter(|(last, _)| *last > timestamp) {
+                    return Err(format!("Record at {}ms is older than the previous one at {}ms", timestamp, last));
+                }
+                let closed = self.close_before(timestamp);
+                if self.records.is_empty() {
+                    self.next_start = self.next_start.max(self.config.first_start_covering(timestamp));
+                }
+                self.records.push_back((timestamp, text));
+                Ok(closed)
+            }
+        }
+    }
+
+    // Ends the stream: every open window that holds a record, as partial
+    pub(crate) fn finish(mut self) -> Vec<ClosedWindow> {
+        let Some(&(last, _)) = self.records.back() else {
+            return Vec::new();
+        };
+        let mut windows = Vec::new();
+        while self.next_start <= last {
+            windows.extend(self.take_window(true));
+        }
+        windows
+    }
+
+    // Closes every window ending at or before `watermark`, the position no
+    // later record can come before
+    fn close_before(&mut self, watermark: u64) -> Vec<ClosedWindow> {
+        let mut windows = Vec::new();
+        // Once no records are left, the next one decides where windows resume
+        while !self.records.is_empty() && self.next_start.checked_add(self.config.size).is_some_and(|end| end <= watermark) {
+            windows.extend(self.take_window(false));
+        }
+        windows
+    }
+
+    // The window at `next_start` (if it holds any records), then moves on
+    // to the next window that could. `push` keeps out records whose windows
+    // would end past u64::MAX, so neither sum saturates in practice.
+    fn take_window(&mut self, partial: bool) -> Option<ClosedWindow> {
+        let start = self.next_start;
+        let end = start.saturating_add(self.config.size);
+        let texts: Vec<String> = self.records.iter()
+            .take_while(|(position, _)| *position < end)
+            .map(|(_, text)| text.clone())
+            .collect();
+        self.next_start = self.next_start.saturating_add(self.config.slide());
+        while self.records.front().is_some_and(|(position, _)| *position < self.next_start) {
+            self.records.pop_front();
+        }
+        if let Some(&(next, _)) = self.records.front() {
+            self.next_start = self.next_start.max(self.config.first_start_covering(next));
+        }
+        (!texts.is_empty()).then_some(ClosedWindow { start, end, partial, texts })
+    }
+}
+
+// One window state per instance, shared by every thread the host calls from
+fn with_windows<R>(f: impl FnOnce(&mut Option<Windows>) -> R) -> R {
+    #[cfg(not(test))]
+    {
+        static WINDOWS: Mutex<Option<Windows>> = Mutex::new(None);
+        f(&mut WINDOWS.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    // Per test thread, as in `state`
+    #[cfg(test)]
+    {
+        thread_local! {
+            static WINDOWS: Mutex<Option<Windows>> = const { Mutex::new(None) };
+        }
+        WINDOWS.with(|windows| f(&mut windows.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+// Adds a record to the current stream, starting one if there is none
+pub(crate) fn push(config: WindowConfig, text: String, timestamp_ms: Option<u64>) -> Result<Vec<ClosedWindow>, String> {
+    with_windows(|windows| {
+        let windows = windows.get_or_insert_with(|| Windows::new(config));
+        if windows.config != config {
+            return Err("The window config changed mid-stream; flush the stream first".to_string());
+        }
+        windows.push(text, timestamp_ms)
+    })
+}
+
+// Ends the current stream, leaving none
+pub(crate) fn finish() -> Vec<ClosedWindow> {
+    with_windows(Option::take).map(Windows::finish).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(kind: WindowKind, size: u64, slide: Option<u64>, by: WindowUnit) -> Windows {
+        let config = WindowConfig { kind, size, slide, by };
+        config.check().unwrap();
+        Windows::new(config)
+    }
+
+    fn bounds(windows: &[ClosedWindow]) -> Vec<(u64, u64, usize)> {
+        windows.iter().map(|w| (w.start, w.end, w.texts.len())).collect()
+    }
+
+    #[test]
+    fn tumbling_record_windows_close_every_size_records() {
+        let mut windows = windows(WindowKind::Tumbling, 3, None, WindowUnit::Records);
+        let closed: Vec<usize> = (0..7).map(|i| windows.push(i.to_string(), None).unwrap().len()).collect();
+        assert_eq!(closed, [0, 0, 1, 0, 0, 1, 0]);
+        let rest = windows.finish();
+        assert_eq!(rest, [ClosedWindow { start: 6, end: 9, partial: true, texts: vec!["6".to_string()] }]);
+    }
+
+    #[test]
+    fn sliding_record_windows_overlap() {
+        let mut windows = windows(WindowKind::Sliding, 4, Some(2), WindowUnit::Records);
+        let mut closed = Vec::new();
+        for i in 0..7 {
+            closed.extend(windows.push(i.to_string(), None).unwrap());
+        }
+        assert_eq!(bounds(&closed), [(0, 4, 4), (2, 6, 4)]);
+        assert_eq!(closed[1].texts, ["2", "3", "4", "5"]);
+        assert_eq!(bounds(&windows.finish()), [(4, 8, 3), (6, 10, 1)]);
+    }
+
+    #[test]
+    fn time_windows_skip_gaps_without_records() {
+        let mut windows = windows(WindowKind::Tumbling, 1000, None, WindowUnit::Ms);
+        assert!(windows.push("a".to_string(), Some(100)).unwrap().is_empty());
+        assert!(windows.push("b".to_string(), Some(999)).unwrap().is_empty());
+        assert_eq!(bounds(&windows.push("c".to_string(), Some(1000)).unwrap()), [(0, 1000, 2)]);
+        assert_eq!(bounds(&windows.push("d".to_string(), Some(7500)).unwrap()), [(1000, 2000, 1)]);
+        assert_eq!(bounds(&windows.finish()), [(7000, 8000, 1)]);
+    }
+
+    #[test]
+    fn time_windows_reject_missing_and_backwards_timestamps() {
+        let mut windows = windows(WindowKind::Sliding, 1000, Some(500), WindowUnit::Ms);
+        assert!(windows.push("a".to_string(), None).is_err());
+        windows.push("a".to_string(), Some(600)).unwrap();
+        assert_eq!(
+            windows.push("b".to_string(), Some(599)),
+            Err("Record at 599ms is older than the previous one at 600ms".to_string())
+        );
+    }
+
+    #[test]
+    fn timestamps_whose_windows_cant_end_are_refused() {
+        let mut windows = windows(WindowKind::Sliding, 1000, Some(500), WindowUnit::Ms);
+        let last = u64::MAX - 1000;
+        let fitting = last / 500 * 500 - 1;
+        windows.push("a".to_string(), Some(fitting)).unwrap();
+        assert_eq!(
+            windows.push("b".to_string(), Some(u64::MAX)),
+            Err(format!("Record at {}ms is too late for a 1000ms window to end", u64::MAX))
+        );
+        let rest = windows.finish();
+        assert!(rest.iter().all(|window| window.start <= fitting && window.end > fitting));
+        assert_eq!(rest.len(), 2);
+    }
+
+    #[test]
+    fn inconsistent_slides_are_rejected() {
+        let config = |kind, slide| WindowConfig { kind, size: 4, slide, by: WindowUnit::Records };
+        assert!(config(WindowKind::Tumbling, Some(4)).check().is_ok());
+        assert!(config(WindowKind::Tumbling, Some(2)).check().is_err());
+        assert!(config(WindowKind::Sliding, None).check().is_err());
+        assert!(config(WindowKind::Sliding, Some(5)).check().is_err());
+        assert!(WindowConfig { size: 0, ..config(WindowKind::Tumbling, None) }.check().is_err());
+    }
+}
@@ -0,0 +1,502 @@
+// Everything the plugin hands back to the host goes through
+// `serialize_to_guest`, which owns the encoding choice, optional
+// compression, and the buffer-size protocol shared by every export:
+//
+// The return value is the full encoded length, packed with an
+// `abi::AbiError` code if the host negotiated them (see `abi`). If the
+// buffer is too small nothing is written, the code is `BufferTooSmall`, and
+// the host should retry with a buffer of at least that length.
+//
+// Payloads larger than the plugin's memory permission are never handed
+// over; the host gets a `resource_exhausted` error response instead, with
+// the code `OutOfMemory`.
+//
+// `serialize_owned` is the single-call alternative: the plugin allocates a
+// buffer of exactly the encoded length and hands it over. The return value
+// is again a code and the length, and `last_output` the buffer's address. From then on
+// the buffer belongs to the host, which reads it and must release it with
+// `dealloc(ptr, len)`; until then it counts against the memory permission.
+//
+// Frequency maps from similar documents share most of their bytes, which
+// per-response compression can't exploit. A host can install a zstd
+// dictionary (trained on earlier outputs) with `set_dictionary` and then
+// ask for `dictionary` compression; it decompresses with the same
+// dictionary.
+//
+// Each encoding is a `WireCodec`, looked up at runtime by `codec`, so an
+// export takes whichever the host named without a build per format:
+// requests come in the encoding negotiated in `init_with` (see `session`),
+// and responses go out in the request's `wire.encoding`, or that one. The
+// trait is object safe (values pass through `erased_serde`); `encode` and
+// `decode` on `dyn WireCodec` are the typed entry points. Every decoder
+// rejects bytes left over after the value.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use std::cell::Cell;
+
+use crate::abi::{self, AbiError};
+use crate::{memory, parse_memory_limit, session, ErrorCode, Response, PERMISSION_MEMORY};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Encoding {
+    #[default]
+    Json,
+    Msgpack,
+    Cbor,
+}
+
+impl Encoding {
+    // Every encoding, as `Capabilities` advertises them
+    pub(crate) const ALL: [Encoding; 3] = [Encoding::Json, Encoding::Msgpack, Encoding::Cbor];
+}
+
+// Hands a decoder to whatever deserializes from it; see `WireCodec::decode`
+pub(crate) type Visit<'a> =
+    dyn for<'de> FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error> + 'a;
+
+pub(crate) trait WireCodec: Sync {
+    fn encoding(&self) -> Encoding;
+    fn encode_erased(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String>;
+    fn decode_erased(&self, bytes: &[u8], visit: &mut Visit) -> Result<(), String>;
+}
+
+impl dyn WireCodec + '_ {
+    pub(crate) fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        self.encode_erased(value)
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        let mut decoded = None;
+        self.decode_erased(bytes, &mut |deserializer| {
+            decoded = Some(erased_serde::deserialize(deserializer)?);
+            Ok(())
+        })?;
+        decoded.ok_or_else(|| "Nothing was decoded".to_string())
+    }
+}
+
+pub(crate) struct Json;
+
+impl WireCodec for Json {
+    fn encoding(&self) -> Encoding {
+        Encoding::Json
+    }
+
+    fn encode_erased(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(value).map_err(|e| e.to_string())
+    }
+
+    fn decode_erased(&self, bytes: &[u8], visit: &mut Visit) -> Result<(), String> {
+        let text = std::str::from_utf8(bytes).map_err(|_| "Invalid UTF-8 input".to_string())?;
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut deserializer)).map_err(|e| e.to_string())?;
+        deserializer.end().map_err(|e| e.to_string())
+    }
+}
+
+// Maps are encoded with their keys, as JSON's are
+pub(crate) struct Msgpack;
+
+impl WireCodec for Msgpack {
+    fn encoding(&self) -> Encoding {
+        Encoding::Msgpack
+    }
+
+    fn encode_erased(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        rmp_serde::to_vec_named(value).map_err(|e| e.to_string())
+    }
+
+    fn decode_erased(&self, bytes: &[u8], visit: &mut Visit) -> Result<(), String> {
+        let mut deserializer = rmp_serde::Deserializer::new(std::io::Cursor::new(bytes));
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut deserializer)).map_err(|e| e.to_string())?;
+        trailing(bytes.len() - deserializer.position() as usize)
+    }
+}
+
+pub(crate) struct Cbor;
+
+impl WireCodec for Cbor {
+    fn encoding(&self) -> Encoding {
+        Encoding::Cbor
+    }
+
+    fn encode_erased(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        ciborium::into_writer(&value, &mut out).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+
+    fn decode_erased(&self, bytes: &[u8], visit: &mut Visit) -> Result<(), String> {
+        use cbor4ii::core::dec::Read;
+
+        let mut deserializer = cbor4ii::serde::Deserializer::new(cbor4ii::core::utils::SliceReader::new(bytes));
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut deserializer)).map_err(|e| e.to_string())?;
+        let mut rest = deserializer.into_inner();
+        let Ok(left) = rest.fill(1);
+        trailing(left.as_ref().len())
+    }
+}
+
+fn trailing(left: usize) -> Result<(), String> {
+    match left {
+        0 => Ok(()),
+        _ => Err("trailing bytes after the value".to_string()),
+    }
+}
+
+pub(crate) fn codec(encoding: Encoding) -> &'static dyn WireCodec {
+    match encoding {
+        Encoding::Json => &Json,
+        Encoding::Msgpack => &Msgpack,
+        Encoding::Cbor => &Cbor,
+    }
+}
+
+// Output options the host selects per request
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct WireOpts {
+    // The negotiated encoding when unset
+    #[serde(default)]
+    pub(crate) encoding: Option<Encoding>,
+    // zstd-compress the encoded payload
+    #[serde(default)]
+    pub(crate) compress: bool,
+    // Compress against the dictionary from `set_dictionary`
+    #[serde(default)]
+    pub(crate) dictionary: bool,
+}
+
+impl WireOpts {
+    // Uncompressed, in `codec`'s encoding
+    pub(crate) fn with_codec(codec: &dyn WireCodec) -> Self {
+        WireOpts { encoding: Some(codec.encoding()), ..WireOpts::default() }
+    }
+
+    // Fills in `codec`'s encoding if the request named none
+    pub(crate) fn or_codec(self, codec: &dyn WireCodec) -> Self {
+        WireOpts { encoding: Some(self.encoding.unwrap_or(codec.encoding())), ..self }
+    }
+
+    fn codec(&self) -> &'static dyn WireCodec {
+        codec(self.encoding.unwrap_or_else(session::encoding))
+    }
+}
+
+#[cfg(feature = "compression")]
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[cfg(feature = "compression")]
+static DICTIONARY: std::sync::Mutex<Option<Vec<u8>>> = std::sync::Mutex::new(None);
+
+// Installs the host's dictionary, or removes it if `dictionary` is empty
+#[cfg(feature = "compression")]
+pub(crate) fn set_dictionary(dictionary: &[u8]) -> Result<(), String> {
+    let dictionary = if dictionary.is_empty() {
+        None
+    } else {
+        // Reject it now rather than on every response
+        zstd::bulk::Compressor::with_dictionary(COMPRESSION_LEVEL, dictionary).map_err(|e| e.to_string())?;
+        Some(dictionary.to_vec())
+    };
+    *DICTIONARY.lock().unwrap_or_else(std::sync::PoisonError::into_inner) = dictionary;
+    Ok(())
+}
+
+#[cfg(not(feature = "compression"))]
+pub(crate) fn set_dictionary(_dictionary: &[u8]) -> Result<(), String> {
+    Err("Compression is not supported by this build".to_string())
+}
+
+pub(crate) fn encode<T: Serialize>(value: &T, opts: WireOpts) -> Result<Vec<u8>, String> {
+    let bytes = opts.codec().encode(value)?;
+    if opts.compress {
+        return compress(&bytes, opts.dictionary);
+    }
+    Ok(bytes)
+}
+
+#[cfg(feature = "compression")]
+fn compress(bytes: &[u8], use_dictionary: bool) -> Result<Vec<u8>, String> {
+    if !use_dictionary {
+        return compress_with(bytes, None);
+    }
+    match DICTIONARY.lock().unwrap_or_else(std::sync::PoisonError::into_inner).as_deref() {
+        Some(dictionary) => compress_with(bytes, Some(dictionary)),
+        None => Err("No compression dictionary has been set".to_string()),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compress_with(bytes: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    match dictionary {
+        Some(dictionary) => zstd::bulk::Compressor::with_dictionary(COMPRESSION_LEVEL, dictionary)
+            .and_then(|mut compressor| compressor.compress(bytes)),
+        None => zstd::bulk::compress(bytes, COMPRESSION_LEVEL),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_bytes: &[u8], _use_dictionary: bool) -> Result<Vec<u8>, String> {
+    Err("Compression is not supported by this build".to_string())
+}
+
+// Encodes `value` per `opts` and copies it to `out_ptr` if it fits. If the
+// value can't be encoded, an uncompressed JSON error `Response` is written
+// in its place so the host always receives something decodable.
+//
+// # Safety
+//
+// `out_ptr` must be null (nothing is written) or valid for writes of
+// `out_len` bytes.
+pub(crate) unsafe fn serialize_to_guest<T: Serialize>(
+    value: &T,
+    out_ptr: *mut u8,
+    out_len: usize,
+    opts: WireOpts,
+) -> usize {
+    serialize_within_limit(value, out_ptr, out_len, opts, permission_limit())
+}
+
+// Also the most a return value can report
+fn permission_limit() -> usize {
+    parse_memory_limit(PERMISSION_MEMORY).unwrap_or(usize::MAX).min(abi::MAX_LENGTH)
+}
+
+// Per thread, so concurrent callers each read back their own buffer
+thread_local! {
+    static LAST_OUTPUT: Cell<*mut u8> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+// Encodes `value` per `opts` into a buffer the host takes ownership of, and
+// returns its length
+pub(crate) fn serialize_owned<T: Serialize>(value: &T, opts: WireOpts) -> usize {
+    let (bytes, code) = encode_within_limit(value, opts, permission_limit());
+    let bytes = bytes.into_boxed_slice();
+    let len = bytes.len();
+    memory::charge(len);
+    LAST_OUTPUT.with(|last| last.set(Box::into_raw(bytes).cast::<u8>()));
+    abi::return_value(code, len)
+}
+
+// The buffer most recently handed over by `serialize_owned` on this thread,
+// or null if there was none
+pub(crate) fn last_output() -> *mut u8 {
+    LAST_OUTPUT.with(Cell::get)
+}
+
+// `serialize_to_guest` with an explicit payload ceiling in bytes.
+//
+// # Safety
+//
+// `out_ptr` must be valid for writes of `out_len` bytes.
+unsafe fn serialize_within_limit<T: Serialize>(
+    value: &T,
+    out_ptr: *mut u8,
+    out_len: usize,
+    opts: WireOpts,
+    limit: usize,
+) -> usize {
+    let (bytes, code) = encode_within_limit(value, opts, limit);
+    if out_ptr.is_null() || bytes.len() > out_len {
+        return abi::return_value(AbiError::BufferTooSmall, bytes.len());
+    }
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len());
+    abi::return_value(code, bytes.len())
+}
+
+// The bytes to hand over for `value`: its encoding, or an error response
+// if it can't be encoded or is larger than `limit`, with the code saying
+// which
+fn encode_within_limit<T: Serialize>(value: &T, opts: WireOpts, limit: usize) -> (Vec<u8>, AbiError) {
+    match encode(value, opts) {
+        Ok(bytes) if bytes.len() > limit => {
+            let response = Response::failure(
+                ErrorCode::ResourceExhausted,
+                format!("Response of {} bytes exceeds the {} byte memory permission", bytes.len(), limit),
+            );
+            let bytes = encode(&response, opts).unwrap_or_else(|_| serde_json::to_vec(&response).unwrap());
+            (bytes, AbiError::OutOfMemory)
+        }
+        Ok(bytes) => (bytes, AbiError::Ok),
+        Err(e) => {
+            let response = Response::error(format!("Failed to encode output: {}", e));
+            (serde_json::to_vec(&response).unwrap(), AbiError::SerializationFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn write(value: &Value, out_len: usize, opts: WireOpts) -> (usize, Vec<u8>) {
+        let mut out = vec![0u8; out_len];
+        let len = unsafe { serialize_to_guest(value, out.as_mut_ptr(), out.len(), opts) };
+        (len, out)
+    }
+
+    #[test]
+    fn fitting_payload_is_written_in_full() {
+        let value = json!({"hello": "world"});
+        let (len, out) = write(&value, 64, WireOpts::default());
+        assert_eq!(serde_json::from_slice::<Value>(&out[..len]).unwrap(), value);
+    }
+
+    #[test]
+    fn overflow_returns_required_size_without_writing() {
+        let value = json!({"hello": "world"});
+        let (returned, _) = write(&value, 4, WireOpts::default());
+        assert_eq!(returned, serde_json::to_vec(&value).unwrap().len());
+
+        crate::session::apply(&serde_json::from_str(r#"{"returnCodes": true}"#).unwrap());
+        let (returned, out) = write(&value, 4, WireOpts::default());
+        let (code, len) = abi::decode_return(returned).unwrap();
+        assert_eq!(code, AbiError::BufferTooSmall);
+        assert_eq!(len, serde_json::to_vec(&value).unwrap().len());
+        assert!(len > 4);
+        assert_eq!(out, [0u8; 4]);
+    }
+
+    #[test]
+    fn oversized_payload_becomes_resource_exhausted_error() {
+        crate::session::apply(&serde_json::from_str(r#"{"returnCodes": true}"#).unwrap());
+        let value = json!({"text": "word ".repeat(1000)});
+        let mut out = vec![0u8; 64 * 1024];
+        let returned = unsafe { serialize_within_limit(&value, out.as_mut_ptr(), out.len(), WireOpts::default(), 1024) };
+        let (code, len) = abi::decode_return(returned).unwrap();
+        assert_eq!(code, AbiError::OutOfMemory);
+        let response: Value = serde_json::from_slice(&out[..len]).unwrap();
+        assert_eq!(response["success"], false);
+        assert_eq!(response["error_code"], "resource_exhausted");
+        assert!(len <= 1024);
+    }
+
+    #[test]
+    fn msgpack_payload_decodes_to_same_value() {
+        let value = json!({"total_words": 3, "word_frequencies": {"a": 2, "b": 1}});
+        let opts = WireOpts::with_codec(&Msgpack);
+        let (len, out) = write(&value, 256, opts);
+        assert_eq!(rmp_serde::from_slice::<Value>(&out[..len]).unwrap(), value);
+    }
+
+    #[test]
+    fn every_codec_decodes_what_it_encodes() {
+        let value = json!({"total_words": 3, "ratio": 0.5, "words": ["a", "b"], "none": null});
+        for encoding in Encoding::ALL {
+            let codec = codec(encoding);
+            let bytes = codec.encode(&value).unwrap();
+            assert_eq!(codec.decode::<Value>(&bytes).unwrap(), value, "{:?}", encoding);
+            assert!(codec.decode::<Value>(&bytes[..bytes.len() - 1]).is_err(), "{:?}", encoding);
+            let mut trailing = bytes.clone();
+            trailing.extend_from_slice(&bytes);
+            assert!(codec.decode::<Value>(&trailing).is_err(), "{:?}", encoding);
+        }
+        let (len, out) = write(&value, 256, WireOpts::with_codec(&Cbor));
+        assert_eq!(cbor4ii::serde::from_slice::<Value>(&out[..len]).unwrap(), value);
+    }
+
+    #[test]
+    fn binary_codecs_keep_byte_strings_and_integer_keys() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Payload {
+            #[serde(with = "serde_bytes_compat")]
+            data: Vec<u8>,
+            counts: std::collections::BTreeMap<u32, u32>,
+        }
+        let payload = Payload { data: vec![0, 255, 7], counts: [(1, 2), (40, 3)].into_iter().collect() };
+        for codec in [&Msgpack as &dyn WireCodec, &Cbor] {
+            let bytes = codec.encode(&payload).unwrap();
+            assert_eq!(codec.decode::<Payload>(&bytes).unwrap(), payload);
+        }
+    }
+
+    // Byte strings as the binary formats' native bytes type
+    mod serde_bytes_compat {
+        pub(super) fn serialize<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(bytes)
+        }
+
+        pub(super) fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+            struct Bytes;
+
+            impl serde::de::Visitor<'_> for Bytes {
+                type Value = Vec<u8>;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("a byte string")
+                }
+
+                fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+                    Ok(bytes.to_vec())
+                }
+            }
+
+            deserializer.deserialize_bytes(Bytes)
+        }
+    }
+
+    #[test]
+    fn json_decoding_rejects_trailing_bytes_and_bad_utf8() {
+        assert!(Json.decode_erased(b"{} {}", &mut |_| Ok(())).is_err());
+        assert_eq!((&Json as &dyn WireCodec).decode::<Value>(b"\xff"), Err("Invalid UTF-8 input".to_string()));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_payload_decompresses_to_encoded_value() {
+        let value = json!({"text": "word ".repeat(500)});
+        let opts = WireOpts { compress: true, ..WireOpts::default() };
+        let (len, out) = write(&value, 64 * 1024, opts);
+        assert!(len < serde_json::to_vec(&value).unwrap().len());
+        let decompressed = zstd::bulk::decompress(&out[..len], 64 * 1024).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&decompressed).unwrap(), value);
+    }
+
+    // Outputs of similar documents: the same vocabulary with varying counts
+    #[cfg(feature = "compression")]
+    fn similar_outputs(count: u64) -> Vec<Vec<u8>> {
+        let vocabulary = [
+            "analysis", "document", "frequency", "workflow", "pipeline", "plugin", "counter", "sentence",
+            "paragraph", "language", "collation", "normalize", "statistics", "response", "request", "payload",
+        ];
+        let mut rng = crate::rng::SeededRng::new(7);
+        (0..count)
+            .map(|_| {
+                let mut frequencies = serde_json::Map::new();
+                for word in vocabulary {
+                    if rng.below(4) != 0 {
+                        frequencies.insert(word.to_string(), json!(rng.below(50) + 1));
+                    }
+                }
+                serde_json::to_vec(&json!({"total_words": rng.below(1000), "word_frequencies": frequencies})).unwrap()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn dictionary_compresses_similar_outputs_better() {
+        let dictionary = zstd::dict::from_samples(&similar_outputs(500), 4096).unwrap();
+        let outputs = similar_outputs(100);
+        let plain: usize = outputs.iter().map(|o| compress_with(o, None).unwrap().len()).sum();
+        let with_dictionary: usize = outputs.iter().map(|o| compress_with(o, Some(&dictionary)).unwrap().len()).sum();
+        assert!(with_dictionary * 2 < plain, "{} vs {} bytes", with_dictionary, plain);
+
+        let compressed = compress_with(&outputs[0], Some(&dictionary)).unwrap();
+        let mut decompressor = zstd::bulk::Decompressor::with_dictionary(&dictionary).unwrap();
+        assert_eq!(decompressor.decompress(&compressed, 64 * 1024).unwrap(), outputs[0]);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn dictionary_compression_needs_a_dictionary() {
+        assert_eq!(set_dictionary(&[]), Ok(()));
+        let opts = WireOpts { compress: true, dictionary: true, ..WireOpts::default() };
+        assert_eq!(encode(&json!({}), opts), Err("No compression dictionary has been set".to_string()));
+    }
+}
@@ -0,0 +1,78 @@
+// Zipf's law fit: how closely word frequency falls off with frequency rank
+// as frequency ∝ rank^slope. A least-squares line through (ln rank,
+// ln frequency) gives the slope, about -1 for natural text, and R² says how
+// well the line fits (1 is a perfect power law).
+//
+// Ranks follow descending frequency; words tied on a frequency each take
+// their own rank, as in most corpus tools.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ZipfFit {
+    // Both `None` when there's no line to fit
+    pub slope: Option<f64>,
+    pub r_squared: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+pub(crate) fn fit(frequencies: impl IntoIterator<Item = usize>) -> ZipfFit {
+    let mut frequencies: Vec<usize> = frequencies.into_iter().collect();
+    frequencies.sort_unstable_by(|a, b| b.cmp(a));
+    // With one distinct frequency every y is the same, so there's no slope
+    // worth reporting and R² is undefined
+    if frequencies.first() == frequencies.last() {
+        return ZipfFit {
+            slope: None,
+            r_squared: None,
+            warning: Some("Zipf fit needs at least 2 distinct word frequencies".to_string()),
+        };
+    }
+
+    let points: Vec<(f64, f64)> = frequencies.iter()
+        .enumerate()
+        .map(|(index, frequency)| (((index + 1) as f64).ln(), (*frequency as f64).ln()))
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let syy: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    let slope = sxy / sxx;
+    ZipfFit {
+        slope: Some(slope),
+        r_squared: Some(sxy * sxy / (sxx * syy)),
+        warning: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zipfian_frequencies_fit_a_slope_of_minus_one() {
+        let frequencies = (1..=200).map(|rank| 10_000 / rank);
+        let fit = fit(frequencies);
+        assert!((fit.slope.unwrap() + 1.0).abs() < 0.01, "{:?}", fit);
+        assert!(fit.r_squared.unwrap() > 0.999, "{:?}", fit);
+    }
+
+    #[test]
+    fn uniform_frequencies_fit_poorly() {
+        // A flat head then a cliff: not a power law
+        let fit = fit([5, 5, 5, 5, 5, 5, 5, 1]);
+        assert!(fit.r_squared.unwrap() < 0.6, "{:?}", fit);
+    }
+
+    #[test]
+    fn fewer_than_two_distinct_frequencies_give_nulls() {
+        for frequencies in [vec![], vec![7], vec![2, 2, 2]] {
+            let fit = fit(frequencies);
+            assert_eq!((fit.slope, fit.r_squared), (None, None));
+            assert!(fit.warning.is_some());
+        }
+    }
+}
@@ -0,0 +1,9 @@
+// `#[derive(PocketNode)]` expanded in a crate of its own, the way a plugin
+// built on this one would use it
+
+#[test]
+fn derive_compiles_outside_the_crate() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/minimal_node.rs");
+    cases.compile_fail("tests/ui/missing_node_type.rs");
+}
@@ -0,0 +1,65 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use word_counter::{Node, PocketNode};
+
+#[derive(PocketNode, Default)]
+#[pocket(node_type = "shout", description = "Uppercase a text")]
+#[pocket(input = ShoutInputSchema)]
+struct Shout;
+
+// Deliberately without `JsonSchema`; the schema comes from the mirror below
+#[derive(Deserialize)]
+struct ShoutInput {
+    text: String,
+}
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct ShoutInputSchema {
+    /// The text to shout
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ShoutOutput {
+    text: String,
+}
+
+impl Node for Shout {
+    type Config = ();
+    type Input = ShoutInput;
+    type Output = ShoutOutput;
+
+    fn exec(&self, _: &(), input: &ShoutInput) -> Result<ShoutOutput, String> {
+        Ok(ShoutOutput { text: input.text.to_uppercase() })
+    }
+
+    fn post(&self, _: &ShoutOutput) -> &'static str {
+        "default"
+    }
+}
+
+fn call(request: serde_json::Value) -> serde_json::Value {
+    let request = request.to_string();
+    let mut out = vec![0u8; 64 * 1024];
+    let len = unsafe { word_counter::call(request.as_ptr(), request.len(), out.as_mut_ptr(), out.len()) };
+    serde_json::from_slice(&out[..len]).unwrap()
+}
+
+fn main() {
+    assert_eq!(<Shout as word_counter::derived::PocketNode>::NODE_TYPE, "shout");
+    assert_eq!(<Shout as word_counter::derived::PocketNode>::CATEGORY, "general");
+    assert!(word_counter::derived::schema::<()>().is_none());
+    let schema = word_counter::derived::schema::<ShoutInputSchema>().unwrap();
+    assert_eq!(schema["properties"]["text"]["description"], "The text to shout");
+
+    let unknown = call(serde_json::json!({"node": "shout", "function": "exec", "input": {"text": "hi"}}));
+    assert_eq!(unknown["error_code"], "unknown_node");
+
+    word_counter::derived::register::<Shout>().unwrap();
+    assert!(word_counter::derived::register::<Shout>().is_err());
+    let exec = call(serde_json::json!({"node": "shout", "function": "exec", "input": {"text": "hi"}}));
+    assert_eq!(exec["output"], serde_json::json!({"text": "HI"}));
+    let post = call(serde_json::json!({"node": "shout", "function": "post", "input": exec["output"]}));
+    assert_eq!(post["next"], "default");
+}
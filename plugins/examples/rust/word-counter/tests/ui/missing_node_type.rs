@@ -0,0 +1,7 @@
+use word_counter::PocketNode;
+
+#[derive(PocketNode, Default)]
+#[pocket(description = "No type to register it under")]
+struct Unnamed;
+
+fn main() {}